@@ -0,0 +1,364 @@
+//! ASCII armor
+//!
+//! Only the armor itself is checked: the decoded data is returned as-is, and
+//! must be parsed (for instance with [`crate::signature::read_signature`]) like
+//! any other untrusted packet data.  Text before the armor is ignored, as
+//! OpenPGP implementations commonly emit it, but anything after it is an error.
+
+use super::{Error, Limits};
+use alloc::vec::Vec;
+
+/// The kind of data in an armor block
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Kind {
+    /// `PGP SIGNATURE`
+    Signature,
+    /// `PGP PUBLIC KEY BLOCK`
+    PublicKey,
+}
+
+impl Kind {
+    /// The label in the armor header and tail lines
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Signature => "PGP SIGNATURE",
+            Kind::PublicKey => "PGP PUBLIC KEY BLOCK",
+        }
+    }
+}
+
+const CRC24_INIT: u32 = 0xB704CE;
+const CRC24_POLY: u32 = 0x1864CFB;
+
+/// The CRC-24 checksum of `data`, as used in armor
+pub(crate) fn crc24(data: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for &byte in data {
+        crc ^= u32::from(byte) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xFFFFFF
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The number of base64 characters per line of armor emitted by [`encode`]
+pub const LINE_LENGTH: usize = 64;
+
+fn encode_base64(data: &[u8], out: &mut Vec<u8>) {
+    for (i, chunk) in data.chunks(LINE_LENGTH / 4 * 3).enumerate() {
+        if i != 0 {
+            out.push(b'\n')
+        }
+        for group in chunk.chunks(3) {
+            let mut bytes = [0; 4];
+            bytes[1..=group.len()].copy_from_slice(group);
+            let value = u32::from_be_bytes(bytes);
+            for j in 0..4 {
+                out.push(if j <= group.len() {
+                    BASE64[(value >> (18 - 6 * j) & 63) as usize]
+                } else {
+                    b'='
+                })
+            }
+        }
+    }
+}
+
+fn base64_value(c: u8) -> Result<u32, Error> {
+    Ok(match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return Err(Error::BadArmor),
+    }
+    .into())
+}
+
+/// Decode one line of base64 into `out`.  Padding is only allowed if `last`
+/// is set.
+fn decode_base64_line(line: &[u8], last: bool, out: &mut Vec<u8>) -> Result<(), Error> {
+    if line.len() & 3 != 0 {
+        return Err(Error::BadArmor);
+    }
+    for (i, chunk) in line.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && (!last || (i + 1) * 4 != line.len())) {
+            return Err(Error::BadArmor);
+        }
+        let mut value = 0;
+        for &c in &chunk[..4 - padding] {
+            value = value << 6 | base64_value(c)?;
+        }
+        value <<= 6 * padding;
+        let bytes = value.to_be_bytes();
+        // Bits that fall into the padding must be zero
+        if bytes[4 - padding..].iter().any(|&b| b != 0) {
+            return Err(Error::BadArmor);
+        }
+        out.extend_from_slice(&bytes[1..4 - padding]);
+    }
+    Ok(())
+}
+
+/// Strips trailing whitespace, including any carriage return, from `line`
+pub(crate) fn trim_line(line: &[u8]) -> &[u8] {
+    let end = line
+        .iter()
+        .rposition(|&c| !matches!(c, b' ' | b'\t' | b'\r'))
+        .map_or(0, |i| i + 1);
+    &line[..end]
+}
+
+/// Splits `data` into lines, without their line endings or trailing
+/// whitespace
+fn lines(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split(|&c| c == b'\n').map(trim_line)
+}
+
+fn kind_of(line: &[u8], prefix: &[u8]) -> Option<Kind> {
+    let label = line.strip_prefix(prefix)?.strip_suffix(b"-----")?;
+    [Kind::Signature, Kind::PublicKey]
+        .iter()
+        .copied()
+        .find(|kind| kind.label().as_bytes() == label)
+}
+
+/// Decode the first armor block in `armor`.  The checksum line is optional,
+/// but if present, it must match.  Armor headers (such as `Version:`) are
+/// skipped.  The default [`Limits`] apply.
+pub fn decode(armor: &[u8]) -> Result<(Kind, Vec<u8>), Error> {
+    decode_with_limits(armor, &Limits::default())
+}
+
+/// Decode the first armor block in `armor`, as [`decode`] does, but with the
+/// given limits on its size and line length
+pub fn decode_with_limits(armor: &[u8], limits: &Limits) -> Result<(Kind, Vec<u8>), Error> {
+    if armor.len() > limits.armor_size {
+        return Err(Error::ArmorTooLarge);
+    }
+    if armor
+        .split_inclusive(|&c| c == b'\n')
+        .any(|line| line.len() > limits.armor_line_length)
+    {
+        return Err(Error::ArmorLineTooLong);
+    }
+    let mut lines = lines(armor);
+    let kind = loop {
+        match lines.next() {
+            Some(line) => {
+                if let Some(kind) = kind_of(line, b"-----BEGIN ") {
+                    break kind;
+                }
+            }
+            None => return Err(Error::BadArmor),
+        }
+    };
+    // Armor headers, terminated by an empty line
+    loop {
+        match lines.next() {
+            Some(b"") => break,
+            Some(line) if line.windows(2).any(|w| w == b": ") => {}
+            _ => return Err(Error::BadArmor),
+        }
+    }
+    let mut data = Vec::new();
+    let mut checksum = None;
+    let mut last = false;
+    loop {
+        let line = lines.next().ok_or(Error::BadArmor)?;
+        if let Some(crc) = line.strip_prefix(b"=") {
+            let mut crc_bytes = Vec::new();
+            decode_base64_line(crc, false, &mut crc_bytes)?;
+            if crc_bytes.len() != 3 {
+                return Err(Error::BadArmor);
+            }
+            checksum = Some(u32::from_be_bytes([
+                0,
+                crc_bytes[0],
+                crc_bytes[1],
+                crc_bytes[2],
+            ]));
+            if kind_of(lines.next().ok_or(Error::BadArmor)?, b"-----END ") != Some(kind) {
+                return Err(Error::BadArmor);
+            }
+            break;
+        } else if line.starts_with(b"-----") {
+            if kind_of(line, b"-----END ") != Some(kind) {
+                return Err(Error::BadArmor);
+            }
+            break;
+        } else if last {
+            // Padded data must be on the last line
+            return Err(Error::BadArmor);
+        }
+        last = line.ends_with(b"=");
+        decode_base64_line(line, true, &mut data)?;
+    }
+    if lines.any(|line| !line.is_empty()) {
+        return Err(Error::TrailingJunk);
+    }
+    match checksum {
+        Some(crc) if crc != crc24(&data) => Err(Error::BadArmorChecksum),
+        _ => Ok((kind, data)),
+    }
+}
+
+/// Armor `data` in canonical form: no armor headers, lines of
+/// [`LINE_LENGTH`] characters, a checksum line, and `\n` line endings.  `data`
+/// is not checked.
+pub fn encode(kind: Kind, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 3 * 4 + data.len() / 48 + 80);
+    for &part in &[b"-----BEGIN ", kind.label().as_bytes(), b"-----\n\n"] {
+        out.extend_from_slice(part)
+    }
+    if !data.is_empty() {
+        encode_base64(data, &mut out);
+        out.push(b'\n')
+    }
+    out.push(b'=');
+    encode_base64(&crc24(data).to_be_bytes()[1..], &mut out);
+    for &part in &[b"\n-----END ", kind.label().as_bytes(), b"-----\n"] {
+        out.extend_from_slice(part)
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDDSA_SIG_ARMORED: &str = "\
+-----BEGIN PGP SIGNATURE-----
+Comment: test vector
+
+iHUEABYIAB0WIQToOjgLhXVWKzxvQcoopFyTsLW24AUCYA93GgAKCRAopFyTsLW2
+4GFYAP96t6+ngjoe0wsfvu5QbKCnsYl6l2m4bYRECXeM+v4Q9gEAy1PZxcEOLIRi
+IGWwuRpG9/m0/v0sHplyWEjAImNHEgo=
+=dUDW
+-----END PGP SIGNATURE-----
+";
+
+    #[test]
+    fn decodes_armor() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let armored = EDDSA_SIG_ARMORED.as_bytes();
+        assert_eq!(
+            decode(armored).unwrap(),
+            (Kind::Signature, EDDSA_SIG.to_vec())
+        );
+        // CRLF line endings, leading text, and no checksum
+        let mut crlf = b"leading text\r\n".to_vec();
+        for line in EDDSA_SIG_ARMORED
+            .lines()
+            .filter(|l| !l.starts_with("=dUDW"))
+        {
+            crlf.extend_from_slice(line.as_bytes());
+            crlf.extend_from_slice(b"\r\n");
+        }
+        assert_eq!(decode(&crlf).unwrap().1, EDDSA_SIG);
+        // Corrupt the checksum
+        let bad_crc = EDDSA_SIG_ARMORED.replace("=dUDW", "=dUDX");
+        assert_eq!(decode(bad_crc.as_bytes()), Err(Error::BadArmorChecksum));
+        // Corrupt the data
+        let bad_data = EDDSA_SIG_ARMORED.replace("iHUE", "iHUF");
+        assert_eq!(decode(bad_data.as_bytes()), Err(Error::BadArmorChecksum));
+        let wrong_tail = EDDSA_SIG_ARMORED.replace("END PGP SIGNATURE", "END PGP MESSAGE");
+        assert_eq!(decode(wrong_tail.as_bytes()), Err(Error::BadArmor));
+        let junk = [armored, b"junk\n"].concat();
+        assert_eq!(decode(&junk), Err(Error::TrailingJunk));
+        assert_eq!(decode(&armored[..100]), Err(Error::BadArmor));
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let armored = EDDSA_SIG_ARMORED.as_bytes();
+        let mut limits = Limits {
+            armor_size: armored.len(),
+            ..Limits::default()
+        };
+        assert!(decode_with_limits(armored, &limits).is_ok());
+        limits.armor_size -= 1;
+        assert_eq!(
+            decode_with_limits(armored, &limits),
+            Err(Error::ArmorTooLarge)
+        );
+        // The longest line is 64 characters of base64 and a newline
+        let mut limits = Limits {
+            armor_line_length: 65,
+            ..Limits::default()
+        };
+        assert!(decode_with_limits(armored, &limits).is_ok());
+        limits.armor_line_length = 64;
+        assert_eq!(
+            decode_with_limits(armored, &limits),
+            Err(Error::ArmorLineTooLong)
+        );
+        // Long lines and masses of blank lines anywhere in the input
+        let mut long = vec![b' '; 5000];
+        long.extend_from_slice(armored);
+        assert_eq!(decode(&long), Err(Error::ArmorLineTooLong));
+        let mut blank = armored.to_vec();
+        blank.resize(17 << 20, b'\n');
+        assert_eq!(decode(&blank), Err(Error::ArmorTooLarge));
+        assert_eq!(
+            Error::ArmorTooLarge.category(),
+            crate::ErrorCategory::Policy
+        );
+    }
+
+    #[test]
+    fn encodes_armor() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let canonical = EDDSA_SIG_ARMORED.replace("Comment: test vector\n", "");
+        let encoded = encode(Kind::Signature, EDDSA_SIG);
+        assert_eq!(core::str::from_utf8(&encoded).unwrap(), canonical);
+        let mut data = Vec::new();
+        for len in 0..200 {
+            for kind in [Kind::Signature, Kind::PublicKey].iter().copied() {
+                assert_eq!(decode(&encode(kind, &data)).unwrap(), (kind, data.clone()));
+            }
+            data.push(len as u8 ^ 0xA5);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_base64() {
+        let mut out = Vec::new();
+        assert!(decode_base64_line(b"AA==", true, &mut out).is_ok());
+        assert_eq!(out, [0]);
+        // Non-zero bits in the padding
+        assert_eq!(
+            decode_base64_line(b"AB==", true, &mut out),
+            Err(Error::BadArmor)
+        );
+        assert_eq!(
+            decode_base64_line(b"AA==", false, &mut out),
+            Err(Error::BadArmor)
+        );
+        assert_eq!(
+            decode_base64_line(b"A===", true, &mut out),
+            Err(Error::BadArmor)
+        );
+        assert_eq!(
+            decode_base64_line(b"AA==AAAA", true, &mut out),
+            Err(Error::BadArmor)
+        );
+        assert_eq!(
+            decode_base64_line(b"AAA", true, &mut out),
+            Err(Error::BadArmor)
+        );
+        assert_eq!(
+            decode_base64_line(b"AA!A", true, &mut out),
+            Err(Error::BadArmor)
+        );
+    }
+}
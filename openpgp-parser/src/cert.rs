@@ -0,0 +1,1016 @@
+//! Transferable public keys (certificates)
+//!
+//! A certificate is a primary key, followed by direct-key signatures, user
+//! IDs and user attributes with their certifications, and subkeys with their
+//! binding signatures.  [`read_certificate`] checks that the packets come in
+//! that order and parses the keys, but leaves the signatures unparsed: they
+//! are returned as [`KeySignature`]s for the caller to verify.  Trust packets
+//! are local to the keyring that exported them, so they are skipped.
+//!
+//! This crate has no cryptography, so checks that depend on self-signatures
+//! take a [`KeyVerifier`] that computes fingerprints and verifies signatures.
+
+use super::key::{self, PublicKey};
+use super::packet::get_varlen_bytes;
+use super::signature::{
+    SUBPACKET_CREATION_TIME, SUBPACKET_FINGERPRINT, SUBPACKET_ISSUER_KEYID,
+    SUBPACKET_KEY_EXPIRATION_TIME, SUBPACKET_REVOCATION_REASON,
+};
+use super::{is_unsafe_char, packet, Error, Limits, Reader};
+use alloc::{borrow::Cow, string::String, vec::Vec};
+
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_TRUST: u8 = 12;
+const TAG_USER_ID: u8 = 13;
+const TAG_PUBLIC_SUBKEY: u8 = 14;
+const TAG_USER_ATTRIBUTE: u8 = 17;
+
+const SIGNATURE_TYPE_GENERIC_CERTIFICATION: u8 = 0x10;
+const SIGNATURE_TYPE_POSITIVE_CERTIFICATION: u8 = 0x13;
+const SIGNATURE_TYPE_SUBKEY_BINDING: u8 = 0x18;
+const SIGNATURE_TYPE_DIRECT_KEY: u8 = 0x1F;
+const SIGNATURE_TYPE_KEY_REVOCATION: u8 = 0x20;
+const SIGNATURE_TYPE_SUBKEY_REVOCATION: u8 = 0x28;
+const SIGNATURE_TYPE_CERTIFICATION_REVOCATION: u8 = 0x30;
+
+/// A revocation, found in a revocation signature.  Revocation signatures are
+/// not verified, as this crate has no cryptography.  Honoring a forged
+/// revocation can only make verification fail, never succeed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Revocation {
+    /// When the revocation was made
+    pub time: u32,
+    /// The reason code, if the signature gives one
+    pub reason: Option<u8>,
+}
+
+impl Revocation {
+    /// Is this a hard revocation?  Hard revocations (the key was compromised,
+    /// or no reason was given) also invalidate signatures made before the
+    /// revocation.  Soft revocations (the key was superseded or retired, or
+    /// the user ID is no longer valid) do not.
+    pub fn is_hard(&self) -> bool {
+        !matches!(self.reason, Some(1) | Some(3) | Some(32))
+    }
+
+    /// Does this revocation invalidate a signature made at `time`?
+    pub fn applies_at(&self, time: u32) -> bool {
+        self.is_hard() || time >= self.time
+    }
+}
+
+/// The revocation that applies at `time`, if any, among the signatures of
+/// type `sig_type`.  Hard revocations take precedence, then the earliest.
+fn revocation(signatures: &[KeySignature<'_>], sig_type: u8) -> Option<Revocation> {
+    signatures
+        .iter()
+        .filter(|sig| sig.sig_type == sig_type)
+        .map(KeySignature::revocation)
+        .min_by_key(|r| (!r.is_hard(), r.time))
+}
+
+/// A signature over part of a certificate, not yet parsed or verified
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeySignature<'a> {
+    version: u8,
+    sig_type: u8,
+    body: &'a [u8],
+}
+
+impl<'a> KeySignature<'a> {
+    fn new(body: &'a [u8]) -> Result<Self, Error> {
+        let (version, sig_type) = match *body {
+            [3, 5, sig_type, ..] => (3, sig_type),
+            [version @ 4..=6, sig_type, ..] => (version, sig_type),
+            [3, ..] => return Err(Error::IllFormedSignature),
+            _ => return Err(Error::UnsupportedSignatureVersion),
+        };
+        Ok(Self {
+            version,
+            sig_type,
+            body,
+        })
+    }
+
+    /// The signature version
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The signature type, such as `0x13` for a positive certification
+    pub fn sig_type(&self) -> u8 {
+        self.sig_type
+    }
+
+    /// The body of the signature packet
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// The whole signature packet, for passing to a cryptographic library
+    pub fn packet(&self) -> Vec<u8> {
+        packet::Packet::new(TAG_SIGNATURE, self.body).serialize()
+    }
+
+    /// Calls `cb` with the tag (without the critical bit) and body of each
+    /// hashed subpacket, and then of each unhashed subpacket if `unhashed` is
+    /// [`true`].  v3 signatures have no subpackets.
+    fn subpackets(&self, unhashed: bool, cb: &mut dyn FnMut(u8, &'a [u8])) -> Result<(), Error> {
+        let mut reader = Reader::new(self.body);
+        reader.get_bytes(4)?;
+        for _ in 0..=(unhashed as u8) {
+            let len = match self.version {
+                3 => return Ok(()),
+                6 => reader.be_u32()? as usize,
+                _ => reader.be_u16()?.into(),
+            };
+            let mut area = Reader::new(reader.get_bytes(len)?);
+            while !area.is_empty() {
+                let mut subpacket = Reader::new(get_varlen_bytes(&mut area)?);
+                let tag = subpacket.byte()? & 0x7F;
+                cb(tag, subpacket.as_untrusted_slice())
+            }
+        }
+        Ok(())
+    }
+
+    fn hashed_subpackets(&self, cb: &mut dyn FnMut(u8, &'a [u8])) -> Result<(), Error> {
+        self.subpackets(false, cb)
+    }
+
+    /// Was this signature made by the key with the given fingerprint,
+    /// according to its issuer fingerprint and key ID subpackets?  There
+    /// must be at least one, and all of them must match.  The issuer key ID
+    /// is not protected by the signature, so this is only a filter: the
+    /// signature must still be verified.
+    fn issued_by(&self, fingerprint: &[u8]) -> bool {
+        let key_id = match fingerprint.len() {
+            20 => &fingerprint[12..],
+            32 => &fingerprint[..8],
+            _ => return false,
+        };
+        if self.version == 3 {
+            return self.body.get(7..15) == Some(key_id);
+        }
+        let (mut found, mut mismatch) = (false, false);
+        let ok = self.subpackets(true, &mut |tag, body| {
+            let matches = match tag {
+                SUBPACKET_FINGERPRINT => body.get(1..) == Some(fingerprint),
+                SUBPACKET_ISSUER_KEYID => body == key_id,
+                _ => return,
+            };
+            found = true;
+            mismatch |= !matches
+        });
+        ok.is_ok() && found && !mismatch
+    }
+
+    /// The creation time, if the signature is well-formed enough to have one
+    pub fn creation_time(&self) -> Option<u32> {
+        if self.version == 3 {
+            return Reader::new(self.body.get(3..)?).be_u32().ok();
+        }
+        let mut time = None;
+        self.hashed_subpackets(&mut |tag, body| {
+            if let (SUBPACKET_CREATION_TIME, &[a, b, c, d]) = (tag, body) {
+                time = Some(u32::from_be_bytes([a, b, c, d]))
+            }
+        })
+        .ok()?;
+        time
+    }
+
+    /// The revocation made by this signature, assuming that it is a
+    /// revocation signature.  A revocation that is too malformed to have a
+    /// creation time is treated as a hard revocation made at time 0.
+    pub fn revocation(&self) -> Revocation {
+        let mut reason = None;
+        let _ = self.hashed_subpackets(&mut |tag, body| {
+            if tag == SUBPACKET_REVOCATION_REASON {
+                reason = body.first().copied()
+            }
+        });
+        match self.creation_time() {
+            Some(time) => Revocation { time, reason },
+            None => Revocation {
+                time: 0,
+                reason: None,
+            },
+        }
+    }
+
+    /// The key expiration time, in seconds after the creation of the key.
+    /// Returns [`None`] if the signature does not give one or says that the
+    /// key does not expire.
+    pub fn key_expiration_time(&self) -> Option<u32> {
+        let mut expiration = None;
+        self.hashed_subpackets(&mut |tag, body| {
+            if let (SUBPACKET_KEY_EXPIRATION_TIME, &[a, b, c, d]) = (tag, body) {
+                expiration = Some(u32::from_be_bytes([a, b, c, d]))
+            }
+        })
+        .ok()?;
+        expiration.filter(|&e| e != 0)
+    }
+}
+
+/// Why a key cannot be used at a given time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyInvalid {
+    /// The key was created after the given time
+    NotYetCreated {
+        /// The creation time of the key
+        created: u32,
+    },
+    /// The key expired at or before the given time
+    Expired {
+        /// The expiration time of the key
+        expired: u32,
+    },
+    /// The key was revoked
+    Revoked(Revocation),
+    /// The key is not part of the certificate
+    NotInCertificate,
+    /// The key has no self-signature (for the primary key) or binding
+    /// signature (for a subkey) that was made by the primary key and
+    /// verifies
+    NoSelfSignature,
+}
+
+/// A function that passes signed data, in pieces, to its argument
+pub type SignedData<'a> = dyn FnMut(&mut dyn FnMut(&[u8])) + 'a;
+
+/// Cryptographic operations needed to check self-signatures
+pub trait KeyVerifier {
+    /// The fingerprint of `key`
+    fn fingerprint(&mut self, key: &PublicKey<'_>) -> Vec<u8>;
+
+    /// Does `signature` verify with `signer`?  `data` passes the signed
+    /// data, which does not include the signature trailer.
+    fn verify(
+        &mut self,
+        signer: &PublicKey<'_>,
+        signature: &KeySignature<'_>,
+        data: &mut SignedData<'_>,
+    ) -> bool;
+}
+
+/// The most characters of a user ID shown by [`UserId::display`]
+const DISPLAY_LENGTH: usize = 256;
+
+/// A user ID or user attribute, with its signatures
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserId<'a> {
+    value: &'a [u8],
+    attribute: bool,
+    signatures: Vec<KeySignature<'a>>,
+}
+
+impl<'a> UserId<'a> {
+    /// The raw user ID, which is normally (but not necessarily) UTF-8, or the
+    /// contents of the user attribute.  This is untrusted and must not be
+    /// displayed as is: use [`UserId::display`] for that.
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// The user ID or user attribute packet, for passing on to another
+    /// OpenPGP implementation.  The contents are untrusted: use
+    /// [`UserId::validate`] or [`UserId::display`] to get a string.
+    pub fn packet(&self) -> Vec<u8> {
+        let tag = if self.attribute {
+            TAG_USER_ATTRIBUTE
+        } else {
+            TAG_USER_ID
+        };
+        packet::Packet::new(tag, self.value).serialize()
+    }
+
+    /// The user ID as a string.  Fails with [`Error::IllFormedUserId`] if
+    /// this is a user attribute, or if the user ID is longer than
+    /// [`Limits::user_id_length`], is not UTF-8, or contains control or
+    /// bidirectional characters.
+    pub fn validate(&self, limits: &Limits) -> Result<&'a str, Error> {
+        if self.attribute || self.value.len() > limits.user_id_length {
+            return Err(Error::IllFormedUserId);
+        }
+        match core::str::from_utf8(self.value) {
+            Ok(s) if !s.chars().any(is_unsafe_char) => Ok(s),
+            _ => Err(Error::IllFormedUserId),
+        }
+    }
+
+    /// The user ID in a form safe to log or display.  Invalid UTF-8, control
+    /// characters, and bidirectional characters are replaced with U+FFFD
+    /// REPLACEMENT CHARACTER, and long user IDs are truncated with an
+    /// ellipsis.  User attributes are shown as `[user attribute]`.
+    pub fn display(&self) -> Cow<'a, str> {
+        if self.attribute {
+            return Cow::Borrowed("[user attribute]");
+        }
+        let lossy = String::from_utf8_lossy(self.value);
+        if lossy.chars().take(DISPLAY_LENGTH + 1).count() <= DISPLAY_LENGTH
+            && !lossy.chars().any(is_unsafe_char)
+        {
+            return lossy;
+        }
+        let mut out: String = lossy
+            .chars()
+            .take(DISPLAY_LENGTH)
+            .map(|c| if is_unsafe_char(c) { '\u{FFFD}' } else { c })
+            .collect();
+        if lossy.chars().nth(DISPLAY_LENGTH).is_some() {
+            out.push('\u{2026}')
+        }
+        Cow::Owned(out)
+    }
+
+    /// Is this a user attribute (tag 17), rather than a user ID (tag 13)?
+    pub fn is_attribute(&self) -> bool {
+        self.attribute
+    }
+
+    /// The certifications and certification revocations of this user ID
+    pub fn signatures(&self) -> &[KeySignature<'a>] {
+        &self.signatures
+    }
+
+    /// The certification revocation of this user ID, if any
+    pub fn revocation(&self) -> Option<Revocation> {
+        revocation(&self.signatures, SIGNATURE_TYPE_CERTIFICATION_REVOCATION)
+    }
+
+    /// Passes the user ID to `cb` as it is hashed by certifications
+    fn signed_data(&self, cb: &mut dyn FnMut(&[u8])) {
+        let tag = if self.attribute { 0xD1 } else { 0xB4 };
+        cb(&[tag]);
+        cb(&(self.value.len() as u32).to_be_bytes());
+        cb(self.value)
+    }
+}
+
+/// A subkey, with its signatures
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subkey<'a> {
+    key: PublicKey<'a>,
+    signatures: Vec<KeySignature<'a>>,
+}
+
+impl<'a> Subkey<'a> {
+    /// The subkey
+    pub fn key(&self) -> &PublicKey<'a> {
+        &self.key
+    }
+
+    /// The binding signatures and revocations of this subkey
+    pub fn signatures(&self) -> &[KeySignature<'a>] {
+        &self.signatures
+    }
+
+    /// The revocation of this subkey, if any.  This does not include
+    /// revocations of the primary key.
+    pub fn revocation(&self) -> Option<Revocation> {
+        revocation(&self.signatures, SIGNATURE_TYPE_SUBKEY_REVOCATION)
+    }
+}
+
+/// A parsed certificate
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Certificate<'a> {
+    primary: PublicKey<'a>,
+    direct_signatures: Vec<KeySignature<'a>>,
+    user_ids: Vec<UserId<'a>>,
+    subkeys: Vec<Subkey<'a>>,
+}
+
+impl<'a> Certificate<'a> {
+    /// The primary key
+    pub fn primary(&self) -> &PublicKey<'a> {
+        &self.primary
+    }
+
+    /// Signatures directly on the primary key: direct-key signatures and key
+    /// revocations
+    pub fn direct_signatures(&self) -> &[KeySignature<'a>] {
+        &self.direct_signatures
+    }
+
+    /// The user IDs and user attributes, in order
+    pub fn user_ids(&self) -> &[UserId<'a>] {
+        &self.user_ids
+    }
+
+    /// The subkeys, in order
+    pub fn subkeys(&self) -> &[Subkey<'a>] {
+        &self.subkeys
+    }
+
+    /// The primary key, followed by the subkeys
+    pub fn keys(&self) -> impl Iterator<Item = &PublicKey<'a>> {
+        core::iter::once(&self.primary).chain(self.subkeys.iter().map(Subkey::key))
+    }
+
+    /// The revocation of the primary key, if any.  This revokes the whole
+    /// certificate.
+    pub fn revocation(&self) -> Option<Revocation> {
+        revocation(&self.direct_signatures, SIGNATURE_TYPE_KEY_REVOCATION)
+    }
+
+    /// The time from which the primary key is revoked, if it is.  For hard
+    /// revocations, this is 0, as they apply to all signatures.
+    pub fn revoked_at(&self) -> Option<u32> {
+        self.revocation()
+            .map(|r| if r.is_hard() { 0 } else { r.time })
+    }
+
+    /// Is `key` (the primary key or one of the subkeys) revoked for
+    /// signatures made at `time`?  Revoking the primary key revokes every
+    /// subkey.  Returns [`None`] if `key` is not part of this certificate.
+    pub fn is_revoked_at(&self, key: &PublicKey<'_>, time: u32) -> Option<bool> {
+        let applies = |r: Option<Revocation>| r.is_some_and(|r| r.applies_at(time));
+        let primary_revoked = applies(self.revocation());
+        if key.body() == self.primary.body() {
+            return Some(primary_revoked);
+        }
+        let subkey = self.subkeys.iter().find(|s| s.key.body() == key.body())?;
+        Some(primary_revoked || applies(subkey.revocation()))
+    }
+
+    /// The most recent self-signature over the primary key: a direct-key
+    /// signature or a certification of a user ID that names the primary key
+    /// as its issuer and that `verifier` accepts.  Newer signatures that do
+    /// not verify are skipped, so appending a forged signature cannot change
+    /// the key's properties.
+    fn primary_self_signature(&self, verifier: &mut dyn KeyVerifier) -> Option<&KeySignature<'a>> {
+        let fingerprint = verifier.fingerprint(&self.primary);
+        let direct = self
+            .direct_signatures
+            .iter()
+            .filter(|sig| sig.sig_type == SIGNATURE_TYPE_DIRECT_KEY)
+            .map(|sig| (sig, None));
+        let certifications = self.user_ids.iter().flat_map(|user_id| {
+            user_id
+                .signatures
+                .iter()
+                .filter(|sig| {
+                    (SIGNATURE_TYPE_GENERIC_CERTIFICATION..=SIGNATURE_TYPE_POSITIVE_CERTIFICATION)
+                        .contains(&sig.sig_type)
+                })
+                .map(move |sig| (sig, Some(user_id)))
+        });
+        let mut candidates: Vec<_> = direct
+            .chain(certifications)
+            .filter(|(sig, _)| sig.issued_by(&fingerprint))
+            .filter_map(|(sig, user_id)| Some((sig.creation_time()?, sig, user_id)))
+            .collect();
+        candidates.sort_by_key(|&(time, ..)| core::cmp::Reverse(time));
+        let primary = &self.primary;
+        candidates
+            .into_iter()
+            .find(|&(_, sig, user_id)| {
+                verifier.verify(primary, sig, &mut |cb| {
+                    primary.fingerprint_data(cb);
+                    if let Some(user_id) = user_id {
+                        user_id.signed_data(cb)
+                    }
+                })
+            })
+            .map(|(_, sig, _)| sig)
+    }
+
+    /// The most recent binding signature of `subkey` that was made by the
+    /// primary key and that `verifier` accepts
+    pub fn binding_signature<'s>(
+        &self,
+        subkey: &'s Subkey<'a>,
+        verifier: &mut dyn KeyVerifier,
+    ) -> Option<&'s KeySignature<'a>> {
+        let fingerprint = verifier.fingerprint(&self.primary);
+        let mut candidates: Vec<_> = subkey
+            .signatures
+            .iter()
+            .filter(|sig| sig.sig_type == SIGNATURE_TYPE_SUBKEY_BINDING)
+            .filter(|sig| sig.issued_by(&fingerprint))
+            .filter_map(|sig| Some((sig.creation_time()?, sig)))
+            .collect();
+        candidates.sort_by_key(|&(time, _)| core::cmp::Reverse(time));
+        let primary = &self.primary;
+        candidates.into_iter().map(|(_, sig)| sig).find(|sig| {
+            verifier.verify(primary, sig, &mut |cb| {
+                primary.fingerprint_data(cb);
+                subkey.key.fingerprint_data(cb)
+            })
+        })
+    }
+
+    /// Checks that the primary key was created, has a self-signature, has
+    /// not expired, and is not revoked at `time`.  The expiration time comes
+    /// from the most recent self-signature that verifies.  Revocations are
+    /// not verified: honoring a forged one can only make this fail.
+    pub fn valid_at(&self, time: u32, verifier: &mut dyn KeyVerifier) -> Result<(), KeyInvalid> {
+        check_key(
+            &self.primary,
+            self.primary_self_signature(verifier),
+            self.revocation(),
+            time,
+        )
+    }
+
+    /// Checks that `key` (the primary key or one of the subkeys) can be used
+    /// for a signature made at `time`.  A subkey can only be used if the
+    /// primary key can also be used, and its expiration time comes from its
+    /// most recent binding signature that verifies.
+    pub fn key_valid_at(
+        &self,
+        key: &PublicKey<'_>,
+        time: u32,
+        verifier: &mut dyn KeyVerifier,
+    ) -> Result<(), KeyInvalid> {
+        if key.body() == self.primary.body() {
+            return self.valid_at(time, verifier);
+        }
+        let subkey = self
+            .subkeys
+            .iter()
+            .find(|s| s.key.body() == key.body())
+            .ok_or(KeyInvalid::NotInCertificate)?;
+        self.valid_at(time, verifier)?;
+        let binding = self.binding_signature(subkey, verifier);
+        check_key(&subkey.key, binding, subkey.revocation(), time)
+    }
+}
+
+fn check_key(
+    key: &PublicKey<'_>,
+    self_signature: Option<&KeySignature<'_>>,
+    revocation: Option<Revocation>,
+    time: u32,
+) -> Result<(), KeyInvalid> {
+    let created = key.creation_time;
+    if time < created {
+        return Err(KeyInvalid::NotYetCreated { created });
+    }
+    let self_signature = self_signature.ok_or(KeyInvalid::NoSelfSignature)?;
+    if let Some(expiration) = self_signature.key_expiration_time() {
+        let expired = created.saturating_add(expiration);
+        if time >= expired {
+            return Err(KeyInvalid::Expired { expired });
+        }
+    }
+    match revocation {
+        Some(revocation) if revocation.applies_at(time) => Err(KeyInvalid::Revoked(revocation)),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the next packet that is not a trust packet, without consuming
+/// anything if it is a primary key (which starts the next certificate)
+fn next_packet<'a>(reader: &mut Reader<'a>) -> Result<Option<packet::Packet<'a>>, Error> {
+    loop {
+        let mut peek = reader.clone();
+        match packet::next(&mut peek)? {
+            Some(packet) if packet.tag() == TAG_PUBLIC_KEY => return Ok(None),
+            Some(packet) if packet.tag() == TAG_TRUST => *reader = peek,
+            packet => {
+                *reader = peek;
+                return Ok(packet);
+            }
+        }
+    }
+}
+
+/// Reads one certificate from `reader`.  Reading stops at the end of the data
+/// or before the next primary key, so a keyring can be read by calling this
+/// until `reader` is empty.
+pub fn read_certificate<'a>(reader: &mut Reader<'a>) -> Result<Certificate<'a>, Error> {
+    let packet = packet::next(reader)?.ok_or(Error::PrematureEOF)?;
+    if packet.tag() != TAG_PUBLIC_KEY {
+        return Err(Error::IllFormedKey);
+    }
+    let mut cert = Certificate {
+        primary: key::parse_body(packet.contents(), false)?,
+        direct_signatures: Vec::new(),
+        user_ids: Vec::new(),
+        subkeys: Vec::new(),
+    };
+    while let Some(packet) = next_packet(reader)? {
+        let contents = packet.contents();
+        match packet.tag() {
+            TAG_SIGNATURE => {
+                let signature = KeySignature::new(contents)?;
+                // Signatures belong to the most recent component
+                match (cert.subkeys.last_mut(), cert.user_ids.last_mut()) {
+                    (Some(subkey), _) => subkey.signatures.push(signature),
+                    (None, Some(user_id)) => user_id.signatures.push(signature),
+                    (None, None) => cert.direct_signatures.push(signature),
+                }
+            }
+            // User IDs must come before subkeys
+            TAG_USER_ID | TAG_USER_ATTRIBUTE if cert.subkeys.is_empty() => {
+                cert.user_ids.push(UserId {
+                    value: contents,
+                    attribute: packet.tag() == TAG_USER_ATTRIBUTE,
+                    signatures: Vec::new(),
+                })
+            }
+            TAG_PUBLIC_SUBKEY => cert.subkeys.push(Subkey {
+                key: key::parse_body(contents, true)?,
+                signatures: Vec::new(),
+            }),
+            _ => return Err(Error::IllFormedKey),
+        }
+    }
+    Ok(cert)
+}
+
+/// Parse exactly one certificate from a slice
+pub fn parse(data: &[u8]) -> Result<Certificate<'_>, Error> {
+    Reader::read_all(data, Error::TrailingJunk, read_certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PublicKeyAlgorithm;
+
+    static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+
+    #[test]
+    fn parses_certificate() {
+        let cert = parse(CERT).unwrap();
+        assert_eq!(cert.primary().algorithm, PublicKeyAlgorithm::EdDsaLegacy);
+        assert!(cert.direct_signatures().is_empty());
+        assert_eq!(cert.user_ids().len(), 1);
+        let user_id = &cert.user_ids()[0];
+        assert_eq!(user_id.value(), b"Test Key <test@example.com>");
+        assert!(!user_id.is_attribute());
+        assert_eq!(user_id.signatures().len(), 1);
+        assert_eq!(user_id.signatures()[0].sig_type(), 0x13);
+        assert_eq!(user_id.signatures()[0].version(), 4);
+        let algorithms: Vec<u8> = cert.keys().map(|k| k.algorithm.into()).collect();
+        assert_eq!(algorithms, [22, 18, 1, 19, 18]);
+        for subkey in cert.subkeys() {
+            assert!(subkey.key().subkey);
+            let types: Vec<u8> = subkey.signatures().iter().map(|s| s.sig_type()).collect();
+            assert_eq!(types, [0x18]);
+        }
+    }
+
+    #[test]
+    fn reads_keyrings() {
+        // Two certificates, with a trust packet in between
+        let keyring = [CERT, &[0xB0, 2, 0, 0], CERT].concat();
+        assert_eq!(parse(&keyring), Err(Error::TrailingJunk));
+        let mut reader = Reader::new(&keyring);
+        let first = read_certificate(&mut reader).unwrap();
+        let second = read_certificate(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn rejects_bad_certificates() {
+        // The primary key packet, then the user ID packet
+        let (primary, user_id) = (&CERT[..53], &CERT[53..82]);
+        assert_eq!(user_id[2..], b"Test Key <test@example.com>"[..]);
+        assert_eq!(parse(&CERT[53..]), Err(Error::IllFormedKey));
+        // A user ID after a subkey
+        assert_eq!(parse(&[CERT, user_id].concat()), Err(Error::IllFormedKey));
+        // A literal data packet
+        assert_eq!(
+            parse(&[CERT, &[0xCB, 1, 0]].concat()),
+            Err(Error::IllFormedKey)
+        );
+        // A primary key alone is a valid certificate
+        assert_eq!(parse(primary).unwrap().user_ids().len(), 0);
+        // An unknown signature version
+        let bad_sig = [primary, user_id, &[0x88, 2, 7, 0x13]].concat();
+        assert_eq!(parse(&bad_sig), Err(Error::UnsupportedSignatureVersion));
+    }
+
+    #[test]
+    fn validates_user_ids() {
+        let limits = Limits::default();
+        let user_id = |value: &'static [u8], attribute| UserId {
+            value,
+            attribute,
+            signatures: Vec::new(),
+        };
+        let cert = parse(CERT).unwrap();
+        let good = &cert.user_ids()[0];
+        assert_eq!(good.validate(&limits), Ok("Test Key <test@example.com>"));
+        assert_eq!(good.display(), "Test Key <test@example.com>");
+        assert_eq!(good.value(), b"Test Key <test@example.com>");
+        assert!(matches!(good.display(), Cow::Borrowed(_)));
+        let photo = user_id(b"\x10\0\x01\x01", true);
+        assert_eq!(photo.validate(&limits), Err(Error::IllFormedUserId));
+        assert_eq!(photo.display(), "[user attribute]");
+        for (bad, shown) in [
+            (
+                &b"Evil\x1b[2J <evil@example.com>"[..],
+                "Evil\u{FFFD}[2J <evil@example.com>",
+            ),
+            ("a\u{202E}moc.elpmaxe".as_bytes(), "a\u{FFFD}moc.elpmaxe"),
+            (b"Latin-1 \xe9", "Latin-1 \u{FFFD}"),
+        ] {
+            let raw = bad;
+            let bad = user_id(bad, false);
+            assert_eq!(bad.validate(&limits), Err(Error::IllFormedUserId));
+            assert_eq!(bad.display(), shown);
+            assert_eq!(bad.value(), raw);
+        }
+        static LONG: [u8; 1025] = [b'a'; 1025];
+        let long = user_id(&LONG, false);
+        assert_eq!(long.validate(&limits), Err(Error::IllFormedUserId));
+        assert_eq!(
+            user_id(&LONG[1..], false).validate(&limits).unwrap().len(),
+            1024
+        );
+        let shown = long.display();
+        assert_eq!(shown.chars().count(), DISPLAY_LENGTH + 1);
+        assert!(shown.ends_with("a\u{2026}"));
+    }
+
+    /// A v4 signature packet of type `sig_type`, with the given hashed
+    /// subpackets and no actual signature
+    fn signature_packet(sig_type: u8, subpackets: &[&[u8]]) -> Vec<u8> {
+        let hashed = subpackets.concat();
+        let mut body = vec![4, sig_type, 22, 8, 0, hashed.len() as u8];
+        body.extend_from_slice(&hashed);
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        [&[0x88, body.len() as u8][..], &body].concat()
+    }
+
+    fn revocation_packet(sig_type: u8, time: u32, reason: Option<u8>) -> Vec<u8> {
+        let t = time.to_be_bytes();
+        match reason {
+            Some(code) => signature_packet(
+                sig_type,
+                &[&[5, 2, t[0], t[1], t[2], t[3]], &[3, 29, code, b'x']],
+            ),
+            None => signature_packet(sig_type, &[&[5, 2, t[0], t[1], t[2], t[3]]]),
+        }
+    }
+
+    #[test]
+    fn parses_revocations() {
+        let cert = parse(CERT).unwrap();
+        assert_eq!(cert.revocation(), None);
+        assert_eq!(cert.revoked_at(), None);
+        for key in cert.keys() {
+            assert_eq!(cert.is_revoked_at(key, u32::MAX), Some(false));
+        }
+        assert_eq!(cert.user_ids()[0].revocation(), None);
+        assert_eq!(
+            cert.user_ids()[0].signatures()[0].creation_time(),
+            Some(1792111174)
+        );
+
+        // A soft revocation (key superseded) of the last subkey
+        let soft = [CERT, &revocation_packet(0x28, 2000, Some(1))].concat();
+        let cert = parse(&soft).unwrap();
+        assert_eq!(cert.revoked_at(), None);
+        let subkey = cert.subkeys().last().unwrap();
+        let revocation = subkey.revocation().unwrap();
+        assert_eq!(
+            revocation,
+            Revocation {
+                time: 2000,
+                reason: Some(1)
+            }
+        );
+        assert!(!revocation.is_hard());
+        assert_eq!(cert.is_revoked_at(subkey.key(), 1999), Some(false));
+        assert_eq!(cert.is_revoked_at(subkey.key(), 2000), Some(true));
+        assert_eq!(
+            cert.is_revoked_at(cert.subkeys()[0].key(), 2000),
+            Some(false)
+        );
+        assert_eq!(cert.is_revoked_at(cert.primary(), 2000), Some(false));
+        // Not part of the certificate
+        let primary_only = parse(&CERT[..53]).unwrap();
+        assert_eq!(primary_only.is_revoked_at(subkey.key(), 0), None);
+
+        // A hard revocation (key compromised) of the primary key, which
+        // revokes every subkey
+        let (primary, rest) = CERT.split_at(53);
+        let hard = [primary, &revocation_packet(0x20, 2000, Some(2)), rest].concat();
+        let cert = parse(&hard).unwrap();
+        assert_eq!(cert.revoked_at(), Some(0));
+        for key in cert.keys() {
+            assert_eq!(cert.is_revoked_at(key, 0), Some(true));
+        }
+        // No reason is a hard revocation, and hard revocations win
+        let both = [
+            primary,
+            &revocation_packet(0x20, 1000, Some(3)),
+            &revocation_packet(0x20, 3000, None),
+            rest,
+        ]
+        .concat();
+        let cert = parse(&both).unwrap();
+        assert_eq!(
+            cert.revocation(),
+            Some(Revocation {
+                time: 3000,
+                reason: None
+            })
+        );
+        // A revocation without a creation time is hard
+        let untimed = [primary, &signature_packet(0x20, &[]), rest].concat();
+        let cert = parse(&untimed).unwrap();
+        assert_eq!(
+            cert.revocation(),
+            Some(Revocation {
+                time: 0,
+                reason: None
+            })
+        );
+
+        // A user ID revocation (no longer valid)
+        let (head, tail) = CERT.split_at(82);
+        let user_id = [head, &revocation_packet(0x30, 5, Some(32)), tail].concat();
+        let cert = parse(&user_id).unwrap();
+        let revocation = cert.user_ids()[0].revocation().unwrap();
+        assert!(!revocation.is_hard() && revocation.applies_at(5));
+        assert_eq!(cert.revoked_at(), None);
+    }
+
+    static FINGERPRINT: [u8; 20] = [
+        0xE6, 0x0F, 0x4C, 0x72, 0x4B, 0x87, 0xE0, 0x1A, 0xC3, 0x83, 0xFA, 0xDC, 0x93, 0x0F, 0x26,
+        0x82, 0x77, 0x4C, 0xEC, 0xF4,
+    ];
+
+    /// Accepts every signature over the primary key, except those with the
+    /// forged bodies
+    #[derive(Default)]
+    struct MockVerifier {
+        forged: Vec<Vec<u8>>,
+    }
+
+    impl KeyVerifier for MockVerifier {
+        fn fingerprint(&mut self, _: &PublicKey<'_>) -> Vec<u8> {
+            FINGERPRINT.to_vec()
+        }
+
+        fn verify(
+            &mut self,
+            signer: &PublicKey<'_>,
+            signature: &KeySignature<'_>,
+            data: &mut SignedData<'_>,
+        ) -> bool {
+            let (mut signed, mut primary) = (Vec::new(), Vec::new());
+            data(&mut |d| signed.extend_from_slice(d));
+            signer.fingerprint_data(&mut |d| primary.extend_from_slice(d));
+            assert!(signed.starts_with(&primary));
+            !self.forged.iter().any(|f| f[..] == *signature.body())
+        }
+    }
+
+    /// A self-signature made at `time`, with the given key expiration time,
+    /// issued by `fingerprint`
+    fn self_signature(sig_type: u8, time: u32, expiration: u32, fingerprint: &[u8]) -> Vec<u8> {
+        let (t, e) = (time.to_be_bytes(), expiration.to_be_bytes());
+        signature_packet(
+            sig_type,
+            &[
+                &[5, 2, t[0], t[1], t[2], t[3]],
+                &[5, 9, e[0], e[1], e[2], e[3]],
+                &[22, 33, 4],
+                fingerprint,
+            ],
+        )
+    }
+
+    #[test]
+    fn checks_validity() {
+        const CREATED: u32 = 1792111174;
+        let verifier = &mut MockVerifier::default();
+        let cert = parse(CERT).unwrap();
+        assert_eq!(cert.valid_at(u32::MAX, verifier), Ok(()));
+        assert_eq!(
+            cert.valid_at(CREATED - 1, verifier),
+            Err(KeyInvalid::NotYetCreated { created: CREATED })
+        );
+        let primary_only = parse(&CERT[..53]).unwrap();
+        assert_eq!(
+            primary_only.key_valid_at(cert.subkeys()[0].key(), CREATED, verifier),
+            Err(KeyInvalid::NotInCertificate)
+        );
+        assert_eq!(
+            primary_only.valid_at(CREATED, verifier),
+            Err(KeyInvalid::NoSelfSignature)
+        );
+
+        // The newer direct-key signature wins over the older one and over
+        // the user ID certification, which has no expiration
+        let (primary, rest) = CERT.split_at(53);
+        let expiring = [
+            primary,
+            &self_signature(0x1F, CREATED + 1000, 10, &FINGERPRINT),
+            &self_signature(0x1F, CREATED + 2000, 100, &FINGERPRINT),
+            rest,
+        ]
+        .concat();
+        let cert = parse(&expiring).unwrap();
+        assert_eq!(cert.valid_at(CREATED + 99, verifier), Ok(()));
+        let expired = Err(KeyInvalid::Expired {
+            expired: CREATED + 100,
+        });
+        assert_eq!(cert.valid_at(CREATED + 100, verifier), expired);
+        // Subkeys expire with the primary key
+        let subkey = cert.subkeys()[0].key();
+        assert_eq!(cert.key_valid_at(subkey, CREATED + 99, verifier), Ok(()));
+        assert_eq!(cert.key_valid_at(subkey, CREATED + 100, verifier), expired);
+        // An expiration time of 0 means that the key does not expire
+        let forever = [
+            primary,
+            &self_signature(0x1F, CREATED, 0, &FINGERPRINT),
+            rest,
+        ]
+        .concat();
+        assert_eq!(
+            parse(&forever).unwrap().valid_at(u32::MAX, verifier),
+            Ok(())
+        );
+
+        // A newer binding signature makes the last subkey expire
+        let binding = [CERT, &self_signature(0x18, CREATED + 100, 50, &FINGERPRINT)].concat();
+        let cert = parse(&binding).unwrap();
+        let subkey = cert.subkeys().last().unwrap().key();
+        let subkey_created = subkey.creation_time;
+        assert_eq!(
+            cert.key_valid_at(subkey, subkey_created + 49, verifier),
+            Ok(())
+        );
+        assert_eq!(
+            cert.key_valid_at(subkey, subkey_created + 50, verifier),
+            Err(KeyInvalid::Expired {
+                expired: subkey_created + 50
+            })
+        );
+        assert_eq!(cert.valid_at(u32::MAX, verifier), Ok(()));
+
+        // Revocations
+        let revoked = [CERT, &revocation_packet(0x28, CREATED + 10, Some(3))].concat();
+        let cert = parse(&revoked).unwrap();
+        let subkey = cert.subkeys().last().unwrap().key();
+        assert_eq!(cert.key_valid_at(subkey, CREATED + 9, verifier), Ok(()));
+        assert_eq!(
+            cert.key_valid_at(subkey, CREATED + 10, verifier),
+            Err(KeyInvalid::Revoked(Revocation {
+                time: CREATED + 10,
+                reason: Some(3)
+            }))
+        );
+    }
+
+    #[test]
+    fn ignores_forged_self_signatures() {
+        const CREATED: u32 = 1792111174;
+        let (primary, rest) = CERT.split_at(53);
+        let genuine = self_signature(0x1F, CREATED + 1000, 100, &FINGERPRINT);
+        // A newer signature that removes the expiration, appended by an
+        // attacker who cannot make it verify
+        let forged = self_signature(0x1F, CREATED + 2000, 0, &FINGERPRINT);
+        let mut other = FINGERPRINT;
+        other[19] ^= 1;
+        // A newer signature by another key, which does verify
+        let foreign = self_signature(0x1F, CREATED + 3000, 0, &other);
+        let cert = [primary, &genuine, &forged, &foreign, rest].concat();
+        let cert = parse(&cert).unwrap();
+        let verifier = &mut MockVerifier {
+            forged: vec![forged[2..].to_vec()],
+        };
+        let expired = Err(KeyInvalid::Expired {
+            expired: CREATED + 100,
+        });
+        assert_eq!(cert.valid_at(CREATED + 100, verifier), expired);
+        let subkey = cert.subkeys()[0].key();
+        assert_eq!(cert.key_valid_at(subkey, CREATED + 100, verifier), expired);
+        // Only the forged signature verifies if the real one is rejected
+        verifier.forged = vec![genuine[2..].to_vec(), forged[2..].to_vec()];
+        let user_id = &cert.user_ids()[0].signatures()[0];
+        verifier.forged.push(user_id.body().to_vec());
+        assert_eq!(
+            cert.valid_at(CREATED, verifier),
+            Err(KeyInvalid::NoSelfSignature)
+        );
+
+        // A subkey whose only binding signature is forged cannot be used
+        let subkey = &cert.subkeys()[1];
+        let verifier = &mut MockVerifier {
+            forged: vec![subkey.signatures()[0].body().to_vec()],
+        };
+        assert_eq!(cert.binding_signature(subkey, verifier), None);
+        assert_eq!(
+            cert.key_valid_at(subkey.key(), CREATED, verifier),
+            Err(KeyInvalid::NoSelfSignature)
+        );
+        assert!(cert
+            .binding_signature(&cert.subkeys()[0], verifier)
+            .is_some());
+    }
+}
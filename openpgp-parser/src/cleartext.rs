@@ -0,0 +1,158 @@
+//! The cleartext signature framework
+//!
+//! A cleartext-signed document is human-readable text, with dash-escaping,
+//! followed by an armored signature.  [`parse`] splits such a document into
+//! the signed text, in the canonical form that the signature covers, and the
+//! binary signature.  Neither is checked: the signature must be parsed and
+//! verified like any other untrusted signature.
+//!
+//! Unlike [`crate::armor::decode`], [`parse`] rejects text before the
+//! document, as a reader could mistake it for signed text.
+
+use super::armor::{self, trim_line, Kind};
+use super::{Error, HashAlgorithm};
+use alloc::vec::Vec;
+
+/// A parsed cleartext-signed document
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Cleartext {
+    /// The hash algorithms named in the `Hash` armor headers.  If this is not
+    /// empty, the signature should use one of them.
+    pub hash_algorithms: Vec<u8>,
+    /// The signed text, dash-escaping removed, in canonical form: trailing
+    /// whitespace is removed from each line, lines end with CR LF, and the
+    /// last line has no line ending.
+    pub text: Vec<u8>,
+    /// The binary signature data, as returned by [`armor::decode`]
+    pub signature: Vec<u8>,
+}
+
+/// The hash algorithm named `name` in a `Hash` header.  Only the SHA family
+/// is allowed.
+fn hash_algorithm(name: &[u8]) -> Option<HashAlgorithm> {
+    HashAlgorithm::from_name(name).filter(|alg| alg.name().starts_with("SHA"))
+}
+
+/// Removes the next line from `rest` and returns it without its line ending
+/// or trailing whitespace.  Returns [`None`] at the end of the data.
+fn next_line<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if rest.is_empty() {
+        return None;
+    }
+    let (line, tail) = match rest.iter().position(|&c| c == b'\n') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (*rest, &b""[..]),
+    };
+    *rest = tail;
+    Some(trim_line(line))
+}
+
+/// Parse a cleartext-signed document
+pub fn parse(document: &[u8]) -> Result<Cleartext, Error> {
+    let mut rest = document;
+    if next_line(&mut rest) != Some(b"-----BEGIN PGP SIGNED MESSAGE-----") {
+        return Err(Error::BadArmor);
+    }
+    let mut hash_algorithms = Vec::new();
+    loop {
+        let line = next_line(&mut rest).ok_or(Error::BadArmor)?;
+        if line.is_empty() {
+            break;
+        }
+        let names = line.strip_prefix(b"Hash: ").ok_or(Error::BadArmor)?;
+        for name in names.split(|&c| c == b',') {
+            let name = name.strip_prefix(b" ").unwrap_or(name);
+            let alg = hash_algorithm(name).ok_or(Error::BadArmor)?;
+            hash_algorithms.push(alg.into())
+        }
+    }
+    let mut text = Vec::new();
+    let mut first = true;
+    loop {
+        let signature_start = rest;
+        let mut line = next_line(&mut rest).ok_or(Error::BadArmor)?;
+        if line == b"-----BEGIN PGP SIGNATURE-----" {
+            let (kind, signature) = armor::decode(signature_start)?;
+            debug_assert_eq!(kind, Kind::Signature);
+            return Ok(Cleartext {
+                hash_algorithms,
+                text,
+                signature,
+            });
+        } else if line.starts_with(b"-") {
+            // Every other line starting with a dash must be dash-escaped
+            line = match line {
+                b"-" => b"",
+                _ => line.strip_prefix(b"- ").ok_or(Error::BadArmor)?,
+            };
+        }
+        if !first {
+            text.extend_from_slice(b"\r\n")
+        }
+        first = false;
+        text.extend_from_slice(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE: &str = "\
+-----BEGIN PGP SIGNATURE-----
+
+iHUEABYIAB0WIQToOjgLhXVWKzxvQcoopFyTsLW24AUCYA93GgAKCRAopFyTsLW2
+4GFYAP96t6+ngjoe0wsfvu5QbKCnsYl6l2m4bYRECXeM+v4Q9gEAy1PZxcEOLIRi
+IGWwuRpG9/m0/v0sHplyWEjAImNHEgo=
+=dUDW
+-----END PGP SIGNATURE-----
+";
+
+    fn document(headers: &str, text: &str) -> Vec<u8> {
+        [
+            "-----BEGIN PGP SIGNED MESSAGE-----\n",
+            headers,
+            "\n",
+            text,
+            SIGNATURE,
+        ]
+        .concat()
+        .into_bytes()
+    }
+
+    #[test]
+    fn parses_cleartext() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let doc = document(
+            "Hash: SHA256, SHA512\nHash: SHA384, SHA3-512\n",
+            "Origin: Qubes  \r\n- -----BEGIN PGP SIGNATURE-----\n\n- \n- - x\t\n",
+        );
+        let parsed = parse(&doc).unwrap();
+        assert_eq!(parsed.hash_algorithms, [8, 10, 9, 14]);
+        assert_eq!(
+            parsed.text,
+            &b"Origin: Qubes\r\n-----BEGIN PGP SIGNATURE-----\r\n\r\n\r\n- x"[..]
+        );
+        assert_eq!(parsed.signature, EDDSA_SIG);
+        let empty = parse(&document("", "")).unwrap();
+        assert!(empty.hash_algorithms.is_empty());
+        assert!(empty.text.is_empty());
+        // The line ending before the signature is not signed
+        assert_eq!(parse(&document("", "a\n")).unwrap().text, b"a");
+        assert_eq!(parse(&document("", "a\n\n")).unwrap().text, b"a\r\n");
+    }
+
+    #[test]
+    fn rejects_bad_cleartext() {
+        let bad = |doc: &[u8]| assert_eq!(parse(doc), Err(Error::BadArmor));
+        bad(&document("", "-unescaped\n"));
+        bad(&document("Hash: MD5\n", ""));
+        bad(&document("Comment: x\n", ""));
+        bad(&[&b"leading text\n"[..], &document("", "")].concat());
+        bad(&document("", "text\n")[..60]);
+        let doc = document("", "text\n");
+        let junk = [&doc[..], b"junk\n"].concat();
+        assert_eq!(parse(&junk), Err(Error::TrailingJunk));
+    }
+}
@@ -0,0 +1,206 @@
+//! Elliptic curves
+//!
+//! ECDSA, legacy EdDSA, and ECDH keys name their curve by OID.  The key
+//! parser accepts any OID, so that a keyring with one key on an unusual
+//! curve can still be read.  [`CurvePolicy`] decides which curves are
+//! acceptable, and distinguishes a curve the policy rejects from one that is
+//! not known at all.
+
+use super::key::{KeyMaterial, PublicKey};
+use super::Error;
+
+/// A known elliptic curve
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Curve {
+    /// NIST P-256
+    NistP256,
+    /// NIST P-384
+    NistP384,
+    /// NIST P-521
+    NistP521,
+    /// brainpoolP256r1
+    BrainpoolP256,
+    /// brainpoolP384r1
+    BrainpoolP384,
+    /// brainpoolP512r1
+    BrainpoolP512,
+    /// Ed25519, for legacy EdDSA keys
+    Ed25519Legacy,
+    /// Curve25519, for legacy ECDH keys
+    Curve25519Legacy,
+}
+
+impl Curve {
+    /// Every known curve
+    pub const ALL: &'static [Curve] = &[
+        Curve::NistP256,
+        Curve::NistP384,
+        Curve::NistP521,
+        Curve::BrainpoolP256,
+        Curve::BrainpoolP384,
+        Curve::BrainpoolP512,
+        Curve::Ed25519Legacy,
+        Curve::Curve25519Legacy,
+    ];
+
+    /// The OID of the curve, without its length
+    pub fn oid(self) -> &'static [u8] {
+        match self {
+            Curve::NistP256 => &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07],
+            Curve::NistP384 => &[0x2B, 0x81, 0x04, 0x00, 0x22],
+            Curve::NistP521 => &[0x2B, 0x81, 0x04, 0x00, 0x23],
+            Curve::BrainpoolP256 => &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x07],
+            Curve::BrainpoolP384 => &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0B],
+            Curve::BrainpoolP512 => &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0D],
+            Curve::Ed25519Legacy => &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01],
+            Curve::Curve25519Legacy => {
+                &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x97, 0x55, 0x01, 0x05, 0x01]
+            }
+        }
+    }
+
+    /// Looks up a curve by OID
+    pub fn from_oid(oid: &[u8]) -> Option<Self> {
+        Self::ALL.iter().copied().find(|curve| curve.oid() == oid)
+    }
+
+    /// Can this curve be used with key material of this kind?  Ed25519 is
+    /// only for EdDSA, Curve25519 only for ECDH, and the others are for
+    /// ECDSA and ECDH.
+    fn fits(self, material: &KeyMaterial<'_>) -> bool {
+        match (self, material) {
+            (Curve::Ed25519Legacy, KeyMaterial::EdDsaLegacy { .. }) => true,
+            (Curve::Curve25519Legacy, KeyMaterial::Ecdh { .. }) => true,
+            (Curve::Ed25519Legacy, _) | (Curve::Curve25519Legacy, _) => false,
+            (_, KeyMaterial::Ecdsa { .. }) | (_, KeyMaterial::Ecdh { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+/// What a [`CurvePolicy`] says about a curve OID
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CurveStatus {
+    /// The curve is known and allowed
+    Allowed(Curve),
+    /// The curve is known, but the policy rejects it
+    RejectedByPolicy(Curve),
+    /// The OID is not a known curve
+    Unknown,
+}
+
+/// The elliptic curves a caller accepts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CurvePolicy<'a> {
+    allowed: &'a [Curve],
+}
+
+impl Default for CurvePolicy<'_> {
+    /// Every known curve is allowed
+    fn default() -> Self {
+        Self::new(Curve::ALL)
+    }
+}
+
+impl<'a> CurvePolicy<'a> {
+    /// A policy allowing only the curves in `allowed`
+    pub fn new(allowed: &'a [Curve]) -> Self {
+        Self { allowed }
+    }
+
+    /// Checks the curve with OID `oid` against the policy
+    pub fn status(&self, oid: &[u8]) -> CurveStatus {
+        match Curve::from_oid(oid) {
+            None => CurveStatus::Unknown,
+            Some(curve) if self.allowed.contains(&curve) => CurveStatus::Allowed(curve),
+            Some(curve) => CurveStatus::RejectedByPolicy(curve),
+        }
+    }
+
+    /// Checks the curve of `key` against the policy.  Keys that do not use a
+    /// curve OID are always accepted.  Fails with [`Error::UnknownCurve`] if
+    /// the curve is not known, [`Error::CurveRejected`] if the policy
+    /// rejects it, and [`Error::IllFormedKey`] if the curve cannot be used
+    /// with the key’s algorithm.
+    pub fn check_key(&self, key: &PublicKey<'_>) -> Result<(), Error> {
+        let oid = match key.material {
+            KeyMaterial::Ecdsa { curve, .. }
+            | KeyMaterial::EdDsaLegacy { curve, .. }
+            | KeyMaterial::Ecdh { curve, .. } => curve,
+            _ => return Ok(()),
+        };
+        match self.status(oid) {
+            CurveStatus::Allowed(curve) if curve.fits(&key.material) => Ok(()),
+            CurveStatus::Allowed(_) => Err(Error::IllFormedKey),
+            CurveStatus::RejectedByPolicy(_) => Err(Error::CurveRejected),
+            CurveStatus::Unknown => Err(Error::UnknownCurve),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+
+    #[test]
+    fn checks_curves() {
+        for &curve in Curve::ALL {
+            assert_eq!(Curve::from_oid(curve.oid()), Some(curve));
+        }
+        assert_eq!(Curve::from_oid(&[0x2B, 0x81, 0x04, 0x00, 0x0A]), None);
+        let nist_only = CurvePolicy::new(&[Curve::NistP256, Curve::NistP384]);
+        assert_eq!(
+            nist_only.status(Curve::NistP256.oid()),
+            CurveStatus::Allowed(Curve::NistP256)
+        );
+        assert_eq!(
+            nist_only.status(Curve::BrainpoolP256.oid()),
+            CurveStatus::RejectedByPolicy(Curve::BrainpoolP256)
+        );
+        assert_eq!(nist_only.status(&[1, 2, 3]), CurveStatus::Unknown);
+
+        // Ed25519 (legacy EdDSA), Curve25519 (ECDH), RSA, P-256 (ECDSA), and
+        // P-256 (ECDH)
+        let cert = crate::cert::parse(CERT).unwrap();
+        let results: Vec<_> = cert.keys().map(|k| nist_only.check_key(k)).collect();
+        assert_eq!(
+            results,
+            [
+                Err(Error::CurveRejected),
+                Err(Error::CurveRejected),
+                Ok(()),
+                Ok(()),
+                Ok(())
+            ]
+        );
+        for key in cert.keys() {
+            assert_eq!(CurvePolicy::default().check_key(key), Ok(()));
+        }
+        assert_eq!(
+            CurvePolicy::new(&[]).check_key(cert.primary()),
+            Err(Error::CurveRejected)
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_curves() {
+        // An ECDSA key on Ed25519, then on an unknown curve
+        let mut body = vec![4, 0, 0, 0, 0, 19, 9];
+        body.extend_from_slice(Curve::Ed25519Legacy.oid());
+        body.extend_from_slice(&[0, 7, 0x40]);
+        let key = crate::key::parse_body(&body, false).unwrap();
+        assert_eq!(
+            CurvePolicy::default().check_key(&key),
+            Err(Error::IllFormedKey)
+        );
+        body[7] ^= 1;
+        let key = crate::key::parse_body(&body, false).unwrap();
+        assert_eq!(
+            CurvePolicy::default().check_key(&key),
+            Err(Error::UnknownCurve)
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Hash algorithms
+//!
+//! OpenPGP names hash algorithms by a one-byte ID.  [`HashAlgorithm`] covers
+//! every registered ID except the experimental “double-width SHA” (4), which
+//! was never specified.  Whether an algorithm is acceptable is a matter of
+//! [`HashSecurity`], which [`HashAlgorithm::check`] compares against
+//! [`AllowWeakHashes`].
+
+use super::{AllowWeakHashes, Error};
+use core::convert::TryFrom;
+
+/// A registered OpenPGP hash algorithm
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    /// MD5
+    Md5 = 1,
+    /// SHA-1
+    Sha1 = 2,
+    /// RIPEMD-160
+    Ripemd160 = 3,
+    /// MD2
+    Md2 = 5,
+    /// TIGER/192
+    Tiger192 = 6,
+    /// HAVAL (5 pass, 160-bit)
+    Haval5_160 = 7,
+    /// SHA-256
+    Sha256 = 8,
+    /// SHA-384
+    Sha384 = 9,
+    /// SHA-512
+    Sha512 = 10,
+    /// SHA-224
+    Sha224 = 11,
+    /// SHA3-256
+    Sha3_256 = 12,
+    /// SHA3-512
+    Sha3_512 = 14,
+}
+
+/// How much a hash algorithm can be trusted for signatures
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashSecurity {
+    /// Broken or obscure.  Never accepted.
+    Insecure,
+    /// Broken (MD5 and SHA-1) or with a somewhat low security level
+    /// (SHA-224).  Only accepted with [`AllowWeakHashes::Yes`].
+    Weak,
+    /// Accepted
+    Secure,
+}
+
+impl HashAlgorithm {
+    /// Every known hash algorithm, in order of ID
+    pub const ALL: &'static [HashAlgorithm] = &[
+        HashAlgorithm::Md5,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Ripemd160,
+        HashAlgorithm::Md2,
+        HashAlgorithm::Tiger192,
+        HashAlgorithm::Haval5_160,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha384,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Sha224,
+        HashAlgorithm::Sha3_256,
+        HashAlgorithm::Sha3_512,
+    ];
+
+    /// The length of the digest, in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::Md5 | HashAlgorithm::Md2 => 16,
+            HashAlgorithm::Sha1 | HashAlgorithm::Ripemd160 | HashAlgorithm::Haval5_160 => 20,
+            HashAlgorithm::Tiger192 => 24,
+            HashAlgorithm::Sha224 => 28,
+            HashAlgorithm::Sha256 | HashAlgorithm::Sha3_256 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 | HashAlgorithm::Sha3_512 => 64,
+        }
+    }
+
+    /// How much the algorithm can be trusted
+    pub fn security(self) -> HashSecurity {
+        match self {
+            HashAlgorithm::Sha256
+            | HashAlgorithm::Sha384
+            | HashAlgorithm::Sha512
+            | HashAlgorithm::Sha3_256
+            | HashAlgorithm::Sha3_512 => HashSecurity::Secure,
+            HashAlgorithm::Md5 | HashAlgorithm::Sha1 | HashAlgorithm::Sha224 => HashSecurity::Weak,
+            HashAlgorithm::Ripemd160
+            | HashAlgorithm::Md2
+            | HashAlgorithm::Tiger192
+            | HashAlgorithm::Haval5_160 => HashSecurity::Insecure,
+        }
+    }
+
+    /// Checks the algorithm against `allow_weak_hashes`.  Fails with
+    /// [`Error::InsecureAlgorithm`] if it is not acceptable.
+    pub fn check(self, allow_weak_hashes: AllowWeakHashes) -> Result<(), Error> {
+        match (self.security(), allow_weak_hashes) {
+            (HashSecurity::Secure, _) | (HashSecurity::Weak, AllowWeakHashes::Yes) => Ok(()),
+            _ => Err(Error::InsecureAlgorithm(u8::from(self).into())),
+        }
+    }
+
+    /// The length of the salt of a v6 signature using this algorithm, or
+    /// [`None`] if v6 signatures cannot use it
+    pub fn v6_salt_len(self) -> Option<usize> {
+        match self {
+            HashAlgorithm::Sha224 | HashAlgorithm::Sha256 | HashAlgorithm::Sha3_256 => Some(16),
+            HashAlgorithm::Sha384 => Some(24),
+            HashAlgorithm::Sha512 | HashAlgorithm::Sha3_512 => Some(32),
+            _ => None,
+        }
+    }
+
+    /// The name of the algorithm in the `Hash` header of cleartext signatures
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha1 => "SHA1",
+            HashAlgorithm::Ripemd160 => "RIPEMD160",
+            HashAlgorithm::Md2 => "MD2",
+            HashAlgorithm::Tiger192 => "TIGER192",
+            HashAlgorithm::Haval5_160 => "HAVAL-5-160",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Sha384 => "SHA384",
+            HashAlgorithm::Sha512 => "SHA512",
+            HashAlgorithm::Sha224 => "SHA224",
+            HashAlgorithm::Sha3_256 => "SHA3-256",
+            HashAlgorithm::Sha3_512 => "SHA3-512",
+        }
+    }
+
+    /// Looks up an algorithm by [name](HashAlgorithm::name)
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|alg| alg.name().as_bytes() == name)
+    }
+}
+
+impl TryFrom<u8> for HashAlgorithm {
+    type Error = Error;
+
+    /// Fails with [`Error::UnsupportedHashAlgorithm`] for unknown IDs
+    fn try_from(id: u8) -> Result<Self, Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&alg| u8::from(alg) == id)
+            .ok_or(Error::UnsupportedHashAlgorithm(id.into()))
+    }
+}
+
+impl From<HashAlgorithm> for u8 {
+    fn from(alg: HashAlgorithm) -> u8 {
+        alg as u8
+    }
+}
+
+impl core::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_hashes() {
+        for &alg in HashAlgorithm::ALL {
+            assert_eq!(HashAlgorithm::try_from(u8::from(alg)), Ok(alg));
+            assert_eq!(HashAlgorithm::from_name(alg.name().as_bytes()), Some(alg));
+        }
+        for &id in &[0, 4, 13, 15, 110] {
+            assert_eq!(
+                HashAlgorithm::try_from(id),
+                Err(Error::UnsupportedHashAlgorithm(id.into()))
+            );
+        }
+        assert_eq!(HashAlgorithm::Sha3_256.digest_len(), 32);
+        assert_eq!(HashAlgorithm::Sha224.security(), HashSecurity::Weak);
+        assert_eq!(HashAlgorithm::Sha1.check(AllowWeakHashes::Yes), Ok(()));
+        assert_eq!(
+            HashAlgorithm::Sha1.check(AllowWeakHashes::No),
+            Err(Error::InsecureAlgorithm(2))
+        );
+        assert_eq!(
+            HashAlgorithm::Md2.check(AllowWeakHashes::Yes),
+            Err(Error::InsecureAlgorithm(5))
+        );
+        assert_eq!(HashAlgorithm::Sha512.v6_salt_len(), Some(32));
+        assert_eq!(HashAlgorithm::Sha1.v6_salt_len(), None);
+    }
+}
@@ -0,0 +1,373 @@
+//! OpenPGP public keys
+//!
+//! Parses public key (tag 6) and public subkey (tag 14) packets.  Only the
+//! structure is checked: nothing here checks that the key material is
+//! mathematically valid, and there is no cryptography to compute
+//! fingerprints with.  [`PublicKey::fingerprint_data`] provides what must be
+//! hashed to compute one.
+
+use super::{packet, Error, HashAlgorithm, PublicKeyAlgorithm, Reader};
+use crate::signature::read_mpi;
+use core::convert::TryFrom;
+
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_PUBLIC_SUBKEY: u8 = 14;
+
+/// The algorithm-specific part of a public key.  Multiprecision integers and
+/// points are returned without their length prefixes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyMaterial<'a> {
+    /// RSA
+    Rsa {
+        /// Modulus
+        n: &'a [u8],
+        /// Public exponent
+        e: &'a [u8],
+    },
+    /// DSA
+    Dsa {
+        /// Prime
+        p: &'a [u8],
+        /// Group order
+        q: &'a [u8],
+        /// Group generator
+        g: &'a [u8],
+        /// Public key
+        y: &'a [u8],
+    },
+    /// Elgamal
+    Elgamal {
+        /// Prime
+        p: &'a [u8],
+        /// Group generator
+        g: &'a [u8],
+        /// Public key
+        y: &'a [u8],
+    },
+    /// ECDSA
+    Ecdsa {
+        /// Curve OID, without its length
+        curve: &'a [u8],
+        /// Public point
+        point: &'a [u8],
+    },
+    /// EdDSA in the legacy (pre-RFC 9580) encoding
+    EdDsaLegacy {
+        /// Curve OID, without its length
+        curve: &'a [u8],
+        /// Public point, including its `0x40` prefix
+        point: &'a [u8],
+    },
+    /// ECDH
+    Ecdh {
+        /// Curve OID, without its length
+        curve: &'a [u8],
+        /// Public point
+        point: &'a [u8],
+        /// Hash algorithm for the key derivation function
+        kdf_hash: u8,
+        /// Symmetric algorithm used to wrap session keys
+        kek_cipher: u8,
+    },
+    /// X25519, as a native 32-byte key
+    X25519(&'a [u8]),
+    /// X448, as a native 56-byte key
+    X448(&'a [u8]),
+    /// Ed25519, as a native 32-byte key
+    Ed25519(&'a [u8]),
+    /// Ed448, as a native 57-byte key
+    Ed448(&'a [u8]),
+}
+
+impl KeyMaterial<'_> {
+    /// The size in bits of an RSA modulus or a DSA or Elgamal prime.
+    /// [`None`] for elliptic-curve keys, whose strength is fixed by the curve.
+    pub fn bits(&self) -> Option<u32> {
+        let mpi = match *self {
+            KeyMaterial::Rsa { n, .. } => n,
+            KeyMaterial::Dsa { p, .. } | KeyMaterial::Elgamal { p, .. } => p,
+            _ => return None,
+        };
+        let mpi = &mpi[mpi.iter().take_while(|&&i| i == 0).count()..];
+        Some(match mpi.first() {
+            Some(first) => mpi.len() as u32 * 8 - first.leading_zeros(),
+            None => 0,
+        })
+    }
+}
+
+/// A parsed public key or subkey packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PublicKey<'a> {
+    /// Is this a subkey (tag 14), rather than a primary key (tag 6)?
+    pub subkey: bool,
+    /// Key version: 4, 5, or 6
+    pub version: u8,
+    /// Creation time
+    pub creation_time: u32,
+    /// Public-key algorithm
+    pub algorithm: PublicKeyAlgorithm,
+    /// The algorithm-specific key material
+    pub material: KeyMaterial<'a>,
+    body: &'a [u8],
+}
+
+impl<'a> PublicKey<'a> {
+    /// The packet body, as hashed for fingerprints and key signatures
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// The OpenPGP hash algorithm used for this key’s fingerprint: SHA-1 for
+    /// v4 keys and SHA-256 otherwise
+    pub fn fingerprint_hash_algorithm(&self) -> HashAlgorithm {
+        match self.version {
+            4 => HashAlgorithm::Sha1,
+            _ => HashAlgorithm::Sha256,
+        }
+    }
+
+    /// Passes the data hashed to compute this key’s fingerprint to `cb`, in
+    /// order.  Hash it with [`PublicKey::fingerprint_hash_algorithm`].
+    pub fn fingerprint_data(&self, cb: &mut dyn FnMut(&[u8])) {
+        let len = self.body.len();
+        match self.version {
+            4 => cb(&[0x99, (len >> 8) as u8, len as u8]),
+            v => {
+                let [a, b, c, d] = (len as u32).to_be_bytes();
+                cb(&[0x95 + v, a, b, c, d])
+            }
+        }
+        cb(self.body)
+    }
+}
+
+fn read_oid<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8], Error> {
+    match reader.byte()? {
+        0 | 0xFF => Err(Error::IllFormedKey),
+        len => Ok(reader.get_bytes(len.into())?),
+    }
+}
+
+fn read_material<'a>(
+    reader: &mut Reader<'a>,
+    version: u8,
+    alg: PublicKeyAlgorithm,
+) -> Result<KeyMaterial<'a>, Error> {
+    Ok(match alg {
+        PublicKeyAlgorithm::Rsa
+        | PublicKeyAlgorithm::RsaEncryptOnly
+        | PublicKeyAlgorithm::RsaSignOnly => KeyMaterial::Rsa {
+            n: read_mpi(reader)?,
+            e: read_mpi(reader)?,
+        },
+        PublicKeyAlgorithm::Dsa => KeyMaterial::Dsa {
+            p: read_mpi(reader)?,
+            q: read_mpi(reader)?,
+            g: read_mpi(reader)?,
+            y: read_mpi(reader)?,
+        },
+        PublicKeyAlgorithm::ElgamalEncryptOnly | PublicKeyAlgorithm::ElgamalEncryptOrSign => {
+            KeyMaterial::Elgamal {
+                p: read_mpi(reader)?,
+                g: read_mpi(reader)?,
+                y: read_mpi(reader)?,
+            }
+        }
+        PublicKeyAlgorithm::Ecdsa => KeyMaterial::Ecdsa {
+            curve: read_oid(reader)?,
+            point: read_mpi(reader)?,
+        },
+        // Legacy EdDSA is forbidden in v6 keys
+        PublicKeyAlgorithm::EdDsaLegacy if version == 6 => {
+            return Err(Error::InvalidPkeyAlgorithm(alg.into()))
+        }
+        PublicKeyAlgorithm::EdDsaLegacy => KeyMaterial::EdDsaLegacy {
+            curve: read_oid(reader)?,
+            point: read_mpi(reader)?,
+        },
+        PublicKeyAlgorithm::Ecdh => {
+            let curve = read_oid(reader)?;
+            let point = read_mpi(reader)?;
+            // KDF parameters: length, reserved byte, hash, and cipher
+            match *reader.get_bytes(4)? {
+                [3, 1, kdf_hash, kek_cipher] => KeyMaterial::Ecdh {
+                    curve,
+                    point,
+                    kdf_hash,
+                    kek_cipher,
+                },
+                _ => return Err(Error::IllFormedKey),
+            }
+        }
+        PublicKeyAlgorithm::X25519 => KeyMaterial::X25519(reader.get_bytes(32)?),
+        PublicKeyAlgorithm::X448 => KeyMaterial::X448(reader.get_bytes(56)?),
+        PublicKeyAlgorithm::Ed25519 => KeyMaterial::Ed25519(reader.get_bytes(32)?),
+        PublicKeyAlgorithm::Ed448 => KeyMaterial::Ed448(reader.get_bytes(57)?),
+        // Reserved, with no defined key format
+        PublicKeyAlgorithm::DiffieHellman => return Err(Error::UnknownPkeyAlgorithm(alg.into())),
+    })
+}
+
+/// Parse the body of a public key packet, or of a public subkey packet if
+/// `subkey` is set
+pub fn parse_body(body: &[u8], subkey: bool) -> Result<PublicKey<'_>, Error> {
+    Reader::read_all(body, Error::TrailingJunk, |reader| {
+        let version = reader.byte()?;
+        let creation_time = reader.be_u32()?;
+        let algorithm = PublicKeyAlgorithm::try_from(reader.byte()?)?;
+        let material = match version {
+            4 => read_material(reader, version, algorithm)?,
+            // v5 and v6 keys give the length of the key material
+            5 | 6 => {
+                let len = reader.be_u32()? as usize;
+                Reader::read_all(reader.get_bytes(len)?, Error::IllFormedKey, |reader| {
+                    read_material(reader, version, algorithm)
+                })?
+            }
+            _ => return Err(Error::UnsupportedKeyVersion),
+        };
+        Ok(PublicKey {
+            subkey,
+            version,
+            creation_time,
+            algorithm,
+            material,
+            body,
+        })
+    })
+}
+
+/// Reads a public key or public subkey packet from `reader`
+pub fn read_key<'a>(reader: &mut Reader<'a>) -> Result<PublicKey<'a>, Error> {
+    let packet = packet::next(reader)?.ok_or(Error::PrematureEOF)?;
+    match packet.tag() {
+        TAG_PUBLIC_KEY => parse_body(packet.contents(), false),
+        TAG_PUBLIC_SUBKEY => parse_body(packet.contents(), true),
+        _ => Err(Error::IllFormedKey),
+    }
+}
+
+/// Parse a public key or public subkey packet from a slice
+pub fn parse(data: &[u8]) -> Result<PublicKey<'_>, Error> {
+    Reader::read_all(data, Error::TrailingJunk, read_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED25519_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01];
+    const P256_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+
+    #[test]
+    fn parses_keys() {
+        static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+        let mut reader = Reader::new(CERT);
+        let primary = read_key(&mut reader).unwrap();
+        assert!(!primary.subkey);
+        assert_eq!(
+            (primary.version, primary.algorithm),
+            (4, PublicKeyAlgorithm::EdDsaLegacy)
+        );
+        assert_eq!(primary.creation_time, 1792111174);
+        match primary.material {
+            KeyMaterial::EdDsaLegacy { curve, point } => {
+                assert_eq!(curve, ED25519_OID);
+                assert_eq!((point.len(), point[0]), (33, 0x40));
+            }
+            m => panic!("wrong key material {:?}", m),
+        }
+        assert_eq!(primary.material.bits(), None);
+        let mut preimage = [0; 3];
+        let mut first = true;
+        primary.fingerprint_data(&mut |data| {
+            if first {
+                preimage.copy_from_slice(data);
+                first = false
+            } else {
+                assert_eq!(data, &CERT[2..][..51])
+            }
+        });
+        assert_eq!(preimage, [0x99, 0, 51]);
+        assert_eq!(primary.fingerprint_hash_algorithm(), HashAlgorithm::Sha1);
+        let mut algorithms = [PublicKeyAlgorithm::Rsa; 4];
+        let mut i = 0;
+        while let Some(packet) = packet::next(&mut reader).unwrap() {
+            if packet.tag() != TAG_PUBLIC_SUBKEY {
+                continue;
+            }
+            let subkey = parse_body(packet.contents(), true).unwrap();
+            assert!(subkey.subkey);
+            algorithms[i] = subkey.algorithm;
+            match (subkey.algorithm, subkey.material) {
+                (PublicKeyAlgorithm::Ecdh, KeyMaterial::Ecdh { kdf_hash, .. }) => {
+                    assert!(kdf_hash >= 8)
+                }
+                (PublicKeyAlgorithm::Rsa, KeyMaterial::Rsa { n, e }) => {
+                    assert_eq!((n.len(), e), (256, &[1, 0, 1][..]));
+                    assert_eq!(subkey.material.bits(), Some(2048))
+                }
+                (PublicKeyAlgorithm::Ecdsa, KeyMaterial::Ecdsa { curve, point }) => {
+                    assert_eq!((curve, point.len()), (P256_OID, 65))
+                }
+                (_, m) => panic!("wrong key material {:?}", m),
+            }
+            i += 1
+        }
+        assert_eq!(
+            algorithms,
+            [
+                PublicKeyAlgorithm::Ecdh,
+                PublicKeyAlgorithm::Rsa,
+                PublicKeyAlgorithm::Ecdsa,
+                PublicKeyAlgorithm::Ecdh
+            ]
+        );
+        // A primary key packet on its own
+        assert_eq!(parse(&CERT[..53]).unwrap(), primary);
+        assert_eq!(parse(&CERT[..52]), Err(Error::PrematureEOF));
+    }
+
+    #[test]
+    fn parses_v6_keys() {
+        let mut body = vec![6, 0x68, 0, 0, 0, 27, 0, 0, 0, 32];
+        body.resize(10 + 32, 0xCD);
+        let key = parse_body(&body, false).unwrap();
+        assert_eq!(key.version, 6);
+        assert_eq!(key.creation_time, 0x6800_0000);
+        assert_eq!(key.material, KeyMaterial::Ed25519(&body[10..]));
+        assert_eq!(key.fingerprint_hash_algorithm(), HashAlgorithm::Sha256);
+        let mut prefix = None;
+        key.fingerprint_data(&mut |data| {
+            prefix.get_or_insert(data.to_vec());
+        });
+        assert_eq!(prefix.unwrap(), [0x9B, 0, 0, 0, 42]);
+        // Wrong material length
+        body[9] = 33;
+        assert_eq!(parse_body(&body, false), Err(Error::PrematureEOF));
+        body.push(0);
+        assert_eq!(parse_body(&body, false), Err(Error::IllFormedKey));
+        // Ed448
+        let mut ed448 = vec![6, 0x68, 0, 0, 0, 28, 0, 0, 0, 57];
+        ed448.resize(10 + 57, 0xCD);
+        let key = parse_body(&ed448, false).unwrap();
+        assert_eq!(key.material, KeyMaterial::Ed448(&ed448[10..]));
+        // Legacy EdDSA is not allowed
+        let legacy = [6, 0, 0, 0, 0, 22, 0, 0, 0, 0];
+        assert_eq!(
+            parse_body(&legacy, false),
+            Err(Error::InvalidPkeyAlgorithm(22))
+        );
+        assert_eq!(
+            parse_body(&[3, 0, 0, 0, 0, 1], false),
+            Err(Error::UnsupportedKeyVersion)
+        );
+        assert_eq!(
+            parse_body(&[4, 0, 0, 0, 0, 99], false),
+            Err(Error::UnknownPkeyAlgorithm(99))
+        );
+    }
+}
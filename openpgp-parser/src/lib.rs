@@ -7,18 +7,45 @@
 #![forbid(missing_docs, unsafe_code, deprecated)]
 #![deny(warnings)]
 #![cfg_attr(not(any(feature = "std", test)), no_std)]
+#[cfg(feature = "alloc")]
+extern crate alloc;
 pub use buffer::{EOFError, Reader};
+#[cfg(feature = "alloc")]
+pub mod armor;
 mod buffer;
+#[cfg(feature = "alloc")]
+pub mod cert;
+#[cfg(feature = "alloc")]
+pub mod cleartext;
+pub mod curve;
+pub mod hash;
+pub mod key;
 pub mod packet;
+pub mod pkey;
 pub mod signature;
+#[cfg(feature = "alloc")]
+pub mod subpacket;
 
 #[cfg(target_pointer_width = "16")]
 compile_error!("Sorry, 16-bit targets not supported");
 
+pub use hash::HashAlgorithm;
+pub use pkey::PublicKeyAlgorithm;
 pub use signature::AllowWeakHashes;
 
+/// Returns [`true`] if `c` is a control character (C0, DEL, or C1) or a
+/// bidirectional formatting character.  Neither kind is safe to display.
+pub fn is_unsafe_char(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+        )
+}
+
 /// Errors that can occur during parsing
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     /// First bit of the first byte of a packet is zero
     PacketFirstBitZero,
@@ -60,4 +87,128 @@ pub enum Error {
     NoCreationTime,
     /// Unsupported critical subpacket
     UnsupportedCriticalSubpacket,
+    /// Invalid ASCII armor
+    BadArmor,
+    /// ASCII armor checksum mismatch
+    BadArmorChecksum,
+    /// Unsupported public key version
+    UnsupportedKeyVersion,
+    /// Ill-formed public key
+    IllFormedKey,
+    /// Unknown elliptic curve
+    UnknownCurve,
+    /// Elliptic curve rejected by policy
+    CurveRejected,
+    /// A line of ASCII armor is longer than [`Limits::armor_line_length`]
+    ArmorLineTooLong,
+    /// ASCII armor is larger than [`Limits::armor_size`]
+    ArmorTooLarge,
+    /// A user ID is not valid UTF-8, contains control or bidirectional
+    /// characters, or is longer than [`Limits::user_id_length`]
+    IllFormedUserId,
+}
+
+/// Resource limits for untrusted input.  Inputs exceeding them are rejected
+/// up front, before any expensive processing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Limits {
+    /// The longest line of ASCII armor, in bytes, including any trailing
+    /// whitespace and line ending.  This applies to every line of the input,
+    /// including text before and after the armor.
+    pub armor_line_length: usize,
+    /// The largest ASCII armor input, in bytes
+    pub armor_size: usize,
+    /// The largest signature packet read from a stream, in bytes, including
+    /// the packet header
+    pub signature_size: usize,
+    /// The longest user ID accepted by [`cert::UserId::validate`], in bytes
+    pub user_id_length: usize,
+}
+
+impl Default for Limits {
+    /// Lines of up to 4 KiB, up to 16 MiB of armor, signatures of up to
+    /// 64 KiB, and user IDs of up to 1 KiB.  This is far more than any real
+    /// signature or key block needs.
+    fn default() -> Self {
+        Self {
+            armor_line_length: 4096,
+            armor_size: 16 << 20,
+            signature_size: 64 << 10,
+            user_id_length: 1 << 10,
+        }
+    }
+}
+
+/// The broad category of an [`Error`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The input is malformed
+    Malformed,
+    /// The input is well-formed, but the caller’s policy rejects it
+    Policy,
+    /// The input is well-formed, but cannot be cryptographically verified,
+    /// for instance because it uses an unsupported algorithm
+    Crypto,
+}
+
+impl Error {
+    /// The category of this error
+    pub fn category(self) -> ErrorCategory {
+        match self {
+            Error::InsecureAlgorithm(_)
+            | Error::SignatureNotValidYet
+            | Error::SignatureExpired
+            | Error::CurveRejected
+            | Error::ArmorLineTooLong
+            | Error::ArmorTooLarge => ErrorCategory::Policy,
+            Error::UnsupportedSignatureVersion
+            | Error::UnsupportedKeyVersion
+            | Error::UnsupportedHashAlgorithm(_)
+            | Error::UnknownPkeyAlgorithm(_)
+            | Error::UnsupportedPkeyAlgorithm(_)
+            | Error::UnsupportedCriticalSubpacket
+            | Error::UnknownCurve => ErrorCategory::Crypto,
+            Error::PacketFirstBitZero
+            | Error::TooLong
+            | Error::PrematureEOF
+            | Error::IndefiniteLength
+            | Error::PartialLength
+            | Error::BadTag
+            | Error::TrailingJunk
+            | Error::BadMPI
+            | Error::IllFormedSignature
+            | Error::InvalidPkeyAlgorithm(_)
+            | Error::PkeyAlgorithmRequiresV4Sig(_)
+            | Error::NoCreationTime
+            | Error::BadArmor
+            | Error::BadArmorChecksum
+            | Error::IllFormedKey
+            | Error::IllFormedUserId => ErrorCategory::Malformed,
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// The [`Error`] is kept as the payload of the [`std::io::Error`], so it can
+/// be retrieved with [`std::io::Error::get_ref`] and downcasting, along with
+/// its [`ErrorCategory`].
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::PrematureEOF => std::io::ErrorKind::UnexpectedEof,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
 }
@@ -1,6 +1,8 @@
 //! Utility functions for parsing OpenPGP packets
 
 use super::{Error, Reader};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 /// The format of a packet
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
@@ -20,53 +22,68 @@ pub struct Packet<'a> {
 }
 
 pub(crate) fn get_varlen_bytes<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8], Error> {
-    let keybyte: u8 = reader.byte()?;
+    let len = get_varlen(&mut || Ok(reader.byte()?))?;
+    Ok(reader.get_bytes(len)?)
+}
+
+/// Parses a new-format length, reading it from `byte` one byte at a time
+fn get_varlen(byte: &mut dyn FnMut() -> Result<u8, Error>) -> Result<usize, Error> {
+    let keybyte: u8 = byte()?;
     #[cfg(test)]
-    eprintln!("Keybyte is {}, reader length is {}", keybyte, reader.len());
-    let len: usize = match keybyte {
+    eprintln!("Keybyte is {}", keybyte);
+    Ok(match keybyte {
         0..=191 => keybyte.into(),
-        192..=223 => ((usize::from(keybyte) - 192) << 8) + usize::from(reader.byte()?) + 192,
+        192..=223 => ((usize::from(keybyte) - 192) << 8) + usize::from(byte()?) + 192,
         // Partial lengths are deliberately unsupported, as we don’t handle PGP signed and/or
         // encrypted data ourselves.
         224..=254 => return Err(Error::PartialLength),
-        255 => reader.be_u32()? as _,
-    };
-    Ok(reader.get_bytes(len)?)
+        255 => u32::from_be_bytes([byte()?, byte()?, byte()?, byte()?]) as _,
+    })
 }
 
-/// Read a packet from `reader`.  Returns:
-///
-/// - `Ok(Some(packet))` if a packet is read
-/// - `Ok(None)` if the reader is empty.
-/// - `Err` if an error occurred, such as trailing junk.
-pub fn next<'a>(reader: &mut Reader<'a>) -> Result<Option<Packet<'a>>, Error> {
-    let tagbyte: u8 = match reader.maybe_byte() {
-        Some(e) if e & 0x80 == 0 => return Err(Error::PacketFirstBitZero),
-        Some(e) => e,
-        None => return Ok(None),
-    };
+/// Parses the header of a packet starting with `tagbyte`, reading the rest of
+/// it from `byte` one byte at a time.  Returns the tag, in the form stored by
+/// [`Packet`], and the length of the packet body.  This lets packets be read
+/// from streams as well as from a [`Reader`].
+pub(crate) fn parse_header(
+    tagbyte: u8,
+    byte: &mut dyn FnMut() -> Result<u8, Error>,
+) -> Result<(u8, usize), Error> {
+    if tagbyte & 0x80 == 0 {
+        return Err(Error::PacketFirstBitZero);
+    }
     #[cfg(test)]
     eprintln!("Tag byte is 0b{:b}", tagbyte);
-    let packet = if tagbyte & 0x40 == 0 {
+    if tagbyte & 0x40 == 0 {
         let lenlen = 1u8 << (tagbyte & 0b11);
         // We deliberately do not support indefinite-length packets.
         if lenlen > 4 {
             return Err(Error::PartialLength);
         }
         let mut len = 0usize;
-        for &i in reader.get_bytes(usize::from(lenlen))? {
-            len = len << 8 | usize::from(i)
-        }
-        Packet {
-            tag: 0xF & (tagbyte >> 2),
-            buffer: reader.get_bytes(len)?,
+        for _ in 0..lenlen {
+            len = len << 8 | usize::from(byte()?)
         }
+        Ok((0xF & (tagbyte >> 2), len))
     } else {
-        let buffer = get_varlen_bytes(reader)?;
-        Packet {
-            tag: tagbyte & 0x7F,
-            buffer,
-        }
+        Ok((tagbyte & 0x7F, get_varlen(byte)?))
+    }
+}
+
+/// Read a packet from `reader`.  Returns:
+///
+/// - `Ok(Some(packet))` if a packet is read
+/// - `Ok(None)` if the reader is empty.
+/// - `Err` if an error occurred, such as trailing junk.
+pub fn next<'a>(reader: &mut Reader<'a>) -> Result<Option<Packet<'a>>, Error> {
+    let tagbyte: u8 = match reader.maybe_byte() {
+        Some(e) => e,
+        None => return Ok(None),
+    };
+    let (tag, len) = parse_header(tagbyte, &mut || Ok(reader.byte()?))?;
+    let packet = Packet {
+        tag,
+        buffer: reader.get_bytes(len)?,
     };
     if packet.tag & 0x3F != 0 {
         Ok(Some(packet))
@@ -76,6 +93,19 @@ pub fn next<'a>(reader: &mut Reader<'a>) -> Result<Option<Packet<'a>>, Error> {
 }
 
 impl<'a> Packet<'a> {
+    /// Creates a new-format packet with the given tag and contents.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag is zero or not less than 64.
+    pub fn new(tag: u8, buffer: &'a [u8]) -> Self {
+        assert!(tag != 0 && tag < 64, "bad packet tag {}", tag);
+        Self {
+            tag: tag | 0x40,
+            buffer,
+        }
+    }
+
     /// Retrieves the packet’s tag.  Will always return non-zero.
     pub fn tag(&self) -> u8 {
         self.tag & 0x3F
@@ -123,6 +153,7 @@ impl<'a> Packet<'a> {
                 // 5-byte
                 let mut v = Vec::with_capacity(6 + len);
                 v.push(tag_byte);
+                v.push(255);
                 v.extend_from_slice(&(len as u32).to_be_bytes());
                 v.extend_from_slice(self.buffer);
                 v
@@ -130,3 +161,37 @@ impl<'a> Packet<'a> {
         }
     }
 }
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::next as next_packet;
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Packet lengths, with the boundaries of every length encoding
+    fn length() -> impl Strategy<Value = usize> {
+        prop_oneof![
+            prop::sample::select(&[0, 191, 192, 8383, 8384, 65535, 65536][..]),
+            0..70000usize,
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn serialize_roundtrip(tag in 1..64u8, len in length(), byte: u8) {
+            let data = vec![byte; len];
+            let serialized = Packet::new(tag, &data).serialize();
+            let mut reader = Reader::new(&serialized);
+            let parsed = next_packet(&mut reader).unwrap().unwrap();
+            prop_assert!(reader.is_empty());
+            prop_assert_eq!((parsed.tag(), parsed.contents()), (tag, &data[..]));
+            prop_assert_eq!(parsed.format(), Format::New);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "bad packet tag 64")]
+    fn rejects_bad_tags() {
+        Packet::new(64, &[]);
+    }
+}
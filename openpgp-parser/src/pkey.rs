@@ -0,0 +1,202 @@
+//! Public-key algorithms
+//!
+//! [`PublicKeyAlgorithm`] covers every registered OpenPGP public-key
+//! algorithm ID.  Only some of them can make signatures, and which of those
+//! a signature may use depends on its version; see
+//! [`PublicKeyAlgorithm::expected_mpis`].
+
+use super::Error;
+use core::convert::TryFrom;
+
+/// A registered OpenPGP public-key algorithm
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PublicKeyAlgorithm {
+    /// RSA (encrypt or sign)
+    Rsa = 1,
+    /// RSA encrypt-only (deprecated)
+    RsaEncryptOnly = 2,
+    /// RSA sign-only (deprecated)
+    RsaSignOnly = 3,
+    /// Elgamal encrypt-only
+    ElgamalEncryptOnly = 16,
+    /// DSA
+    Dsa = 17,
+    /// ECDH
+    Ecdh = 18,
+    /// ECDSA
+    Ecdsa = 19,
+    /// Elgamal encrypt or sign (insecure and forbidden)
+    ElgamalEncryptOrSign = 20,
+    /// Diffie-Hellman (reserved)
+    DiffieHellman = 21,
+    /// Legacy EdDSA, with the curve named by OID
+    EdDsaLegacy = 22,
+    /// X25519
+    X25519 = 25,
+    /// X448
+    X448 = 26,
+    /// Ed25519
+    Ed25519 = 27,
+    /// Ed448
+    Ed448 = 28,
+}
+
+impl PublicKeyAlgorithm {
+    /// Every known public-key algorithm, in order of ID
+    pub const ALL: &'static [PublicKeyAlgorithm] = &[
+        PublicKeyAlgorithm::Rsa,
+        PublicKeyAlgorithm::RsaEncryptOnly,
+        PublicKeyAlgorithm::RsaSignOnly,
+        PublicKeyAlgorithm::ElgamalEncryptOnly,
+        PublicKeyAlgorithm::Dsa,
+        PublicKeyAlgorithm::Ecdh,
+        PublicKeyAlgorithm::Ecdsa,
+        PublicKeyAlgorithm::ElgamalEncryptOrSign,
+        PublicKeyAlgorithm::DiffieHellman,
+        PublicKeyAlgorithm::EdDsaLegacy,
+        PublicKeyAlgorithm::X25519,
+        PublicKeyAlgorithm::X448,
+        PublicKeyAlgorithm::Ed25519,
+        PublicKeyAlgorithm::Ed448,
+    ];
+
+    /// Can this algorithm make signatures accepted by this crate?
+    pub fn is_signing_capable(self) -> bool {
+        match self {
+            PublicKeyAlgorithm::Rsa
+            | PublicKeyAlgorithm::RsaSignOnly
+            | PublicKeyAlgorithm::Dsa
+            | PublicKeyAlgorithm::Ecdsa
+            | PublicKeyAlgorithm::EdDsaLegacy
+            | PublicKeyAlgorithm::Ed25519
+            | PublicKeyAlgorithm::Ed448 => true,
+            PublicKeyAlgorithm::RsaEncryptOnly
+            | PublicKeyAlgorithm::ElgamalEncryptOnly
+            | PublicKeyAlgorithm::Ecdh
+            | PublicKeyAlgorithm::ElgamalEncryptOrSign
+            | PublicKeyAlgorithm::DiffieHellman
+            | PublicKeyAlgorithm::X25519
+            | PublicKeyAlgorithm::X448 => false,
+        }
+    }
+
+    /// Is this an elliptic-curve algorithm?  The strength of such a key
+    /// depends on its curve, not on a size in bits.
+    pub fn is_elliptic_curve(self) -> bool {
+        match self {
+            PublicKeyAlgorithm::Ecdh
+            | PublicKeyAlgorithm::Ecdsa
+            | PublicKeyAlgorithm::EdDsaLegacy
+            | PublicKeyAlgorithm::X25519
+            | PublicKeyAlgorithm::X448
+            | PublicKeyAlgorithm::Ed25519
+            | PublicKeyAlgorithm::Ed448 => true,
+            PublicKeyAlgorithm::Rsa
+            | PublicKeyAlgorithm::RsaEncryptOnly
+            | PublicKeyAlgorithm::RsaSignOnly
+            | PublicKeyAlgorithm::ElgamalEncryptOnly
+            | PublicKeyAlgorithm::Dsa
+            | PublicKeyAlgorithm::ElgamalEncryptOrSign
+            | PublicKeyAlgorithm::DiffieHellman => false,
+        }
+    }
+
+    /// Returns the number of MPIs in a signature by this algorithm, checking
+    /// it against signature version `sig_version`.  Algorithms with native
+    /// signatures (see [`PublicKeyAlgorithm::native_signature_len`]) have
+    /// none.  Returns `Err` if the algorithm is invalid or unsupported for
+    /// the given signature version.
+    pub fn expected_mpis(self, sig_version: u8) -> Result<u8, Error> {
+        let alg = u8::from(self);
+        let is_v4 = match sig_version {
+            3 => false,
+            4 | 5 => true,
+            // Legacy EdDSA is forbidden in v6 signatures
+            6 if self == PublicKeyAlgorithm::EdDsaLegacy => {
+                return Err(Error::InvalidPkeyAlgorithm(alg))
+            }
+            6 => true,
+            _ => return Err(Error::UnsupportedSignatureVersion),
+        };
+        match self {
+            _ if !self.is_signing_capable() => Err(Error::InvalidPkeyAlgorithm(alg)),
+            PublicKeyAlgorithm::Rsa | PublicKeyAlgorithm::RsaSignOnly => Ok(1),
+            PublicKeyAlgorithm::Dsa => Ok(2),
+            PublicKeyAlgorithm::Ecdsa | PublicKeyAlgorithm::EdDsaLegacy if is_v4 => Ok(2),
+            PublicKeyAlgorithm::Ed25519 | PublicKeyAlgorithm::Ed448 if is_v4 => Ok(0),
+            _ => Err(Error::PkeyAlgorithmRequiresV4Sig(alg)),
+        }
+    }
+
+    /// The length of a signature by this algorithm, for algorithms whose
+    /// signatures are fixed-length byte strings rather than MPIs
+    pub fn native_signature_len(self) -> Option<usize> {
+        match self {
+            PublicKeyAlgorithm::Ed25519 => Some(64),
+            PublicKeyAlgorithm::Ed448 => Some(114),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for PublicKeyAlgorithm {
+    type Error = Error;
+
+    /// Fails with [`Error::UnknownPkeyAlgorithm`] for unknown IDs
+    fn try_from(id: u8) -> Result<Self, Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&alg| u8::from(alg) == id)
+            .ok_or(Error::UnknownPkeyAlgorithm(id))
+    }
+}
+
+impl From<PublicKeyAlgorithm> for u8 {
+    fn from(alg: PublicKeyAlgorithm) -> u8 {
+        alg as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_algorithms() {
+        for &alg in PublicKeyAlgorithm::ALL {
+            assert_eq!(PublicKeyAlgorithm::try_from(u8::from(alg)), Ok(alg));
+            if !alg.is_signing_capable() {
+                assert_eq!(
+                    alg.expected_mpis(4),
+                    Err(Error::InvalidPkeyAlgorithm(alg.into()))
+                );
+            }
+        }
+        assert_eq!(
+            PublicKeyAlgorithm::try_from(99),
+            Err(Error::UnknownPkeyAlgorithm(99))
+        );
+        let rsa = PublicKeyAlgorithm::Rsa;
+        assert_eq!((rsa.expected_mpis(3), rsa.expected_mpis(6)), (Ok(1), Ok(1)));
+        assert_eq!(
+            rsa.expected_mpis(7),
+            Err(Error::UnsupportedSignatureVersion)
+        );
+        assert_eq!(PublicKeyAlgorithm::Ecdsa.expected_mpis(4), Ok(2));
+        assert_eq!(PublicKeyAlgorithm::Ed25519.expected_mpis(6), Ok(0));
+        assert_eq!(
+            PublicKeyAlgorithm::Ed448.expected_mpis(3),
+            Err(Error::PkeyAlgorithmRequiresV4Sig(28))
+        );
+        assert_eq!(
+            PublicKeyAlgorithm::EdDsaLegacy.expected_mpis(6),
+            Err(Error::InvalidPkeyAlgorithm(22))
+        );
+        assert_eq!(PublicKeyAlgorithm::Ed448.native_signature_len(), Some(114));
+        assert_eq!(PublicKeyAlgorithm::Dsa.native_signature_len(), None);
+        assert!(PublicKeyAlgorithm::EdDsaLegacy.is_elliptic_curve());
+        assert!(!PublicKeyAlgorithm::Dsa.is_elliptic_curve());
+    }
+}
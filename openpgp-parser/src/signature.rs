@@ -1,9 +1,9 @@
 //! OpenPGP signatures
 
-use super::{packet, Error, Reader};
+use super::{hash::HashAlgorithm, packet, pkey::PublicKeyAlgorithm, Error, Reader};
 use packet::get_varlen_bytes;
 
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 /// Should weak hashes (less than 256 bits and vulnerable to collisions) be allowed?
@@ -14,6 +14,190 @@ pub enum AllowWeakHashes {
     Yes,
 }
 
+/// Options for [`parse_with_options`] and [`read_signature_with_options`].
+/// `'a` is the lifetime of [`SubpacketPolicy::allowed_notations`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseOptions<'a> {
+    /// Should weak hashes be allowed?
+    pub allow_weak_hashes: AllowWeakHashes,
+    /// Accept version 5 signatures, as made by v5 keys?  RPM cannot verify
+    /// these, so they are rejected by default.
+    pub allow_v5: bool,
+    /// Accept version 6 (RFC 9580) signatures?  Only RPM built with the
+    /// Sequoia backend can verify these, so they are rejected by default.
+    pub allow_v6: bool,
+    /// Accept Ed25519 and Ed448 (RFC 9580) signatures, in any signature
+    /// version?  As with v6 signatures, only RPM built with the Sequoia
+    /// backend can verify these, so they are rejected by default.  Legacy
+    /// EdDSA (algorithm 22) is not affected.
+    pub allow_native_eddsa: bool,
+    /// The kind of signature to accept, or [`None`] to accept any allowed
+    /// kind.  RPM only makes binary signatures, so only those are accepted
+    /// by default.
+    pub kind: Option<SignatureKind>,
+    /// Accept standalone signatures (type 0x02)?  They sign no data, so
+    /// they are rejected unless explicitly allowed, even if `kind` is
+    /// [`SignatureKind::Standalone`].
+    pub allow_standalone: bool,
+    /// Accept timestamp signatures (type 0x40)?  As with standalone
+    /// signatures, they must be explicitly allowed.
+    pub allow_timestamp: bool,
+    /// Accept signatures over keys and user IDs, such as self-signatures?
+    /// These sign key material rather than a document, so they must be
+    /// explicitly allowed.
+    pub allow_key_signatures: bool,
+    /// What to do with each kind of hashed subpacket
+    pub subpackets: SubpacketPolicy<'a>,
+}
+
+impl<'a> ParseOptions<'a> {
+    /// The options used by [`parse`] and [`read_signature`]
+    pub fn new(allow_weak_hashes: AllowWeakHashes) -> Self {
+        Self {
+            allow_weak_hashes,
+            allow_v5: false,
+            allow_v6: false,
+            allow_native_eddsa: false,
+            kind: Some(SignatureKind::Binary),
+            allow_standalone: false,
+            allow_timestamp: false,
+            allow_key_signatures: false,
+            subpackets: SubpacketPolicy::strict_rpm(),
+        }
+    }
+
+    /// The permissive profile, for signatures that RPM will not verify: like
+    /// [`Self::new`], but with [`SubpacketPolicy::permissive`]
+    pub fn permissive(allow_weak_hashes: AllowWeakHashes) -> Self {
+        Self {
+            subpackets: SubpacketPolicy::permissive(),
+            ..Self::new(allow_weak_hashes)
+        }
+    }
+}
+
+/// What to do with a hashed subpacket of some type
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SubpacketAction {
+    /// Reject the signature
+    Reject,
+    /// Skip the subpacket if it is not critical, and reject the signature
+    /// otherwise, as the OpenPGP standard says to do with unknown subpackets
+    SkipIfNotCritical,
+    /// Accept the subpacket.  Subpackets that this crate understands are
+    /// checked, and recorded in [`SigInfo`] if they carry information; others
+    /// are skipped, critical or not.
+    Accept,
+}
+
+/// What to do with each type of hashed subpacket.  Subpackets in the unhashed
+/// area are not affected: only an issuer key ID is ever allowed there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubpacketPolicy<'a> {
+    actions: [SubpacketAction; 128],
+    /// Names of notations to accept, such as `rpm-*@rpm.org`, if notations
+    /// are accepted at all.  A name may contain one `*`, which matches any
+    /// sequence of bytes.  Notations with any other name are rejected,
+    /// critical or not.  The names are borrowed, so they can come from
+    /// configuration read at runtime.
+    pub allowed_notations: &'a [&'a str],
+}
+
+/// Subpackets that make no sense in a document signature.  They are rejected
+/// as ill-formed, rather than as unsupported.
+const INVALID_IN_DOCUMENT_SIGNATURES: &[u8] = &[
+    // only valid in self-signature
+    SUBPACKET_KEY_EXPIRATION_TIME,
+    SUBPACKET_PREFERRED_SYMMETRIC,
+    SUBPACKET_PREFERRED_HASH,
+    SUBPACKET_PREFERRED_COMPRESSION,
+    SUBPACKET_KEY_SERVER_PREFERENCES,
+    SUBPACKET_PRIMARY_USER_ID,
+    SUBPACKET_PREFERRED_KEY_SERVERS,
+    SUBPACKET_FEATURES,
+    // only valid on certification
+    SUBPACKET_EXPORTABLE,
+    SUBPACKET_TRUST_SIG,
+    SUBPACKET_REGEX,
+    SUBPACKET_REVOCATION_KEY,
+    // only valid on certifications or self-signatures
+    SUBPACKET_KEY_FLAGS,
+    // only valid on revocations
+    SUBPACKET_REVOCATION_REASON,
+    // not valid on document signatures
+    SUBPACKET_SIGNATURE_TARGET,
+    // only valid in subkey binding signatures
+    SUBPACKET_EMBEDDED_SIGNATURE,
+    // RPM doesn’t handle revocation, so this is pointless.
+    // GPG only generates this for certifications.
+    SUBPACKET_REVOCABLE,
+    // useless, not generated
+    SUBPACKET_PLACEHOLDER,
+];
+
+impl<'a> SubpacketPolicy<'a> {
+    /// The policy used for RPM: only the creation and expiration times, the
+    /// issuer key ID and fingerprint, and (if `allowed_notations` is set)
+    /// notations are accepted.  Everything else is rejected, to make exploits
+    /// against RPM less likely.
+    pub fn strict_rpm() -> Self {
+        Self::custom(SubpacketAction::Reject)
+            .with(SUBPACKET_CREATION_TIME, SubpacketAction::Accept)
+            .with(SUBPACKET_SIG_EXPIRATION_TIME, SubpacketAction::Accept)
+            .with(SUBPACKET_ISSUER_KEYID, SubpacketAction::Accept)
+            .with(SUBPACKET_FINGERPRINT, SubpacketAction::Accept)
+            .with(SUBPACKET_NOTATION, SubpacketAction::Accept)
+    }
+
+    /// A policy for signatures that RPM will not verify: like
+    /// [`Self::strict_rpm`], but policy URIs and signer user IDs are accepted,
+    /// and unknown subpackets are skipped unless they are critical.
+    /// Subpackets that make no sense in a document signature are still
+    /// rejected.
+    pub fn permissive() -> Self {
+        let mut policy = Self::strict_rpm()
+            .with(SUBPACKET_POLICY_URI, SubpacketAction::Accept)
+            .with(SUBPACKET_SIGNER_USER_ID, SubpacketAction::Accept);
+        for action in policy.actions.iter_mut() {
+            if *action == SubpacketAction::Reject {
+                *action = SubpacketAction::SkipIfNotCritical
+            }
+        }
+        for &tag in INVALID_IN_DOCUMENT_SIGNATURES {
+            policy.actions[usize::from(tag)] = SubpacketAction::Reject
+        }
+        policy
+    }
+
+    /// A policy that does `action` with every subpacket, to be refined with
+    /// [`Self::with`].  No notations are allowed.
+    pub fn custom(action: SubpacketAction) -> Self {
+        Self {
+            actions: [action; 128],
+            allowed_notations: &[],
+        }
+    }
+
+    /// Does `action` with subpackets of type `tag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` has its high bit set, as that bit is the critical flag.
+    pub fn with(mut self, tag: u8, action: SubpacketAction) -> Self {
+        assert!(tag & 0x80 == 0, "critical bit is not part of the type");
+        self.actions[usize::from(tag)] = action;
+        self
+    }
+
+    /// What to do with subpackets of type `tag`, which must not have its high
+    /// bit set
+    pub fn action(&self, tag: u8) -> SubpacketAction {
+        self.actions[usize::from(tag & 0x7F)]
+    }
+}
+
 /// Read a multiprecision integer (MPI) from `reader`.  Value is returned as a
 /// slice.
 pub fn read_mpi<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8], Error> {
@@ -21,7 +205,7 @@ pub fn read_mpi<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8], Error> {
         let bits = 7 + usize::from(reader.be_u16()?);
         let mpi_buf = reader.get_bytes(bits >> 3)?;
         // don’t use ‘Reader::byte’, which mutates the reader
-        if let Some(first_byte) = mpi_buf.get(0) {
+        if let Some(first_byte) = mpi_buf.first() {
             // check that there are no spurious leading zeros
             // this is not valid for encrypted MPIs, but we don’t deal with
             // them, as we only parse signatures
@@ -34,107 +218,154 @@ pub fn read_mpi<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8], Error> {
 }
 
 const OPENPGP_SIGNATURE_TYPE_BINARY: u8 = 0;
+const OPENPGP_SIGNATURE_TYPE_TEXT: u8 = 1;
+const OPENPGP_SIGNATURE_TYPE_STANDALONE: u8 = 2;
+const OPENPGP_SIGNATURE_TYPE_TIMESTAMP: u8 = 0x40;
+
+/// What a signature covers
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SignatureKind {
+    /// A binary document (signature type 0x00), hashed as-is
+    Binary,
+    /// A canonical text document (signature type 0x01), hashed with CR LF
+    /// line endings
+    Text,
+    /// A standalone signature (signature type 0x02), over no data but the
+    /// signature itself
+    Standalone,
+    /// A timestamp signature (signature type 0x40), which only attests to
+    /// its creation time.  It also signs no data.
+    Timestamp,
+    /// A signature over a key or user ID, with the given signature type: a
+    /// certification (0x10 to 0x13), a key binding (0x18 or 0x19), a
+    /// direct-key signature (0x1F), or a revocation (0x20, 0x28, or 0x30).
+    /// It signs key material, as passed by [`crate::cert`].
+    Key(u8),
+}
 
-const OPENPGP_HASH_INSECURE_MD5: i32 = 1;
-const OPENPGP_HASH_INSECURE_SHA1: i32 = 2;
-const OPENPGP_HASH_INSECURE_RIPEMD160: i32 = 3;
-const OPENPGP_HASH_EXPIRIMENTAL_DOUBLE_SHA: i32 = 4;
-const OPENPGP_HASH_INSECURE_MD2: i32 = 5;
-const OPENPGP_HASH_INSECURE_TIGER192: i32 = 6;
-const OPENPGP_HASH_INSECURE_HAVAL_5_160: i32 = 7;
-const OPENPGP_HASH_SHA256: i32 = 8;
-const OPENPGP_HASH_SHA384: i32 = 9;
-const OPENPGP_HASH_SHA512: i32 = 10;
-const OPENPGP_HASH_SHA224: i32 = 11;
-
-// Public key algorithms
-const OPENPGP_PUBLIC_KEY_RSA: u8 = 1;
-const OPENPGP_PUBLIC_KEY_LEGACY_RSA_ENCRYPT_ONLY: u8 = 2;
-const OPENPGP_PUBLIC_KEY_LEGACY_RSA_SIGN_ONLY: u8 = 3;
-const OPENPGP_PUBLIC_KEY_ELGAMAL_ENCRYPT_ONLY: u8 = 16;
-const OPENPGP_PUBLIC_KEY_DSA: u8 = 17;
-const OPENPGP_PUBLIC_KEY_ECDH: u8 = 18;
-const OPENPGP_PUBLIC_KEY_ECDSA: u8 = 19;
-const OPENPGP_PUBLIC_KEY_INSECURE_ELGAMAL_SIGN_ENCRYPT: u8 = 20;
-const OPENPGP_PUBLIC_KEY_DH: u8 = 21;
-const OPENPGP_PUBLIC_KEY_EDDSA: u8 = 22;
+impl SignatureKind {
+    /// Checks signature type `sig_type` against `options`
+    fn check(sig_type: u8, options: &ParseOptions) -> Result<Self, Error> {
+        let kind = match sig_type {
+            OPENPGP_SIGNATURE_TYPE_BINARY => SignatureKind::Binary,
+            OPENPGP_SIGNATURE_TYPE_TEXT => SignatureKind::Text,
+            OPENPGP_SIGNATURE_TYPE_STANDALONE if options.allow_standalone => {
+                SignatureKind::Standalone
+            }
+            OPENPGP_SIGNATURE_TYPE_TIMESTAMP if options.allow_timestamp => SignatureKind::Timestamp,
+            0x10..=0x13 | 0x18 | 0x19 | 0x1F | 0x20 | 0x28 | 0x30
+                if options.allow_key_signatures =>
+            {
+                SignatureKind::Key(sig_type)
+            }
+            _ => return Err(Error::IllFormedSignature),
+        };
+        match options.kind {
+            Some(expected) if expected != kind => Err(Error::IllFormedSignature),
+            _ => Ok(kind),
+        }
+    }
+}
 
 // Signature subpackets
-const SUBPACKET_CREATION_TIME: u8 = 2;
-const SUBPACKET_SIG_EXPIRATION_TIME: u8 = 3;
-const SUBPACKET_EXPORTABLE: u8 = 4;
-const SUBPACKET_TRUST_SIG: u8 = 5;
-const SUBPACKET_REGEX: u8 = 6;
-const SUBPACKET_REVOCABLE: u8 = 7;
-const SUBPACKET_KEY_EXPIRATION_TIME: u8 = 9;
-const SUBPACKET_PLACEHOLDER: u8 = 10;
-const SUBPACKET_PREFERRED_SYMMETRIC: u8 = 11;
-const SUBPACKET_REVOCATION_KEY: u8 = 12;
-const SUBPACKET_ISSUER_KEYID: u8 = 16;
-const SUBPACKET_NOTATION: u8 = 20;
-const SUBPACKET_PREFERRED_HASH: u8 = 21;
-const SUBPACKET_PREFERRED_COMPRESSION: u8 = 22;
-const SUBPACKET_KEY_SERVER_PREFERENCES: u8 = 23;
-const SUBPACKET_PREFERRED_KEY_SERVERS: u8 = 24;
-const SUBPACKET_PRIMARY_USER_ID: u8 = 25;
-const SUBPACKET_POLICY_URI: u8 = 26;
-const SUBPACKET_KEY_FLAGS: u8 = 27;
-const SUBPACKET_SIGNER_USER_ID: u8 = 28;
-const SUBPACKET_REVOCATION_REASON: u8 = 29;
-const SUBPACKET_FEATURES: u8 = 30;
-const SUBPACKET_SIGNATURE_TARGET: u8 = 31;
-const SUBPACKET_EMBEDDED_SIGNATURE: u8 = 32;
-const SUBPACKET_FINGERPRINT: u8 = 33;
+pub(crate) const SUBPACKET_CREATION_TIME: u8 = 2;
+pub(crate) const SUBPACKET_SIG_EXPIRATION_TIME: u8 = 3;
+pub(crate) const SUBPACKET_EXPORTABLE: u8 = 4;
+pub(crate) const SUBPACKET_TRUST_SIG: u8 = 5;
+pub(crate) const SUBPACKET_REGEX: u8 = 6;
+pub(crate) const SUBPACKET_REVOCABLE: u8 = 7;
+pub(crate) const SUBPACKET_KEY_EXPIRATION_TIME: u8 = 9;
+pub(crate) const SUBPACKET_PLACEHOLDER: u8 = 10;
+pub(crate) const SUBPACKET_PREFERRED_SYMMETRIC: u8 = 11;
+pub(crate) const SUBPACKET_REVOCATION_KEY: u8 = 12;
+pub(crate) const SUBPACKET_ISSUER_KEYID: u8 = 16;
+pub(crate) const SUBPACKET_NOTATION: u8 = 20;
+pub(crate) const SUBPACKET_PREFERRED_HASH: u8 = 21;
+pub(crate) const SUBPACKET_PREFERRED_COMPRESSION: u8 = 22;
+pub(crate) const SUBPACKET_KEY_SERVER_PREFERENCES: u8 = 23;
+pub(crate) const SUBPACKET_PREFERRED_KEY_SERVERS: u8 = 24;
+pub(crate) const SUBPACKET_PRIMARY_USER_ID: u8 = 25;
+pub(crate) const SUBPACKET_POLICY_URI: u8 = 26;
+pub(crate) const SUBPACKET_KEY_FLAGS: u8 = 27;
+pub(crate) const SUBPACKET_SIGNER_USER_ID: u8 = 28;
+pub(crate) const SUBPACKET_REVOCATION_REASON: u8 = 29;
+pub(crate) const SUBPACKET_FEATURES: u8 = 30;
+pub(crate) const SUBPACKET_SIGNATURE_TARGET: u8 = 31;
+pub(crate) const SUBPACKET_EMBEDDED_SIGNATURE: u8 = 32;
+pub(crate) const SUBPACKET_FINGERPRINT: u8 = 33;
 
 /// Return the number of MPIs for the public-key algorithm `alg`, checking it
 /// against signature version `sig_version`.  Returns `Err` if the algorithm is
-/// invalid or unsupported for the given signature version.
+/// invalid or unsupported for the given signature version.  This is
+/// [`PublicKeyAlgorithm::expected_mpis`] for callers holding a raw algorithm
+/// ID.
 pub fn pkey_alg_mpis(alg: u8, sig_version: u8) -> Result<u8, Error> {
-    let is_v4 = match sig_version {
-        3 => false,
-        4 => true,
-        _ => return Err(Error::UnsupportedSignatureVersion),
-    };
-    match alg {
-        OPENPGP_PUBLIC_KEY_LEGACY_RSA_ENCRYPT_ONLY
-        | OPENPGP_PUBLIC_KEY_ELGAMAL_ENCRYPT_ONLY
-        | OPENPGP_PUBLIC_KEY_INSECURE_ELGAMAL_SIGN_ENCRYPT
-        | OPENPGP_PUBLIC_KEY_ECDH
-        | OPENPGP_PUBLIC_KEY_DH => Err(Error::InvalidPkeyAlgorithm(alg)),
-        OPENPGP_PUBLIC_KEY_RSA | OPENPGP_PUBLIC_KEY_LEGACY_RSA_SIGN_ONLY => Ok(1),
-        OPENPGP_PUBLIC_KEY_EDDSA if is_v4 => Ok(2),
-        OPENPGP_PUBLIC_KEY_DSA => Ok(2),
-        OPENPGP_PUBLIC_KEY_ECDSA if is_v4 => Err(Error::UnsupportedPkeyAlgorithm(alg)),
-        OPENPGP_PUBLIC_KEY_ECDSA | OPENPGP_PUBLIC_KEY_EDDSA => {
-            Err(Error::PkeyAlgorithmRequiresV4Sig(alg))
-        }
-        _ => Err(Error::UnknownPkeyAlgorithm(alg)),
-    }
+    PublicKeyAlgorithm::try_from(alg)?.expected_mpis(sig_version)
+}
+
+/// The length of the signature of public-key algorithm `alg`, for algorithms
+/// whose signatures are fixed-length byte strings rather than MPIs
+pub fn native_signature_len(alg: u8) -> Option<usize> {
+    PublicKeyAlgorithm::try_from(alg)
+        .ok()?
+        .native_signature_len()
 }
 
+/// The largest ECDSA signature MPI, for NIST P-521.  The curve is part of the
+/// key, not the signature, so this is the most that can be checked here.
+const ECDSA_MAX_MPI_LEN: usize = 66;
+
 /// Checks that a hash algorithm is secure; if it is, returns the length (in bytes) of the hash it
-/// generates.  If `allow_weak_hashes` is set, also allow SHA1 and SHA224.
+/// generates.  If `allow_weak_hashes` is set, also allow MD5, SHA1, and SHA224.  This is
+/// [`HashAlgorithm::check`] for callers holding a raw algorithm ID.
 pub fn check_hash_algorithm(hash: i32, allow_weak_hashes: AllowWeakHashes) -> Result<u16, Error> {
-    match hash {
-        // Okay hash algorithms
-        OPENPGP_HASH_SHA256 => Ok(32),
-        OPENPGP_HASH_SHA384 => Ok(48),
-        OPENPGP_HASH_SHA512 => Ok(64),
-        OPENPGP_HASH_SHA224 if allow_weak_hashes == AllowWeakHashes::Yes => Ok(28),
-        OPENPGP_HASH_INSECURE_MD5 if allow_weak_hashes == AllowWeakHashes::Yes => Ok(16),
-        OPENPGP_HASH_INSECURE_SHA1 if allow_weak_hashes == AllowWeakHashes::Yes => Ok(20),
-        // Insecure hash algorithms
-        OPENPGP_HASH_INSECURE_SHA1 |
-        OPENPGP_HASH_INSECURE_RIPEMD160 |
-        OPENPGP_HASH_INSECURE_MD2 |
-        OPENPGP_HASH_INSECURE_TIGER192 |
-        OPENPGP_HASH_INSECURE_HAVAL_5_160 |
-        // SHA224 is secure, but its security level is a bit low
-        OPENPGP_HASH_SHA224 => Err(Error::InsecureAlgorithm(hash)),
-        // Invalid algorithms
-        OPENPGP_HASH_EXPIRIMENTAL_DOUBLE_SHA |
-        // Unknown algorithms
-        _ => Err(Error::UnsupportedHashAlgorithm(hash)),
+    let alg = u8::try_from(hash)
+        .map_err(|_| Error::UnsupportedHashAlgorithm(hash))
+        .and_then(HashAlgorithm::try_from)?;
+    alg.check(allow_weak_hashes)?;
+    Ok(alg.digest_len() as u16)
+}
+
+/// The fingerprint of an OpenPGP key, as found in an issuer fingerprint
+/// subpacket
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Fingerprint {
+    /// The SHA-1 fingerprint of a v4 key
+    V4([u8; 20]),
+    /// The SHA-256 fingerprint of a v5 key
+    V5([u8; 32]),
+    /// The SHA-256 fingerprint of a v6 key
+    V6([u8; 32]),
+}
+
+impl Fingerprint {
+    /// The fingerprint, without the version
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Fingerprint::V4(fpr) => fpr,
+            Fingerprint::V5(fpr) | Fingerprint::V6(fpr) => fpr,
+        }
+    }
+
+    /// The version of the key, which prefixes the fingerprint in an issuer
+    /// fingerprint subpacket
+    pub fn version(&self) -> u8 {
+        match self {
+            Fingerprint::V4(_) => 4,
+            Fingerprint::V5(_) => 5,
+            Fingerprint::V6(_) => 6,
+        }
+    }
+
+    /// The key ID of the key: the last 8 bytes of a v4 fingerprint, or the
+    /// first 8 bytes of a v5 or v6 fingerprint
+    pub fn key_id(&self) -> [u8; 8] {
+        let key_id = match self {
+            Fingerprint::V4(fpr) => &fpr[12..],
+            Fingerprint::V5(fpr) | Fingerprint::V6(fpr) => &fpr[..8],
+        };
+        key_id.try_into().expect("length is correct")
     }
 }
 
@@ -143,74 +374,150 @@ pub fn check_hash_algorithm(hash: i32, allow_weak_hashes: AllowWeakHashes) -> Re
 #[non_exhaustive]
 pub struct SigInfo {
     /// Hash algorithm
-    pub hash_alg: u8,
+    pub hash_alg: HashAlgorithm,
     /// Public-key algorithm
-    pub pkey_alg: u8,
+    pub pkey_alg: PublicKeyAlgorithm,
     /// Key ID
     pub key_id: [u8; 8],
-    /// Fingerprint
-    pub fingerprint: Option<[u8; 20]>,
+    /// Where [`SigInfo::key_id`] came from
+    pub key_id_source: KeyIdSource,
+    /// The left 16 bits of the signed hash.  These are not protected by the
+    /// signature, but a mismatch means that the signature cannot be valid,
+    /// without any public-key operation.
+    pub hash_prefix: [u8; 2],
+    /// Issuer fingerprint, if any.  Its version always matches that of the
+    /// signature, and the key ID is always that of the fingerprint.
+    pub fingerprint: Option<Fingerprint>,
+    /// Salt, for v6 signatures
+    pub salt: Option<Salt>,
     /// Creation time
     pub creation_time: u32,
     /// Expiration time, if any
     pub expiration_time: Option<u32>,
+    /// Signature version
+    pub version: u8,
+    /// What the signature covers
+    pub kind: SignatureKind,
+    /// The URI of the policy under which the signature was made, if any.
+    /// Only accepted if the [`SubpacketPolicy`] accepts it, and never if it
+    /// is critical.  This is not checked in any way, and must be escaped
+    /// before being displayed.
+    #[cfg(feature = "alloc")]
+    pub policy_uri: Option<alloc::vec::Vec<u8>>,
+    /// The user ID of the signer, if any.  Only accepted if the
+    /// [`SubpacketPolicy`] accepts it.  It is chosen by the signer
+    /// and need not be a user ID of the signing key, so it is only fit for
+    /// display, and must be escaped first.
+    #[cfg(feature = "alloc")]
+    pub signer_user_id: Option<alloc::vec::Vec<u8>>,
 }
 
-struct InternalSigInfo {
+/// Where the key ID of a signature came from.  Only the hashed area and the v3
+/// header are covered by the signature, so an unhashed key ID can be changed
+/// by anyone.  Changing it can only make verification fail, but it can
+/// mislead a user interface that displays it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyIdSource {
+    /// An issuer key ID or issuer fingerprint subpacket in the hashed area
+    Hashed,
+    /// An issuer key ID subpacket in the unhashed area, with no issuer
+    /// fingerprint in the hashed area to check it against
+    Unhashed,
+    /// The header of a v3 signature
+    V3Header,
+}
+
+/// The salt of a v6 signature, which is hashed before the signed data
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Salt {
+    len: u8,
+    bytes: [u8; 32],
+}
+
+impl Salt {
+    /// The salt
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len.into()]
+    }
+}
+
+/// Does the notation name `name` match `pattern`, which may contain one `*`?
+fn notation_matches(pattern: &str, name: &[u8]) -> bool {
+    let pattern = pattern.as_bytes();
+    match pattern.iter().position(|&c| c == b'*') {
+        None => pattern == name,
+        Some(star) => {
+            let (prefix, suffix) = (&pattern[..star], &pattern[star + 1..]);
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        }
+    }
+}
+
+/// Checks the notation data subpacket in `reader` against `allowed`
+fn check_notation(reader: &mut Reader<'_>, allowed: &[&str]) -> Result<(), Error> {
+    // Flags
+    reader.get_bytes(4)?;
+    let name_len = reader.be_u16()?;
+    let value_len = reader.be_u16()?;
+    let name = reader.get_bytes(name_len.into())?;
+    reader.get_bytes(value_len.into())?;
+    if allowed
+        .iter()
+        .any(|&pattern| notation_matches(pattern, name))
+    {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedCriticalSubpacket)
+    }
+}
+
+struct InternalSigInfo<'a> {
+    /// Signature version
+    version: u8,
     /// Signer Key ID
     id: Option<[u8; 8]>,
     /// Fingerprint
-    fpr: Option<[u8; 20]>,
+    fpr: Option<Fingerprint>,
     /// Creation time
     creation_time: Option<u32>,
     /// Expiration time
     expiration_time: Option<u32>,
+    /// Policy URI
+    policy_uri: Option<&'a [u8]>,
+    /// Signer user ID
+    signer_user_id: Option<&'a [u8]>,
 }
 
 fn process_subpacket<'a>(
     reader: &mut Reader<'a>,
     time: u32,
-    tag: u8,
-    id: &mut InternalSigInfo,
+    tag_byte: u8,
+    id: &mut InternalSigInfo<'a>,
+    options: &ParseOptions,
 ) -> Result<(), Error> {
-    match tag {
-        // Subpackets invalid in this context
-        // only valid in self-signature
-        SUBPACKET_KEY_EXPIRATION_TIME |
-        SUBPACKET_PREFERRED_SYMMETRIC |
-        SUBPACKET_PREFERRED_HASH |
-        SUBPACKET_PREFERRED_COMPRESSION |
-        SUBPACKET_KEY_SERVER_PREFERENCES |
-        SUBPACKET_PRIMARY_USER_ID |
-        SUBPACKET_PREFERRED_KEY_SERVERS |
-        SUBPACKET_FEATURES |
-        // only valid on certification
-        SUBPACKET_EXPORTABLE |
-        SUBPACKET_TRUST_SIG |
-        SUBPACKET_REGEX |
-        SUBPACKET_REVOCATION_KEY |
-        // only valid on certifications or self-signatures
-        SUBPACKET_KEY_FLAGS |
-        // only valid on revocations
-        SUBPACKET_REVOCATION_REASON |
-        // not valid on document signatures
-        SUBPACKET_SIGNATURE_TARGET |
-        // only valid in subkey binding signatures
-        SUBPACKET_EMBEDDED_SIGNATURE |
-        // RPM doesn’t handle revocation, so this is pointless.
-        // GPG only generates this for certifications.
-        SUBPACKET_REVOCABLE |
-        // useless, not generated
-        SUBPACKET_PLACEHOLDER => {
+    let (tag, critical) = (tag_byte & 0x7F, tag_byte & 0x80 != 0);
+    match options.subpackets.action(tag) {
+        SubpacketAction::Accept => {}
+        SubpacketAction::SkipIfNotCritical if !critical => {
+            reader.get_bytes(reader.len())?;
+            return Ok(());
+        }
+        _ if INVALID_IN_DOCUMENT_SIGNATURES.contains(&tag) => {
             #[cfg(test)]
             eprintln!("Unsupported packet!");
-            Err(Error::IllFormedSignature)
+            return Err(Error::IllFormedSignature);
         }
+        _ => return Err(Error::UnsupportedCriticalSubpacket),
+    }
+    match tag {
         SUBPACKET_SIG_EXPIRATION_TIME => {
             let timestamp = reader.be_u32()?;
             if time != 0 && timestamp >= time {
                 Err(Error::SignatureExpired)
-            } else if std::mem::replace(&mut id.expiration_time, Some(timestamp)).is_some() {
+            } else if id.expiration_time.replace(timestamp).is_some() {
                 Err(Error::IllFormedSignature)
             } else {
                 Ok(())
@@ -220,7 +527,7 @@ fn process_subpacket<'a>(
             let timestamp = reader.be_u32()?;
             if time != 0 && timestamp < time {
                 Err(Error::SignatureNotValidYet)
-            } else if std::mem::replace(&mut id.creation_time, Some(timestamp)).is_some() {
+            } else if id.creation_time.replace(timestamp).is_some() {
                 Err(Error::IllFormedSignature)
             } else {
                 Ok(())
@@ -234,28 +541,68 @@ fn process_subpacket<'a>(
             Ok(())
         }
         // RPM doesn’t care about this, but we do
-        SUBPACKET_FINGERPRINT => match reader.get_bytes(21)? {
-            &[4, ref fpr @ ..] if id.fpr.is_none() => {
-                id.fpr = Some(fpr.try_into().expect("length is correct; qed"));
+        SUBPACKET_FINGERPRINT => match *reader.get_bytes(reader.len())? {
+            [4, ref fpr @ ..] if id.version == 4 && fpr.len() == 20 && id.fpr.is_none() => {
+                id.fpr = Some(Fingerprint::V4(
+                    fpr.try_into().expect("length is correct; qed"),
+                ));
+                Ok(())
+            }
+            [v @ 5..=6, ref fpr @ ..] if id.version == v && fpr.len() == 32 && id.fpr.is_none() => {
+                let fpr = fpr.try_into().expect("length is correct; qed");
+                id.fpr = Some(if v == 5 {
+                    Fingerprint::V5(fpr)
+                } else {
+                    Fingerprint::V6(fpr)
+                });
                 Ok(())
             }
             _ => Err(Error::IllFormedSignature),
         },
-        // We reject unknown subpackets to make exploits against RPM less likely
-        SUBPACKET_NOTATION |
-        SUBPACKET_POLICY_URI |
-        SUBPACKET_SIGNER_USER_ID | _ => Err(Error::UnsupportedCriticalSubpacket),
+        // Only notations the caller expects
+        SUBPACKET_NOTATION => check_notation(reader, options.subpackets.allowed_notations),
+        // Only recorded, as nothing here can follow the policy, so a critical
+        // policy URI cannot be accepted
+        SUBPACKET_POLICY_URI if !critical => {
+            if id.policy_uri.is_some() {
+                return Err(Error::IllFormedSignature);
+            }
+            id.policy_uri = Some(reader.get_bytes(reader.len())?);
+            Ok(())
+        }
+        SUBPACKET_SIGNER_USER_ID => {
+            if id.signer_user_id.is_some() {
+                return Err(Error::IllFormedSignature);
+            }
+            id.signer_user_id = Some(reader.get_bytes(reader.len())?);
+            Ok(())
+        }
+        SUBPACKET_POLICY_URI => Err(Error::UnsupportedCriticalSubpacket),
+        // Accepted by the policy, but not understood
+        _ => {
+            reader.get_bytes(reader.len())?;
+            Ok(())
+        }
     }
 }
 
 /// Parse a signature from a slice
-pub fn parse<'a>(
-    data: &'a [u8],
+pub fn parse(
+    data: &[u8],
     timestamp: u32,
     allow_weak_hashes: AllowWeakHashes,
+) -> Result<SigInfo, Error> {
+    parse_with_options(data, timestamp, &ParseOptions::new(allow_weak_hashes))
+}
+
+/// Parse a signature from a slice, with the given options
+pub fn parse_with_options(
+    data: &[u8],
+    timestamp: u32,
+    options: &ParseOptions,
 ) -> Result<SigInfo, Error> {
     Reader::read_all(data, Error::TrailingJunk, |reader| {
-        read_signature(reader, timestamp, allow_weak_hashes)
+        read_signature_with_options(reader, timestamp, options)
     })
 }
 
@@ -264,97 +611,236 @@ pub fn read_signature<'a>(
     reader: &mut Reader<'a>,
     timestamp: u32,
     allow_weak_hashes: AllowWeakHashes,
+) -> Result<SigInfo, Error> {
+    read_signature_with_options(reader, timestamp, &ParseOptions::new(allow_weak_hashes))
+}
+
+/// Reads a signature from `reader`, with the given options
+pub fn read_signature_with_options<'a>(
+    reader: &mut Reader<'a>,
+    timestamp: u32,
+    options: &ParseOptions,
 ) -> Result<SigInfo, Error> {
     let packet = packet::next(reader)?.ok_or(Error::PrematureEOF)?;
     if packet.tag() != 2 {
         return Err(Error::IllFormedSignature);
     }
     Reader::read_all(packet.contents(), Error::TrailingJunk, |e| {
-        parse_packet_body(e, timestamp, allow_weak_hashes)
+        parse_packet_body(e, timestamp, options)
     })
 }
 
+/// Reads a signature packet from `r`, with the given options, and returns it
+/// along with the parsed signature.  Exactly one packet is read from `r`, so
+/// more data can follow it.  The packet is buffered internally, and is
+/// rejected without being read if it is larger than
+/// [`Limits::signature_size`](crate::Limits::signature_size).
+#[cfg(feature = "std")]
+pub fn read_signature_from(
+    r: &mut dyn std::io::Read,
+    timestamp: u32,
+    options: &ParseOptions,
+    limits: &crate::Limits,
+) -> std::io::Result<(Vec<u8>, SigInfo)> {
+    let mut packet = vec![0; 1];
+    r.read_exact(&mut packet)?;
+    let mut io_error = None;
+    let header = packet::parse_header(packet[0], &mut || {
+        let mut byte = [0];
+        match r.read_exact(&mut byte) {
+            Ok(()) => {
+                packet.push(byte[0]);
+                Ok(byte[0])
+            }
+            Err(e) => {
+                io_error = Some(e);
+                Err(Error::PrematureEOF)
+            }
+        }
+    });
+    let (tag, len) = match header {
+        Ok(header) => header,
+        Err(e) => return Err(io_error.unwrap_or_else(|| e.into())),
+    };
+    if tag & 0x3F != 2 {
+        return Err(Error::IllFormedSignature.into());
+    }
+    let header_len = packet.len();
+    match header_len.checked_add(len) {
+        Some(total) if total <= limits.signature_size => packet.resize(total, 0),
+        _ => return Err(Error::TooLong.into()),
+    }
+    r.read_exact(&mut packet[header_len..])?;
+    let sig = parse_with_options(&packet, timestamp, options)?;
+    Ok((packet, sig))
+}
+
 fn parse_packet_body<'a>(
     reader: &mut Reader<'a>,
     timestamp: u32,
-    allow_weak_hashes: AllowWeakHashes,
+    options: &ParseOptions,
 ) -> Result<SigInfo, Error> {
     let version = reader.byte()?;
     #[cfg(test)]
     eprintln!("Version is {}", version);
     let pkey_alg;
     let hash_alg;
+    let kind;
     let key_id: [u8; 8];
+    let mut key_id_source;
     let mut siginfo = InternalSigInfo {
+        version,
         id: None,
         fpr: None,
         creation_time: None,
         expiration_time: None,
+        policy_uri: None,
+        signer_user_id: None,
     };
     match version {
         3 => {
-            if reader.byte()? != 5 || reader.byte()? != OPENPGP_SIGNATURE_TYPE_BINARY {
+            if reader.byte()? != 5 {
                 return Err(Error::IllFormedSignature);
             }
+            kind = SignatureKind::check(reader.byte()?, options)?;
             siginfo.creation_time = Some(reader.be_u32()?);
             key_id = u64::to_be_bytes(reader.be_u64()?);
+            key_id_source = KeyIdSource::V3Header;
             // Get the public-key algorithm
             pkey_alg = reader.byte()?;
             hash_alg = reader.byte()?;
         }
-        4 => {
-            // Signature type; we only allow OPENPGP_SIGNATURE_TYPE_BINARY
-            if reader.byte()? != OPENPGP_SIGNATURE_TYPE_BINARY {
-                return Err(Error::IllFormedSignature);
-            }
+        // v5 signatures have the same layout as v4 signatures.  Only the
+        // fingerprint and the hash trailer (which RPM computes) differ.  v6
+        // signatures also have 4-byte subpacket area lengths and a salt.
+        4..=6
+            if version == 4
+                || (version == 5 && options.allow_v5)
+                || (version == 6 && options.allow_v6) =>
+        {
+            // Signature type: binary or, if allowed, text
+            kind = SignatureKind::check(reader.byte()?, options)?;
             pkey_alg = reader.byte()?;
             hash_alg = reader.byte()?;
-            let hashed_subpackets = reader.be_u16()?;
+            let read_len = |reader: &mut Reader<'a>| -> Result<usize, Error> {
+                Ok(match version {
+                    6 => reader.be_u32()? as usize,
+                    _ => reader.be_u16()?.into(),
+                })
+            };
+            let hashed_subpackets = read_len(reader)?;
             Reader::read_all(
-                reader.get_bytes(hashed_subpackets.into())?,
+                reader.get_bytes(hashed_subpackets)?,
                 Error::TrailingJunk,
                 |reader| {
-                    Ok(while !reader.is_empty() {
+                    while !reader.is_empty() {
                         Reader::read_all(
                             get_varlen_bytes(reader)?,
                             Error::TrailingJunk,
                             |reader| {
                                 let tag_byte = reader.byte()?;
-                                process_subpacket(reader, timestamp, tag_byte & 0x7F, &mut siginfo)
+                                process_subpacket(
+                                    reader,
+                                    timestamp,
+                                    tag_byte,
+                                    &mut siginfo,
+                                    options,
+                                )
                             },
                         )?
-                    })
+                    }
+                    Ok(())
                 },
             )?;
             // The only non-hashed subpacket allowed is the key ID, and only if
-            // it has not already been seen.
-            key_id = match siginfo.id {
-                None if reader.get_bytes(4)? == &[0, 10, 9, SUBPACKET_ISSUER_KEYID] => {
-                    reader.get_bytes(8)?.try_into().expect("length correct")
+            // it has not already been seen.  The key ID is part of the
+            // fingerprint, so signatures with one need not include it at all.
+            let unhashed_subpackets = read_len(reader)?;
+            let mut unhashed = reader.get_bytes(unhashed_subpackets)?;
+            // Key signatures may also carry unhashed embedded signatures,
+            // such as the back signature of a signing subkey.  They are
+            // not covered by the signature, so only the key ID is kept.
+            if let SignatureKind::Key(_) = kind {
+                let mut area = Reader::new(unhashed);
+                unhashed = &[];
+                while !area.is_empty() {
+                    let rest = area.as_untrusted_slice();
+                    let subpacket = get_varlen_bytes(&mut area)?;
+                    if unhashed.is_empty() && subpacket.first() == Some(&SUBPACKET_ISSUER_KEYID) {
+                        unhashed = &rest[..rest.len() - area.len()]
+                    }
                 }
-                Some(e) if reader.be_u16()? == 0 => e,
+            }
+            // v4 signatures made by gpg have both a hashed fingerprint and an
+            // unhashed key ID, which is checked against it below
+            let fingerprint_key_id = match siginfo.fpr {
+                Some(Fingerprint::V4(_)) if !unhashed.is_empty() => None,
+                fpr => fpr.map(|fpr| fpr.key_id()),
+            };
+            (key_id, key_id_source) = match (siginfo.id.or(fingerprint_key_id), unhashed) {
+                (None, &[9, SUBPACKET_ISSUER_KEYID, ref id @ ..]) if id.len() == 8 => (
+                    id.try_into().expect("length correct"),
+                    KeyIdSource::Unhashed,
+                ),
+                (Some(e), &[]) => (e, KeyIdSource::Hashed),
                 _ => return Err(Error::IllFormedSignature),
             };
-            if let Some(s) = siginfo.fpr {
-                if s[12..] != key_id[..] {
-                    return Err(Error::IllFormedSignature);
-                }
+            match siginfo.fpr {
+                Some(fpr) if fpr.key_id() != key_id => return Err(Error::IllFormedSignature),
+                // An unhashed key ID must match the hashed fingerprint
+                Some(_) => key_id_source = KeyIdSource::Hashed,
+                // v5 and v6 signatures must identify the key by fingerprint
+                None if version > 4 => return Err(Error::IllFormedSignature),
+                None => {}
             }
         }
+        5 | 6 => return Err(Error::UnsupportedSignatureVersion),
         _ => return Err(Error::IllFormedSignature),
     }
-    let mpis = pkey_alg_mpis(pkey_alg, version)?;
-    check_hash_algorithm(hash_alg.into(), allow_weak_hashes)?;
+    let pkey_alg = PublicKeyAlgorithm::try_from(pkey_alg)?;
+    if pkey_alg.native_signature_len().is_some() && !options.allow_native_eddsa {
+        return Err(Error::UnsupportedPkeyAlgorithm(pkey_alg.into()));
+    }
+    let mpis = pkey_alg.expected_mpis(version)?;
+    let hash_alg = HashAlgorithm::try_from(hash_alg)?;
+    hash_alg.check(options.allow_weak_hashes)?;
+    let hash_len = hash_alg.digest_len();
+    // Ed448 signatures must use a hash of at least 512 bits
+    if pkey_alg == PublicKeyAlgorithm::Ed448 && hash_len < 64 {
+        return Err(Error::IllFormedSignature);
+    }
     // Check the creation time
     let creation_time = match siginfo.creation_time {
         Some(t) => t,
         None => return Err(Error::NoCreationTime),
     };
-    // Ignore first 16 bits of hash
-    reader.get_bytes(2)?;
+    let hash_prefix = [reader.byte()?, reader.byte()?];
+    let salt = if version == 6 {
+        let len = hash_alg
+            .v6_salt_len()
+            .ok_or(Error::UnsupportedHashAlgorithm(u8::from(hash_alg).into()))?;
+        if usize::from(reader.byte()?) != len {
+            return Err(Error::IllFormedSignature);
+        }
+        let mut bytes = [0; 32];
+        bytes[..len].copy_from_slice(reader.get_bytes(len)?);
+        Some(Salt {
+            len: len as u8,
+            bytes,
+        })
+    } else {
+        None
+    };
     // Read the MPIs
     for _ in 0..mpis {
-        read_mpi(reader)?;
+        let mpi = read_mpi(reader)?;
+        // ECDSA signatures with r or s zero are invalid
+        if pkey_alg == PublicKeyAlgorithm::Ecdsa && !(1..=ECDSA_MAX_MPI_LEN).contains(&mpi.len()) {
+            return Err(Error::BadMPI);
+        }
+    }
+    if let Some(len) = pkey_alg.native_signature_len() {
+        reader.get_bytes(len)?;
     }
     Ok(SigInfo {
         hash_alg,
@@ -362,17 +848,362 @@ fn parse_packet_body<'a>(
         creation_time,
         expiration_time: siginfo.expiration_time,
         key_id,
+        key_id_source,
+        hash_prefix,
         fingerprint: siginfo.fpr,
+        salt,
+        version,
+        kind,
+        #[cfg(feature = "alloc")]
+        policy_uri: siginfo.policy_uri.map(<[u8]>::to_vec),
+        #[cfg(feature = "alloc")]
+        signer_user_id: siginfo.signer_user_id.map(<[u8]>::to_vec),
+    })
+}
+
+/// Passes the data that the signature `data` hashes after the signed data to
+/// `cb`, in order.  Hashing the signed data, then this, gives the hash whose
+/// left 16 bits are [`SigInfo::hash_prefix`].  v6 signatures also hash their
+/// [salt](SigInfo::salt) before the signed data.  v5 signatures are not
+/// supported, as their trailer includes metadata about the signed data that
+/// the signature does not contain.  `data` must have been accepted by
+/// [`parse`].
+pub fn hash_trailer(data: &[u8], cb: &mut dyn FnMut(&[u8])) -> Result<(), Error> {
+    let packet = packet::next(&mut Reader::new(data))?.ok_or(Error::PrematureEOF)?;
+    let body = packet.contents();
+    // The length of the version, signature type, algorithms, and hashed
+    // subpackets
+    let len = match body.first() {
+        Some(3) => {
+            cb(body.get(2..7).ok_or(Error::PrematureEOF)?);
+            return Ok(());
+        }
+        Some(4) => {
+            let hashed_len = Reader::new(body.get(4..6).ok_or(Error::PrematureEOF)?).be_u16()?;
+            6 + usize::from(hashed_len)
+        }
+        Some(5) => return Err(Error::UnsupportedSignatureVersion),
+        Some(6) => {
+            let hashed_len = Reader::new(body.get(4..8).ok_or(Error::PrematureEOF)?).be_u32()?;
+            8 + hashed_len as usize
+        }
+        _ => return Err(Error::UnsupportedSignatureVersion),
+    };
+    cb(body.get(..len).ok_or(Error::PrematureEOF)?);
+    cb(&[body[0], 0xFF]);
+    cb(&(len as u32).to_be_bytes());
+    Ok(())
+}
+
+/// Re-serializes the signature packet `data` in canonical form: a new-format
+/// packet header and unhashed subpackets, each with the shortest length
+/// encoding.  Everything the signature covers, including the hashed
+/// subpackets, is copied unchanged, so a valid signature stays valid.  The
+/// signature is not otherwise checked, so parse it first.
+#[cfg(feature = "alloc")]
+pub fn serialize(data: &[u8]) -> Result<alloc::vec::Vec<u8>, Error> {
+    reserialize(data, None)
+}
+
+/// Re-serializes the signature packet `data` as [`serialize`] does, but
+/// with its issuer key ID replaced by `key_id`.  Only a key ID that the
+/// signature does not cover can be replaced: the one in the header of a v3
+/// signature, or an issuer key ID subpacket in the unhashed area of a v4
+/// signature with no issuer in its hashed area.  Otherwise this fails with
+/// [`Error::IllFormedSignature`].  Verifiers that find keys by key ID, such
+/// as RPM, can then try a signature with a wildcard key ID with a specific
+/// key.
+#[cfg(feature = "alloc")]
+pub fn with_issuer_key_id(data: &[u8], key_id: [u8; 8]) -> Result<alloc::vec::Vec<u8>, Error> {
+    reserialize(data, Some(key_id))
+}
+
+#[cfg(feature = "alloc")]
+fn reserialize(data: &[u8], key_id: Option<[u8; 8]>) -> Result<alloc::vec::Vec<u8>, Error> {
+    use crate::subpacket::Subpacket;
+    let packet = Reader::read_all(data, Error::TrailingJunk, |reader| {
+        packet::next(reader)?.ok_or(Error::PrematureEOF)
+    })?;
+    if packet.tag() != 2 {
+        return Err(Error::IllFormedSignature);
+    }
+    let body = packet.contents();
+    let version = match (body.first(), key_id) {
+        (Some(3), None) => return Ok(packet::Packet::new(2, body).serialize()),
+        // Version, hashed length, signature type, and creation time, then
+        // the key ID
+        (Some(3), Some(key_id)) => {
+            let mut body = body.to_vec();
+            body.get_mut(7..15)
+                .ok_or(Error::PrematureEOF)?
+                .copy_from_slice(&key_id);
+            return Ok(packet::Packet::new(2, &body).serialize());
+        }
+        (Some(4), _) => 4,
+        (Some(&version @ 5..=6), None) => version,
+        // v5 and v6 signatures must name their issuer by hashed fingerprint
+        (Some(5..=6), Some(_)) => return Err(Error::IllFormedSignature),
+        _ => return Err(Error::UnsupportedSignatureVersion),
+    };
+    let read_len = |reader: &mut Reader<'_>| -> Result<usize, Error> {
+        Ok(match version {
+            6 => reader.be_u32()? as usize,
+            _ => reader.be_u16()?.into(),
+        })
+    };
+    let parse_area = |area: &[u8]| -> Result<alloc::vec::Vec<Subpacket>, Error> {
+        let mut subpackets = alloc::vec::Vec::new();
+        Reader::read_all(area, Error::TrailingJunk, |reader| {
+            while !reader.is_empty() {
+                let (&tag_byte, contents) = get_varlen_bytes(reader)?
+                    .split_first()
+                    .ok_or(Error::IllFormedSignature)?;
+                subpackets.push(Subpacket::new(
+                    tag_byte & 0x7F,
+                    tag_byte & 0x80 != 0,
+                    contents.to_vec(),
+                ))
+            }
+            Ok(())
+        })?;
+        Ok(subpackets)
+    };
+    // Version, signature type, and algorithms, then the hashed subpackets
+    let mut reader = Reader::new(body);
+    reader.get_bytes(4)?;
+    let hashed_len = read_len(&mut reader)?;
+    let hashed_area = reader.get_bytes(hashed_len)?;
+    let hashed = &body[..body.len() - reader.len()];
+    let unhashed_len = read_len(&mut reader)?;
+    let mut subpackets = parse_area(reader.get_bytes(unhashed_len)?)?;
+    if let Some(key_id) = key_id {
+        let is_key_id = |subpacket: &Subpacket| subpacket.tag() == SUBPACKET_ISSUER_KEYID;
+        let hashed_issuer = parse_area(hashed_area)?
+            .iter()
+            .any(|s| is_key_id(s) || s.tag() == SUBPACKET_FINGERPRINT);
+        if hashed_issuer || subpackets.iter().filter(|s| is_key_id(s)).count() != 1 {
+            return Err(Error::IllFormedSignature);
+        }
+        for subpacket in subpackets.iter_mut().filter(|s| is_key_id(s)) {
+            *subpacket = Subpacket::issuer_key_id(key_id, subpacket.critical())
+        }
+    }
+    let area = crate::subpacket::serialize_area(&subpackets)?;
+    let mut canonical = hashed.to_vec();
+    match version {
+        // serialize_area() gives a two-byte length, but v6 signatures have
+        // four-byte ones
+        6 => {
+            canonical.extend_from_slice(&(area.len() as u32 - 2).to_be_bytes());
+            canonical.extend_from_slice(&area[2..]);
+        }
+        _ => canonical.extend_from_slice(&area),
+    }
+    canonical.extend_from_slice(reader.get_bytes(reader.len())?);
+    Ok(packet::Packet::new(2, &canonical).serialize())
+}
+
+/// A problem found by [`inspect`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Finding {
+    /// A subpacket appears more than once in the same area
+    DuplicateSubpacket {
+        /// The subpacket tag, without the critical bit
+        tag: u8,
+        /// Is the duplicate in the hashed area?
+        hashed: bool,
+    },
+    /// The hashed and unhashed areas contain different issuer key IDs
+    ConflictingKeyIds,
+    /// The issuer fingerprint does not match the issuer key ID
+    KeyIdFingerprintMismatch,
+    /// The signature is malformed; inspection stopped here
+    Malformed(Error),
+}
+
+fn inspect_area<'a>(
+    area: &'a [u8],
+    hashed: bool,
+    key_id: &mut Option<&'a [u8]>,
+    fpr_key_id: &mut Option<&'a [u8]>,
+    cb: &mut dyn FnMut(Finding),
+) -> Result<(), Error> {
+    let mut seen = [false; 128];
+    Reader::read_all(area, Error::TrailingJunk, |reader| {
+        while !reader.is_empty() {
+            let mut subpacket = Reader::new(get_varlen_bytes(reader)?);
+            let tag = subpacket.byte()? & 0x7F;
+            if core::mem::replace(&mut seen[usize::from(tag)], true) {
+                cb(Finding::DuplicateSubpacket { tag, hashed })
+            }
+            let body = subpacket.as_untrusted_slice();
+            match (tag, body.len()) {
+                (SUBPACKET_ISSUER_KEYID, 8) => match *key_id {
+                    Some(k) if k != body => cb(Finding::ConflictingKeyIds),
+                    Some(_) => {}
+                    None => *key_id = Some(body),
+                },
+                // The key ID is the low 64 bits of a v4 fingerprint, and the
+                // high 64 bits of a v5 or v6 one
+                (SUBPACKET_FINGERPRINT, _) if fpr_key_id.is_none() => {
+                    *fpr_key_id = match body {
+                        [4, fpr @ ..] if fpr.len() == 20 => Some(&fpr[12..]),
+                        [5..=6, fpr @ ..] if fpr.len() == 32 => Some(&fpr[..8]),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
     })
 }
 
+/// Inspect a possibly-malicious signature, reporting every duplicate or
+/// conflicting subpacket to `cb`.  Unlike [`parse`], this does not stop at
+/// the first problem, and does not check anything else.  It is intended for
+/// incident response, not for deciding whether a signature is acceptable.
+pub fn inspect(data: &[u8], cb: &mut dyn FnMut(Finding)) {
+    let mut reader = Reader::new(data);
+    let r = (|| -> Result<(), Error> {
+        let packet = packet::next(&mut reader)?.ok_or(Error::PrematureEOF)?;
+        if packet.tag() != 2 {
+            return Err(Error::IllFormedSignature);
+        }
+        let mut reader = Reader::new(packet.contents());
+        let version = reader.byte()?;
+        match version {
+            // v3 signatures have no subpackets
+            3 => return Ok(()),
+            4..=6 => {}
+            _ => return Err(Error::UnsupportedSignatureVersion),
+        }
+        let read_len = |reader: &mut Reader<'_>| -> Result<usize, Error> {
+            Ok(match version {
+                6 => reader.be_u32()? as usize,
+                _ => reader.be_u16()?.into(),
+            })
+        };
+        reader.get_bytes(3)?;
+        let (mut key_id, mut fpr_key_id) = (None, None);
+        let hashed_len = read_len(&mut reader)?;
+        let hashed = reader.get_bytes(hashed_len)?;
+        inspect_area(hashed, true, &mut key_id, &mut fpr_key_id, cb)?;
+        let unhashed_len = read_len(&mut reader)?;
+        let unhashed = reader.get_bytes(unhashed_len)?;
+        inspect_area(unhashed, false, &mut key_id, &mut fpr_key_id, cb)?;
+        match (key_id, fpr_key_id) {
+            (Some(k), Some(f)) if k != f => cb(Finding::KeyIdFingerprintMismatch),
+            _ => {}
+        }
+        Ok(())
+    })();
+    if let Err(e) = r {
+        cb(Finding::Malformed(e))
+    }
+}
+
+/// A subpacket yielded by [`Subpackets`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RawSubpacket<'a> {
+    /// Is the subpacket in the hashed area?
+    pub hashed: bool,
+    /// The tag, without the critical bit
+    pub tag: u8,
+    /// Is the critical bit set?
+    pub critical: bool,
+    /// The body, not including the length or tag
+    pub body: &'a [u8],
+}
+
+/// An iterator over the subpackets of a signature, first those in the hashed
+/// area and then those in the unhashed area.  Created by [`subpackets`].  A
+/// malformed subpacket yields [`Err`] and ends the iteration.
+#[derive(Clone, Debug, Default)]
+pub struct Subpackets<'a> {
+    hashed: &'a [u8],
+    unhashed: &'a [u8],
+}
+
+impl<'a> Iterator for Subpackets<'a> {
+    type Item = Result<RawSubpacket<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hashed = !self.hashed.is_empty();
+        let area = if hashed {
+            &mut self.hashed
+        } else {
+            &mut self.unhashed
+        };
+        if area.is_empty() {
+            return None;
+        }
+        let mut reader = Reader::new(core::mem::take(area));
+        let r = get_varlen_bytes(&mut reader).and_then(|subpacket| {
+            let (&tag_byte, body) = subpacket.split_first().ok_or(Error::PrematureEOF)?;
+            Ok(RawSubpacket {
+                hashed,
+                tag: tag_byte & 0x7F,
+                critical: tag_byte & 0x80 != 0,
+                body,
+            })
+        });
+        match r {
+            Ok(_) => *area = reader.as_untrusted_slice(),
+            Err(_) => *self = Subpackets::default(),
+        }
+        Some(r)
+    }
+}
+
+/// Iterate over the subpackets of the signature packet in `data`, without
+/// otherwise checking the signature.  Version 3 signatures have no
+/// subpackets.  Fails if `data` does not start with a signature packet, or if
+/// the packet is too short to hold its subpacket areas.
+///
+/// The subpackets are not checked at all.  Only those in the hashed area are
+/// protected by the signature, and only if the signature is valid.
+pub fn subpackets<'a>(data: &'a [u8]) -> Result<Subpackets<'a>, Error> {
+    let mut reader = Reader::new(data);
+    let packet = packet::next(&mut reader)?.ok_or(Error::PrematureEOF)?;
+    if packet.tag() != 2 {
+        return Err(Error::IllFormedSignature);
+    }
+    let mut reader = Reader::new(packet.contents());
+    let version = reader.byte()?;
+    let read_area = |reader: &mut Reader<'a>| -> Result<&'a [u8], Error> {
+        let len = match version {
+            6 => reader.be_u32()? as usize,
+            _ => reader.be_u16()?.into(),
+        };
+        Ok(reader.get_bytes(len)?)
+    };
+    match version {
+        3 => Ok(Subpackets::default()),
+        4..=6 => {
+            // Signature type, public-key algorithm, and hash algorithm
+            reader.get_bytes(3)?;
+            let hashed = read_area(&mut reader)?;
+            let unhashed = read_area(&mut reader)?;
+            Ok(Subpackets { hashed, unhashed })
+        }
+        _ => Err(Error::UnsupportedSignatureVersion),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "alloc")]
+    use crate::subpacket::{serialize_area, Subpacket};
+    use proptest::prelude::*;
+
     #[test]
     fn parses_real_world_sig() {
-        static EDDSA_SIG: &'static [u8] = include_bytes!("../../eddsa.asc");
-        static TRAILING_JUNK: &'static [u8] = include_bytes!("../../trailing-junk.asc");
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        static TRAILING_JUNK: &[u8] = include_bytes!("../../trailing-junk.asc");
         assert_eq!(TRAILING_JUNK.len(), EDDSA_SIG.len() + 1);
         assert_eq!(
             Reader::read_all(TRAILING_JUNK, Error::TrailingJunk, |r| read_signature(
@@ -396,6 +1227,749 @@ mod tests {
         let sig = read_signature(&mut Reader::new(EDDSA_SIG), 0, AllowWeakHashes::No).unwrap();
         assert_eq!(u64::from_be_bytes(sig.key_id), 0x28A45C93B0B5B6E0);
         assert_eq!(sig.creation_time, 1611626266);
-        assert_eq!(sig.fingerprint.unwrap()[12..], sig.key_id[..]);
+        assert_eq!(sig.fingerprint.unwrap().key_id(), sig.key_id);
+        assert_eq!(sig.key_id_source, KeyIdSource::Hashed);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn serializes_canonically() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        static ECDSA_SIG: &[u8] = include_bytes!("../../ecdsa.asc");
+        for &sig in &[EDDSA_SIG, ECDSA_SIG] {
+            let canonical = serialize(sig).unwrap();
+            assert_eq!(canonical[0], 0xC2);
+            assert_eq!(canonical[2..], sig[2..]);
+            assert_eq!(serialize(&canonical).unwrap(), canonical);
+            let mut old = vec![];
+            hash_trailer(sig, &mut |data| old.extend_from_slice(data)).unwrap();
+            let mut new = vec![];
+            hash_trailer(&canonical, &mut |data| new.extend_from_slice(data)).unwrap();
+            assert_eq!(old, new);
+        }
+        // A four-byte packet length and a five-byte unhashed subpacket length
+        let (header, body) = EDDSA_SIG.split_at(2);
+        assert_eq!(header, [0x88, body.len() as u8]);
+        let hashed_len = 6 + usize::from(u16::from_be_bytes([body[4], body[5]]));
+        let (hashed, rest) = body.split_at(hashed_len);
+        let (unhashed, rest) = rest[2..].split_at(usize::from(rest[1]));
+        assert_eq!(unhashed[..2], [9, SUBPACKET_ISSUER_KEYID]);
+        let long_body = [hashed, &[0, 14, 255, 0, 0, 0, 9], &unhashed[1..], rest].concat();
+        let mut long = vec![0x8A];
+        long.extend_from_slice(&(long_body.len() as u32).to_be_bytes());
+        long.extend_from_slice(&long_body);
+        assert_eq!(
+            serialize(&long).unwrap(),
+            [&[0xC2, body.len() as u8][..], body].concat()
+        );
+        assert_eq!(
+            serialize(&[EDDSA_SIG, &[0]].concat()),
+            Err(Error::TrailingJunk)
+        );
+        assert_eq!(
+            serialize(&[0x88, 1, 7]),
+            Err(Error::UnsupportedSignatureVersion)
+        );
+    }
+
+    /// An unhashed subpacket, and whether to encode its length with five
+    /// bytes even if fewer would do
+    #[cfg(feature = "alloc")]
+    fn unhashed_subpacket() -> impl Strategy<Value = (Subpacket, bool)> {
+        let len = prop::sample::select(&[0, 190, 191, 300][..]);
+        (len, 0..3usize)
+            .prop_flat_map(|(len, extra)| {
+                let body = prop::collection::vec(any::<u8>(), len + extra);
+                (0..0x80u8, any::<bool>(), body, any::<bool>())
+            })
+            .prop_map(|(tag, critical, body, long)| (Subpacket::new(tag, critical, body), long))
+    }
+
+    proptest! {
+        #[cfg(feature = "alloc")]
+        #[test]
+        fn serialize_roundtrip(
+            unhashed_subpackets in prop::collection::vec(unhashed_subpacket(), 0..6),
+            sig_type: u8,
+            hashed_area in prop::collection::vec(any::<u8>(), 0..32),
+            rest in prop::collection::vec(any::<u8>(), 2..258),
+        ) {
+            let mut subpackets = vec![];
+            let mut unhashed = vec![];
+            for (subpacket, long) in unhashed_subpackets {
+                // Unhashed subpackets may use longer length encodings than
+                // needed, which serialize() must replace
+                let mut encoded = vec![];
+                subpacket.serialize_into(&mut encoded);
+                if long {
+                    let len = subpacket.body().len() + 1;
+                    let shortest = match len {
+                        0..=191 => 1,
+                        192..=8383 => 2,
+                        _ => 5,
+                    };
+                    let mut long = vec![0xFF];
+                    long.extend_from_slice(&(len as u32).to_be_bytes());
+                    encoded.splice(..shortest, long);
+                }
+                unhashed.extend_from_slice(&encoded);
+                subpackets.push(subpacket)
+            }
+            let mut hashed = vec![4, sig_type, 22, 8];
+            hashed.extend_from_slice(&(hashed_area.len() as u16).to_be_bytes());
+            hashed.extend_from_slice(&hashed_area);
+            let body = [
+                &hashed[..],
+                &(unhashed.len() as u16).to_be_bytes(),
+                &unhashed,
+                &rest,
+            ]
+            .concat();
+            // An old-format packet with a four-byte length
+            let mut packet = vec![0x8A];
+            packet.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            packet.extend_from_slice(&body);
+            let canonical = serialize(&packet).unwrap();
+            let expected = [&hashed[..], &serialize_area(&subpackets).unwrap(), &rest].concat();
+            prop_assert_eq!(&canonical, &packet::Packet::new(2, &expected).serialize());
+            prop_assert_eq!(serialize(&canonical).unwrap(), canonical);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn replaces_unhashed_key_ids() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let hashed = serialize_area(&[Subpacket::creation_time(1611626266, false)]).unwrap();
+        let unhashed = serialize_area(&[Subpacket::issuer_key_id([0; 8], false)]).unwrap();
+        let body = [&[4, 0, 22, 8][..], &hashed, &unhashed, EDDSA_TAIL].concat();
+        let packet = packet::Packet::new(2, &body).serialize();
+        let wildcard = parse(&packet, 0, AllowWeakHashes::No).unwrap();
+        assert_eq!(wildcard.key_id, [0; 8]);
+        let named = with_issuer_key_id(&packet, [0x55; 8]).unwrap();
+        let sig = parse(&named, 0, AllowWeakHashes::No).unwrap();
+        assert_eq!(
+            (sig.key_id, sig.key_id_source),
+            ([0x55; 8], KeyIdSource::Unhashed)
+        );
+        // Only the unhashed area changed
+        let trailer = |packet: &[u8]| {
+            let mut trailer = vec![];
+            hash_trailer(packet, &mut |data| trailer.extend_from_slice(data)).unwrap();
+            trailer
+        };
+        assert_eq!(trailer(&named), trailer(&packet));
+        // v3 signatures have the key ID in their unhashed header
+        let v3 = [
+            &[0x88, 22, 3, 5, 0, 0x60, 0, 0, 0][..],
+            &[0; 8],
+            &[1, 8, 0xab, 0xcd, 0, 8, 0xff],
+        ]
+        .concat();
+        let sig = parse(
+            &with_issuer_key_id(&v3, [0x55; 8]).unwrap(),
+            0,
+            AllowWeakHashes::No,
+        );
+        assert_eq!(sig.unwrap().key_id, [0x55; 8]);
+        // A hashed issuer cannot be replaced
+        assert_eq!(
+            with_issuer_key_id(EDDSA_SIG, [0x55; 8]),
+            Err(Error::IllFormedSignature)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reads_signatures_from_streams() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        static TRAILING_JUNK: &[u8] = include_bytes!("../../trailing-junk.asc");
+        let options = ParseOptions::new(AllowWeakHashes::No);
+        let mut limits = crate::Limits::default();
+        let mut stream = TRAILING_JUNK;
+        let (packet, sig) = read_signature_from(&mut stream, 0, &options, &limits).unwrap();
+        assert_eq!(packet, EDDSA_SIG);
+        assert_eq!(u64::from_be_bytes(sig.key_id), 0x28A45C93B0B5B6E0);
+        assert_eq!(sig.creation_time, 1611626266);
+        assert_eq!(stream, &TRAILING_JUNK[EDDSA_SIG.len()..]);
+        let err = read_signature_from(&mut &EDDSA_SIG[..EDDSA_SIG.len() - 1], 0, &options, &limits)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        limits.signature_size = EDDSA_SIG.len() - 1;
+        let mut stream = EDDSA_SIG;
+        let err = read_signature_from(&mut stream, 0, &options, &limits).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let inner = err.get_ref().unwrap().downcast_ref::<Error>();
+        assert_eq!(inner, Some(&Error::TooLong));
+        assert_eq!(inner.unwrap().category(), crate::ErrorCategory::Malformed);
+        // The packet body was not read
+        assert_eq!(stream.len(), EDDSA_SIG.len() - 2);
+    }
+
+    #[test]
+    fn parses_ecdsa_sig() {
+        // Made by the P-256 subkey of test-key.pgp
+        static ECDSA_SIG: &[u8] = include_bytes!("../../ecdsa.asc");
+        let sig = parse(ECDSA_SIG, 0, AllowWeakHashes::No).unwrap();
+        assert_eq!(
+            (sig.pkey_alg, sig.hash_alg),
+            (PublicKeyAlgorithm::Ecdsa, HashAlgorithm::Sha256)
+        );
+        assert_eq!(u64::from_be_bytes(sig.key_id), 0x07D349FC56248DE4);
+        // The signed data is "hello\n", and SHA-256 of it followed by the
+        // trailer starts with these bytes
+        assert_eq!(sig.hash_prefix, [0xE5, 0x13]);
+        let mut trailer = vec![];
+        hash_trailer(ECDSA_SIG, &mut |data| trailer.extend_from_slice(data)).unwrap();
+        assert_eq!(
+            trailer,
+            [&ECDSA_SIG[2..37], &[4, 0xFF, 0, 0, 0, 35]].concat()
+        );
+        assert_eq!(
+            pkey_alg_mpis(19, 3),
+            Err(Error::PkeyAlgorithmRequiresV4Sig(19))
+        );
+        // Replace r with a zero MPI, then with one too long for any NIST curve
+        let (head, mpis) = ECDSA_SIG.split_at(51);
+        let (r, s) = mpis.split_at(34);
+        assert_eq!(r[..2], [1, 0]);
+        let with_r = |r: &[u8]| {
+            let mut packet = [head, r, s].concat();
+            packet[1] = (packet.len() - 2) as u8;
+            parse(&packet, 0, AllowWeakHashes::No)
+        };
+        assert!(with_r(r).is_ok());
+        assert_eq!(with_r(&[0, 0]).unwrap_err(), Error::BadMPI);
+        let long = [&[2, 0x10, 0x80][..], &[0; 66]].concat();
+        assert_eq!(with_r(&long).unwrap_err(), Error::BadMPI);
+    }
+
+    #[test]
+    fn parses_ed448_sig() {
+        let ed448_sig = |hash_alg| {
+            let mut body = vec![4, 0, 28, hash_alg, 0, 16, 5, 2, 0x60, 0, 0, 0, 9, 16];
+            body.extend_from_slice(&[1; 8]);
+            body.extend_from_slice(&[0, 0, 0xAB, 0xCD]);
+            body.resize(body.len() + 114, 0xEF);
+            [&[0x88, body.len() as u8][..], &body].concat()
+        };
+        assert_eq!(
+            parse(&ed448_sig(10), 0, AllowWeakHashes::No).unwrap_err(),
+            Error::UnsupportedPkeyAlgorithm(28)
+        );
+        let options = ParseOptions {
+            allow_native_eddsa: true,
+            ..ParseOptions::new(AllowWeakHashes::No)
+        };
+        let sig = parse_with_options(&ed448_sig(10), 0, &options).unwrap();
+        assert_eq!(
+            (sig.pkey_alg, sig.hash_alg),
+            (PublicKeyAlgorithm::Ed448, HashAlgorithm::Sha512)
+        );
+        let mut short = ed448_sig(10);
+        short[1] -= 1;
+        short.pop();
+        assert_eq!(
+            parse_with_options(&short, 0, &options).unwrap_err(),
+            Error::PrematureEOF
+        );
+        // SHA-256 is too short for Ed448
+        assert_eq!(
+            parse_with_options(&ed448_sig(8), 0, &options).unwrap_err(),
+            Error::IllFormedSignature
+        );
+        assert_eq!(
+            pkey_alg_mpis(28, 3),
+            Err(Error::PkeyAlgorithmRequiresV4Sig(28))
+        );
+    }
+
+    #[test]
+    fn reports_key_id_source() {
+        let id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let time = [0x60, 0, 0, 0];
+        // Two one-bit MPIs, for EdDSA
+        let trailer = [0xAB, 0xCD, 0, 1, 1, 0, 1, 1];
+        let parse_body = |body: &[&[u8]]| {
+            let body = body.concat();
+            let packet = [&[0x88, body.len() as u8][..], &body].concat();
+            parse(&packet, 0, AllowWeakHashes::No).unwrap()
+        };
+        let v4 = |hashed: &[u8], unhashed: &[u8]| {
+            parse_body(&[
+                &[4, 0, 22, 8, 0, hashed.len() as u8 + 6, 5, 2],
+                &time,
+                hashed,
+                &[0, unhashed.len() as u8],
+                unhashed,
+                &trailer,
+            ])
+        };
+        let issuer = [&[9, SUBPACKET_ISSUER_KEYID][..], &id].concat();
+        let hashed = v4(&issuer, &[]);
+        assert_eq!(
+            (hashed.key_id, hashed.key_id_source),
+            (id, KeyIdSource::Hashed)
+        );
+        let unhashed = v4(&[], &issuer);
+        assert_eq!(
+            (unhashed.key_id, unhashed.key_id_source),
+            (id, KeyIdSource::Unhashed)
+        );
+        // EdDSA requires v4 signatures, so use RSA
+        let v3 = parse_body(&[&[3, 5, 0], &time, &id, &[1, 8], &trailer[..5]]);
+        assert_eq!((v3.key_id, v3.key_id_source), (id, KeyIdSource::V3Header));
+    }
+
+    #[test]
+    fn accepts_text_signatures_on_request() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let mut text = EDDSA_SIG.to_vec();
+        text[3] = OPENPGP_SIGNATURE_TYPE_TEXT;
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        assert_eq!(
+            parse_with_options(EDDSA_SIG, 0, &options).unwrap().kind,
+            SignatureKind::Binary
+        );
+        assert_eq!(
+            parse_with_options(&text, 0, &options).unwrap_err(),
+            Error::IllFormedSignature
+        );
+        options.kind = Some(SignatureKind::Text);
+        assert_eq!(
+            parse_with_options(&text, 0, &options).unwrap().kind,
+            SignatureKind::Text
+        );
+        assert!(parse_with_options(EDDSA_SIG, 0, &options).is_err());
+        options.kind = None;
+        assert!(parse_with_options(EDDSA_SIG, 0, &options).is_ok());
+        assert!(parse_with_options(&text, 0, &options).is_ok());
+        // Other signature types are never accepted
+        text[3] = 0x10;
+        assert!(parse_with_options(&text, 0, &options).is_err());
+    }
+
+    #[test]
+    fn accepts_standalone_and_timestamp_signatures_on_request() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let mut standalone = EDDSA_SIG.to_vec();
+        standalone[3] = OPENPGP_SIGNATURE_TYPE_STANDALONE;
+        let mut timestamp = EDDSA_SIG.to_vec();
+        timestamp[3] = OPENPGP_SIGNATURE_TYPE_TIMESTAMP;
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        options.kind = None;
+        for sig in &[&standalone, &timestamp] {
+            assert_eq!(
+                parse_with_options(sig, 0, &options).unwrap_err(),
+                Error::IllFormedSignature
+            );
+        }
+        // Asking for the kind is not enough
+        options.kind = Some(SignatureKind::Standalone);
+        assert!(parse_with_options(&standalone, 0, &options).is_err());
+        options.allow_standalone = true;
+        assert_eq!(
+            parse_with_options(&standalone, 0, &options).unwrap().kind,
+            SignatureKind::Standalone
+        );
+        assert!(parse_with_options(&timestamp, 0, &options).is_err());
+        options.kind = None;
+        assert!(parse_with_options(&timestamp, 0, &options).is_err());
+        options.allow_timestamp = true;
+        assert_eq!(
+            parse_with_options(&timestamp, 0, &options).unwrap().kind,
+            SignatureKind::Timestamp
+        );
+        assert!(parse_with_options(EDDSA_SIG, 0, &options).is_ok());
+        let mut certification = EDDSA_SIG.to_vec();
+        certification[3] = 0x13;
+        assert!(parse_with_options(&certification, 0, &options).is_err());
+        options.allow_key_signatures = true;
+        assert_eq!(
+            parse_with_options(&certification, 0, &options)
+                .unwrap()
+                .kind,
+            SignatureKind::Key(0x13)
+        );
+    }
+
+    #[test]
+    fn checks_hash_algorithms() {
+        for &weak in &[AllowWeakHashes::No, AllowWeakHashes::Yes] {
+            assert_eq!(check_hash_algorithm(8, weak), Ok(32));
+            assert_eq!(check_hash_algorithm(12, weak), Ok(32));
+            assert_eq!(check_hash_algorithm(14, weak), Ok(64));
+            // Reserved, and out of range
+            for &hash in &[4, 13, 256, -1] {
+                assert_eq!(
+                    check_hash_algorithm(hash, weak),
+                    Err(Error::UnsupportedHashAlgorithm(hash))
+                );
+            }
+            assert_eq!(
+                check_hash_algorithm(3, weak),
+                Err(Error::InsecureAlgorithm(3))
+            );
+        }
+        assert_eq!(check_hash_algorithm(11, AllowWeakHashes::Yes), Ok(28));
+        assert_eq!(
+            check_hash_algorithm(2, AllowWeakHashes::No),
+            Err(Error::InsecureAlgorithm(2))
+        );
+    }
+
+    #[test]
+    fn inspects_signatures() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let mut findings = vec![];
+        inspect(EDDSA_SIG, &mut |f| findings.push(f));
+        assert!(findings.is_empty());
+        // Duplicate the issuer key ID subpacket into the hashed area
+        let mut sig = EDDSA_SIG.to_vec();
+        let issuer = [9, SUBPACKET_ISSUER_KEYID, 0, 0, 0, 0, 0, 0, 0, 0];
+        sig.splice(8..8, issuer.iter().copied());
+        sig.splice(8..8, issuer.iter().copied());
+        sig[1] += 20;
+        sig[7] += 20;
+        inspect(&sig, &mut |f| findings.push(f));
+        assert_eq!(
+            findings,
+            [
+                Finding::DuplicateSubpacket {
+                    tag: SUBPACKET_ISSUER_KEYID,
+                    hashed: true
+                },
+                Finding::ConflictingKeyIds,
+                Finding::KeyIdFingerprintMismatch,
+            ]
+        );
+        findings.clear();
+        inspect(&sig[..50], &mut |f| findings.push(f));
+        assert_eq!(findings, [Finding::Malformed(Error::PrematureEOF)]);
+    }
+
+    #[test]
+    fn iterates_over_subpackets() {
+        static EDDSA_SIG: &[u8] = include_bytes!("../../eddsa.asc");
+        let all: Result<Vec<_>, _> = subpackets(EDDSA_SIG).unwrap().collect();
+        let all = all.unwrap();
+        let sig = parse(EDDSA_SIG, 0, AllowWeakHashes::No).unwrap();
+        let creation_time = all
+            .iter()
+            .find(|s| s.hashed && s.tag == SUBPACKET_CREATION_TIME)
+            .unwrap();
+        assert!(!creation_time.critical);
+        assert_eq!(creation_time.body, sig.creation_time.to_be_bytes());
+        // The hashed area comes first
+        assert!(all.windows(2).all(|w| w[0].hashed || !w[1].hashed));
+        let area = [5, SUBPACKET_CREATION_TIME, 0, 0, 0, 0, 0x40];
+        let mut sig = vec![0xC2, 4 + 2 + area.len() as u8 + 2, 4, 0, 22, 8, 0];
+        sig.push(area.len() as u8);
+        sig.extend_from_slice(&area);
+        sig.extend_from_slice(&[0, 0]);
+        let mut iter = subpackets(&sig).unwrap();
+        assert_eq!(
+            iter.next(),
+            Some(Ok(RawSubpacket {
+                hashed: true,
+                tag: SUBPACKET_CREATION_TIME,
+                critical: false,
+                body: &[0; 4],
+            }))
+        );
+        // A subpacket whose length runs past the end of the area
+        assert!(matches!(iter.next(), Some(Err(_))));
+        assert_eq!(iter.next(), None);
+        assert_eq!(
+            subpackets(&[0xC2, 1, 7]).unwrap_err(),
+            Error::UnsupportedSignatureVersion
+        );
+    }
+
+    /// The hash prefix and two 8-bit MPIs of a legacy EdDSA signature
+    #[cfg(feature = "alloc")]
+    const EDDSA_TAIL: &[u8] = &[0xab, 0xcd, 0, 8, 0xff, 0, 8, 0xff];
+
+    /// A SHA-256 signature packet of the given version and public-key
+    /// algorithm, with `hashed` as its hashed subpackets, no unhashed
+    /// subpackets, and `tail` after the subpacket areas
+    #[cfg(feature = "alloc")]
+    fn build_signature(version: u8, pkey_alg: u8, hashed: &[Subpacket], tail: &[u8]) -> Vec<u8> {
+        let area = serialize_area(hashed).unwrap();
+        let mut body = vec![version, 0, pkey_alg, 8];
+        // v6 signatures have 4-byte area lengths
+        if version == 6 {
+            body.extend_from_slice(&(area.len() as u32 - 2).to_be_bytes());
+            body.extend_from_slice(&area[2..]);
+            body.extend_from_slice(&[0; 4]);
+        } else {
+            body.extend_from_slice(&area);
+            body.extend_from_slice(&[0; 2]);
+        }
+        body.extend_from_slice(tail);
+        let mut packet = vec![0xC2, body.len() as u8];
+        packet.extend(body);
+        packet
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parses_v5_signature() {
+        let fpr = [0x55; 32];
+        let hashed = [
+            Subpacket::issuer_fingerprint(Fingerprint::V5(fpr), false),
+            Subpacket::creation_time(1611626266, false),
+        ];
+        let mut packet = build_signature(5, 22, &hashed, EDDSA_TAIL);
+        assert_eq!(
+            parse(&packet, 0, AllowWeakHashes::No).unwrap_err(),
+            Error::UnsupportedSignatureVersion
+        );
+        // Allowing v6 signatures does not allow v5 ones
+        let v6_only = ParseOptions {
+            allow_v6: true,
+            ..ParseOptions::new(AllowWeakHashes::No)
+        };
+        assert_eq!(
+            parse_with_options(&packet, 0, &v6_only).unwrap_err(),
+            Error::UnsupportedSignatureVersion
+        );
+        let options = ParseOptions {
+            allow_v5: true,
+            ..ParseOptions::new(AllowWeakHashes::No)
+        };
+        let sig = parse_with_options(&packet, 0, &options).unwrap();
+        assert_eq!(sig.version, 5);
+        assert_eq!(sig.key_id, [0x55; 8]);
+        assert_eq!(sig.fingerprint, Some(Fingerprint::V5(fpr)));
+        let mut findings = vec![];
+        inspect(&packet, &mut |f| findings.push(f));
+        assert_eq!(findings, []);
+        // The trailer depends on the signed data
+        assert_eq!(
+            hash_trailer(&packet, &mut |_| {}),
+            Err(Error::UnsupportedSignatureVersion)
+        );
+        // A v4 fingerprint is not valid in a v5 signature
+        packet[10] = 4;
+        assert_eq!(
+            parse_with_options(&packet, 0, &options).unwrap_err(),
+            Error::IllFormedSignature
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parses_v6_signature() {
+        let fpr = [0x66; 32];
+        let hashed = [
+            Subpacket::issuer_fingerprint(Fingerprint::V6(fpr), false),
+            Subpacket::creation_time(1611626266, false),
+        ];
+        // Ed25519: the hash prefix, the salt, and the native signature
+        let tail = [&[0xab, 0xcd, 16][..], &[0x5A; 16 + 64]].concat();
+        let mut packet = build_signature(6, 27, &hashed, &tail);
+        assert_eq!(
+            parse(&packet, 0, AllowWeakHashes::No).unwrap_err(),
+            Error::UnsupportedSignatureVersion
+        );
+        let mut options = ParseOptions {
+            allow_v6: true,
+            ..ParseOptions::new(AllowWeakHashes::No)
+        };
+        assert_eq!(
+            parse_with_options(&packet, 0, &options).unwrap_err(),
+            Error::UnsupportedPkeyAlgorithm(27)
+        );
+        options.allow_native_eddsa = true;
+        let sig = parse_with_options(&packet, 0, &options).unwrap();
+        assert_eq!(sig.version, 6);
+        assert_eq!(sig.key_id, [0x66; 8]);
+        assert_eq!(sig.fingerprint, Some(Fingerprint::V6(fpr)));
+        assert_eq!(sig.salt.unwrap().as_bytes(), &[0x5A; 16][..]);
+        // The areas have four-byte lengths
+        assert_eq!(serialize(&packet).unwrap(), packet);
+        let mut findings = vec![];
+        inspect(&packet, &mut |f| findings.push(f));
+        assert_eq!(findings, []);
+        let mut trailer = vec![];
+        hash_trailer(&packet, &mut |data| trailer.extend_from_slice(data)).unwrap();
+        let hashed_len = packet.len() - 2 - 4 - 3 - 16 - 64;
+        let expected = [
+            &packet[2..][..hashed_len],
+            &[6, 0xFF, 0, 0, 0, hashed_len as u8],
+        ]
+        .concat();
+        assert_eq!(trailer, expected);
+        // The salt length must match the hash algorithm
+        let salt_len = packet.len() - 64 - 16 - 1;
+        packet[salt_len] = 32;
+        assert_eq!(
+            parse_with_options(&packet, 0, &options).unwrap_err(),
+            Error::IllFormedSignature
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn gates_native_eddsa() {
+        // A v4 Ed25519 signature
+        let hashed = [
+            Subpacket::issuer_key_id([0x55; 8], false),
+            Subpacket::creation_time(1611626266, false),
+        ];
+        let tail = [&[0xab, 0xcd][..], &[0x5A; 64]].concat();
+        let packet = build_signature(4, 27, &hashed, &tail);
+        assert_eq!(
+            parse(&packet, 0, AllowWeakHashes::No).unwrap_err(),
+            Error::UnsupportedPkeyAlgorithm(27)
+        );
+        let options = ParseOptions {
+            allow_native_eddsa: true,
+            ..ParseOptions::new(AllowWeakHashes::No)
+        };
+        assert_eq!(
+            parse_with_options(&packet, 0, &options).unwrap().pkey_alg,
+            PublicKeyAlgorithm::Ed25519
+        );
+    }
+
+    /// A v4 EdDSA signature with an issuer key ID, a creation time, and
+    /// `extra`, all in the hashed area
+    #[cfg(feature = "alloc")]
+    fn signature_with(extra: Subpacket) -> Vec<u8> {
+        let hashed = [
+            Subpacket::issuer_key_id([0x55; 8], false),
+            Subpacket::creation_time(1611626266, false),
+            extra,
+        ];
+        build_signature(4, 22, &hashed, EDDSA_TAIL)
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn accepts_allowed_notations() {
+        let sig = |name: &[u8], critical: bool| {
+            signature_with(Subpacket::notation(name, b"value", true, critical).unwrap())
+        };
+        let sequoia = sig(b"rpm-sequoia@rpm.org", true);
+        let other = sig(b"other@example.com", false);
+        // The names need not be static, so they can come from configuration
+        let configured = ["other@".to_owned() + "example.com"];
+        let configured: Vec<&str> = configured.iter().map(|i| &**i).collect();
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        for packet in &[&sequoia, &other] {
+            assert_eq!(
+                parse_with_options(packet, 0, &options).unwrap_err(),
+                Error::UnsupportedCriticalSubpacket
+            );
+        }
+        options.subpackets.allowed_notations = &["rpm-*@rpm.org"];
+        assert!(parse_with_options(&sequoia, 0, &options).is_ok());
+        assert_eq!(
+            parse_with_options(&other, 0, &options).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+        // The `*` may match nothing, but the rest must match
+        assert!(parse_with_options(&sig(b"rpm-@rpm.org", false), 0, &options).is_ok());
+        assert!(parse_with_options(&sig(b"rpm@rpm.org", false), 0, &options).is_err());
+        options.subpackets.allowed_notations = &configured;
+        assert!(parse_with_options(&other, 0, &options).is_ok());
+        assert!(parse_with_options(&sequoia, 0, &options).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn tolerates_non_critical_policy_uris() {
+        let uri = b"https://example.com/policy".to_vec();
+        let sig = signature_with(Subpacket::new(SUBPACKET_POLICY_URI, false, uri.clone()));
+        let critical = signature_with(Subpacket::new(SUBPACKET_POLICY_URI, true, uri.clone()));
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        assert_eq!(
+            parse_with_options(&sig, 0, &options).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+        options.subpackets = options
+            .subpackets
+            .with(SUBPACKET_POLICY_URI, SubpacketAction::Accept);
+        assert_eq!(
+            parse_with_options(&sig, 0, &options).unwrap().policy_uri,
+            Some(uri)
+        );
+        assert_eq!(
+            parse_with_options(&critical, 0, &options).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn captures_signer_user_id() {
+        let user_id = b"Packager <packager@example.com>".to_vec();
+        let sig = signature_with(Subpacket::new(
+            SUBPACKET_SIGNER_USER_ID,
+            false,
+            user_id.clone(),
+        ));
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        assert_eq!(
+            parse_with_options(&sig, 0, &options).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+        options.subpackets = options
+            .subpackets
+            .with(SUBPACKET_SIGNER_USER_ID, SubpacketAction::Accept);
+        let info = parse_with_options(&sig, 0, &options).unwrap();
+        assert_eq!(info.signer_user_id, Some(user_id));
+        assert_eq!(info.policy_uri, None);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn skips_unknown_non_critical_subpackets() {
+        let unknown = signature_with(Subpacket::new(100, false, b"unknown".to_vec()));
+        let critical = signature_with(Subpacket::new(100, true, b"unknown".to_vec()));
+        let revocable = signature_with(Subpacket::new(SUBPACKET_REVOCABLE, false, vec![1]));
+        let strict = ParseOptions::new(AllowWeakHashes::No);
+        let permissive = ParseOptions::permissive(AllowWeakHashes::No);
+        assert_eq!(
+            parse_with_options(&unknown, 0, &strict).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+        assert!(parse_with_options(&unknown, 0, &permissive).is_ok());
+        assert_eq!(
+            parse_with_options(&critical, 0, &permissive).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+        assert_eq!(
+            parse_with_options(&revocable, 0, &permissive).unwrap_err(),
+            Error::IllFormedSignature
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn follows_custom_subpacket_policies() {
+        let uri = b"https://example.com/policy".to_vec();
+        let policy_uri = signature_with(Subpacket::new(SUBPACKET_POLICY_URI, false, uri));
+        let unknown = signature_with(Subpacket::new(100, true, b"unknown".to_vec()));
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        options.subpackets = SubpacketPolicy::custom(SubpacketAction::Accept)
+            .with(SUBPACKET_POLICY_URI, SubpacketAction::SkipIfNotCritical);
+        let info = parse_with_options(&policy_uri, 0, &options).unwrap();
+        assert_eq!(info.policy_uri, None);
+        // Even critical subpackets can be accepted, if they are not understood
+        assert!(parse_with_options(&unknown, 0, &options).is_ok());
+        options.subpackets = options.subpackets.with(100, SubpacketAction::Reject);
+        assert_eq!(
+            parse_with_options(&unknown, 0, &options).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
+        // The issuer key ID is in the hashed area
+        options.subpackets = SubpacketPolicy::strict_rpm().with(16, SubpacketAction::Reject);
+        assert_eq!(
+            parse_with_options(&policy_uri, 0, &options).unwrap_err(),
+            Error::UnsupportedCriticalSubpacket
+        );
     }
 }
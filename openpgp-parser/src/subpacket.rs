@@ -0,0 +1,225 @@
+//! Serialization of OpenPGP signature subpackets
+//!
+//! This only covers the subpackets that [`crate::signature`] understands.  No
+//! attempt is made to check that a combination of subpackets is sensible; that
+//! is the job of whoever builds the signature.
+
+use super::signature::{
+    Fingerprint, SUBPACKET_CREATION_TIME, SUBPACKET_FINGERPRINT, SUBPACKET_ISSUER_KEYID,
+    SUBPACKET_NOTATION, SUBPACKET_SIG_EXPIRATION_TIME,
+};
+
+use super::Error;
+use alloc::vec::Vec;
+
+/// A signature subpacket
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Subpacket {
+    tag: u8,
+    critical: bool,
+    body: Vec<u8>,
+}
+
+impl Subpacket {
+    /// Creates a subpacket with an arbitrary tag and body.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` has its high bit set, as that bit is the critical flag.
+    pub fn new(tag: u8, critical: bool, body: Vec<u8>) -> Self {
+        assert!(tag & 0x80 == 0, "critical bit is passed separately");
+        Self {
+            tag,
+            critical,
+            body,
+        }
+    }
+
+    /// A signature creation time subpacket
+    pub fn creation_time(time: u32, critical: bool) -> Self {
+        Self::new(SUBPACKET_CREATION_TIME, critical, time.to_be_bytes().to_vec())
+    }
+
+    /// A signature expiration time subpacket.  `seconds` is relative to the
+    /// creation time.
+    pub fn expiration_time(seconds: u32, critical: bool) -> Self {
+        Self::new(
+            SUBPACKET_SIG_EXPIRATION_TIME,
+            critical,
+            seconds.to_be_bytes().to_vec(),
+        )
+    }
+
+    /// An issuer key ID subpacket
+    pub fn issuer_key_id(key_id: [u8; 8], critical: bool) -> Self {
+        Self::new(SUBPACKET_ISSUER_KEYID, critical, key_id.to_vec())
+    }
+
+    /// An issuer fingerprint subpacket.  Its version must match that of the
+    /// signature it is in.
+    pub fn issuer_fingerprint(fingerprint: Fingerprint, critical: bool) -> Self {
+        let fpr = fingerprint.as_bytes();
+        let mut body = Vec::with_capacity(1 + fpr.len());
+        body.push(fingerprint.version());
+        body.extend_from_slice(fpr);
+        Self::new(SUBPACKET_FINGERPRINT, critical, body)
+    }
+
+    /// A notation data subpacket.  Returns [`Error::TooLong`] if `name` or
+    /// `value` is longer than 65535 bytes.
+    pub fn notation(
+        name: &[u8],
+        value: &[u8],
+        human_readable: bool,
+        critical: bool,
+    ) -> Result<Self, Error> {
+        if name.len() > 0xFFFF || value.len() > 0xFFFF {
+            return Err(Error::TooLong);
+        }
+        let mut body = Vec::with_capacity(8 + name.len() + value.len());
+        body.extend_from_slice(&[if human_readable { 0x80 } else { 0 }, 0, 0, 0]);
+        body.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        body.extend_from_slice(name);
+        body.extend_from_slice(value);
+        Ok(Self::new(SUBPACKET_NOTATION, critical, body))
+    }
+
+    /// The tag, without the critical bit
+    pub fn tag(&self) -> u8 {
+        self.tag
+    }
+
+    /// Is the critical bit set?
+    pub fn critical(&self) -> bool {
+        self.critical
+    }
+
+    /// The body, not including the length or tag
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Appends the serialized subpacket (length, tag, and body) to `out`.
+    /// Lengths always use the shortest encoding.
+    pub fn serialize_into(&self, out: &mut Vec<u8>) {
+        // The length includes the tag byte
+        let len = self.body.len() + 1;
+        match len {
+            0..=191 => out.push(len as u8),
+            192..=8383 => {
+                let len = len - 192;
+                out.push((len >> 8) as u8 + 192);
+                out.push(len as u8);
+            }
+            _ => {
+                out.push(255);
+                out.extend_from_slice(&(len as u32).to_be_bytes());
+            }
+        }
+        out.push(self.tag | if self.critical { 0x80 } else { 0 });
+        out.extend_from_slice(&self.body);
+    }
+}
+
+/// Serializes a subpacket area, including its two-byte length prefix.
+/// Returns [`Error::TooLong`] if the area does not fit in 65535 bytes.
+pub fn serialize_area(subpackets: &[Subpacket]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0, 0]);
+    for i in subpackets {
+        i.serialize_into(&mut out)
+    }
+    let len = out.len() - 2;
+    if len > 0xFFFF {
+        return Err(Error::TooLong);
+    }
+    out[..2].copy_from_slice(&(len as u16).to_be_bytes());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::{parse, AllowWeakHashes, KeyIdSource};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    #[test]
+    fn length_encoding() {
+        for &(body_len, header_len) in &[(0, 1), (190, 1), (191, 2), (8382, 2), (8383, 5)] {
+            let mut out = vec![];
+            Subpacket::new(100, true, vec![0; body_len]).serialize_into(&mut out);
+            assert_eq!(out.len(), header_len + 1 + body_len);
+            assert_eq!(out[header_len], 100 | 0x80);
+            let mut reader = crate::Reader::new(&out);
+            let body = crate::packet::get_varlen_bytes(&mut reader).unwrap();
+            assert!(reader.is_empty());
+            assert_eq!(body.len(), body_len + 1);
+        }
+    }
+
+    /// Subpackets with lengths near the boundaries of each length encoding
+    fn subpacket() -> impl Strategy<Value = Subpacket> {
+        let len = prop::sample::select(&[0, 190, 191, 8382, 8383][..]);
+        (len, 0..3usize)
+            .prop_flat_map(|(len, extra)| (0..0x80u8, any::<bool>(), vec(any::<u8>(), len + extra)))
+            .prop_map(|(tag, critical, body)| Subpacket::new(tag, critical, body))
+    }
+
+    proptest! {
+        #[test]
+        fn area_roundtrip(subpackets in vec(subpacket(), 0..8)) {
+            let area = match serialize_area(&subpackets) {
+                Ok(area) => area,
+                Err(e) => {
+                    prop_assert_eq!(e, Error::TooLong);
+                    return Ok(());
+                }
+            };
+            let mut reader = crate::Reader::new(&area);
+            let len = reader.be_u16().unwrap();
+            prop_assert_eq!(len as usize, reader.len());
+            let mut parsed = vec![];
+            while !reader.is_empty() {
+                let data = crate::packet::get_varlen_bytes(&mut reader).unwrap();
+                parsed.push(Subpacket::new(
+                    data[0] & 0x7F,
+                    data[0] & 0x80 != 0,
+                    data[1..].to_vec(),
+                ))
+            }
+            prop_assert_eq!(parsed, subpackets);
+        }
+    }
+
+    #[test]
+    fn builds_parseable_signature() {
+        let fpr = [0x55; 20];
+        let mut key_id = [0; 8];
+        key_id.copy_from_slice(&fpr[12..]);
+        let mut body = vec![4, 0, 22, 8];
+        body.extend(
+            serialize_area(&[
+                Subpacket::issuer_fingerprint(Fingerprint::V4(fpr), false),
+                Subpacket::creation_time(1611626266, false),
+            ])
+            .unwrap(),
+        );
+        // left 16 bits of the hash, then two 8-bit MPIs
+        let rest = [0xab, 0xcd, 0, 8, 0xff, 0, 8, 0xff];
+        let unhashed = serialize_area(&[Subpacket::issuer_key_id(key_id, false)]).unwrap();
+        let packet = |unhashed: &[u8]| {
+            let body = [&body[..], unhashed, &rest].concat();
+            [&[0xC2, body.len() as u8][..], &body].concat()
+        };
+        let sig = parse(&packet(&unhashed), 0, AllowWeakHashes::No).unwrap();
+        assert_eq!(sig.key_id, key_id);
+        assert_eq!(sig.fingerprint, Some(Fingerprint::V4(fpr)));
+        assert_eq!(sig.creation_time, 1611626266);
+        // The fingerprint alone identifies the key
+        let sig = parse(&packet(&[0, 0]), 0, AllowWeakHashes::No).unwrap();
+        assert_eq!(sig.key_id, key_id);
+        assert_eq!(sig.key_id_source, KeyIdSource::Hashed);
+    }
+}
@@ -1,4 +1,4 @@
-use openpgp_parser::AllowWeakHashes;
+use openpgp_parser::{AllowWeakHashes, Error};
 use std::os::raw::{c_int, c_void};
 use std::ptr;
 
@@ -54,16 +54,17 @@ impl std::io::Write for DigestCtx {
 }
 
 impl DigestCtx {
-    /// Initialize an RPM digest context
+    /// Initialize an RPM digest context.  Fails if `algorithm` is not
+    /// allowed by `allow_weak_hashes`, or if RPM does not support it.
     pub fn init(
         algorithm: u8,
         allow_weak_hashes: AllowWeakHashes,
         _: super::InitToken,
-    ) -> Result<DigestCtx, ()> {
+    ) -> Result<DigestCtx, Error> {
         use openpgp_parser::signature::check_hash_algorithm;
-        let len = check_hash_algorithm(algorithm.into(), allow_weak_hashes).map_err(drop)?;
+        let len = check_hash_algorithm(algorithm.into(), allow_weak_hashes)?;
         if rpm_hash_len(algorithm.into()) != len.into() {
-            return Err(());
+            return Err(Error::UnsupportedHashAlgorithm(algorithm.into()));
         }
         let raw_p = unsafe { rpmDigestInit(algorithm.into(), 0) };
         assert!(!raw_p.0.is_null());
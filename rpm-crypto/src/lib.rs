@@ -7,7 +7,9 @@
 #![forbid(improper_ctypes)]
 #![deny(warnings)]
 
-use openpgp_parser::{AllowWeakHashes, Error};
+use openpgp_parser::signature::{ParseOptions, SigInfo};
+use openpgp_parser::{AllowWeakHashes, Error, Limits};
+use std::rc::Rc;
 
 mod digests;
 mod signatures;
@@ -16,9 +18,14 @@ pub use digests::{rpm_hash_len, DigestCtx};
 pub use signatures::Signature as RawSignature;
 
 /// An OpenPGP signature
+#[derive(Clone)]
 pub struct Signature {
-    sig: RawSignature,
+    /// RPM only reads its parsed signature, so clones can share it
+    sig: Rc<RawSignature>,
     ctx: DigestCtx,
+    info: SigInfo,
+    trailer: Option<Vec<u8>>,
+    packet: Vec<u8>,
 }
 
 pub use init::{init, InitToken};
@@ -51,10 +58,66 @@ impl Signature {
         allow_weak_hashes: AllowWeakHashes,
         token: InitToken,
     ) -> Result<Self, Error> {
-        let sig = RawSignature::parse(untrusted_buffer, time, allow_weak_hashes, token)?;
-        let ctx = DigestCtx::init(sig.hash_algorithm(), allow_weak_hashes, token)
+        let options = ParseOptions::new(allow_weak_hashes);
+        Self::parse_with_options(untrusted_buffer, time, &options, token)
+    }
+
+    /// As [`Signature::parse`], but with the given parser options.  Use this
+    /// to accept text signatures, for instance.
+    pub fn parse_with_options(
+        untrusted_buffer: &[u8],
+        time: u32,
+        options: &ParseOptions,
+        token: InitToken,
+    ) -> Result<Self, Error> {
+        let (sig, info) = RawSignature::parse_with_options(untrusted_buffer, time, options, token)?;
+        let packet = untrusted_buffer.to_vec();
+        Ok(Self::new(sig, info, packet, options, token))
+    }
+
+    /// Reads an OpenPGP signature packet from `r`, leaving any data after it
+    /// unread.  See [`openpgp_parser::signature::read_signature_from`]; as
+    /// there, errors from parsing the signature are the payload of the
+    /// returned [`std::io::Error`].
+    pub fn read_from(
+        r: &mut dyn std::io::Read,
+        time: u32,
+        options: &ParseOptions,
+        limits: &Limits,
+        token: InitToken,
+    ) -> std::io::Result<Self> {
+        let (packet, info) =
+            openpgp_parser::signature::read_signature_from(r, time, options, limits)?;
+        let sig = RawSignature::from_validated(&packet, &info)?;
+        Ok(Self::new(sig, info, packet, options, token))
+    }
+
+    fn new(
+        sig: RawSignature,
+        info: SigInfo,
+        packet: Vec<u8>,
+        options: &ParseOptions,
+        token: InitToken,
+    ) -> Self {
+        let mut ctx = DigestCtx::init(sig.hash_algorithm(), options.allow_weak_hashes, token)
             .expect("Digest algorithm already validated");
-        Ok(Self { sig, ctx })
+        // v6 signatures hash their salt before the signed data
+        if let Some(ref salt) = info.salt {
+            ctx.update(salt.as_bytes())
+        }
+        let mut trailer = vec![];
+        let trailer = openpgp_parser::signature::hash_trailer(&packet, &mut |data| {
+            trailer.extend_from_slice(data)
+        })
+        .ok()
+        .map(|()| trailer);
+        Self {
+            sig: Rc::new(sig),
+            ctx,
+            info,
+            trailer,
+            packet,
+        }
     }
 
     /// Update the sigatures’s internal digest context with data from `buf`.
@@ -65,4 +128,60 @@ impl Signature {
     pub fn public_key_algorithm(&self) -> u8 {
         self.sig.public_key_algorithm()
     }
+
+    /// The signature packet in canonical form, as produced by
+    /// [`openpgp_parser::signature::serialize`]
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        openpgp_parser::signature::serialize(&self.packet)
+    }
+
+    /// This signature, and the data hashed so far, with the issuer key ID
+    /// replaced by `key_id` as
+    /// [`openpgp_parser::signature::with_issuer_key_id`] does.  RPM finds
+    /// keys by key ID, so this lets it try a signature with a wildcard key
+    /// ID with a specific key.
+    pub fn with_issuer_key_id(&self, key_id: [u8; 8]) -> Result<Self, Error> {
+        let packet = openpgp_parser::signature::with_issuer_key_id(&self.packet, key_id)?;
+        let mut info = self.info.clone();
+        info.key_id = key_id;
+        let sig = RawSignature::from_validated(&packet, &info)?;
+        Ok(Self {
+            sig: Rc::new(sig),
+            ctx: self.ctx.clone(),
+            info,
+            trailer: self.trailer.clone(),
+            packet,
+        })
+    }
+
+    /// Information about the signature, as parsed by `openpgp-parser`
+    pub fn info(&self) -> &SigInfo {
+        &self.info
+    }
+
+    /// Compares the left 16 bits of the hash of the data so far with those
+    /// stored in the signature.  If they differ, the signature cannot be
+    /// valid for any key, so the public-key operation can be skipped.
+    /// Returns `true` if they match, or if the signature version does not
+    /// allow checking them.
+    pub fn quick_check(&self) -> bool {
+        let trailer = match self.trailer {
+            Some(ref trailer) => trailer,
+            None => return true,
+        };
+        let mut ctx = self.ctx.clone();
+        ctx.update(trailer);
+        ctx.finalize(false)[..2] == self.info.hash_prefix
+    }
+}
+
+impl std::io::Write for Signature {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
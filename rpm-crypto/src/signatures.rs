@@ -1,7 +1,7 @@
 use super::InitToken;
 use openpgp_parser::{signature, AllowWeakHashes, Error};
 use std::os::raw::{c_int, c_uint};
-enum RpmPgpDigParams {}
+pub(crate) enum RpmPgpDigParams {}
 
 #[repr(transparent)]
 pub struct Signature(*mut RpmPgpDigParams);
@@ -11,22 +11,49 @@ impl Signature {
         untrusted_buffer: &[u8],
         time: u32,
         allow_weak_hashes: AllowWeakHashes,
+        token: InitToken,
+    ) -> Result<(Self, signature::SigInfo), Error> {
+        let options = signature::ParseOptions::new(allow_weak_hashes);
+        Self::parse_with_options(untrusted_buffer, time, &options, token)
+    }
+
+    /// As [`Signature::parse`], but with the given parser options
+    pub fn parse_with_options(
+        untrusted_buffer: &[u8],
+        time: u32,
+        options: &signature::ParseOptions,
         _: InitToken,
+    ) -> Result<(Self, signature::SigInfo), Error> {
+        // Check that the signature is valid
+        let sig_info = signature::parse_with_options(untrusted_buffer, time, options)?;
+        Ok((Self::from_validated(untrusted_buffer, &sig_info)?, sig_info))
+    }
+
+    /// Passes a signature packet that `openpgp-parser` has already parsed,
+    /// yielding `sig_info`, to RPM.  RPM’s parser is not the same as ours,
+    /// and may reject a signature that we accept, or read it differently;
+    /// either way the signature is treated as ill-formed.
+    pub(crate) fn from_validated(
+        packet: &[u8],
+        sig_info: &signature::SigInfo,
     ) -> Result<Self, Error> {
         super::init();
-        // Check that the signature is valid
-        let sig_info = signature::parse(untrusted_buffer, time, allow_weak_hashes)?;
-        // We can now pass the buffer to RPM, since it is a valid signature
-        let slice = untrusted_buffer;
         let mut params = Signature(std::ptr::null_mut());
-        let r = unsafe { pgpPrtParams(slice.as_ptr(), slice.len(), 2, &mut params) };
-        assert!(r == 0, "we accepted a signature RPM rejected");
-        assert!(!params.0.is_null());
-        assert_eq!(params.hash_algorithm(), sig_info.hash_alg);
-        assert_eq!(params.public_key_algorithm(), sig_info.pkey_alg);
+        let r = unsafe { pgpPrtParams(packet.as_ptr(), packet.len(), 2, &mut params) };
+        if r != 0
+            || params.0.is_null()
+            || params.hash_algorithm() != u8::from(sig_info.hash_alg)
+            || params.public_key_algorithm() != u8::from(sig_info.pkey_alg)
+        {
+            return Err(Error::IllFormedSignature);
+        }
         Ok(params)
     }
 
+    pub(crate) fn as_ptr(&self) -> *const RpmPgpDigParams {
+        self.0
+    }
+
     /// Retrieve the hash algorithm of the signature
     pub fn hash_algorithm(&self) -> u8 {
         let alg = unsafe { pgpDigParamsAlgo(self.0, 9) };
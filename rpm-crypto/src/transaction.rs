@@ -1,8 +1,11 @@
-use super::{DigestCtx, InitToken, RawSignature, Signature};
+use super::signatures::RpmPgpDigParams;
+use super::{DigestCtx, InitToken, Signature};
+use std::io::{Error, ErrorKind};
 use std::os::raw::c_int;
 
 enum Rpmts {}
 enum RpmKeyring_ {}
+enum RpmPubkey {}
 #[repr(transparent)]
 pub struct RpmTransactionSet(*mut Rpmts);
 
@@ -12,7 +15,11 @@ pub struct RpmKeyring(*mut RpmKeyring_);
 #[link(name = "rpm")]
 extern "C" {
     fn rpmtsCreate() -> RpmTransactionSet;
+    fn rpmKeyringNew() -> *mut RpmKeyring_;
     fn rpmKeyringLink(Keyring: *mut RpmKeyring_) -> RpmKeyring;
+    fn rpmKeyringAddKey(keyring: *mut RpmKeyring_, key: *mut RpmPubkey) -> c_int;
+    fn rpmPubkeyNew(pkt: *const u8, pktlen: usize) -> *mut RpmPubkey;
+    fn rpmPubkeyFree(key: *mut RpmPubkey) -> *mut RpmPubkey;
     fn rpmKeyringFree(Keyring: *mut RpmKeyring_) -> *mut RpmKeyring_;
     fn rpmtsLink(ts: *mut Rpmts) -> RpmTransactionSet;
     fn rpmtsFree(ts: *mut Rpmts) -> *mut Rpmts;
@@ -56,16 +63,45 @@ impl RpmTransactionSet {
 }
 
 impl RpmKeyring {
+    /// Creates an empty keyring, not backed by the RPM database
+    pub fn new(_: InitToken) -> Self {
+        let ptr = unsafe { rpmKeyringNew() };
+        assert!(!ptr.is_null(), "out of memory");
+        RpmKeyring(ptr)
+    }
+
+    /// Adds the binary (not armored) OpenPGP public key in `pkt` to the
+    /// keyring.  Fails with [`ErrorKind::InvalidData`] if RPM rejects the
+    /// key.  Adding a key that is already present succeeds.
+    pub fn add_key(&self, pkt: &[u8]) -> std::io::Result<()> {
+        let r = unsafe {
+            let key = rpmPubkeyNew(pkt.as_ptr(), pkt.len());
+            if key.is_null() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "RPM could not parse the key",
+                ));
+            }
+            let r = rpmKeyringAddKey(self.0, key);
+            rpmPubkeyFree(key);
+            r
+        };
+        match r {
+            0 | 1 => Ok(()),
+            _ => Err(Error::new(ErrorKind::InvalidData, "RPM rejected the key")),
+        }
+    }
+
     pub fn validate_sig(&self, sig: Signature) -> Result<(), c_int> {
         #[link(name = "rpm")]
         extern "C" {
             fn rpmKeyringVerifySig(
                 keyring: *mut RpmKeyring_,
-                sig: RawSignature,
+                sig: *const RpmPgpDigParams,
                 ctx: DigestCtx,
             ) -> c_int;
         }
-        match unsafe { rpmKeyringVerifySig(self.0, sig.sig, sig.ctx) } {
+        match unsafe { rpmKeyringVerifySig(self.0, sig.sig.as_ptr(), sig.ctx) } {
             0 => Ok(()),
             e => Err(e),
         }
@@ -1,27 +1,116 @@
 use openpgp_parser::AllowWeakHashes;
+use rpm_parser::PlatformPolicy;
 use std::fs::File;
-use std::io::{copy, Error, ErrorKind, Result};
-fn main() -> Result<()> {
+use std::io::{copy, Error, ErrorKind, Read, Result};
+
+const USAGE: &str = "Usage: rpmcheck [--distro DISTRO] PACKAGE...\n\n\
+                     Check each PACKAGE, which may also be an uncompressed tar or zip archive\n\
+                     of packages.  DISTRO accepts the architectures and operating systems that\n\
+                     distribution adds to RPM’s own: fedora, rhel, or openeuler.";
+
+fn main() {
+    if let Err(e) = check() {
+        eprintln!(
+            "Error checking package: {}",
+            rpm_parser::escape_terminal(&e.to_string())
+        );
+        std::process::exit(1)
+    }
+}
+
+fn check() -> Result<()> {
     let mut args = std::env::args_os();
     if args.next().is_none() {
         return Ok(());
     };
     let token = rpm_crypto::init();
-    for i in args {
-        let mut s = File::open(i)?;
-        let package = rpm_parser::RPMPackage::read(&mut s, AllowWeakHashes::No, token)?;
-        package
-            .signature
-            .header_signature
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Package header is not signed"))?;
-        let (mut ctx, digest) = package.immutable.payload_digest()?;
-        copy(&mut s, &mut ctx)?;
-        if ctx.finalize(true) != digest {
+    let mut platform_policy = PlatformPolicy::default();
+    let mut packages = vec![];
+    while let Some(i) = args.next() {
+        match i.to_str() {
+            Some("--distro") => {
+                let distro = args.next().unwrap_or_default();
+                platform_policy = match distro.to_str().and_then(PlatformPolicy::distro) {
+                    Some(policy) => policy,
+                    None => {
+                        eprintln!("{}", USAGE);
+                        std::process::exit(1)
+                    }
+                }
+            }
+            Some("--help") => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => packages.push(i),
+        }
+    }
+    for i in packages {
+        // Tarballs and zip files of packages are checked member by member
+        let contents = rpm_parser::for_each_package(&mut File::open(&i)?, &mut |name, s| {
+            check_package(s, &platform_policy, token).map_err(|e| match name {
+                Some(name) => Error::new(e.kind(), format!("{}: {}", name, e)),
+                None => e,
+            })
+        })?;
+        for name in contents.skipped {
+            eprintln!(
+                "Warning: skipped {}, which is not a package",
+                rpm_parser::escape_terminal(&name)
+            )
+        }
+        if contents.packages == 0 {
             return Err(Error::new(
                 ErrorKind::InvalidData,
-                "Payload digest failed to verify!",
+                format!("No packages found in {}", i.to_string_lossy()),
             ));
         }
     }
     Ok(())
 }
+
+fn check_package(
+    s: &mut dyn Read,
+    platform_policy: &PlatformPolicy,
+    token: rpm_crypto::InitToken,
+) -> Result<()> {
+    let package =
+        rpm_parser::RPMPackage::read_with_platform(s, AllowWeakHashes::No, platform_policy, token)?;
+    package
+        .signature
+        .header_signature
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Package header is not signed"))?;
+    let (mut ctx, digest) = package.immutable.payload_digest()?;
+    copy(s, &mut ctx)?;
+    if ctx.finalize(true) != digest {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Payload digest failed to verify!",
+        ));
+    }
+    let immutable = &package.immutable;
+    for finding in rpm_parser::check_platform(
+        &package.lead,
+        &immutable.arch,
+        &immutable.os,
+        platform_policy,
+    )? {
+        eprintln!(
+            "Warning: {}",
+            rpm_parser::escape_terminal(&format!("{:?}", finding))
+        )
+    }
+    let policy = rpm_parser::SymlinkPolicy::default();
+    match rpm_parser::check_symlinks(&package.immutable.header, &policy) {
+        Some(findings) => {
+            for finding in findings {
+                eprintln!(
+                    "Warning: {}",
+                    rpm_parser::escape_terminal(&format!("{:?}", finding))
+                )
+            }
+        }
+        None => return Err(Error::new(ErrorKind::InvalidData, "Bad file list")),
+    }
+    Ok(())
+}
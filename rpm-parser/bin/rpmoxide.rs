@@ -0,0 +1,31 @@
+const USAGE: &str = "Usage: rpmoxide COMMAND\n\n\
+                     Commands:\n\n  \
+                     selftest  Check digests and signature verification against\n            \
+                     known-answer vectors.  Exits with status 1 if any check fails.";
+
+fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let command = args.next();
+    if args.next().is_some() {
+        eprintln!("{}", USAGE);
+        std::process::exit(1)
+    }
+    match command.as_ref().and_then(|c| c.to_str()) {
+        Some("selftest") => match rpm_parser::selftest(rpm_crypto::init()) {
+            Ok(passed) => {
+                for name in passed {
+                    println!("{}: ok", name)
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", rpm_parser::escape_terminal(&e.to_string()));
+                std::process::exit(1)
+            }
+        },
+        Some("--help") => println!("{}", USAGE),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1)
+        }
+    }
+}
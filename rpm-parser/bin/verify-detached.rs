@@ -0,0 +1,79 @@
+use openpgp_parser::AllowWeakHashes;
+use rpm_crypto::transaction::RpmTransactionSet;
+use rpm_parser::Keyring;
+use std::fs::{read, File};
+use std::io::{Error, ErrorKind, Result};
+
+const USAGE: &str =
+    "Usage: verify-detached [--keyring KEYRING] [--allow-weak-hashes] FILE SIGNATURE\n\n\
+                     Verify SIGNATURE, a detached OpenPGP signature over FILE.  Keys are taken\n\
+                     from KEYRING if given, and from the RPM database otherwise.";
+
+fn load(path: &std::ffi::OsStr) -> Result<Vec<u8>> {
+    let data = read(path)?;
+    match data.first() {
+        Some(i) if i & 0x80 != 0 => Ok(data),
+        _ => match openpgp_parser::armor::decode(&data) {
+            Ok((_, data)) => Ok(data),
+            Err(e) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("bad ASCII armor: {:?}", e),
+            )),
+        },
+    }
+}
+
+fn main() {
+    if let Err(e) = verify() {
+        eprintln!(
+            "Error verifying signature: {}",
+            rpm_parser::escape_terminal(&e.to_string())
+        );
+        std::process::exit(1)
+    }
+}
+
+fn verify() -> Result<()> {
+    let mut args = std::env::args_os();
+    if args.next().is_none() {
+        return Ok(());
+    };
+    let token = rpm_crypto::init();
+    let mut allow_weak_hashes = AllowWeakHashes::No;
+    let mut keyring_path = None;
+    let mut positional = vec![];
+    while let Some(i) = args.next() {
+        match i.to_str() {
+            Some("--keyring") => keyring_path = args.next(),
+            Some("--allow-weak-hashes") => allow_weak_hashes = AllowWeakHashes::Yes,
+            Some("--help") => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => positional.push(i),
+        }
+    }
+    if positional.len() != 2 {
+        eprintln!("{}", USAGE);
+        std::process::exit(1)
+    }
+    let keyring = match keyring_path {
+        None => RpmTransactionSet::new(token).keyring(),
+        Some(path) => {
+            let data = load(&path)?;
+            let mut keyring = Keyring::new();
+            if keyring.add(&data, token)? == 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "keyring has no keys"));
+            }
+            keyring.to_rpm_keyring(token)?
+        }
+    };
+    let sig = load(&positional[1])?;
+    rpm_parser::verify_detached(
+        &mut File::open(&positional[0])?,
+        &sig,
+        &keyring,
+        allow_weak_hashes,
+        token,
+    )
+}
@@ -0,0 +1,495 @@
+//! Packages inside tar and zip archives
+//!
+//! Batch verification is often pointed at a tarball or zip file of packages
+//! rather than at the packages themselves.  [`for_each_package`] recognizes
+//! such archives and hands each package in them to a callback as a stream, so
+//! nothing needs to be unpacked to disk first.  Anything else is taken to be
+//! a single package.
+//!
+//! Only uncompressed archives are supported: POSIX (ustar and pax) and GNU
+//! tar, and zip files whose members are stored rather than deflated.  A compressed
+//! tarball must be decompressed by the caller.  Archive metadata, including
+//! the CRC-32 of zip members, is not checked; packages are verified on their
+//! own, and nothing in the archive is trusted.
+
+use std::io::{copy, sink, Cursor, Read, Result};
+
+/// The kind of an archive
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ArchiveFormat {
+    /// A tar archive
+    Tar,
+    /// A zip file
+    Zip,
+}
+
+const TAR_BLOCK: usize = 512;
+const ZIP_LOCAL_HEADER: [u8; 4] = *b"PK\x03\x04";
+const ZIP_CENTRAL_HEADER: [u8; 4] = *b"PK\x01\x02";
+const ZIP_END_OF_CENTRAL_DIRECTORY: [u8; 4] = *b"PK\x05\x06";
+/// The largest GNU long name or pax extended header that will be read
+const TAR_EXTENDED_HEADER_MAX: u64 = 1 << 16;
+
+/// The callback of [`for_each_package`]
+pub type PackageCallback<'a> = dyn FnMut(Option<&str>, &mut dyn Read) -> Result<()> + 'a;
+
+type MemberCallback<'a> = dyn FnMut(&str, &mut dyn Read) -> Result<()> + 'a;
+
+/// What [`for_each_package`] found in its input
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ArchiveContents {
+    /// How many packages were passed to the callback
+    pub packages: usize,
+    /// The names of the archive members that were skipped because their names
+    /// do not end in `.rpm`.  Directories, links, and other members that are
+    /// not regular files are not included.
+    pub skipped: Vec<String>,
+}
+
+/// Recognize an archive from (up to) its first 512 bytes.  Returns [`None`]
+/// for anything else, including RPM packages.
+pub fn detect_archive(prefix: &[u8]) -> Option<ArchiveFormat> {
+    if prefix.starts_with(&ZIP_LOCAL_HEADER) || prefix.starts_with(&ZIP_END_OF_CENTRAL_DIRECTORY) {
+        Some(ArchiveFormat::Zip)
+    } else if prefix.len() >= TAR_BLOCK && prefix[257..262] == *b"ustar" {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
+    }
+}
+
+fn skip(r: &mut dyn Read, len: u64) -> Result<()> {
+    fail_if!(
+        copy(&mut r.take(len), &mut sink())? != len,
+        "truncated archive"
+    );
+    Ok(())
+}
+
+/// Reads as much of `buf` as `r` provides, returning how much that was
+fn read_up_to(r: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match r.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Call `cb` with the name and contents of every package in `r`.  If `r` is a
+/// tar or zip archive, `cb` is called for every member whose name ends in
+/// `.rpm`, and other members are skipped.  Otherwise, `r` is taken to be a
+/// single package and `cb` is called once, with no name.  Whatever `cb` does
+/// not read of a member is skipped.  The first error, from `cb` or from
+/// reading the archive, stops the iteration and is returned.
+///
+/// An archive with no packages in it is not an error, so callers that expect
+/// packages should check [`ArchiveContents::packages`].
+pub fn for_each_package(r: &mut dyn Read, cb: &mut PackageCallback<'_>) -> Result<ArchiveContents> {
+    let mut prefix = [0; TAR_BLOCK];
+    let len = read_up_to(r, &mut prefix)?;
+    let format = detect_archive(&prefix[..len]);
+    let mut r = Cursor::new(&prefix[..len]).chain(r);
+    let mut contents = ArchiveContents::default();
+    match format {
+        None => {
+            cb(None, &mut r)?;
+            contents.packages += 1
+        }
+        Some(format) => {
+            let mut cb = |name: &str, member: &mut dyn Read| {
+                if name.ends_with(".rpm") {
+                    cb(Some(name), member)?;
+                    contents.packages += 1
+                } else {
+                    contents.skipped.push(name.to_owned())
+                }
+                Ok(())
+            };
+            match format {
+                ArchiveFormat::Tar => for_each_tar_member(&mut r, &mut cb)?,
+                ArchiveFormat::Zip => for_each_zip_member(&mut r, &mut cb)?,
+            }
+        }
+    }
+    Ok(contents)
+}
+
+fn octal_field(field: &[u8]) -> Result<u64> {
+    let field = field
+        .iter()
+        .position(|&c| c == 0)
+        .map_or(field, |end| &field[..end]);
+    let digits = std::str::from_utf8(field).ok().map(|f| f.trim_matches(' '));
+    match digits.and_then(|f| u64::from_str_radix(f, 8).ok()) {
+        Some(value) => Ok(value),
+        None => bad_data!("bad tar header field {:?}", field),
+    }
+}
+
+fn nul_terminated(field: &[u8]) -> Result<&str> {
+    let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    match std::str::from_utf8(&field[..end]) {
+        Ok(s) => Ok(s),
+        Err(_) => bad_data!("tar member name is not UTF-8"),
+    }
+}
+
+/// The padding after a tar member of `size` bytes.  Fails if the padded size
+/// does not fit in a [`u64`], as it can with an untrusted pax size.
+fn tar_padding(size: u64) -> Result<u64> {
+    let padding = size.wrapping_neg() % TAR_BLOCK as u64;
+    match size.checked_add(padding) {
+        Some(_) => Ok(padding),
+        None => bad_data!("tar member size {} too large", size),
+    }
+}
+
+/// Reads the contents of a GNU long name or pax extended header member
+fn read_extended_header(r: &mut dyn Read, size: u64, padding: u64) -> Result<Vec<u8>> {
+    fail_if!(
+        size > TAR_EXTENDED_HEADER_MAX,
+        "tar extended header too large"
+    );
+    let mut data = vec![0; size as usize];
+    r.read_exact(&mut data)?;
+    skip(r, padding)?;
+    Ok(data)
+}
+
+/// Parses the records of a pax extended header, each of the form
+/// `"<length> <key>=<value>\n"`, and returns the path and size it sets
+fn pax_overrides(mut data: &[u8]) -> Result<(Option<String>, Option<u64>)> {
+    let (mut path, mut size) = (None, None);
+    while !data.is_empty() {
+        let record = data.iter().position(|&c| c == b' ').and_then(|space| {
+            let len = std::str::from_utf8(&data[..space]).ok()?.parse().ok()?;
+            match data.get(space + 1..len)? {
+                [body @ .., b'\n'] => Some((len, body)),
+                _ => None,
+            }
+        });
+        let (len, body) = match record {
+            Some(record) => record,
+            None => bad_data!("bad pax extended header record"),
+        };
+        data = &data[len..];
+        let equals = match body.iter().position(|&c| c == b'=') {
+            Some(equals) => equals,
+            None => bad_data!("bad pax extended header record"),
+        };
+        let value = &body[equals + 1..];
+        match &body[..equals] {
+            b"path" => path = Some(nul_terminated(value)?.to_owned()),
+            b"size" => match std::str::from_utf8(value).ok().and_then(|v| v.parse().ok()) {
+                Some(value) => size = Some(value),
+                None => bad_data!("bad pax size {:?}", value),
+            },
+            _ => {}
+        }
+    }
+    Ok((path, size))
+}
+
+/// Call `cb` for every regular file in the tar archive `r`.  GNU long names
+/// and the `path` and `size` keys of pax extended headers are honored.
+fn for_each_tar_member(r: &mut dyn Read, cb: &mut MemberCallback<'_>) -> Result<()> {
+    let (mut long_name, mut long_size) = (None, None);
+    loop {
+        let mut header = [0; TAR_BLOCK];
+        r.read_exact(&mut header)?;
+        if header.iter().all(|&c| c == 0) {
+            // The first of the two zero blocks that end the archive
+            return Ok(());
+        }
+        let checksum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    c.into()
+                }
+            })
+            .sum();
+        fail_if!(
+            octal_field(&header[148..156])? != checksum,
+            "bad tar header checksum"
+        );
+        let size = octal_field(&header[124..136])?;
+        let padding = tar_padding(size)?;
+        match header[156] {
+            b'L' => {
+                let name = read_extended_header(r, size, padding)?;
+                long_name = Some(nul_terminated(&name)?.to_owned())
+            }
+            b'x' => {
+                let (path, size) = pax_overrides(&read_extended_header(r, size, padding)?)?;
+                long_name = path.or(long_name);
+                long_size = size.or(long_size)
+            }
+            b'0' | 0 => {
+                let name = match long_name.take() {
+                    Some(name) => name,
+                    None => {
+                        let name = nul_terminated(&header[..100])?;
+                        let prefix = match &header[257..263] {
+                            b"ustar\0" => nul_terminated(&header[345..500])?,
+                            _ => "",
+                        };
+                        if prefix.is_empty() {
+                            name.to_owned()
+                        } else {
+                            format!("{}/{}", prefix, name)
+                        }
+                    }
+                };
+                let size = long_size.take().unwrap_or(size);
+                let padding = tar_padding(size)?;
+                let mut member = r.take(size);
+                cb(&name, &mut member)?;
+                let rest = member.limit();
+                skip(r, rest + padding)?
+            }
+            _ => {
+                // Directories, links, and global extended headers
+                let size = long_size.take().unwrap_or(size);
+                let padding = tar_padding(size)?;
+                long_name = None;
+                skip(r, size + padding)?
+            }
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Call `cb` for every file in the zip file `r`, reading the local headers in
+/// order and stopping at the central directory
+fn for_each_zip_member(r: &mut dyn Read, cb: &mut MemberCallback<'_>) -> Result<()> {
+    loop {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        match magic {
+            ZIP_LOCAL_HEADER => {}
+            ZIP_CENTRAL_HEADER | ZIP_END_OF_CENTRAL_DIRECTORY => return Ok(()),
+            _ => bad_data!("bad zip local header"),
+        }
+        let mut header = [0; 26];
+        r.read_exact(&mut header)?;
+        let (flags, method) = (u16_at(&header, 2), u16_at(&header, 4));
+        let size = u32_at(&header, 14);
+        let (name_len, extra_len) = (u16_at(&header, 22), u16_at(&header, 24));
+        fail_if!(flags & 1 != 0, "encrypted zip members are not supported");
+        fail_if!(
+            flags & 8 != 0,
+            "zip members with data descriptors are not supported"
+        );
+        fail_if!(method != 0, "compressed zip members are not supported");
+        fail_if!(size == u32::MAX, "ZIP64 members are not supported");
+        fail_if!(
+            size != u32_at(&header, 18),
+            "stored zip member changes size"
+        );
+        let mut name = vec![0; name_len.into()];
+        r.read_exact(&mut name)?;
+        skip(r, extra_len.into())?;
+        let name = match String::from_utf8(name) {
+            Ok(name) => name,
+            Err(_) => bad_data!("zip member name is not UTF-8"),
+        };
+        let mut member = r.take(size.into());
+        if !name.ends_with('/') {
+            cb(&name, &mut member)?
+        }
+        let rest = member.limit();
+        skip(r, rest)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_member(name: &str, contents: &[u8], kind: u8) -> Vec<u8> {
+        let mut header = [0; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", contents.len()).as_bytes());
+        header[148..156].copy_from_slice(b"        ");
+        header[156] = kind;
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        let checksum: u32 = header.iter().map(|&c| u32::from(c)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        let mut out = header.to_vec();
+        out.extend_from_slice(contents);
+        out.resize(out.len() + contents.len().wrapping_neg() % TAR_BLOCK, 0);
+        out
+    }
+
+    fn zip_member(name: &str, contents: &[u8], method: u16) -> Vec<u8> {
+        let mut out = ZIP_LOCAL_HEADER.to_vec();
+        out.extend_from_slice(&[20, 0, 0, 0]);
+        out.extend_from_slice(&method.to_le_bytes());
+        out.extend_from_slice(&[0; 8]);
+        for _ in 0..2 {
+            out.extend_from_slice(&(contents.len() as u32).to_le_bytes())
+        }
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(contents);
+        out
+    }
+
+    fn packages(data: &[u8]) -> Result<Vec<(Option<String>, Vec<u8>)>> {
+        let mut found = vec![];
+        let contents = for_each_package(&mut &data[..], &mut |name, member| {
+            // Read only part of some members, to check that the rest is skipped
+            let mut contents = vec![];
+            member.take(5).read_to_end(&mut contents)?;
+            found.push((name.map(str::to_owned), contents));
+            Ok(())
+        })?;
+        assert_eq!(contents.packages, found.len());
+        Ok(found)
+    }
+
+    fn skipped(data: &[u8]) -> Vec<String> {
+        for_each_package(&mut &data[..], &mut |_, _| Ok(()))
+            .unwrap()
+            .skipped
+    }
+
+    fn found(name: &str, contents: &[u8]) -> (Option<String>, Vec<u8>) {
+        (Some(name.to_owned()), contents.to_vec())
+    }
+
+    #[test]
+    fn reads_tar_archives() {
+        let mut tar = tar_member("pkgs/", b"", b'5');
+        tar.extend(tar_member("pkgs/a.rpm", b"first package", b'0'));
+        tar.extend(tar_member("pkgs/README", b"not a package", b'0'));
+        tar.extend(tar_member("pkgs/b.rpm", b"b", 0));
+        tar.extend_from_slice(&[0; 2 * TAR_BLOCK]);
+        assert_eq!(detect_archive(&tar), Some(ArchiveFormat::Tar));
+        assert_eq!(
+            packages(&tar).unwrap(),
+            [found("pkgs/a.rpm", b"first"), found("pkgs/b.rpm", b"b")]
+        );
+        assert_eq!(skipped(&tar), ["pkgs/README"]);
+        let e = packages(&tar[..3 * TAR_BLOCK]).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+        tar[TAR_BLOCK] = b'x';
+        let e = packages(&tar).unwrap_err();
+        assert_eq!(e.to_string(), "bad tar header checksum");
+    }
+
+    #[test]
+    fn reads_long_tar_names() {
+        let long_name = format!("{}/c.rpm", "d".repeat(150));
+        let mut tar = tar_member("././@LongLink", format!("{}\0", long_name).as_bytes(), b'L');
+        tar.extend(tar_member("ddddd/c.rpm", b"long GNU name", b'0'));
+        let record = format!("path={}\n", "e".repeat(200));
+        let record = format!("{} {}", record.len() + 4, record);
+        tar.extend(tar_member("PaxHeaders/1", record.as_bytes(), b'x'));
+        tar.extend(tar_member("eeeee", b"skipped", b'0'));
+        // pax headers can also give the size, overriding the one in the header
+        tar.extend(tar_member("PaxHeaders/2", b"10 size=8\n", b'x'));
+        let mut short = tar_member("short.rpm", b"12345678", b'0');
+        short[124..136].copy_from_slice(b"00000000000\0");
+        let checksum: u32 = short[..TAR_BLOCK]
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    c.into()
+                }
+            })
+            .sum();
+        short[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        tar.extend(short);
+        tar.extend_from_slice(&[0; 2 * TAR_BLOCK]);
+        assert_eq!(
+            packages(&tar).unwrap(),
+            [found(&long_name, b"long "), found("short.rpm", b"12345")]
+        );
+        assert_eq!(skipped(&tar), ["e".repeat(200)]);
+        let mut bad = tar_member("PaxHeaders/3", b"99 path=x\n", b'x');
+        bad.extend_from_slice(&[0; 2 * TAR_BLOCK]);
+        let e = packages(&bad).unwrap_err();
+        assert_eq!(e.to_string(), "bad pax extended header record");
+        // A pax size whose padding would overflow, for a package and for a
+        // directory
+        let record = format!("size={}\n", u64::MAX);
+        let record = format!("{} {}", record.len() + 3, record);
+        let huge = tar_member("PaxHeaders/4", record.as_bytes(), b'x');
+        for &kind in &[b'0', b'5'] {
+            let mut bad = huge.clone();
+            bad.extend(tar_member("huge.rpm", b"", kind));
+            let e = packages(&bad).unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                format!("tar member size {} too large", u64::MAX)
+            );
+        }
+    }
+
+    #[test]
+    fn reports_archives_without_packages() {
+        let mut tar = tar_member("README", b"not a package", b'0');
+        tar.extend_from_slice(&[0; 2 * TAR_BLOCK]);
+        let contents = for_each_package(&mut &tar[..], &mut |_, _| Ok(())).unwrap();
+        assert_eq!(contents.packages, 0);
+        assert_eq!(contents.skipped, ["README"]);
+    }
+
+    #[test]
+    fn reads_zip_files() {
+        let mut zip = zip_member("a.rpm", b"first package", 0);
+        zip.extend(zip_member("dir/", b"", 0));
+        zip.extend(zip_member("dir/b.rpm", b"b", 0));
+        zip.extend_from_slice(&ZIP_CENTRAL_HEADER);
+        assert_eq!(detect_archive(&zip), Some(ArchiveFormat::Zip));
+        assert_eq!(
+            packages(&zip).unwrap(),
+            [found("a.rpm", b"first"), found("dir/b.rpm", b"b")]
+        );
+        assert!(skipped(&zip).is_empty());
+        let e = packages(&zip_member("c.rpm", b"c", 8)).unwrap_err();
+        assert_eq!(e.to_string(), "compressed zip members are not supported");
+    }
+
+    #[test]
+    fn passes_other_input_through() {
+        let package: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        assert_eq!(detect_archive(package), None);
+        let mut len = 0;
+        for_each_package(&mut &package[..], &mut |name, member| {
+            assert_eq!(name, None);
+            len = copy(member, &mut sink())?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(len, package.len() as u64);
+        assert_eq!(packages(b"tiny").unwrap(), [(None, b"tiny".to_vec())]);
+    }
+}
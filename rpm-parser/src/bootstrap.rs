@@ -0,0 +1,403 @@
+//! Provisioning a keyring from distribution release keys
+//!
+//! Release keys are published over HTTPS, but the first key cannot be
+//! verified by an earlier one, so downloading it is only as safe as the
+//! server and the CA system.  [`import_pinned`] closes this gap: it imports
+//! only the certificates whose primary key fingerprints the caller already
+//! knows (for instance, from release notes or a previous installation), and
+//! fails if any expected key is missing.
+//!
+//! RPM has no notion of time: it trusts a key for every signature, whenever
+//! it was made.  So only keys that are valid when they are imported are
+//! given to RPM, leaving out keys that have expired or been revoked, even
+//! if only “superseded” or “retired”.  Keys that expire later are still
+//! trusted after that, so keyrings should be provisioned again from time
+//! to time.
+//!
+//! This crate has no HTTP client.  [`fetch_pinned`] takes the fetcher as a
+//! callback, so the caller decides how (and whether) to reach the network.
+
+use openpgp_parser::cert::{self, KeySignature, KeyVerifier, SignedData};
+use openpgp_parser::signature::{ParseOptions, SubpacketAction, SubpacketPolicy};
+use openpgp_parser::{armor, key::PublicKey, packet::Packet, AllowWeakHashes, Reader};
+use rpm_crypto::{transaction::RpmKeyring, DigestCtx, InitToken, Signature};
+use std::borrow::Cow;
+use std::io::Result;
+
+/// The Fedora release keys
+pub const FEDORA_KEYS_URL: &str = "https://fedoraproject.org/fedora.gpg";
+
+/// The Red Hat Enterprise Linux release key
+pub const RHEL_KEY_URL: &str = "https://www.redhat.com/security/data/fd431d51.txt";
+
+/// Computes the fingerprint of `key`
+pub fn fingerprint(key: &PublicKey<'_>, token: InitToken) -> Vec<u8> {
+    // SHA-1 is broken for signatures, but v4 fingerprints are defined with it
+    let mut ctx = DigestCtx::init(
+        key.fingerprint_hash_algorithm().into(),
+        AllowWeakHashes::Yes,
+        token,
+    )
+    .expect("fingerprint hash algorithms are supported");
+    key.fingerprint_data(&mut |data| ctx.update(data));
+    ctx.finalize(false)
+}
+
+/// Checks self-signatures with RPM, for [`cert::Certificate::valid_at`] and
+/// related functions
+#[derive(Copy, Clone, Debug)]
+pub struct CertificateVerifier(pub InitToken);
+
+impl KeyVerifier for CertificateVerifier {
+    fn fingerprint(&mut self, key: &PublicKey<'_>) -> Vec<u8> {
+        fingerprint(key, self.0)
+    }
+
+    fn verify(
+        &mut self,
+        signer: &PublicKey<'_>,
+        signature: &KeySignature<'_>,
+        data: &mut SignedData<'_>,
+    ) -> bool {
+        // Self-signatures carry key properties, which the signature parser
+        // has no reason to know about
+        let mut options = ParseOptions::new(AllowWeakHashes::No);
+        options.kind = None;
+        options.allow_key_signatures = true;
+        options.subpackets = SubpacketPolicy::custom(SubpacketAction::Accept);
+        let mut sig = match Signature::parse_with_options(&signature.packet(), 0, &options, self.0)
+        {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        data(&mut |d| sig.update(d));
+        // Only `signer` may have made the signature
+        let keyring = RpmKeyring::new(self.0);
+        let key = Packet::new(6, signer.body()).serialize();
+        keyring.add_key(&key).is_ok() && keyring.validate_sig(sig).is_ok()
+    }
+}
+
+/// Decodes `data` if it is ASCII-armored
+fn binary(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    match data.first() {
+        Some(i) if i & 0x80 != 0 => Ok(Cow::Borrowed(data)),
+        _ => match armor::decode(data) {
+            Ok((armor::Kind::PublicKey, decoded)) => Ok(Cow::Owned(decoded)),
+            Ok((kind, _)) => bad_data!("expected public keys, got {:?}", kind),
+            Err(e) => bad_data!("bad ASCII armor: {:?}", e),
+        },
+    }
+}
+
+/// Which subkeys of `certificate` may sign: those whose binding signature
+/// verifies and that are not revoked outright.  A pin or a keyring only
+/// vouches for the primary key, and RPM trusts every subkey it is given, so
+/// anyone who can append a subkey to a trusted certificate could otherwise
+/// sign packages (CVE-2021-3521).
+pub(crate) fn usable_subkeys(
+    certificate: &cert::Certificate<'_>,
+    verifier: &mut dyn KeyVerifier,
+) -> Vec<bool> {
+    certificate
+        .subkeys()
+        .iter()
+        .map(|subkey| {
+            !subkey.revocation().is_some_and(|r| r.is_hard())
+                && certificate.binding_signature(subkey, verifier).is_some()
+        })
+        .collect()
+}
+
+/// Which subkeys of `certificate` can be used at `time`, as checked by
+/// [`cert::Certificate::key_valid_at`]: bound by a binding signature that
+/// verifies, already created, and neither expired nor revoked
+pub(crate) fn current_subkeys(
+    certificate: &cert::Certificate<'_>,
+    time: u32,
+    verifier: &mut dyn KeyVerifier,
+) -> Vec<bool> {
+    certificate
+        .subkeys()
+        .iter()
+        .map(|subkey| {
+            certificate
+                .key_valid_at(subkey.key(), time, verifier)
+                .is_ok()
+        })
+        .collect()
+}
+
+/// Serializes `certificate` with only the subkeys marked in `usable`, as
+/// returned by [`usable_subkeys`] or [`current_subkeys`]
+pub(crate) fn verified_packets(certificate: &cert::Certificate<'_>, usable: &[bool]) -> Vec<u8> {
+    let mut packets = Packet::new(6, certificate.primary().body()).serialize();
+    let push_signatures = |packets: &mut Vec<u8>, signatures: &[KeySignature<'_>]| {
+        for signature in signatures {
+            packets.extend_from_slice(&signature.packet())
+        }
+    };
+    push_signatures(&mut packets, certificate.direct_signatures());
+    for user_id in certificate.user_ids() {
+        packets.extend_from_slice(&user_id.packet());
+        push_signatures(&mut packets, user_id.signatures());
+    }
+    for (subkey, &usable) in certificate.subkeys().iter().zip(usable) {
+        if usable {
+            packets.extend_from_slice(&Packet::new(14, subkey.key().body()).serialize());
+            push_signatures(&mut packets, subkey.signatures());
+        }
+    }
+    packets
+}
+
+/// Import into `keyring` the certificates in `data` whose primary key
+/// fingerprint is in `pins`.  `data` may be binary or ASCII-armored.  Other
+/// certificates are ignored.  `time` is the current time, in seconds since
+/// the Unix epoch.  Subkeys are only imported if they can be used at `time`:
+/// their binding signature verifies, and they have not expired or been
+/// revoked, for whatever reason.  Fails, without importing anything, if any
+/// fingerprint in `pins` is not found or names a certificate whose primary
+/// key cannot be used at `time`.  Returns the number of certificates
+/// imported.
+pub fn import_pinned(
+    data: &[u8],
+    pins: &[&[u8]],
+    time: u32,
+    keyring: &RpmKeyring,
+    token: InitToken,
+) -> Result<usize> {
+    let data = binary(data)?;
+    let mut reader = Reader::new(&data);
+    let mut pinned = vec![];
+    let mut found = vec![false; pins.len()];
+    while !reader.is_empty() {
+        let certificate = match cert::read_certificate(&mut reader) {
+            Ok(certificate) => certificate,
+            Err(e) => bad_data!("bad OpenPGP certificate: {:?}", e),
+        };
+        let fingerprint = fingerprint(certificate.primary(), token);
+        if let Some(i) = pins.iter().position(|&pin| pin == &fingerprint[..]) {
+            if certificate.revocation().is_some_and(|r| r.is_hard()) {
+                bad_data!("certificate with fingerprint {:02X?} is revoked", pins[i])
+            }
+            let verifier = &mut CertificateVerifier(token);
+            if let Err(e) = certificate.valid_at(time, verifier) {
+                bad_data!(
+                    "certificate with fingerprint {:02X?} cannot be used: {:?}",
+                    pins[i],
+                    e
+                )
+            }
+            found[i] = true;
+            let usable = current_subkeys(&certificate, time, verifier);
+            pinned.push(verified_packets(&certificate, &usable));
+        }
+    }
+    if let Some(i) = found.iter().position(|&found| !found) {
+        bad_data!("no certificate with fingerprint {:02X?}", pins[i])
+    }
+    for packets in &pinned {
+        keyring.add_key(packets)?;
+    }
+    Ok(pinned.len())
+}
+
+/// Fetch each URL in `urls` with `fetch` and import the certificates pinned
+/// by `pins` that can be used at `time`, as with [`import_pinned`].  Every
+/// fingerprint must be found in the data from at least one URL.  Nothing is
+/// imported if any fetch fails.
+pub fn fetch_pinned(
+    urls: &[&str],
+    pins: &[&[u8]],
+    time: u32,
+    fetch: &mut dyn FnMut(&str) -> Result<Vec<u8>>,
+    keyring: &RpmKeyring,
+    token: InitToken,
+) -> Result<usize> {
+    let mut data = vec![];
+    for url in urls {
+        data.extend_from_slice(&binary(&fetch(url)?)?)
+    }
+    import_pinned(&data, pins, time, keyring, token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+    const FINGERPRINT: [u8; 20] = [
+        0xE6, 0x0F, 0x4C, 0x72, 0x4B, 0x87, 0xE0, 0x1A, 0xC3, 0x83, 0xFA, 0xDC, 0x93, 0x0F, 0x26,
+        0x82, 0x77, 0x4C, 0xEC, 0xF4,
+    ];
+    /// A time after the test key was created
+    const NOW: u32 = 1792111174 + 1000;
+
+    /// A v4 revocation signature of type `sig_type`, made at `time`, with
+    /// reason code `reason` and no actual signature
+    fn revocation_packet(sig_type: u8, time: u32, reason: u8) -> Vec<u8> {
+        let t = time.to_be_bytes();
+        let hashed = [5, 2, t[0], t[1], t[2], t[3], 3, 29, reason, b'x'];
+        let mut body = vec![4, sig_type, 22, 8, 0, hashed.len() as u8];
+        body.extend_from_slice(&hashed);
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        Packet::new(2, &body).serialize()
+    }
+
+    #[test]
+    fn imports_pinned_keys() {
+        let token = rpm_crypto::init();
+        let cert = cert::parse(CERT).unwrap();
+        assert_eq!(fingerprint(cert.primary(), token), FINGERPRINT);
+        let keyring = RpmKeyring::new(token);
+        assert_eq!(
+            import_pinned(CERT, &[&FINGERPRINT], NOW, &keyring, token).unwrap(),
+            1
+        );
+        assert_eq!(import_pinned(CERT, &[], NOW, &keyring, token).unwrap(), 0);
+        let mut other = FINGERPRINT;
+        other[0] ^= 1;
+        assert!(import_pinned(CERT, &[&FINGERPRINT, &other], NOW, &keyring, token).is_err());
+        let armored = armor::encode(armor::Kind::PublicKey, CERT);
+        let mut fetched = vec![];
+        let mut fetch = |url: &str| {
+            fetched.push(url.to_owned());
+            Ok(armored.clone())
+        };
+        let urls = [FEDORA_KEYS_URL, RHEL_KEY_URL];
+        let count = fetch_pinned(&urls, &[&FINGERPRINT], NOW, &mut fetch, &keyring, token);
+        assert_eq!(count.unwrap(), 2);
+        assert_eq!(fetched, urls);
+        let signature = armor::encode(armor::Kind::Signature, CERT);
+        assert!(import_pinned(&signature, &[], NOW, &keyring, token).is_err());
+    }
+
+    #[test]
+    fn drops_unbound_subkeys() {
+        let token = rpm_crypto::init();
+        let cert = cert::parse(CERT).unwrap();
+        let verifier = &mut CertificateVerifier(token);
+        assert_eq!(usable_subkeys(&cert, verifier), [true; 4]);
+        let verified = verified_packets(&cert, &usable_subkeys(&cert, verifier));
+        assert_eq!(cert::parse(&verified).unwrap(), cert);
+        // An appended subkey, with a binding signature that does not cover it
+        let subkey = &cert.subkeys()[1];
+        let mut body = subkey.key().body().to_vec();
+        *body.last_mut().unwrap() ^= 1;
+        let appended = [
+            CERT,
+            &Packet::new(14, &body).serialize(),
+            &subkey.signatures()[0].packet(),
+        ]
+        .concat();
+        let appended_cert = cert::parse(&appended).unwrap();
+        assert_eq!(appended_cert.subkeys().len(), 5);
+        let usable = usable_subkeys(&appended_cert, verifier);
+        assert_eq!(usable, [true, true, true, true, false]);
+        assert_eq!(verified_packets(&appended_cert, &usable), verified);
+        let keyring = RpmKeyring::new(token);
+        assert_eq!(
+            import_pinned(&appended, &[&FINGERPRINT], NOW, &keyring, token).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_revoked_pins() {
+        let token = rpm_crypto::init();
+        // A hard revocation (key compromised)
+        let (primary, rest) = CERT.split_at(53);
+        let revoked = [primary, &revocation_packet(0x20, 1, 2), rest].concat();
+        let keyring = RpmKeyring::new(token);
+        let e = import_pinned(&revoked, &[&FINGERPRINT], NOW, &keyring, token).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "certificate with fingerprint {:02X?} is revoked",
+                FINGERPRINT
+            )
+        );
+        // Unpinned revoked certificates are ignored
+        assert_eq!(
+            import_pinned(&revoked, &[], NOW, &keyring, token).unwrap(),
+            0
+        );
+
+        // A retired primary key can only be imported before it was retired
+        let retired = [primary, &revocation_packet(0x20, NOW, 3), rest].concat();
+        assert_eq!(
+            import_pinned(&retired, &[&FINGERPRINT], NOW - 1, &keyring, token).unwrap(),
+            1
+        );
+        let e = import_pinned(&retired, &[&FINGERPRINT], NOW, &keyring, token).unwrap_err();
+        let revocation = cert::Revocation {
+            time: NOW,
+            reason: Some(3),
+        };
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "certificate with fingerprint {:02X?} cannot be used: {:?}",
+                FINGERPRINT,
+                cert::KeyInvalid::Revoked(revocation)
+            )
+        );
+    }
+
+    #[test]
+    fn drops_retired_subkeys() {
+        let token = rpm_crypto::init();
+        let verifier = &mut CertificateVerifier(token);
+        // The last subkey is superseded at NOW
+        let superseded = [CERT, &revocation_packet(0x28, NOW, 1)].concat();
+        let cert = cert::parse(&superseded).unwrap();
+        // Keyring checks the revocation time itself, so it keeps the subkey
+        assert_eq!(usable_subkeys(&cert, verifier), [true; 4]);
+        assert_eq!(current_subkeys(&cert, NOW - 1, verifier), [true; 4]);
+        let usable = current_subkeys(&cert, NOW, verifier);
+        assert_eq!(usable, [true, true, true, false]);
+        // RPM would trust it for any signature, so it is not imported
+        let verified = verified_packets(&cert, &usable);
+        let packets = cert::parse(&verified).unwrap();
+        assert_eq!(packets.subkeys().len(), 3);
+        assert_eq!(packets.subkeys(), &cert.subkeys()[..3]);
+        let keyring = RpmKeyring::new(token);
+        assert_eq!(
+            import_pinned(&superseded, &[&FINGERPRINT], NOW, &keyring, token).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn verifies_self_signatures() {
+        let verifier = &mut CertificateVerifier(rpm_crypto::init());
+        let cert = cert::parse(CERT).unwrap();
+        assert_eq!(cert.valid_at(u32::MAX, verifier), Ok(()));
+        for subkey in cert.subkeys() {
+            assert!(cert.binding_signature(subkey, verifier).is_some());
+            assert_eq!(cert.key_valid_at(subkey.key(), u32::MAX, verifier), Ok(()));
+        }
+        // Changing the user ID breaks its certification, the only
+        // self-signature
+        let mut tampered = CERT.to_vec();
+        tampered[55] ^= 1;
+        let cert = cert::parse(&tampered).unwrap();
+        assert_eq!(
+            cert.valid_at(u32::MAX, verifier),
+            Err(cert::KeyInvalid::NoSelfSignature)
+        );
+        // Binding signatures cover the subkey
+        let cert = cert::parse(CERT).unwrap();
+        let body = cert.subkeys().last().unwrap().key().body();
+        let offset = CERT.windows(body.len()).position(|w| w == body).unwrap();
+        let mut tampered = CERT.to_vec();
+        tampered[offset + body.len() - 1] ^= 1;
+        let cert = cert::parse(&tampered).unwrap();
+        let subkey = cert.subkeys().last().unwrap();
+        assert!(cert.binding_signature(subkey, verifier).is_none());
+        assert!(cert
+            .binding_signature(&cert.subkeys()[0], verifier)
+            .is_some());
+    }
+}
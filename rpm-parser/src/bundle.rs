@@ -0,0 +1,275 @@
+//! Several packages in one stream
+//!
+//! Opening a qrexec connection (or a pipe) per package is expensive.  A bundle
+//! carries any number of packages over one stream:
+//!
+//! - the 8-byte magic [`BUNDLE_MAGIC`];
+//! - for each package, its length as a big-endian `u64`, then the package;
+//! - a zero length, which marks the end of the bundle.
+//!
+//! Because of the end marker, a truncated bundle is an error rather than a
+//! shorter list of packages.  Nothing in a bundle is trusted: every package
+//! is verified on its own, and a bad package does not stop the others from
+//! being verified.
+
+use crate::{load_signature, read_lead, Keyring, Policy, VerifyResult};
+use rpm_crypto::InitToken;
+use std::io::{copy, sink, Error, ErrorKind, Read, Result, Write};
+
+/// The magic number at the start of a bundle
+pub const BUNDLE_MAGIC: [u8; 8] = *b"RPMBNDL\x01";
+
+/// Writes packages to a bundle
+#[derive(Debug)]
+pub struct BundleWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> BundleWriter<W> {
+    /// Starts a bundle by writing the magic number to `inner`
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(&BUNDLE_MAGIC)?;
+        Ok(Self { inner })
+    }
+
+    /// Appends a package of `len` bytes, read from `package`
+    pub fn add(&mut self, package: &mut dyn Read, len: u64) -> Result<()> {
+        fail_if!(len == 0, "cannot add an empty package to a bundle");
+        self.inner.write_all(&len.to_be_bytes())?;
+        if copy(&mut package.take(len), &mut self.inner)? != len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "package shorter than its length",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes the end marker and returns the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.write_all(&0u64.to_be_bytes())?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads packages from a bundle
+#[derive(Debug)]
+pub struct BundleReader<R: Read> {
+    inner: R,
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: Read> BundleReader<R> {
+    /// Checks the magic number at the start of `inner`
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        inner.read_exact(&mut magic)?;
+        fail_if!(magic != BUNDLE_MAGIC, "wrong bundle magic");
+        Ok(Self {
+            inner,
+            remaining: 0,
+            done: false,
+        })
+    }
+
+    /// Moves to the next package, skipping whatever the caller did not read
+    /// of the previous one.  Returns [`None`] at the end marker, after
+    /// checking that nothing follows it.
+    pub fn next_package(&mut self) -> Result<Option<BundleEntry<'_, R>>> {
+        let remaining = std::mem::replace(&mut self.remaining, 0);
+        if copy(&mut (&mut self.inner).take(remaining), &mut sink())? != remaining {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let mut len = [0; 8];
+        self.inner.read_exact(&mut len)?;
+        match u64::from_be_bytes(len) {
+            0 => {
+                self.done = true;
+                fail_if!(self.inner.read(&mut [0])? != 0, "data after end of bundle");
+                Ok(None)
+            }
+            len => {
+                self.remaining = len;
+                Ok(Some(BundleEntry { bundle: self }))
+            }
+        }
+    }
+}
+
+/// One package in a bundle.  Reading it returns end of file at the end of the
+/// package.
+#[derive(Debug)]
+pub struct BundleEntry<'a, R: Read> {
+    bundle: &'a mut BundleReader<R>,
+}
+
+impl<R: Read> BundleEntry<'_, R> {
+    /// The number of bytes of the package not yet read
+    pub fn remaining(&self) -> u64 {
+        self.bundle.remaining
+    }
+}
+
+impl<R: Read> Read for BundleEntry<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let remaining = &mut self.bundle.remaining;
+        let len = buf.len().min((*remaining).min(usize::MAX as u64) as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        let read = self.bundle.inner.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "bundle truncated"));
+        }
+        *remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Verify every package in the bundle read from `src` under `policy`, with
+/// [`Keyring::verify_package`].  `cb` is called with the index and verdict
+/// of each package, in order; an error returned by `cb` stops verification
+/// and is returned.  On success, returns the number of packages.
+///
+/// A package that fails verification does not stop the others from being
+/// verified, but an error in the bundle itself (such as truncation) does.
+pub fn verify_bundle(
+    src: &mut dyn Read,
+    keyring: &Keyring<'_>,
+    policy: &Policy,
+    token: InitToken,
+    cb: &mut dyn FnMut(usize, Result<VerifyResult>) -> Result<()>,
+) -> Result<usize> {
+    let mut bundle = BundleReader::new(src)?;
+    let mut index = 0;
+    while let Some(mut package) = bundle.next_package()? {
+        let verdict = (|| {
+            read_lead(&mut package)?;
+            let mut sig_header = load_signature(&mut package, policy.allow_weak_hashes, token)?;
+            let result = keyring.verify_package(
+                &mut package,
+                &mut sig_header,
+                policy.allow_old_pkgs,
+                false,
+                token,
+                Some(&mut |result, _| policy.check(result)),
+                None,
+            )?;
+            fail_if!(package.remaining() != 0, "data after end of package");
+            Ok(result)
+        })();
+        cb(index, verdict)?;
+        index += 1;
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpm_writer::testing::{placeholder_signature, TestPackage};
+
+    fn bundle(packages: &[&[u8]]) -> Vec<u8> {
+        let mut writer = BundleWriter::new(vec![]).unwrap();
+        for package in packages {
+            writer.add(&mut &package[..], package.len() as _).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn reads_bundles() {
+        let data = bundle(&[b"first", b"second package", b"third"]);
+        assert_eq!(data.len(), 8 + 3 * 8 + 24 + 8);
+        let mut reader = BundleReader::new(&data[..]).unwrap();
+        let mut first = vec![];
+        reader
+            .next_package()
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut first)
+            .unwrap();
+        assert_eq!(first, b"first");
+        // Partially read, then skipped
+        let mut second = reader.next_package().unwrap().unwrap();
+        assert_eq!(second.read(&mut [0; 6]).unwrap(), 6);
+        assert_eq!(second.remaining(), 8);
+        let mut third = vec![];
+        reader
+            .next_package()
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut third)
+            .unwrap();
+        assert_eq!(third, b"third");
+        assert!(reader.next_package().unwrap().is_none());
+        assert!(reader.next_package().unwrap().is_none());
+        assert!(BundleReader::new(&bundle(&[])[..])
+            .unwrap()
+            .next_package()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_bad_bundles() {
+        let data = bundle(&[b"first", b"second"]);
+        assert!(BundleReader::new(&data[1..]).is_err());
+        let next_all = |data: &[u8]| -> Result<usize> {
+            let mut reader = BundleReader::new(data)?;
+            let mut count = 0;
+            while reader.next_package()?.is_some() {
+                count += 1
+            }
+            Ok(count)
+        };
+        assert_eq!(next_all(&data).unwrap(), 2);
+        // Missing end marker, truncated package, and trailing data
+        assert!(next_all(&data[..data.len() - 8]).is_err());
+        assert!(next_all(&data[..data.len() - 10]).is_err());
+        assert!(next_all(&[&data[..], b"x"].concat()).is_err());
+        let mut reader = BundleReader::new(&data[..data.len() - 10]).unwrap();
+        reader.next_package().unwrap();
+        let mut second = reader.next_package().unwrap().unwrap();
+        let e = second.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        let mut writer = BundleWriter::new(vec![]).unwrap();
+        assert!(writer.add(&mut &b"short"[..], 6).is_err());
+        assert!(writer.add(&mut &b""[..], 0).is_err());
+    }
+
+    #[test]
+    fn verifies_bundles() {
+        let token = rpm_crypto::init();
+        // The test signature covers the main header of this package
+        let mut package = TestPackage::new("qubes-template-test");
+        package.scriptlets.push((1024, "echo hi".to_owned()));
+        package.signature = include_bytes!("../../template-header.sig").to_vec();
+        let signed = package.build(token).unwrap();
+        package.signature = placeholder_signature();
+        let unsigned = package.build(token).unwrap();
+        let data = bundle(&[&signed, &unsigned, &signed]);
+        let mut keyring = Keyring::new();
+        keyring
+            .add(include_bytes!("../../template-key.pgp"), token)
+            .unwrap();
+        let mut verdicts = vec![];
+        let count = verify_bundle(
+            &mut &data[..],
+            &keyring,
+            &Policy::default(),
+            token,
+            &mut |index, verdict| {
+                verdicts.push((index, verdict.is_ok()));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(verdicts, [(0, true), (1, false), (2, true)]);
+    }
+}
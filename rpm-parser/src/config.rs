@@ -0,0 +1,105 @@
+//! Signed configuration files
+//!
+//! Provisioning pipelines often fetch kickstart files, cloud-init user data,
+//! and similar bootstrap configuration from places they do not trust, signed
+//! with GnuPG.  [`verify_config`] checks such a file with the same parser
+//! used for packages, and says which key in a [`Keyring`] signed it.  The
+//! signature may be inline (a cleartext-signed file) or detached, binary or
+//! ASCII-armored.
+
+use crate::verify::{openpgp_error, parse_cleartext};
+use crate::{KeyRef, Keyring};
+use openpgp_parser::signature::{ParseOptions, SignatureKind};
+use openpgp_parser::{armor, AllowWeakHashes};
+use rpm_crypto::{InitToken, Signature};
+use std::borrow::Cow;
+use std::io::Result;
+
+/// A verified configuration file
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SignedConfig<'k, 'a> {
+    /// The signed contents.  For a cleartext-signed file, this is the signed
+    /// text in canonical form (see [`crate::verify_cleartext`]); otherwise,
+    /// it is the file exactly as given.
+    pub contents: Vec<u8>,
+    /// The key that made the signature
+    pub signer: KeyRef<'k, 'a>,
+}
+
+/// Decodes `signature` if it is ASCII-armored
+fn binary(signature: &[u8]) -> Result<Cow<'_, [u8]>> {
+    match signature.first() {
+        Some(i) if i & 0x80 != 0 => Ok(Cow::Borrowed(signature)),
+        _ => match armor::decode(signature) {
+            Ok((armor::Kind::Signature, decoded)) => Ok(Cow::Owned(decoded)),
+            Ok((kind, _)) => bad_data!("expected a signature, got {:?}", kind),
+            Err(e) => bad_data!("bad ASCII armor: {:?}", e),
+        },
+    }
+}
+
+/// Converts every line ending in `text` to CR LF, as a text signature
+/// requires
+fn canonical_text(text: &[u8]) -> Vec<u8> {
+    let mut canonical = Vec::with_capacity(text.len());
+    let mut last = 0;
+    for &c in text {
+        if c == b'\n' && last != b'\r' {
+            canonical.push(b'\r')
+        }
+        canonical.push(c);
+        last = c
+    }
+    canonical
+}
+
+/// Verify a signed configuration file.  If `signature` is [`None`], `file`
+/// must be cleartext-signed, and is checked as by [`crate::verify_cleartext`].
+/// Otherwise, `signature` is a detached signature over `file`, binary or
+/// ASCII-armored, and may be a binary or a text signature.  The signer is
+/// found in `keyring` as by [`Keyring::verify`].
+pub fn verify_config<'k, 'a>(
+    file: &[u8],
+    signature: Option<&[u8]>,
+    keyring: &'k Keyring<'a>,
+    allow_weak_hashes: AllowWeakHashes,
+    token: InitToken,
+) -> Result<SignedConfig<'k, 'a>> {
+    let (contents, sig) = match signature {
+        None => parse_cleartext(file, allow_weak_hashes, token)?,
+        Some(signature) => {
+            let mut options = ParseOptions::new(allow_weak_hashes);
+            options.kind = None;
+            let mut sig = Signature::parse_with_options(&binary(signature)?, 0, &options, token)
+                .map_err(openpgp_error)?;
+            match sig.info().kind {
+                SignatureKind::Text => sig.update(&canonical_text(file)),
+                _ => sig.update(file),
+            }
+            (file.to_vec(), sig)
+        }
+    };
+    let signer = keyring.verify(&sig, token)?;
+    Ok(SignedConfig { contents, signer })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonicalizes_line_endings() {
+        assert_eq!(canonical_text(b"a\nb\r\n\nc"), b"a\r\nb\r\n\r\nc");
+        assert_eq!(canonical_text(b"\n"), b"\r\n");
+        assert_eq!(canonical_text(b"no newline"), b"no newline");
+    }
+
+    #[test]
+    fn rejects_wrong_armor() {
+        let key = armor::encode(armor::Kind::PublicKey, b"\x99");
+        let e = binary(&key).unwrap_err();
+        assert_eq!(e.to_string(), "expected a signature, got PublicKey");
+        assert!(binary(b"not armor").is_err());
+    }
+}
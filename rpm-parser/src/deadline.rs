@@ -0,0 +1,88 @@
+//! Time limits for verification
+//!
+//! The verification functions read their input in chunks.  Wrapping the input
+//! in a [`Deadline`] makes every read after the deadline fail with
+//! [`DeadlineExceeded`], so a slow source or a huge payload cannot hold up the
+//! caller for longer than it is willing to wait.  The deadline is only checked
+//! between reads, so a single read from a blocking source can still overrun
+//! it.
+
+use std::io::{Error, ErrorKind, Read, Result};
+use std::time::{Duration, Instant};
+
+/// The deadline of a [`Deadline`] passed before the operation finished.  This
+/// is wrapped in a [`std::io::Error`] of kind [`ErrorKind::TimedOut`]; use
+/// [`DeadlineExceeded::is`] to detect it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl std::fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+impl DeadlineExceeded {
+    /// Was `e` caused by an exceeded deadline?
+    pub fn is(e: &Error) -> bool {
+        e.get_ref().is_some_and(|inner| inner.is::<Self>())
+    }
+}
+
+/// A reader that fails once a deadline has passed
+#[derive(Debug)]
+pub struct Deadline<R> {
+    inner: R,
+    deadline: Instant,
+}
+
+impl<R: Read> Deadline<R> {
+    /// Wraps `inner`, failing reads at or after `deadline`
+    pub fn new(inner: R, deadline: Instant) -> Self {
+        Self { inner, deadline }
+    }
+
+    /// Wraps `inner`, failing reads once `timeout` has elapsed from now
+    pub fn after(inner: R, timeout: Duration) -> Self {
+        Self::new(inner, Instant::now() + timeout)
+    }
+
+    /// The deadline
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Unwraps the reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Deadline<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if Instant::now() >= self.deadline {
+            return Err(Error::new(ErrorKind::TimedOut, DeadlineExceeded));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn enforces_deadline() {
+        let data = [1u8; 100];
+        let mut r = Deadline::after(&data[..], Duration::from_secs(3600));
+        let mut out = vec![];
+        assert_eq!(r.read_to_end(&mut out).unwrap(), 100);
+        let mut r = Deadline::new(&data[..], Instant::now());
+        let e = r.read(&mut [0; 10]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert!(DeadlineExceeded::is(&e));
+        assert!(crate::error_category(&e).is_none());
+        assert!(!DeadlineExceeded::is(&Error::from(ErrorKind::TimedOut)));
+    }
+}
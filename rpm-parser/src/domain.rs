@@ -0,0 +1,117 @@
+//! Verification domains
+//!
+//! An OpenPGP binary signature only says that a key signed some bytes, not
+//! what those bytes were meant to be.  A distribution key signs package
+//! headers and repository metadata alike, so a signature valid for one kind
+//! of artifact could be replayed as a signature for another.  A [`Domain`]
+//! names the kind of artifact, and a [`Verified`] value carries the domain
+//! it was verified in, so that callers handling several kinds of artifacts
+//! must say which one they expect before using the result.
+
+use crate::{verify_detached, PolicyViolation};
+use openpgp_parser::AllowWeakHashes;
+use rpm_crypto::{transaction::RpmKeyring, InitToken};
+use std::io::{Read, Result};
+
+/// The kind of artifact a signature was verified for
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Domain(&'static str);
+
+impl Domain {
+    /// The header signature of an RPM package
+    pub const RPM_HEADER: Domain = Domain("rpm-header-v4");
+    /// A whole RPM package: header signature and payload digests
+    pub const RPM_PACKAGE: Domain = Domain("rpm-package");
+    /// A detached signature over `repomd.xml`
+    pub const REPOMD_DETACHED: Domain = Domain("repomd-detached");
+
+    /// A domain for another kind of artifact.  Names should be specific
+    /// enough to never clash with another use of the same key.
+    pub const fn new(name: &'static str) -> Self {
+        Domain(name)
+    }
+
+    /// The name of the domain
+    pub fn name(self) -> &'static str {
+        self.0
+    }
+}
+
+impl std::fmt::Display for Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A value verified in a given domain.  The value can only be taken out by
+/// naming the domain it is expected to come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[must_use = "a verified value must be checked against its expected domain"]
+pub struct Verified<T> {
+    domain: Domain,
+    value: T,
+}
+
+impl<T> Verified<T> {
+    pub(crate) fn new(domain: Domain, value: T) -> Self {
+        Self { domain, value }
+    }
+
+    /// The domain the value was verified in
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    /// Returns the value if it was verified in `expected`, and a
+    /// [`PolicyViolation`] otherwise
+    pub fn require(self, expected: Domain) -> Result<T> {
+        if self.domain == expected {
+            Ok(self.value)
+        } else {
+            Err(PolicyViolation::error(format!(
+                "Signature verified for {}, but {} was expected",
+                self.domain, expected
+            )))
+        }
+    }
+}
+
+/// Verify a detached signature over the data read from `src`, as
+/// [`verify_detached`] does, recording that it was verified for `domain`
+pub fn verify_detached_in(
+    domain: Domain,
+    src: &mut dyn Read,
+    untrusted_sig: &[u8],
+    keyring: &RpmKeyring,
+    allow_weak_hashes: AllowWeakHashes,
+    token: InitToken,
+) -> Result<Verified<()>> {
+    verify_detached(src, untrusted_sig, keyring, allow_weak_hashes, token)?;
+    Ok(Verified::new(domain, ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_domains() {
+        let verified = Verified::new(Domain::REPOMD_DETACHED, 5);
+        assert_eq!(verified.domain().name(), "repomd-detached");
+        assert_eq!(
+            verified.clone().require(Domain::REPOMD_DETACHED).unwrap(),
+            5
+        );
+        let e = verified.require(Domain::RPM_HEADER).unwrap_err();
+        assert_eq!(
+            crate::error_category(&e),
+            Some(crate::ErrorCategory::Policy)
+        );
+        assert_eq!(
+            e.to_string(),
+            "Signature verified for repomd-detached, but rpm-header-v4 was expected"
+        );
+        const CUSTOM: Domain = Domain::new("example-manifest");
+        assert_ne!(CUSTOM, Domain::RPM_PACKAGE);
+    }
+}
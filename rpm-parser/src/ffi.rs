@@ -4,6 +4,7 @@
 extern "C" {
     fn rpmTagGetType(tag: std::os::raw::c_int) -> std::os::raw::c_int;
     fn rpmTagTypeGetClass(tag: std::os::raw::c_int) -> std::os::raw::c_int;
+    fn rpmTagGetValue(tagstr: *const std::os::raw::c_char) -> std::os::raw::c_int;
 }
 
 #[repr(u32)]
@@ -60,6 +61,41 @@ pub fn tag_class(ty: TagType) -> std::os::raw::c_int {
     unsafe { rpmTagTypeGetClass(ty as _) }
 }
 
+/// Historical tag names that RPM only knows as preprocessor macros, and the
+/// names they stand for
+const TAG_ALIASES: &[(&str, &str)] = &[
+    ("conflicts", "conflictname"),
+    ("copyright", "license"),
+    ("hdrid", "sha1header"),
+    ("obsoletes", "obsoletename"),
+    ("pkgid", "sigmd5"),
+    ("provides", "providename"),
+    ("requires", "requirename"),
+    ("serial", "epoch"),
+];
+
+/// Strip any `RPMTAG_` prefix from `name` and resolve aliases.  The result is
+/// lowercase.
+fn canonical_tag_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_prefix("rpmtag_").unwrap_or(&name);
+    match TAG_ALIASES.iter().find(|i| i.0 == name) {
+        Some(&(_, canonical)) => canonical.to_owned(),
+        None => name.to_owned(),
+    }
+}
+
+/// Look up a tag by name, as used in RPM query formats.  Names are
+/// case-insensitive, may have an `RPMTAG_` prefix, and may be historical
+/// aliases such as `Serial`.
+pub fn tag_by_name(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(canonical_tag_name(name)).ok()?;
+    match unsafe { rpmTagGetValue(name.as_ptr()) } {
+        tag if tag > 0 => Some(tag as u32),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,4 +105,21 @@ mod tests {
             tag_type(i);
         }
     }
+
+    #[test]
+    fn canonicalizes_tag_names() {
+        assert_eq!(canonical_tag_name("Obsoletes"), "obsoletename");
+        assert_eq!(canonical_tag_name("RPMTAG_SERIAL"), "epoch");
+        assert_eq!(canonical_tag_name("rpmtag_Name"), "name");
+        assert_eq!(canonical_tag_name("PayloadDigest"), "payloaddigest");
+    }
+
+    #[test]
+    fn check_rpm_tag_names() {
+        assert_eq!(tag_by_name("NAME"), Some(1000));
+        assert_eq!(tag_by_name("Obsoletes"), Some(1090));
+        assert_eq!(tag_by_name("serial"), Some(1003));
+        assert_eq!(tag_by_name("no such tag"), None);
+        assert_eq!(tag_by_name("nul\0"), None);
+    }
 }
@@ -0,0 +1,238 @@
+//! Checks on the file list of a package
+//!
+//! Symbolic links are a classic way for a package to write outside of where it
+//! appears to write: a symlink to `/etc` followed by a file “inside” the
+//! symlink.  This module reconstructs the file list from the main header and
+//! reports such constructs.  It does not look at the payload, so it is only
+//! meaningful for packages whose payload digest has been verified.
+
+use crate::{RPMHeader, TagValue};
+use std::collections::BTreeSet;
+
+const RPMTAG_FILEMODES: u32 = 1030;
+const RPMTAG_FILELINKTOS: u32 = 1036;
+const RPMTAG_DIRINDEXES: u32 = 1116;
+const RPMTAG_BASENAMES: u32 = 1117;
+const RPMTAG_DIRNAMES: u32 = 1118;
+
+const S_IFMT: u16 = 0o170000;
+const S_IFLNK: u16 = 0o120000;
+
+/// Policy for symbolic links
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SymlinkPolicy {
+    /// Directories that symlinks may not point into.  Each must be an
+    /// absolute, normalized path.
+    pub forbidden_targets: Vec<String>,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        let forbidden_targets = [
+            "/boot",
+            "/dev",
+            "/etc",
+            "/home",
+            "/proc",
+            "/root",
+            "/run",
+            "/sys",
+            "/var/spool",
+        ];
+        Self {
+            forbidden_targets: forbidden_targets.iter().map(|&i| i.to_owned()).collect(),
+        }
+    }
+}
+
+/// A problem found by [`check_symlinks`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SymlinkFinding {
+    /// A symlink resolves into one of [`SymlinkPolicy::forbidden_targets`]
+    ForbiddenTarget {
+        /// The symlink
+        path: String,
+        /// Its target, as stored in the header
+        target: String,
+    },
+    /// A symlink points outside of the filesystem root (too many `..`)
+    EscapesRoot {
+        /// The symlink
+        path: String,
+        /// Its target, as stored in the header
+        target: String,
+    },
+    /// A file is installed under a path that the package also installs as a
+    /// symlink, so the file would be written through the symlink
+    ThroughSymlink {
+        /// The file
+        path: String,
+        /// The symlink it would be written through
+        symlink: String,
+    },
+    /// The same path occurs more than once in the file list
+    DuplicatePath(String),
+}
+
+/// Lexically normalize `path`, which must be absolute.  Returns [`None`] if
+/// `..` would leave the root.
+fn normalize(path: &str) -> Option<String> {
+    let mut components = vec![];
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            c => components.push(c),
+        }
+    }
+    Some(format!("/{}", components.join("/")))
+}
+
+/// Is `path` equal to `dir` or inside of it?
+fn is_under(path: &str, dir: &str) -> bool {
+    match path.strip_prefix(dir) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || dir.ends_with('/'),
+        None => false,
+    }
+}
+
+/// Reconstruct the file list of `header` as `(path, mode, link target)`
+/// triples.  Returns [`None`] if the file list tags are inconsistent.
+/// Packages with no files return an empty list.
+pub fn file_list(header: &RPMHeader) -> Option<Vec<(String, u16, String)>> {
+    let (basenames, dirnames, dirindexes) = match (
+        header.get(RPMTAG_BASENAMES),
+        header.get(RPMTAG_DIRNAMES),
+        header.get(RPMTAG_DIRINDEXES),
+    ) {
+        (None, None, None) => return Some(vec![]),
+        (
+            Some(TagValue::StringArray(basenames)),
+            Some(TagValue::StringArray(dirnames)),
+            Some(TagValue::Int32(dirindexes)),
+        ) => (basenames, dirnames, dirindexes),
+        _ => return None,
+    };
+    let modes = match header.get(RPMTAG_FILEMODES) {
+        Some(TagValue::Int16(modes)) => modes,
+        _ => return None,
+    };
+    let linktos = match header.get(RPMTAG_FILELINKTOS) {
+        Some(TagValue::StringArray(linktos)) => linktos,
+        _ => return None,
+    };
+    let count = basenames.len();
+    if dirindexes.len() != count || modes.len() != count || linktos.len() != count {
+        return None;
+    }
+    let mut files = Vec::with_capacity(count);
+    for i in 0..count {
+        let dirname = dirnames.get(dirindexes[i] as usize)?;
+        files.push((
+            format!("{}{}", dirname, basenames[i]),
+            modes[i],
+            linktos[i].to_owned(),
+        ))
+    }
+    Some(files)
+}
+
+/// Check the symlinks in the file list of `header` against `policy`.
+/// Relative targets are resolved against the directory containing the
+/// symlink.  Returns [`None`] if the file list cannot be reconstructed (see
+/// [`file_list`]).
+pub fn check_symlinks(header: &RPMHeader, policy: &SymlinkPolicy) -> Option<Vec<SymlinkFinding>> {
+    let files = file_list(header)?;
+    let mut findings = vec![];
+    let mut seen = BTreeSet::new();
+    let mut symlinks = BTreeSet::new();
+    for (path, mode, target) in &files {
+        if !seen.insert(path) {
+            findings.push(SymlinkFinding::DuplicatePath(path.clone()))
+        }
+        if mode & S_IFMT != S_IFLNK {
+            continue;
+        }
+        symlinks.insert(path.as_str());
+        let absolute = if target.starts_with('/') {
+            target.clone()
+        } else {
+            let dir = &path[..path.rfind('/').unwrap_or(0)];
+            format!("{}/{}", dir, target)
+        };
+        match normalize(&absolute) {
+            None => findings.push(SymlinkFinding::EscapesRoot {
+                path: path.clone(),
+                target: target.clone(),
+            }),
+            Some(resolved) => {
+                if policy
+                    .forbidden_targets
+                    .iter()
+                    .any(|dir| is_under(&resolved, dir))
+                {
+                    findings.push(SymlinkFinding::ForbiddenTarget {
+                        path: path.clone(),
+                        target: target.clone(),
+                    })
+                }
+            }
+        }
+    }
+    for (path, _, _) in &files {
+        // Check every proper ancestor of the path
+        let mut dir = path.as_str();
+        while let Some(slash) = dir.rfind('/') {
+            dir = &dir[..slash];
+            if symlinks.contains(dir) {
+                findings.push(SymlinkFinding::ThroughSymlink {
+                    path: path.clone(),
+                    symlink: dir.to_owned(),
+                });
+                break;
+            }
+        }
+    }
+    Some(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize("/usr/lib/../bin/./lua").unwrap(), "/usr/bin/lua");
+        assert_eq!(normalize("/usr/lib/../../../etc"), None);
+        assert!(is_under("/etc/shadow", "/etc"));
+        assert!(is_under("/etc", "/etc"));
+        assert!(!is_under("/etcetera", "/etc"));
+        assert!(is_under("/etc", "/"));
+    }
+
+    #[test]
+    fn checks_lua_rpm() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let header =
+            crate::header::load_header(&mut &s[4504..], 63, &mut |_, _, _| Ok(())).unwrap();
+        let files = file_list(&header).unwrap();
+        assert!(files.iter().any(|(path, _, _)| path == "/usr/bin/lua"));
+        assert_eq!(check_symlinks(&header, &Default::default()).unwrap(), []);
+        // The build ID links are relative and point to /usr/bin
+        let policy = SymlinkPolicy {
+            forbidden_targets: vec!["/usr/bin".to_owned()],
+        };
+        let findings = check_symlinks(&header, &policy).unwrap();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(
+            findings[0],
+            SymlinkFinding::ForbiddenTarget {
+                path: "/usr/lib/.build-id/73/4c37254b5a8fd2f70a1650cc41d4d2bc4032c1".to_owned(),
+                target: "../../../../usr/bin/luac".to_owned(),
+            }
+        );
+    }
+}
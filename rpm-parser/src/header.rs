@@ -4,12 +4,20 @@
 
 #![deny(warnings)]
 mod common;
+mod events;
+mod file;
 mod immutable;
+mod inspect;
+mod repair;
 mod signature;
-use common::load_header;
-pub use common::{parse_header_magic, Header, RPM_HDRMAGIC};
+pub(crate) use common::load_header;
+pub use common::{parse_header_magic, Header, TagValue, RPM_HDRMAGIC};
+pub use events::parse_header_events;
+pub use file::load_header_file;
 pub use immutable::{load_immutable, ImmutableHeader};
-pub use signature::{load_signature, SignatureHeader};
+pub use inspect::{inspect_header, HeaderFinding};
+pub use repair::{repair_header, repair_package, PackageRepairs, Repair};
+pub use signature::{load_signature, load_signature_with_options, SignatureHeader};
 use std::io::Result;
 
 /// Check that a `Reader` is a properly formatted, NUL-terminated hex string.
@@ -30,6 +38,7 @@ fn check_hex(untrusted_body: &[u8]) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::StringMode;
     use openpgp_parser::AllowWeakHashes;
     #[test]
     fn parses_lua_rpm() {
@@ -43,12 +52,26 @@ mod tests {
             header_payload_weak_digest,
             header_sha1_hash,
             header_sha256_hash,
+            unknown_tags,
         } = load_signature(&mut r, AllowWeakHashes::No, token).unwrap();
+        assert!(unknown_tags.is_empty());
         assert!(header_signature.is_some());
         assert!(header_payload_signature.is_some());
         assert!(header_payload_weak_digest.is_some());
         assert!(header_sha1_hash.is_some());
         assert!(header_sha256_hash.is_some());
+        let immutable = load_immutable(&mut r, token).unwrap();
+        assert_eq!(
+            immutable
+                .checked_summary(StringMode::Strict)
+                .unwrap()
+                .unwrap(),
+            "Powerful light-weight programming language"
+        );
+        let description = immutable.checked_description(StringMode::Strict).unwrap();
+        assert!(description.unwrap().starts_with(
+            "Lua is a powerful light-weight programming language designed for\nextending"
+        ));
         let ImmutableHeader {
             header: _,
             payload_digest,
@@ -61,7 +84,7 @@ mod tests {
             arch,
             source,
             ..
-        } = load_immutable(&mut r, token).unwrap();
+        } = immutable;
         let payload_digest = payload_digest.unwrap();
         assert_eq!(payload_digest.len(), 65);
         assert_eq!(payload_digest_algorithm.unwrap(), 8);
@@ -21,6 +21,85 @@ pub struct Header {
     pub data: Vec<u8>,
 }
 
+/// The value of a header entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagValue<'a> {
+    /// Array of characters
+    Char(&'a [u8]),
+    /// Array of [`u8`]
+    Int8(&'a [u8]),
+    /// Array of [`u16`]
+    Int16(Vec<u16>),
+    /// Array of [`u32`]
+    Int32(Vec<u32>),
+    /// Array of [`u64`]
+    Int64(Vec<u64>),
+    /// String
+    String(&'a str),
+    /// Binary blob
+    Bin(&'a [u8]),
+    /// String array
+    StringArray(Vec<&'a str>),
+    /// Internationalized string
+    I18NString(Vec<&'a str>),
+}
+
+impl<'a> TagValue<'a> {
+    /// Parse the value of `entry`, whose data starts at the beginning of
+    /// `data`.  Returns [`None`] if the value does not fit in `data` or is not
+    /// valid.
+    pub fn parse(entry: &TagData, data: &'a [u8]) -> Option<Self> {
+        let count = entry.count() as usize;
+        macro_rules! ints {
+            ($t: ty, $v: ident) => {{
+                let data = data.get(..count.checked_mul(size_of!($t))?)?;
+                TagValue::$v(
+                    data.chunks_exact(size_of!($t))
+                        .map(|i| <$t>::from_be_bytes(i.try_into().expect("length is correct")))
+                        .collect(),
+                )
+            }};
+        }
+        let strings = || -> Option<Vec<&'a str>> {
+            let mut reader = Reader::new(data);
+            (0..count)
+                .map(|_| {
+                    let len = reader.as_untrusted_slice().iter().position(|&i| i == 0)?;
+                    let s = reader.get_bytes(len + 1).expect("length is in bounds; qed");
+                    std::str::from_utf8(&s[..len]).ok()
+                })
+                .collect()
+        };
+        Some(match entry.ty() {
+            1 => TagValue::Char(data.get(..count)?),
+            2 => TagValue::Int8(data.get(..count)?),
+            3 => ints!(u16, Int16),
+            4 => ints!(u32, Int32),
+            5 => ints!(u64, Int64),
+            6 if count == 1 => TagValue::String(strings()?[0]),
+            7 => TagValue::Bin(data.get(..count)?),
+            8 => TagValue::StringArray(strings()?),
+            9 => TagValue::I18NString(strings()?),
+            _ => return None,
+        })
+    }
+}
+
+impl Header {
+    /// Retrieves the value of `tag`, or [`None`] if it is not present.
+    pub fn get(&self, tag: u32) -> Option<TagValue<'_>> {
+        let index = self.index.get(1..)?;
+        let entry = &index[index.binary_search_by_key(&tag, TagData::tag).ok()?];
+        TagValue::parse(entry, self.data.get(entry.offset() as usize..)?)
+    }
+
+    /// Retrieves the value of the tag named `name`.  See
+    /// [`crate::tag_by_name`] for the names accepted.
+    pub fn get_by_name(&self, name: &str) -> Option<TagValue<'_>> {
+        self.get(crate::ffi::tag_by_name(name)?)
+    }
+}
+
 pub fn parse_header_magic<'a>(data: &[u8; 16]) -> Result<(u32, u32)> {
     if data[..8] != RPM_HDRMAGIC[..] {
         return Err(Error::new(ErrorKind::InvalidData, "wrong header magic"));
@@ -34,7 +113,7 @@ pub fn parse_header_magic<'a>(data: &[u8; 16]) -> Result<(u32, u32)> {
     Ok((index_length, data_length))
 }
 
-pub fn read_header(r: &mut dyn Read) -> Result<(u32, u32)> {
+pub(super) fn read_header(r: &mut dyn Read) -> Result<(u32, u32)> {
     let _: [u8; 0] = [0u8; if size_of!(usize) >= size_of!(u32) {
         0
     } else {
@@ -48,7 +127,7 @@ pub fn read_header(r: &mut dyn Read) -> Result<(u32, u32)> {
     Ok((index_length as _, data_length as _))
 }
 
-const TAG_REGISTRY: &[(TagType, usize, Option<usize>)] = &[
+pub(super) const TAG_REGISTRY: &[(TagType, usize, Option<usize>)] = &[
     (TagType::Char, 0, Some(1)),
     (TagType::Int8, 0, Some(1)),
     (TagType::Int16, 1, Some(2)),
@@ -60,7 +139,7 @@ const TAG_REGISTRY: &[(TagType, usize, Option<usize>)] = &[
     (TagType::I18NString, 0, None),
 ];
 
-pub(super) fn load_header<'a>(
+pub(crate) fn load_header<'a>(
     r: &mut dyn Read,
     region_tag: u32,
     cb: &mut dyn FnMut(TagType, &TagData, &[u8]) -> Result<()>,
@@ -103,6 +182,7 @@ pub(super) fn load_header<'a>(
     for entry in &index[1..] {
         let tag = entry.tag();
         fail_if!(tag <= last_tag, "entries not sorted");
+        last_tag = tag;
         let &(ty, align, size) = match TAG_REGISTRY.get(entry.ty().wrapping_sub(1) as usize) {
             None => bad_data!("Entry {:?} has an invalid type {}", entry, entry.ty()),
             Some(s) => s,
@@ -180,4 +260,39 @@ mod tests {
             ErrorKind::InvalidData
         );
     }
+
+    #[test]
+    fn gets_values() {
+        let s: &[u8] = include_bytes!("../../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let header = load_header(&mut &s[96..], 62, &mut |_, _, _| Ok(())).unwrap();
+        assert_eq!(header.get(1000), Some(TagValue::Int32(vec![188554])));
+        assert_eq!(
+            header.get(256 + 13),
+            Some(TagValue::String("766bbb99851dfefc3ba04f1564b0962652e3bb3c"))
+        );
+        match header.get(1004) {
+            Some(TagValue::Bin(s)) => assert_eq!(s.len(), 16),
+            e => panic!("bad value {:?}", e),
+        }
+        assert_eq!(header.get(62), None);
+        assert_eq!(header.get(1001), None);
+    }
+
+    #[test]
+    fn rejects_unsorted_index() {
+        let s: &[u8] = include_bytes!("../../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let header = &s[4504..];
+        let first_tag = u32::from_be_bytes(header[32..36].try_into().unwrap());
+        // A duplicate tag, then a tag out of order, in the second entry
+        for &tag in &[first_tag, first_tag - 1] {
+            let mut bad = header.to_vec();
+            bad[48..52].copy_from_slice(&tag.to_be_bytes());
+            let e = match load_header(&mut &bad[..], 63, &mut |_, _, _| Ok(())) {
+                Ok(_) => panic!("tag {} accepted after tag {}", tag, first_tag),
+                Err(e) => e,
+            };
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+            assert_eq!(e.to_string(), "entries not sorted");
+        }
+    }
 }
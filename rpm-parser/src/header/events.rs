@@ -0,0 +1,151 @@
+//! Event-driven header parsing
+//!
+//! [`super::load_header`] reads an entire header into memory before handing it
+//! to the caller.  [`parse_header_events`] instead reports each entry as it is
+//! read, so only the index and the entry currently being parsed are held in
+//! memory.  It enforces the same rules as [`super::load_header`].
+
+use super::common::{read_header, TAG_REGISTRY};
+use crate::ffi::TagType;
+use crate::{TagData, TagValue};
+use std::io::{Read, Result};
+
+/// Parse the header read from `r`, calling `cb` with the tag, type, and value
+/// of each entry in index order.  `region_tag` is 62 for signature headers
+/// and 63 for main headers.  An error returned by `cb` stops parsing and is
+/// returned.
+///
+/// The region trailer is at the end of the header, so it is only checked
+/// after every entry has been reported.  Callers must not act on the events
+/// until this function has returned [`Ok`].  Strings are read one byte at a
+/// time, so `r` should be buffered.
+pub fn parse_header_events(
+    r: &mut dyn Read,
+    region_tag: u32,
+    cb: &mut dyn FnMut(u32, TagType, TagValue<'_>) -> Result<()>,
+) -> Result<()> {
+    let (index_length, data_length) = read_header(r)?;
+    let mut index = vec![TagData::default(); index_length as _];
+    r.read_exact(TagData::as_bytes_mut(&mut index))?;
+    let region = index[0];
+    let region_offset = data_length as usize - 16;
+    if region.count() != 16
+        || region.ty() != TagType::Bin as _
+        || region.offset() as usize != region_offset
+    {
+        bad_data!("bad region trailer location {:?}", region)
+    }
+    fail_if!(
+        region.tag() != region_tag,
+        "bad region kind {}, expected {}",
+        region.tag(),
+        region_tag
+    );
+    let mut cursor = 0;
+    let mut last_tag = 99;
+    let mut buf = vec![];
+    for entry in &index[1..] {
+        let tag = entry.tag();
+        fail_if!(tag <= last_tag, "entries not sorted");
+        last_tag = tag;
+        let &(ty, align, size) = match TAG_REGISTRY.get(entry.ty().wrapping_sub(1) as usize) {
+            None => bad_data!("Entry {:?} has an invalid type {}", entry, entry.ty()),
+            Some(s) => s,
+        };
+        let offset = entry.offset() as usize;
+        let count = entry.count() as usize;
+        if count == 0 || count >= data_length as usize {
+            bad_data!("Entry {:?} has invalid count", entry)
+        }
+        fail_if!(
+            offset & align != 0,
+            "Entry {:?} is not properly aligned",
+            entry
+        );
+        fail_if!(offset < cursor, "Entry {:?} overlaps previous entry", entry);
+        let padding = offset - cursor;
+        fail_if!(
+            padding > align,
+            "Entry {:?} has too much padding ({})",
+            entry,
+            padding
+        );
+        let mut pad = [0u8; 8];
+        r.read_exact(&mut pad[..padding])?;
+        fail_if!(
+            pad != [0u8; 8],
+            "Entry {:?} has padding that is not zeroed",
+            entry
+        );
+        cursor = offset;
+        let available = region_offset.saturating_sub(cursor);
+        buf.clear();
+        match size {
+            Some(s) => {
+                let len = s * count;
+                fail_if!(len > available, "Entry {:?} is too long", entry);
+                buf.resize(len, 0);
+                r.read_exact(&mut buf)?
+            }
+            None => {
+                fail_if!(
+                    ty == TagType::String && count != 1,
+                    "Entry {:?} is a string with nonunit count",
+                    entry
+                );
+                let mut remaining = count;
+                while remaining != 0 {
+                    fail_if!(
+                        buf.len() >= available,
+                        "Entry {:?} is a too long string array",
+                        entry
+                    );
+                    let mut byte = [0u8];
+                    r.read_exact(&mut byte)?;
+                    buf.push(byte[0]);
+                    remaining -= (byte[0] == 0) as usize;
+                }
+                if let Err(e) = std::str::from_utf8(&buf) {
+                    bad_data!("String entry is not valid UTF-8: {}", e)
+                }
+            }
+        }
+        cursor += buf.len();
+        let value = TagValue::parse(entry, &buf).expect("entry validated above");
+        cb(tag, ty, value)?
+    }
+    fail_if!(
+        cursor != region_offset,
+        "{} bytes of trailing junk",
+        region_offset - cursor
+    );
+    let mut trailer = [TagData::default()];
+    r.read_exact(TagData::as_bytes_mut(&mut trailer))?;
+    let [trailer] = trailer;
+    if trailer != TagData::new(region_tag, 7, (-16 * index_length as i32) as u32, 16) {
+        bad_data!("bad region trailer {:?}", trailer)
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn matches_load_header() {
+        let s: &[u8] = include_bytes!("../../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let header = super::super::load_header(&mut &s[4504..], 63, &mut |_, _, _| Ok(())).unwrap();
+        let mut count = 1;
+        parse_header_events(&mut &s[4504..], 63, &mut |tag, ty, value| {
+            let entry = header.index[count];
+            count += 1;
+            assert_eq!((tag, ty as u32), (entry.tag(), entry.ty()));
+            assert_eq!(Some(value), header.get(tag));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, header.index.len());
+        // The signature header is not a main header
+        parse_header_events(&mut &s[96..], 63, &mut |_, _, _| Ok(())).unwrap_err();
+    }
+}
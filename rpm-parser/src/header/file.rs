@@ -0,0 +1,45 @@
+//! Bare header files
+//!
+//! Some tools store the main header of a package on its own, without the lead,
+//! signature header, or payload: dnf’s `*.hdr` cache files are an example.
+//! `headerWrite()` writes such files with the header magic, while
+//! `headerExport()` (used for the RPM database) omits it.  Both forms are
+//! accepted.  The signature header is not stored, so nothing in a bare header
+//! file is signed.
+
+use super::{load_immutable, ImmutableHeader, RPM_HDRMAGIC};
+use rpm_crypto::InitToken;
+use std::io::{Read, Result};
+
+/// Load a bare main header from `r`, which must contain nothing else.
+pub fn load_header_file(r: &mut dyn Read, token: InitToken) -> Result<ImmutableHeader> {
+    let mut start = [0; 8];
+    r.read_exact(&mut start)?;
+    let header = if start == RPM_HDRMAGIC {
+        load_immutable(&mut (&start[..]).chain(&mut *r), token)?
+    } else {
+        load_immutable(&mut RPM_HDRMAGIC.chain(&start[..]).chain(&mut *r), token)?
+    };
+    fail_if!(r.read(&mut [0])? != 0, "trailing data after header");
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn loads_header_files() {
+        const S: &[u8] = include_bytes!("../../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let token = rpm_crypto::init();
+        let mut r = &S[4504..];
+        load_immutable(&mut r, token).unwrap();
+        let hdr = &S[4504..S.len() - r.len()];
+        let header = load_header_file(&mut &hdr[..], token).unwrap();
+        assert_eq!(header.name, "lua");
+        let exported = load_header_file(&mut &hdr[8..], token).unwrap();
+        assert_eq!(exported.header.data, header.header.data);
+        assert!(load_header_file(&mut &S[4504..], token).is_err());
+        assert!(load_header_file(&mut &hdr[..hdr.len() - 1], token).is_err());
+        assert!(load_header_file(&mut &hdr[..4], token).is_err());
+    }
+}
@@ -2,9 +2,10 @@
 
 use super::{check_hex, load_header, Header};
 use crate::ffi::{tag_class, tag_type, TagType};
-use crate::{RPMLead, TagData};
+use crate::{sanitize, sanitize_text, RPMLead, StringMode, TagData, TagValue};
 use openpgp_parser::AllowWeakHashes;
 use rpm_crypto::{rpm_hash_len, DigestCtx, InitToken};
+use std::borrow::Cow;
 use std::convert::TryInto;
 use std::io::{Error, ErrorKind, Read, Result};
 
@@ -32,7 +33,57 @@ pub struct ImmutableHeader {
 }
 include!("../tables.rs");
 
+const RPMTAG_SUMMARY: u32 = 1004;
+const RPMTAG_DESCRIPTION: u32 = 1005;
+
+macro_rules! sanitized_accessors {
+    ($($(#[$s:meta])* ($i: ident, $field: ident))+) => {$(
+        $(#[$s])*
+        pub fn $i(&self, mode: StringMode) -> Result<Cow<'_, str>> {
+            sanitize(&self.$field, mode)
+        }
+    )+}
+}
+
 impl ImmutableHeader {
+    sanitized_accessors! {
+        /// The package name, guaranteed free of control characters
+        (checked_name, name)
+        /// The package version, guaranteed free of control characters
+        (checked_version, version)
+        /// The package release, guaranteed free of control characters
+        (checked_release, release)
+        /// The package target operating system, guaranteed free of control characters
+        (checked_os, os)
+        /// The package architecture, guaranteed free of control characters
+        (checked_arch, arch)
+    }
+
+    /// The package summary, guaranteed free of control characters.
+    /// [`None`] if the header has no summary.
+    pub fn checked_summary(&self, mode: StringMode) -> Result<Option<Cow<'_, str>>> {
+        self.i18n_string(RPMTAG_SUMMARY)
+            .map(|summary| sanitize(summary, mode))
+            .transpose()
+    }
+
+    /// The package description, guaranteed free of control characters other
+    /// than line feeds and tabs.  [`None`] if the header has no description.
+    pub fn checked_description(&self, mode: StringMode) -> Result<Option<Cow<'_, str>>> {
+        self.i18n_string(RPMTAG_DESCRIPTION)
+            .map(|description| sanitize_text(description, mode))
+            .transpose()
+    }
+
+    /// The untranslated value of the internationalized string `tag`
+    fn i18n_string(&self, tag: u32) -> Option<&str> {
+        match self.header.get(tag)? {
+            TagValue::I18NString(strings) => strings.first().copied(),
+            TagValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
     /// Gets a digest context for the package payload, along with the hex digest
     /// to verify it against.
     pub fn payload_digest(&self) -> Result<(DigestCtx, Vec<u8>)> {
@@ -51,6 +102,11 @@ impl ImmutableHeader {
         Ok((ctx, digest))
     }
 
+    /// The OpenPGP hash algorithm of the payload digest, if any
+    pub fn payload_digest_algorithm(&self) -> Option<u8> {
+        self.payload_digest_algorithm
+    }
+
     /// Retrieves the package lead
     pub fn lead(&self) -> [u8; 96] {
         let (osnum, archnum) = (
@@ -137,7 +193,7 @@ pub fn load_immutable(r: &mut dyn Read, token: InitToken) -> Result<ImmutableHea
                 fail_if!(tag_data.count() != 1, "more than one payload digest?");
                 check_hex(body)?;
                 if tag == 5092 {
-                    assert!(payload_digest.is_none(), "duplicate tags rejected earlier");
+                    assert!(payload_digest.is_none(), "duplicate tags rejected by load_header");
                     payload_digest = Some(body.to_owned())
                 }
             }
@@ -0,0 +1,161 @@
+//! Inspection of malformed headers
+//!
+//! [`super::load_header`] stops at the first problem it finds, which is the
+//! right thing to do when deciding whether to accept a package.  When
+//! analyzing a malicious package, it is more useful to know about every
+//! problem, so this module enumerates them instead.
+
+use super::common::parse_header_magic;
+use crate::TagData;
+use openpgp_parser::signature;
+use std::convert::TryInto;
+
+/// A problem found by [`inspect_header`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeaderFinding {
+    /// The header is truncated or its magic is wrong; inspection stopped here
+    Malformed(String),
+    /// A tag appears more than once
+    DuplicateTag(u32),
+    /// A tag is less than the tag before it
+    Unsorted {
+        /// The out-of-order tag
+        tag: u32,
+        /// The tag before it
+        previous: u32,
+    },
+    /// The data of two entries overlaps
+    Overlap {
+        /// The tag of the later entry
+        tag: u32,
+        /// The tag of the entry it overlaps with
+        previous: u32,
+    },
+    /// An entry points outside of the data section
+    OutOfBounds(u32),
+    /// An OpenPGP signature in the signature header has a problem
+    Signature {
+        /// The signature header tag
+        tag: u32,
+        /// What is wrong with the signature
+        finding: signature::Finding,
+    },
+}
+
+pub(super) fn entry_len(entry: &TagData, data: &[u8]) -> Option<usize> {
+    let count = entry.count() as usize;
+    let size = match entry.ty() {
+        1 | 2 | 7 => 1,
+        3 => 2,
+        4 => 4,
+        5 => 8,
+        6 | 8 | 9 => {
+            let mut strings = 0;
+            let len = data.iter().position(|&i| {
+                strings += (i == 0) as usize;
+                strings == count
+            })?;
+            return Some(len + 1);
+        }
+        _ => return None,
+    };
+    count.checked_mul(size).filter(|&len| len <= data.len())
+}
+
+/// Inspect the header that starts at the beginning of `untrusted_header`,
+/// returning every duplicate or conflicting tag found.  OpenPGP signatures in
+/// signature headers are inspected with [`signature::inspect`].  Nothing else
+/// is checked, so an empty result does *not* mean that the header is valid.
+pub fn inspect_header(untrusted_header: &[u8]) -> Vec<HeaderFinding> {
+    let mut findings = vec![];
+    let magic: &[u8; 16] = match untrusted_header.get(..16) {
+        Some(e) => e.try_into().expect("length is correct"),
+        None => {
+            return vec![HeaderFinding::Malformed(
+                "truncated header magic".to_owned(),
+            )]
+        }
+    };
+    let (index_length, data_length) = match parse_header_magic(magic) {
+        Ok(e) => (e.0 as usize, e.1 as usize),
+        Err(e) => return vec![HeaderFinding::Malformed(e.to_string())],
+    };
+    let data_start = 16 * (index_length + 1);
+    let (index_bytes, data) = match untrusted_header.get(16..data_start + data_length) {
+        Some(e) => e.split_at(16 * index_length),
+        None => return vec![HeaderFinding::Malformed("truncated header".to_owned())],
+    };
+    let mut index = vec![TagData::default(); index_length];
+    TagData::as_bytes_mut(&mut index).copy_from_slice(index_bytes);
+    let is_signature = index[0].tag() == 62;
+    let mut seen = std::collections::BTreeSet::new();
+    let mut last_tag = 0;
+    for entry in &index[1..] {
+        let tag = entry.tag();
+        if !seen.insert(tag) {
+            findings.push(HeaderFinding::DuplicateTag(tag))
+        } else if tag < last_tag {
+            findings.push(HeaderFinding::Unsorted {
+                tag,
+                previous: last_tag,
+            })
+        }
+        last_tag = tag;
+    }
+    let mut by_offset: Vec<(usize, usize, u32)> = vec![];
+    for entry in &index[1..] {
+        let (tag, offset) = (entry.tag(), entry.offset() as usize);
+        let len = match data.get(offset..).and_then(|d| entry_len(entry, d)) {
+            Some(len) => len,
+            None => {
+                findings.push(HeaderFinding::OutOfBounds(tag));
+                continue;
+            }
+        };
+        if is_signature && entry.ty() == 7 && [267, 268, 1002, 1005].contains(&tag) {
+            signature::inspect(&data[offset..offset + len], &mut |finding| {
+                findings.push(HeaderFinding::Signature { tag, finding })
+            })
+        }
+        by_offset.push((offset, len, tag));
+    }
+    by_offset.sort_unstable();
+    for pair in by_offset.windows(2) {
+        let ((offset, len, previous), (next_offset, _, tag)) = (pair[0], pair[1]);
+        if offset + len > next_offset {
+            findings.push(HeaderFinding::Overlap { tag, previous })
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn inspects_lua_rpm() {
+        let s: &[u8] = include_bytes!("../../../lua-5.4.2-1.fc33.x86_64.rpm");
+        assert_eq!(inspect_header(&s[96..]), []);
+        assert_eq!(inspect_header(&s[4504..]), []);
+        let mut bad = s[96..4504].to_vec();
+        // Make the SHA1 header digest (third entry) a duplicate of the second
+        bad[16 * 3..16 * 3 + 4].copy_from_slice(&268u32.to_be_bytes());
+        // and make the MD5 digest (seventh entry) overlap the SIZE tag
+        bad[16 * 7 + 8..16 * 7 + 12].copy_from_slice(&670u32.to_be_bytes());
+        assert_eq!(
+            inspect_header(&bad),
+            [
+                HeaderFinding::DuplicateTag(268),
+                HeaderFinding::Overlap {
+                    tag: 1004,
+                    previous: 273
+                },
+                HeaderFinding::Overlap {
+                    tag: 1000,
+                    previous: 1004
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Repair of headers with benign irregularities
+//!
+//! [`super::load_header`] insists on a canonical layout: entries sorted by
+//! tag, data in index order, minimal zeroed padding, and no unreferenced data.
+//! Some third-party tools produce headers that violate these rules without
+//! being malicious.  This module rewrites such headers into the canonical
+//! layout and reports what it changed.
+//!
+//! Repairing a main header invalidates every signature over it, so repaired
+//! packages must be re-signed before they will verify.
+
+use super::common::{load_header, parse_header_magic};
+use super::inspect::entry_len;
+use crate::{read_lead, TagData};
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::io::{Read, Result, Write};
+
+/// An irregularity corrected by [`repair_header`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Repair {
+    /// The index was not sorted by tag
+    Resorted,
+    /// The data of this tag was not in index order
+    DataReordered(u32),
+    /// The data of this tag was preceded by more padding than its alignment
+    /// requires
+    ExcessPadding(u32),
+    /// The data of this tag was preceded by nonzero padding
+    NonzeroPadding(u32),
+    /// The data of this tag was not properly aligned
+    Misaligned(u32),
+    /// This many bytes at the end of the data were not referenced by any entry
+    TrailingData(usize),
+    /// The region trailer was not in canonical form
+    RegionTrailer,
+    /// The padding after the signature header was not zeroed
+    SignaturePadding,
+}
+
+/// The repairs made by [`repair_package`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PackageRepairs {
+    /// Repairs to the signature header.  These do not affect any signature.
+    pub signature_header: Vec<Repair>,
+    /// Repairs to the main header.  If this is not empty, the package must be
+    /// re-signed.
+    pub main_header: Vec<Repair>,
+}
+
+fn alignment(ty: u32) -> usize {
+    match ty {
+        3 => 2,
+        4 => 4,
+        5 => 8,
+        _ => 1,
+    }
+}
+
+/// Rewrite the header at the start of `untrusted_header` into canonical form.
+/// `region_tag` is 62 for signature headers and 63 for main headers.  Returns
+/// the new header and the repairs made.  Problems that cannot be repaired
+/// without guessing, such as duplicate tags or overlapping entries, are
+/// errors.  The result is checked with the same parser used for verification.
+pub fn repair_header(untrusted_header: &[u8], region_tag: u32) -> Result<(Vec<u8>, Vec<Repair>)> {
+    let magic: &[u8; 16] = match untrusted_header.get(..16) {
+        Some(e) => e.try_into().expect("length is correct"),
+        None => bad_data!("truncated header magic"),
+    };
+    let (index_length, data_length) = parse_header_magic(magic)?;
+    let (index_length, data_length) = (index_length as usize, data_length as usize);
+    let data_start = 16 * (index_length + 1);
+    let (index_bytes, data) = match untrusted_header.get(16..data_start + data_length) {
+        Some(e) => e.split_at(16 * index_length),
+        None => bad_data!("truncated header"),
+    };
+    let mut index = vec![TagData::default(); index_length];
+    TagData::as_bytes_mut(&mut index).copy_from_slice(index_bytes);
+    let region = index[0];
+    let region_offset = data_length - 16;
+    fail_if!(
+        region.tag() != region_tag
+            || region.ty() != 7
+            || region.count() != 16
+            || region.offset() as usize != region_offset,
+        "bad region {:?}",
+        region
+    );
+    let mut repairs = vec![];
+    let trailer = TagData::new(region_tag, 7, (-16 * index_length as i32) as u32, 16);
+    if data[region_offset..] != *TagData::as_bytes(&[trailer]) {
+        repairs.push(Repair::RegionTrailer)
+    }
+    let data = &data[..region_offset];
+    let mut entries = index[1..].to_vec();
+    if entries.windows(2).any(|i| i[0].tag() > i[1].tag()) {
+        repairs.push(Repair::Resorted);
+        entries.sort_by_key(TagData::tag)
+    }
+    let mut seen = BTreeSet::new();
+    let mut spans = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        fail_if!(!seen.insert(entry.tag()), "duplicate tag {}", entry.tag());
+        fail_if!(entry.tag() <= 99, "tag {} is reserved", entry.tag());
+        let offset = entry.offset() as usize;
+        let len = match data.get(offset..).and_then(|d| entry_len(entry, d)) {
+            Some(len) if len > 0 => len,
+            _ => bad_data!("entry {:?} is out of bounds", entry),
+        };
+        spans.push((offset, len, entry.tag()));
+    }
+    // Check the original layout, in data order
+    let mut by_offset = spans.clone();
+    by_offset.sort_unstable();
+    let mut cursor = 0;
+    for &(offset, len, tag) in &by_offset {
+        fail_if!(offset < cursor, "entry for tag {} overlaps another", tag);
+        let padding = &data[cursor..offset];
+        if padding.iter().any(|&i| i != 0) {
+            repairs.push(Repair::NonzeroPadding(tag))
+        }
+        let ty = entries[spans.iter().position(|i| i.2 == tag).expect("present")].ty();
+        if offset % alignment(ty) != 0 {
+            repairs.push(Repair::Misaligned(tag))
+        } else if padding.len() >= alignment(ty) {
+            repairs.push(Repair::ExcessPadding(tag))
+        }
+        cursor = offset + len;
+    }
+    if cursor < data.len() {
+        repairs.push(Repair::TrailingData(data.len() - cursor))
+    }
+    if by_offset != spans {
+        for (old, new) in spans.iter().zip(&by_offset) {
+            if old != new {
+                repairs.push(Repair::DataReordered(old.2))
+            }
+        }
+    }
+    // Lay out the data in index order
+    let mut new_index = vec![TagData::default(); index_length];
+    let mut new_data = vec![];
+    for (i, (entry, &(offset, len, _))) in entries.iter().zip(&spans).enumerate() {
+        let align = alignment(entry.ty());
+        new_data.resize((new_data.len() + align - 1) & !(align - 1), 0);
+        new_index[i + 1] = TagData::new(
+            entry.tag(),
+            entry.ty(),
+            new_data.len() as u32,
+            entry.count(),
+        );
+        new_data.extend_from_slice(&data[offset..offset + len]);
+    }
+    new_index[0] = TagData::new(region_tag, 7, new_data.len() as u32, 16);
+    new_data.extend_from_slice(TagData::as_bytes(&[trailer]));
+    let mut out = Vec::with_capacity(16 * (index_length + 1) + new_data.len());
+    out.extend_from_slice(&magic[..8]);
+    out.extend_from_slice(&(index_length as u32).to_be_bytes());
+    out.extend_from_slice(&(new_data.len() as u32).to_be_bytes());
+    out.extend_from_slice(TagData::as_bytes(&new_index));
+    out.extend_from_slice(&new_data);
+    load_header(&mut &out[..], region_tag, &mut |_, _, _| Ok(()))?;
+    Ok((out, repairs))
+}
+
+fn read_raw_header(src: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut magic = [0u8; 16];
+    src.read_exact(&mut magic)?;
+    let (index_length, data_length) = parse_header_magic(&magic)?;
+    let mut header = vec![0; 16 * (index_length as usize + 1) + data_length as usize];
+    header[..16].copy_from_slice(&magic);
+    src.read_exact(&mut header[16..])?;
+    Ok(header)
+}
+
+/// Repair the package read from `src`, writing the result to `dst`.  The lead
+/// and payload are copied unchanged; both headers are passed to
+/// [`repair_header`].  Nothing is verified.
+pub fn repair_package(src: &mut dyn Read, dst: &mut dyn Write) -> Result<PackageRepairs> {
+    let lead = read_lead(src)?;
+    let (signature, mut signature_repairs) = repair_header(&read_raw_header(src)?, 62)?;
+    let data_length = signature.len() - 16;
+    let padding = (8 - (data_length & 7)) & 7;
+    let mut s = [0u8; 7];
+    src.read_exact(&mut s[..padding])?;
+    if s[..padding].iter().any(|&i| i != 0) {
+        signature_repairs.push(Repair::SignaturePadding)
+    }
+    let new_padding = (8 - (signature.len() & 7)) & 7;
+    let (main, main_repairs) = repair_header(&read_raw_header(src)?, 63)?;
+    dst.write_all(&lead.as_slice())?;
+    dst.write_all(&signature)?;
+    dst.write_all(&[0u8; 7][..new_padding])?;
+    dst.write_all(&main)?;
+    std::io::copy(src, dst)?;
+    Ok(PackageRepairs {
+        signature_header: signature_repairs,
+        main_header: main_repairs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const S: &[u8] = include_bytes!("../../../lua-5.4.2-1.fc33.x86_64.rpm");
+
+    #[test]
+    fn canonical_package_unchanged() {
+        let mut out = vec![];
+        let repairs = repair_package(&mut &S[..], &mut out).unwrap();
+        assert_eq!(repairs, PackageRepairs::default());
+        assert!(out == S);
+    }
+
+    #[test]
+    fn repairs_signature_header() {
+        let mut bad = S[96..4504].to_vec();
+        // Swap the first two entries after the region (tags 268 and 269)
+        let (first, second) = (bad[32..48].to_vec(), bad[48..64].to_vec());
+        bad[32..48].copy_from_slice(&second);
+        bad[48..64].copy_from_slice(&first);
+        assert!(load_header(&mut &bad[..], 62, &mut |_, _, _| Ok(())).is_err());
+        let (fixed, repairs) = repair_header(&bad, 62).unwrap();
+        assert_eq!(repairs, [Repair::Resorted]);
+        assert_eq!(fixed[..fixed.len()], S[96..96 + fixed.len()]);
+    }
+}
@@ -1,7 +1,7 @@
 use super::{check_hex, load_header, Header};
 use crate::ffi::TagType;
 use crate::TagData;
-use openpgp_parser::AllowWeakHashes;
+use openpgp_parser::{signature::ParseOptions, AllowWeakHashes, Limits};
 use rpm_crypto::{DigestCtx, Signature};
 use std::io::{Read, Result};
 
@@ -78,18 +78,34 @@ pub struct SignatureHeader {
     pub header_sha1_hash: Option<(DigestCtx, Vec<u8>)>,
     /// The SHA256 hash of the main header, if provided
     pub header_sha256_hash: Option<(DigestCtx, Vec<u8>)>,
+    /// Tags that this library does not know about, in header order.  They are
+    /// not covered by any signature and are otherwise ignored, but callers
+    /// may wish to report or reject them.
+    pub unknown_tags: Vec<u32>,
 }
 
 pub fn load_signature(
     r: &mut dyn Read,
     allow_weak_hashes: AllowWeakHashes,
     token: rpm_crypto::InitToken,
+) -> Result<SignatureHeader> {
+    load_signature_with_options(r, &ParseOptions::new(allow_weak_hashes), token)
+}
+
+/// As [`load_signature`], but with the given options for parsing the
+/// OpenPGP signatures.  Use this to accept v6 signatures, for instance.
+pub fn load_signature_with_options(
+    r: &mut dyn Read,
+    options: &ParseOptions,
+    token: rpm_crypto::InitToken,
 ) -> Result<SignatureHeader> {
     let mut header_signature = None;
     let mut header_payload_signature = None;
     let mut header_payload_weak_digest = None;
     let mut header_sha1_hash = None;
     let mut header_sha256_hash = None;
+    let mut unknown_tags = vec![];
+    let limits = Limits::default();
     if cfg!(test) {
         let mut s = RPM_SIG_TAGS[0].0;
         for i in &RPM_SIG_TAGS[1..] {
@@ -102,7 +118,10 @@ pub fn load_signature(
         let (_, expected_ty, size, flags, _) =
             match RPM_SIG_TAGS.binary_search_by_key(&tag, |x| x.0) {
                 Ok(e) => RPM_SIG_TAGS[e],
-                Err(_) => return Ok(()),
+                Err(_) => {
+                    unknown_tags.push(tag);
+                    return Ok(());
+                }
             };
         if ty != expected_ty {
             bad_data!("bogus tag type {:?} for tag {}", ty, tag)
@@ -142,10 +161,14 @@ pub fn load_signature(
                 Ok(())
             }
             Flags::HeaderSig | Flags::HeaderPayloadSig => {
-                let sig = match Signature::parse(body, 0, allow_weak_hashes, token) {
-                    Ok(e) => e,
-                    Err(e) => bad_data!("bad OpenPGP signature: {:?}", e),
-                };
+                let mut reader = body;
+                let sig = Signature::read_from(&mut reader, 0, options, &limits, token)
+                    .map_err(crate::verify::openpgp_io_error)?;
+                if !reader.is_empty() {
+                    return Err(crate::verify::openpgp_error(
+                        openpgp_parser::Error::TrailingJunk,
+                    ));
+                }
                 let sig_packet =
                     openpgp_parser::packet::next(&mut openpgp_parser::Reader::new(body))
                         .expect("already validated above; qed")
@@ -185,5 +208,6 @@ pub fn load_signature(
         header_payload_weak_digest,
         header_sha1_hash,
         header_sha256_hash,
+        unknown_tags,
     })
 }
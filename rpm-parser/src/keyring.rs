@@ -0,0 +1,883 @@
+//! Parsed keyrings
+//!
+//! [`RpmKeyring`] can verify signatures, but it cannot say which key it
+//! used, or whether a key ID names more than one key.  [`Keyring`] holds
+//! parsed certificates and resolves the issuer of a signature ([`SigInfo`])
+//! to one specific key, primary key or subkey.  A fingerprint names a key
+//! exactly.  A 64-bit key ID can be forged to collide with another key, so
+//! when a key ID alone matches more than one key, lookup fails instead of
+//! picking one.  Verifiers that can afford to try every matching key can use
+//! [`Keyring::candidates`] or [`Keyring::verify`] instead.
+//!
+//! Only keys that the certificate vouches for are used: subkeys need a
+//! binding signature that verifies, and revoked keys are skipped.  A key is
+//! only a candidate for signatures made while it was valid: after it was
+//! created, and before it expired or was revoked.
+
+use crate::bootstrap::{usable_subkeys, verified_packets, CertificateVerifier};
+use crate::verify::{failure_kind, keyring_error, openpgp_error, verify_package_with};
+use crate::{
+    FailureKind, KeyMatch, SignatureHeader, VerificationFailure, VerifyCallback, VerifyResult,
+};
+use openpgp_parser::cert::{self, Certificate, KeyInvalid, KeyVerifier};
+use openpgp_parser::key::PublicKey;
+use openpgp_parser::signature::{Fingerprint, SigInfo};
+use openpgp_parser::AllowWeakHashes;
+use openpgp_parser::Reader;
+use rpm_crypto::{transaction::RpmKeyring, InitToken, Signature};
+use std::convert::TryInto;
+use std::io::{copy, Error, ErrorKind, Read, Result, Write};
+use std::os::raw::c_int;
+
+/// The key ID of a signature that does not name its issuer
+const WILDCARD_KEY_ID: [u8; 8] = [0; 8];
+
+/// RPM’s result codes for a forged signature and a missing key
+pub(crate) const FORGED: c_int = 2;
+pub(crate) const NO_KEY: c_int = 4;
+
+#[derive(Clone, Debug)]
+struct KeyEntry {
+    fingerprint: Vec<u8>,
+    certificate: usize,
+    /// 0 for the primary key, otherwise 1 + the index of the subkey
+    key: usize,
+    /// The packets RPM needs to use this key and no other: the primary key
+    /// and, for a subkey, the subkey
+    packets: Vec<u8>,
+}
+
+/// A set of parsed certificates
+#[derive(Clone, Debug, Default)]
+pub struct Keyring<'a> {
+    /// Each certificate, with the packets of its usable keys for RPM
+    certificates: Vec<(Certificate<'a>, Vec<u8>)>,
+    keys: Vec<KeyEntry>,
+    /// The fingerprints of the keys revoked outright, which are not in `keys`
+    revoked: Vec<Vec<u8>>,
+}
+
+/// A key found in a [`Keyring`]
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+pub struct KeyRef<'k, 'a> {
+    /// The certificate containing the key
+    pub certificate: &'k Certificate<'a>,
+    /// The key: the primary key of `certificate`, or one of its subkeys
+    pub key: &'k PublicKey<'a>,
+    /// The fingerprint of the key
+    pub fingerprint: &'k [u8],
+    packets: &'k [u8],
+}
+
+impl KeyRef<'_, '_> {
+    /// The key ID of the key: the low 64 bits of a v4 fingerprint, or the
+    /// high 64 bits of a v5 or v6 fingerprint
+    pub fn key_id(&self) -> [u8; 8] {
+        key_id(self.key.version, self.fingerprint)
+    }
+}
+
+fn key_id(version: u8, fingerprint: &[u8]) -> [u8; 8] {
+    let id = match version {
+        4 => &fingerprint[fingerprint.len() - 8..],
+        _ => &fingerprint[..8],
+    };
+    id.try_into().expect("length is correct")
+}
+
+impl<'a> Keyring<'a> {
+    /// Creates an empty keyring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the binary (not armored) certificates in `data` and adds them
+    /// to the keyring.  Returns the number of certificates added.  Nothing
+    /// is added if any certificate is malformed.
+    ///
+    /// Subkeys are only used if their binding signature verifies and they
+    /// are not revoked outright.  If the primary key is revoked outright, no
+    /// key of the certificate is used.
+    pub fn add(&mut self, data: &'a [u8], token: InitToken) -> Result<usize> {
+        self.add_with(
+            data,
+            &mut |key| crate::fingerprint(key, token),
+            &mut |certificate| usable_subkeys(certificate, &mut CertificateVerifier(token)),
+        )
+    }
+
+    pub(crate) fn add_with(
+        &mut self,
+        data: &'a [u8],
+        fingerprint: &mut dyn FnMut(&PublicKey<'a>) -> Vec<u8>,
+        usable_subkeys: &mut dyn FnMut(&Certificate<'a>) -> Vec<bool>,
+    ) -> Result<usize> {
+        let mut reader = Reader::new(data);
+        let mut certificates = vec![];
+        while !reader.is_empty() {
+            match cert::read_certificate(&mut reader) {
+                Ok(certificate) => certificates.push(certificate),
+                Err(e) => bad_data!("bad OpenPGP certificate: {:?}", e),
+            }
+        }
+        let count = certificates.len();
+        for certificate in certificates {
+            let index = self.certificates.len();
+            // RPM does not check revocations, so a revoked certificate is
+            // kept for inspection but none of its keys are used
+            if certificate.revocation().is_some_and(|r| r.is_hard()) {
+                self.revoked
+                    .extend(certificate.keys().map(&mut *fingerprint));
+                self.certificates.push((certificate, vec![]));
+                continue;
+            }
+            for subkey in certificate.subkeys() {
+                if subkey.revocation().is_some_and(|r| r.is_hard()) {
+                    self.revoked.push(fingerprint(subkey.key()))
+                }
+            }
+            let usable = usable_subkeys(&certificate);
+            let keys = certificate.keys().enumerate();
+            for (key, public_key) in keys.filter(|&(key, _)| key == 0 || usable[key - 1]) {
+                let mut only = vec![false; usable.len()];
+                if key > 0 {
+                    only[key - 1] = true
+                }
+                self.keys.push(KeyEntry {
+                    fingerprint: fingerprint(public_key),
+                    certificate: index,
+                    key,
+                    packets: verified_packets(&certificate, &only),
+                })
+            }
+            let packets = verified_packets(&certificate, &usable);
+            self.certificates.push((certificate, packets))
+        }
+        Ok(count)
+    }
+
+    /// The certificates in the keyring, in the order they were added
+    pub fn certificates(&self) -> impl Iterator<Item = &Certificate<'a>> {
+        self.certificates.iter().map(|(certificate, _)| certificate)
+    }
+
+    fn key_ref<'k>(&'k self, entry: &'k KeyEntry) -> KeyRef<'k, 'a> {
+        let (certificate, _) = &self.certificates[entry.certificate];
+        let key = match entry.key {
+            0 => certificate.primary(),
+            i => certificate.subkeys()[i - 1].key(),
+        };
+        KeyRef {
+            certificate,
+            key,
+            fingerprint: &entry.fingerprint,
+            packets: &entry.packets,
+        }
+    }
+
+    /// Every distinct key satisfying `pred`, in the order added
+    fn distinct_keys(&self, pred: &mut dyn FnMut(&KeyRef<'_, 'a>) -> bool) -> Vec<KeyRef<'_, 'a>> {
+        let mut found: Vec<KeyRef<'_, 'a>> = vec![];
+        for entry in &self.keys {
+            let key = self.key_ref(entry);
+            if pred(&key) && !found.iter().any(|k| k.fingerprint == key.fingerprint) {
+                found.push(key)
+            }
+        }
+        found
+    }
+
+    /// Finds the key with fingerprint `fingerprint`.  If the same key was
+    /// added more than once, the first is returned.
+    pub fn by_fingerprint(&self, fingerprint: &[u8]) -> Option<KeyRef<'_, 'a>> {
+        let entry = self.keys.iter().find(|e| e.fingerprint == fingerprint)?;
+        Some(self.key_ref(entry))
+    }
+
+    /// Was the key with fingerprint `fingerprint` added to the keyring, but
+    /// revoked outright?  Such a key is never used, but a
+    /// [`KeyringStack`](crate::KeyringStack) lets it shadow lower layers.
+    pub fn is_revoked(&self, fingerprint: &[u8]) -> bool {
+        self.revoked.iter().any(|revoked| revoked == fingerprint)
+    }
+
+    /// Finds the keys with key ID `key_id`.  A key added more than once is
+    /// only returned once.
+    pub fn by_key_id(&self, key_id: [u8; 8]) -> Vec<KeyRef<'_, 'a>> {
+        self.distinct_keys(&mut |key| key.key_id() == key_id)
+    }
+
+    /// The keys that could have made the signature described by `info`, in
+    /// the order they should be tried: the order the keys were added, with
+    /// each primary key before its subkeys.  Only keys using the signature’s
+    /// public-key algorithm are included.  If the signature has an issuer
+    /// fingerprint, only that key is a candidate.  Otherwise, every key with
+    /// the signature’s key ID is, or every key if the key ID is the wildcard
+    /// (all zeros).  Keys that could not be used when the signature was made
+    /// (see [`Certificate::key_valid_at`]) are skipped: keys created after
+    /// the signature, and keys that had expired or were revoked by then.
+    pub fn candidates(&self, info: &SigInfo, token: InitToken) -> Vec<KeyRef<'_, 'a>> {
+        usable(self.matching_keys(info, &mut CertificateVerifier(token))).0
+    }
+
+    /// The keys that match the issuer and public-key algorithm of `info`,
+    /// each with whether it could be used when the signature was made
+    pub(crate) fn matching_keys(
+        &self,
+        info: &SigInfo,
+        verifier: &mut dyn KeyVerifier,
+    ) -> Vec<(KeyRef<'_, 'a>, std::result::Result<(), KeyInvalid>)> {
+        let fingerprint = info.fingerprint.as_ref().map(Fingerprint::as_bytes);
+        let keys = self.distinct_keys(&mut |key| {
+            key.key.algorithm == info.pkey_alg
+                && match fingerprint {
+                    Some(fingerprint) => key.fingerprint == fingerprint,
+                    None if info.key_id == WILDCARD_KEY_ID => true,
+                    None => key.key_id() == info.key_id,
+                }
+        });
+        keys.into_iter()
+            .map(|key| {
+                let valid = key
+                    .certificate
+                    .key_valid_at(key.key, info.creation_time, verifier);
+                (key, valid)
+            })
+            .collect()
+    }
+
+    /// Finds the key that made the signature described by `info`, using the
+    /// issuer fingerprint if the signature has one and the key ID otherwise.
+    /// Fails if no key matches, or if more than one key does.  If the only
+    /// matching keys could not be used when the signature was made, the
+    /// error says why.
+    pub fn lookup(&self, info: &SigInfo, token: InitToken) -> Result<KeyRef<'_, 'a>> {
+        self.lookup_with(info, &mut CertificateVerifier(token))
+    }
+
+    pub(crate) fn lookup_with(
+        &self,
+        info: &SigInfo,
+        verifier: &mut dyn KeyVerifier,
+    ) -> Result<KeyRef<'_, 'a>> {
+        let (mut keys, invalid) = usable(self.matching_keys(info, verifier));
+        match keys.len() {
+            0 => Err(Error::new(
+                ErrorKind::NotFound,
+                no_key_message(info, invalid),
+            )),
+            1 => Ok(keys.remove(0)),
+            n => bad_data!("key ID {:02X?} matches {} keys", info.key_id, n),
+        }
+    }
+
+    /// Verifies `sig`, which must already have hashed the signed data, with
+    /// each of its [candidates](Keyring::candidates) in turn.  Returns the
+    /// first key that verifies it.  If none does, the error is “forged” if
+    /// any candidate found the signature forged.  If there is no candidate
+    /// because the matching keys could not be used when the signature was
+    /// made, the signature is unverifiable and the error says why.
+    ///
+    /// RPM finds the key by key ID, so a signature with a wildcard key ID is
+    /// given the key ID of each candidate in turn.  This fails if the
+    /// signature covers its key ID.
+    pub fn verify(&self, sig: &Signature, token: InitToken) -> Result<KeyRef<'_, 'a>> {
+        if !sig.quick_check() {
+            return Err(keyring_error(FORGED));
+        }
+        let keys = self.matching_keys(sig.info(), &mut CertificateVerifier(token));
+        let (candidates, invalid) = usable(keys);
+        if candidates.is_empty() {
+            return Err(no_key_error(sig.info(), invalid));
+        }
+        let mut error = NO_KEY;
+        for key in candidates {
+            if try_key(&key, sig, &mut error, token)? {
+                return Ok(key);
+            }
+        }
+        Err(keyring_error(error))
+    }
+
+    /// Verifies a detached signature over the data read from `src`, as
+    /// [`crate::verify_detached`] does, but with [`Keyring::verify`].
+    /// Returns the key that made the signature.
+    pub fn verify_detached(
+        &self,
+        src: &mut dyn Read,
+        untrusted_sig: &[u8],
+        allow_weak_hashes: AllowWeakHashes,
+        token: InitToken,
+    ) -> Result<KeyRef<'_, 'a>> {
+        let mut sig =
+            Signature::parse(untrusted_sig, 0, allow_weak_hashes, token).map_err(openpgp_error)?;
+        copy(src, &mut sig)?;
+        self.verify(&sig, token)
+    }
+
+    /// Verifies a package, as [`crate::verify_package`] does, but with the
+    /// header signature and any header+payload signature verified by
+    /// [`Keyring::verify`], so that keys that were not valid when a
+    /// signature was made are not used.  The fingerprint of
+    /// the key that made it is returned in
+    /// [`VerifyResult::header_signer`], and is available to `cb`.  Unlike
+    /// RPM, this finds the key by fingerprint if the signature has one, which
+    /// [`VerifyResult::header_sig_key_match`] reports.
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_package(
+        &self,
+        src: &mut dyn Read,
+        sig_header: &mut SignatureHeader,
+        allow_old_pkgs: bool,
+        preserve_old_sig: bool,
+        token: InitToken,
+        cb: Option<&mut VerifyCallback<'_>>,
+        output: Option<&mut dyn Write>,
+    ) -> Result<VerifyResult> {
+        verify_package_with(
+            src,
+            sig_header,
+            &mut |signature| {
+                let key = self.verify(&signature, token)?;
+                // Keyring::candidates only uses the key ID if the signature
+                // has no fingerprint
+                let key_match = match signature.info().fingerprint {
+                    Some(ref fingerprint) if fingerprint.as_bytes() == key.fingerprint => {
+                        KeyMatch::Fingerprint
+                    }
+                    _ => KeyMatch::KeyIdOnly,
+                };
+                Ok((
+                    key_match,
+                    Some(key.fingerprint.to_vec()),
+                    key.key.material.bits(),
+                ))
+            },
+            &mut |signature| match self.verify(&signature, token) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(failure_kind(&e).unwrap_or(FailureKind::Unverifiable)),
+            },
+            allow_old_pkgs,
+            preserve_old_sig,
+            token,
+            cb,
+            output,
+        )
+    }
+
+    /// Creates an [`RpmKeyring`] with every certificate in this keyring
+    pub fn to_rpm_keyring(&self, token: InitToken) -> Result<RpmKeyring> {
+        let keyring = RpmKeyring::new(token);
+        for (_, packets) in self.certificates.iter().filter(|(_, p)| !p.is_empty()) {
+            keyring.add_key(packets)?;
+        }
+        Ok(keyring)
+    }
+}
+
+/// Splits the result of [`Keyring::matching_keys`] into the keys that can be
+/// used and the reason the last of the others cannot
+pub(crate) fn usable<K>(
+    keys: Vec<(K, std::result::Result<(), KeyInvalid>)>,
+) -> (Vec<K>, Option<KeyInvalid>) {
+    let mut invalid = None;
+    let mut usable = vec![];
+    for (key, valid) in keys {
+        match valid {
+            Ok(()) => usable.push(key),
+            Err(e) => invalid = Some(e),
+        }
+    }
+    (usable, invalid)
+}
+
+fn no_key_message(info: &SigInfo, invalid: Option<KeyInvalid>) -> String {
+    let issuer = match info.fingerprint {
+        Some(ref fingerprint) => format!("fingerprint {:02X?}", fingerprint.as_bytes()),
+        None => format!("key ID {:02X?}", info.key_id),
+    };
+    match invalid {
+        None => format!("no key with {}", issuer),
+        Some(reason) => format!(
+            "no key with {} was valid when the signature was made: {:?}",
+            issuer, reason
+        ),
+    }
+}
+
+/// The error for a signature with no candidate key.  If keys matched but
+/// could not be used when the signature was made, this says why.
+pub(crate) fn no_key_error(info: &SigInfo, invalid: Option<KeyInvalid>) -> Error {
+    match invalid {
+        None => keyring_error(NO_KEY),
+        Some(_) => {
+            VerificationFailure::error(FailureKind::Unverifiable, no_key_message(info, invalid))
+        }
+    }
+}
+
+/// Verifies `sig` with `key` alone.  On failure, records RPM’s result code
+/// in `error`, unless `error` already says the signature is forged.
+pub(crate) fn try_key(
+    key: &KeyRef<'_, '_>,
+    sig: &Signature,
+    error: &mut c_int,
+    token: InitToken,
+) -> Result<bool> {
+    let keyring = RpmKeyring::new(token);
+    keyring.add_key(key.packets)?;
+    let sig = match sig.info().key_id {
+        WILDCARD_KEY_ID => sig
+            .with_issuer_key_id(key.key_id())
+            .map_err(openpgp_error)?,
+        _ => sig.clone(),
+    };
+    match keyring.validate_sig(sig) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            if *error != FORGED {
+                *error = e
+            }
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openpgp_parser::cert::{KeySignature, SignedData};
+    use openpgp_parser::packet::Packet;
+    use openpgp_parser::PublicKeyAlgorithm;
+
+    static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+
+    /// A fake v4 fingerprint, made by folding the key packet body into 20
+    /// bytes, so that tests do not need RPM’s SHA-1.  The length and
+    /// algorithm go in the last bytes, so keys that differ only in the
+    /// folded bytes still get different fingerprints.
+    fn fake_fingerprint(key: &PublicKey<'_>) -> Vec<u8> {
+        let body = key.body();
+        let mut fingerprint = vec![0u8; 20];
+        for (i, &byte) in body.iter().enumerate() {
+            fingerprint[i % 16] = fingerprint[i % 16].rotate_left(3) ^ byte;
+        }
+        fingerprint[16..19].copy_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        fingerprint[19] = key.algorithm.into();
+        fingerprint
+    }
+
+    /// Trusts every subkey, so that tests do not need RPM to check bindings
+    fn all_usable(certificate: &Certificate<'_>) -> Vec<bool> {
+        vec![true; certificate.subkeys().len()]
+    }
+
+    /// When the test key was created, and when the test signatures are made
+    const CREATED: u32 = 1792111174;
+    const SIGNED: u32 = CREATED + 1000;
+
+    /// The real fingerprint of the primary key of the test key
+    const FINGERPRINT: [u8; 20] = [
+        0xE6, 0x0F, 0x4C, 0x72, 0x4B, 0x87, 0xE0, 0x1A, 0xC3, 0x83, 0xFA, 0xDC, 0x93, 0x0F, 0x26,
+        0x82, 0x77, 0x4C, 0xEC, 0xF4,
+    ];
+
+    /// Accepts every self-signature, so that tests do not need RPM
+    struct TrustingVerifier;
+
+    impl KeyVerifier for TrustingVerifier {
+        fn fingerprint(&mut self, _: &PublicKey<'_>) -> Vec<u8> {
+            FINGERPRINT.to_vec()
+        }
+
+        fn verify(
+            &mut self,
+            _: &PublicKey<'_>,
+            _: &KeySignature<'_>,
+            _: &mut SignedData<'_>,
+        ) -> bool {
+            true
+        }
+    }
+
+    impl<'a> Keyring<'a> {
+        fn test_lookup(&self, info: &SigInfo) -> Result<KeyRef<'_, 'a>> {
+            self.lookup_with(info, &mut TrustingVerifier)
+        }
+
+        fn test_candidates(&self, info: &SigInfo) -> Vec<KeyRef<'_, 'a>> {
+            usable(self.matching_keys(info, &mut TrustingVerifier)).0
+        }
+    }
+
+    fn signature_info(pkey_alg: u8, key_id: [u8; 8], fingerprint: Option<Vec<u8>>) -> SigInfo {
+        signature_info_at(SIGNED, pkey_alg, key_id, fingerprint)
+    }
+
+    fn signature_info_at(
+        time: u32,
+        pkey_alg: u8,
+        key_id: [u8; 8],
+        fingerprint: Option<Vec<u8>>,
+    ) -> SigInfo {
+        let t = time.to_be_bytes();
+        let mut hashed = vec![5, 2, t[0], t[1], t[2], t[3]];
+        let mut unhashed = vec![];
+        match fingerprint {
+            Some(fingerprint) => {
+                hashed.extend_from_slice(&[&[22, 33, 4][..], &fingerprint].concat());
+                unhashed.extend_from_slice(&[&[9, 16][..], &fingerprint[12..]].concat());
+            }
+            None => hashed.extend_from_slice(&[&[9, 16][..], &key_id].concat()),
+        }
+        let mut body = vec![4, 0, pkey_alg, 8, 0, hashed.len() as u8];
+        body.extend_from_slice(&hashed);
+        body.extend_from_slice(&[0, unhashed.len() as u8]);
+        body.extend_from_slice(&unhashed);
+        body.extend_from_slice(&[0xAB, 0xCD, 0, 1, 1, 0, 1, 1]);
+        let packet = [&[0x88, body.len() as u8][..], &body].concat();
+        openpgp_parser::signature::parse(&packet, 0, AllowWeakHashes::No).unwrap()
+    }
+
+    #[test]
+    fn looks_up_keys() {
+        let mut keyring = Keyring::new();
+        // The same certificate twice is not a collision
+        let data = [CERT, CERT].concat();
+        assert_eq!(
+            keyring
+                .add_with(&data, &mut fake_fingerprint, &mut all_usable)
+                .unwrap(),
+            2
+        );
+        assert_eq!(keyring.certificates().count(), 2);
+        assert!(keyring
+            .add_with(&CERT[1..], &mut fake_fingerprint, &mut all_usable)
+            .is_err());
+        assert_eq!(keyring.certificates().count(), 2);
+
+        // Every key in the certificate has its own fingerprint
+        let mut fingerprints: Vec<_> = keyring
+            .certificates()
+            .next()
+            .unwrap()
+            .keys()
+            .map(fake_fingerprint)
+            .collect();
+        let count = fingerprints.len();
+        fingerprints.sort();
+        fingerprints.dedup();
+        assert_eq!(fingerprints.len(), count);
+
+        let primary = fake_fingerprint(keyring.certificates().next().unwrap().primary());
+        let key = keyring
+            .test_lookup(&signature_info(22, [0; 8], Some(primary.clone())))
+            .unwrap();
+        assert!(!key.key.subkey);
+        assert_eq!(key.fingerprint, &primary[..]);
+        let primary_id = key.key_id();
+        assert_eq!(
+            keyring
+                .test_lookup(&signature_info(22, primary_id, None))
+                .unwrap()
+                .fingerprint,
+            &primary[..]
+        );
+
+        // The ECDSA subkey
+        let ecdsa = keyring.certificates().next().unwrap().subkeys()[2].key();
+        let key = keyring
+            .test_lookup(&signature_info(19, [0; 8], Some(fake_fingerprint(ecdsa))))
+            .unwrap();
+        assert_eq!(key.key.algorithm, PublicKeyAlgorithm::Ecdsa);
+        assert!(key.key.subkey);
+        // RPM is only given this subkey and the primary key that binds it
+        let packets = cert::parse(key.packets).unwrap();
+        assert_eq!(packets.primary(), key.certificate.primary());
+        assert_eq!(packets.subkeys().len(), 1);
+        assert_eq!(packets.subkeys()[0].key(), key.key);
+        // The signature must use the key’s algorithm
+        let e = keyring
+            .test_lookup(&signature_info(22, [0; 8], Some(fake_fingerprint(ecdsa))))
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+
+        let mut unknown = primary.clone();
+        unknown[0] ^= 1;
+        let e = keyring
+            .test_lookup(&signature_info(22, [0; 8], Some(unknown)))
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().starts_with("no key with fingerprint"));
+        let e = keyring
+            .test_lookup(&signature_info(22, [1; 8], None))
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().starts_with("no key with key ID"));
+
+        // A different key with the same key ID
+        let other = fake_fingerprint(ecdsa);
+        let mut colliding = Keyring::new();
+        colliding
+            .add_with(CERT, &mut fake_fingerprint, &mut all_usable)
+            .unwrap();
+        colliding
+            .add_with(
+                CERT,
+                &mut |key| {
+                    let mut fpr = fake_fingerprint(key);
+                    fpr[0] ^= 1;
+                    fpr
+                },
+                &mut all_usable,
+            )
+            .unwrap();
+        let other_id = key_id(4, &other);
+        assert_eq!(colliding.by_key_id(other_id).len(), 2);
+        let e = colliding
+            .test_lookup(&signature_info(19, other_id, None))
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        // The fingerprint is unambiguous
+        assert!(colliding
+            .test_lookup(&signature_info(19, [0; 8], Some(other.clone())))
+            .is_ok());
+
+        // Candidates are tried in the order the keys were added
+        let candidates = colliding.test_candidates(&signature_info(19, other_id, None));
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].fingerprint, &other[..]);
+        assert_eq!(candidates[1].fingerprint[0], other[0] ^ 1);
+        // A wildcard key ID matches every key using the algorithm
+        let wildcard = colliding.test_candidates(&signature_info(22, [0; 8], None));
+        let primaries: Vec<_> = wildcard.iter().map(|k| k.key.subkey).collect();
+        assert_eq!(primaries, [false, false]);
+        assert_eq!(
+            colliding
+                .test_candidates(&signature_info(19, [0; 8], None))
+                .len(),
+            2
+        );
+        assert!(colliding
+            .test_candidates(&signature_info(17, [0; 8], None))
+            .is_empty());
+    }
+
+    /// A v4 revocation signature of type `sig_type`, made at `time`, with
+    /// reason code `reason` and no actual signature
+    fn revocation_packet(sig_type: u8, time: u32, reason: u8) -> Vec<u8> {
+        let t = time.to_be_bytes();
+        let hashed = [5, 2, t[0], t[1], t[2], t[3], 3, 29, reason, b'x'];
+        let mut body = vec![4, sig_type, 22, 8, 0, hashed.len() as u8];
+        body.extend_from_slice(&hashed);
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        [&[0x88, body.len() as u8][..], &body].concat()
+    }
+
+    #[test]
+    fn skips_revoked_keys() {
+        let (primary, rest) = CERT.split_at(53);
+        // A primary key revoked because it was compromised revokes the
+        // whole certificate, and RPM must not get it either
+        let hard = [primary, &revocation_packet(0x20, 1, 2), rest].concat();
+        let mut keyring = Keyring::new();
+        keyring
+            .add_with(&hard, &mut fake_fingerprint, &mut all_usable)
+            .unwrap();
+        assert_eq!(keyring.certificates().count(), 1);
+        assert!(keyring.keys.is_empty());
+        assert!(keyring.certificates[0].1.is_empty());
+        let certificate = keyring.certificates().next().unwrap();
+        assert!(certificate
+            .keys()
+            .all(|key| keyring.is_revoked(&fake_fingerprint(key))));
+
+        // A compromised subkey is never used
+        let subkey_revoked = [CERT, &revocation_packet(0x28, 1, 2)].concat();
+        let mut keyring = Keyring::new();
+        keyring
+            .add_with(&subkey_revoked, &mut fake_fingerprint, &mut all_usable)
+            .unwrap();
+        let last = keyring.certificates().next().unwrap().subkeys()[3].key();
+        assert!(keyring.by_fingerprint(&fake_fingerprint(last)).is_none());
+        assert!(keyring.is_revoked(&fake_fingerprint(last)));
+        assert_eq!(keyring.keys.len(), 4);
+
+        // A retired subkey is still used for signatures made before it was
+        // retired
+        for (retired_at, usable) in [(SIGNED, false), (SIGNED + 1, true)] {
+            let retired = [CERT, &revocation_packet(0x28, retired_at, 3)].concat();
+            let mut keyring = Keyring::new();
+            keyring
+                .add_with(&retired, &mut fake_fingerprint, &mut all_usable)
+                .unwrap();
+            let last = keyring.certificates().next().unwrap().subkeys()[3].key();
+            let info = signature_info(last.algorithm.into(), [0; 8], Some(fake_fingerprint(last)));
+            assert_eq!(keyring.test_candidates(&info).len(), usable as usize);
+            assert!(keyring.by_fingerprint(&fake_fingerprint(last)).is_some());
+        }
+    }
+
+    /// A v4 self-signature of type `sig_type`, made at `time` by the test
+    /// key, with key expiration time `expiration` and no actual signature
+    fn self_signature(sig_type: u8, time: u32, expiration: u32) -> Vec<u8> {
+        let (t, e) = (time.to_be_bytes(), expiration.to_be_bytes());
+        let mut hashed = vec![5, 2, t[0], t[1], t[2], t[3], 5, 9, e[0], e[1], e[2], e[3]];
+        hashed.extend_from_slice(&[&[22, 33, 4][..], &FINGERPRINT].concat());
+        let mut body = vec![4, sig_type, 22, 8, 0, hashed.len() as u8];
+        body.extend_from_slice(&hashed);
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        [&[0x88, body.len() as u8][..], &body].concat()
+    }
+
+    #[test]
+    fn skips_keys_invalid_at_signature_time() {
+        let mut keyring = Keyring::new();
+        keyring
+            .add_with(CERT, &mut fake_fingerprint, &mut all_usable)
+            .unwrap();
+        let primary = fake_fingerprint(keyring.certificates().next().unwrap().primary());
+        // A signature that predates the key
+        let early = signature_info_at(CREATED - 1, 22, [0; 8], Some(primary.clone()));
+        assert!(keyring.test_candidates(&early).is_empty());
+        let e = keyring.test_lookup(&early).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "no key with fingerprint {:02X?} was valid when the signature was made: {:?}",
+                primary,
+                KeyInvalid::NotYetCreated { created: CREATED }
+            )
+        );
+        let e = no_key_error(&early, Some(KeyInvalid::NotYetCreated { created: CREATED }));
+        assert_eq!(crate::failure_kind(&e), Some(FailureKind::Unverifiable));
+        assert_eq!(
+            crate::failure_kind(&no_key_error(&early, None)),
+            Some(FailureKind::Unverifiable)
+        );
+
+        // A primary key that expires 100 seconds after its creation, taking
+        // its subkeys with it
+        let (head, rest) = CERT.split_at(53);
+        let expiring = [head, &self_signature(0x1F, CREATED + 1, 100), rest].concat();
+        let mut keyring = Keyring::new();
+        keyring
+            .add_with(&expiring, &mut fake_fingerprint, &mut all_usable)
+            .unwrap();
+        let certificate = keyring.certificates().next().unwrap();
+        let ecdsa = fake_fingerprint(certificate.subkeys()[2].key());
+        for (time, valid) in [(CREATED + 99, true), (CREATED + 100, false)] {
+            let info = signature_info_at(time, 22, [0; 8], Some(primary.clone()));
+            assert_eq!(keyring.test_lookup(&info).is_ok(), valid);
+            let info = signature_info_at(time, 19, [0; 8], Some(ecdsa.clone()));
+            assert_eq!(keyring.test_candidates(&info).len(), valid as usize);
+        }
+        let expired = signature_info_at(CREATED + 100, 22, [0; 8], Some(primary.clone()));
+        let e = keyring.test_lookup(&expired).unwrap_err();
+        assert!(e.to_string().ends_with(&format!(
+            "{:?}",
+            KeyInvalid::Expired {
+                expired: CREATED + 100
+            }
+        )));
+
+        // A subkey that expires on its own, through a newer binding signature
+        let binding = [CERT, &self_signature(0x18, CREATED + 100, 50)].concat();
+        let mut keyring = Keyring::new();
+        keyring
+            .add_with(&binding, &mut fake_fingerprint, &mut all_usable)
+            .unwrap();
+        let last = keyring.certificates().next().unwrap().subkeys()[3].key();
+        let expires = last.creation_time + 50;
+        for (time, valid) in [(expires - 1, true), (expires, false)] {
+            let info = signature_info_at(
+                time,
+                last.algorithm.into(),
+                [0; 8],
+                Some(fake_fingerprint(last)),
+            );
+            assert_eq!(keyring.test_candidates(&info).len(), valid as usize);
+        }
+        // The primary key is still valid
+        let info = signature_info_at(expires, 22, [0; 8], Some(primary));
+        assert!(keyring.test_lookup(&info).is_ok());
+    }
+
+    #[test]
+    fn drops_unbound_subkeys() {
+        let token = rpm_crypto::init();
+        let cert = cert::parse(CERT).unwrap();
+        // An appended subkey, with a binding signature that does not cover it
+        let subkey = &cert.subkeys()[1];
+        let mut body = subkey.key().body().to_vec();
+        *body.last_mut().unwrap() ^= 1;
+        let appended = [
+            CERT,
+            &Packet::new(14, &body).serialize(),
+            &subkey.signatures()[0].packet(),
+        ]
+        .concat();
+        let mut keyring = Keyring::new();
+        keyring.add(&appended, token).unwrap();
+        let appended_cert = keyring.certificates().next().unwrap();
+        assert_eq!(appended_cert.subkeys().len(), 5);
+        let unbound = crate::fingerprint(appended_cert.subkeys()[4].key(), token);
+        assert!(keyring.by_fingerprint(&unbound).is_none());
+        for key in cert.keys() {
+            let fingerprint = crate::fingerprint(key, token);
+            assert!(keyring.by_fingerprint(&fingerprint).is_some());
+        }
+        // RPM does not get the unbound subkey either
+        let mut original = Keyring::new();
+        original.add(CERT, token).unwrap();
+        assert_eq!(keyring.certificates[0].1, original.certificates[0].1);
+    }
+
+    #[test]
+    fn reports_key_matches() {
+        let token = rpm_crypto::init();
+        let mut package = rpm_writer::testing::TestPackage::new("qubes-template-test");
+        package.scriptlets.push((1024, "echo hi".to_owned()));
+        package.signature = include_bytes!("../../template-header.sig").to_vec();
+        let package = package.build(token).unwrap();
+        let mut keyring = Keyring::new();
+        keyring
+            .add(include_bytes!("../../template-key.pgp"), token)
+            .unwrap();
+        let sig_header = |s: &mut &[u8]| {
+            crate::read_lead(s).unwrap();
+            crate::load_signature(s, AllowWeakHashes::No, token).unwrap()
+        };
+        // The signature has an issuer fingerprint, which the keyring uses
+        let mut s = &package[..];
+        let mut header = sig_header(&mut s);
+        let result = keyring
+            .verify_package(&mut s, &mut header, false, false, token, None, None)
+            .unwrap();
+        assert_eq!(result.header_sig_key_match, KeyMatch::Fingerprint);
+        // The key is EdDSA, so its size says nothing about its strength
+        assert_eq!(result.header_signer_bits, None);
+        assert_eq!(result.header_digest_algorithms, [8]);
+        let signer = result.header_signer.unwrap();
+        assert!(!keyring.by_fingerprint(&signer).unwrap().key.subkey);
+        // RPM only uses the key ID
+        let rpm_keyring = keyring.to_rpm_keyring(token).unwrap();
+        let mut s = &package[..];
+        let mut header = sig_header(&mut s);
+        let result = crate::verify_package(
+            &mut s,
+            &mut header,
+            &rpm_keyring,
+            false,
+            false,
+            token,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(result.header_sig_key_match, KeyMatch::KeyIdOnly);
+        assert_eq!(result.header_signer, None);
+    }
+}
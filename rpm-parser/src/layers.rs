@@ -0,0 +1,314 @@
+//! Layered keyrings
+//!
+//! A host typically trusts keys from several sources: the distribution’s
+//! keys installed with the system, keys deployed by an organization, and
+//! keys shipped alongside a single repository.  These are not equally
+//! trusted.  A [`KeyringStack`] holds one [`Keyring`] per source, in order
+//! of precedence, each with the scopes (usually repository IDs) it may
+//! validate.
+//!
+//! A key present in more than one layer belongs to the first of them: its
+//! copies in later layers are shadowed and never used.  Otherwise, a
+//! repository-local keyring could re-add a system key to use it outside the
+//! scopes the system layer allows.  A key that a layer has revoked outright
+//! shadows later copies too, so that a lower layer cannot bring it back.
+
+use crate::keyring::{no_key_error, try_key, usable, FORGED, NO_KEY};
+use crate::verify::keyring_error;
+use crate::{CertificateVerifier, KeyRef, Keyring, PolicyViolation};
+use openpgp_parser::cert::{KeyInvalid, KeyVerifier};
+use openpgp_parser::signature::SigInfo;
+use rpm_crypto::{InitToken, Signature};
+use std::io::Result;
+
+/// One layer of a [`KeyringStack`]
+#[derive(Clone, Debug)]
+pub struct KeyringLayer<'a> {
+    name: String,
+    keyring: Keyring<'a>,
+    scopes: Option<Vec<String>>,
+}
+
+impl<'a> KeyringLayer<'a> {
+    /// A layer named `name` (for instance `system`, `org`, or `repo`),
+    /// allowed to validate any scope
+    pub fn new(name: &str, keyring: Keyring<'a>) -> Self {
+        Self {
+            name: name.to_owned(),
+            keyring,
+            scopes: None,
+        }
+    }
+
+    /// Restricts the layer to the scopes in `scopes`.  Calling this more
+    /// than once adds to the allowed scopes.
+    pub fn only_for(mut self, scopes: &[&str]) -> Self {
+        self.scopes
+            .get_or_insert_with(Vec::new)
+            .extend(scopes.iter().map(|&scope| scope.to_owned()));
+        self
+    }
+
+    /// The name of the layer
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The keys in the layer
+    pub fn keyring(&self) -> &Keyring<'a> {
+        &self.keyring
+    }
+
+    /// May keys from this layer validate `scope`?
+    pub fn allows(&self, scope: &str) -> bool {
+        match self.scopes {
+            None => true,
+            Some(ref scopes) => scopes.iter().any(|s| s == scope),
+        }
+    }
+}
+
+/// A key found in a [`KeyringStack`], with the layer that supplied it
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+pub struct LayerKey<'k, 'a> {
+    /// The name of the layer the key came from
+    pub layer: &'k str,
+    /// The key
+    pub key: KeyRef<'k, 'a>,
+    /// Whether the layer may validate the scope the key was looked up for
+    pub allowed: bool,
+}
+
+/// An ordered stack of keyrings, highest precedence first
+#[derive(Clone, Debug, Default)]
+pub struct KeyringStack<'a> {
+    layers: Vec<KeyringLayer<'a>>,
+}
+
+impl<'a> KeyringStack<'a> {
+    /// Creates an empty stack
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `layer` below the layers already in the stack
+    pub fn push(&mut self, layer: KeyringLayer<'a>) -> &mut Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// The layers, highest precedence first
+    pub fn layers(&self) -> &[KeyringLayer<'a>] {
+        &self.layers
+    }
+
+    /// Is the key with fingerprint `fingerprint` in a layer before `index`,
+    /// revoked or not?
+    fn shadowed(&self, index: usize, fingerprint: &[u8]) -> bool {
+        self.layers[..index].iter().any(|layer| {
+            layer.keyring.by_fingerprint(fingerprint).is_some()
+                || layer.keyring.is_revoked(fingerprint)
+        })
+    }
+
+    /// The layer that owns the key with fingerprint `fingerprint`: the first
+    /// layer containing it.  Returns [`None`] if that layer revoked the key.
+    pub fn owner(&self, fingerprint: &[u8]) -> Option<LayerKey<'_, 'a>> {
+        let index = (0..self.layers.len()).find(|&i| !self.shadowed(i + 1, fingerprint))?;
+        let layer = &self.layers[index];
+        Some(LayerKey {
+            layer: &layer.name,
+            key: layer.keyring.by_fingerprint(fingerprint)?,
+            allowed: true,
+        })
+    }
+
+    /// The keys that could have made the signature described by `info`, as
+    /// with [`Keyring::candidates`], layer by layer.  Shadowed keys are
+    /// left out.  Keys from layers that may not validate `scope` are
+    /// included, but not [`allowed`](LayerKey::allowed).
+    pub fn candidates(
+        &self,
+        info: &SigInfo,
+        scope: &str,
+        token: InitToken,
+    ) -> Vec<LayerKey<'_, 'a>> {
+        usable(self.matching_keys(info, scope, &mut CertificateVerifier(token))).0
+    }
+
+    /// As [`Keyring::matching_keys`], layer by layer, without shadowed keys
+    fn matching_keys(
+        &self,
+        info: &SigInfo,
+        scope: &str,
+        verifier: &mut dyn KeyVerifier,
+    ) -> Vec<(LayerKey<'_, 'a>, std::result::Result<(), KeyInvalid>)> {
+        let mut found = vec![];
+        for (index, layer) in self.layers.iter().enumerate() {
+            let allowed = layer.allows(scope);
+            for (key, valid) in layer.keyring.matching_keys(info, verifier) {
+                if !self.shadowed(index, key.fingerprint) {
+                    let key = LayerKey {
+                        layer: &layer.name,
+                        key,
+                        allowed,
+                    };
+                    found.push((key, valid))
+                }
+            }
+        }
+        found
+    }
+
+    /// Verifies `sig`, which must already have hashed the signed data, for
+    /// `scope`, trying the allowed [candidates](KeyringStack::candidates) in
+    /// order.  Returns the key that verified it and the layer it came from.
+    ///
+    /// If the only candidates come from layers that may not validate
+    /// `scope`, fails with a [`PolicyViolation`] naming those layers.
+    /// Otherwise, errors are as for [`Keyring::verify`].
+    pub fn verify(
+        &self,
+        sig: &Signature,
+        scope: &str,
+        token: InitToken,
+    ) -> Result<LayerKey<'_, 'a>> {
+        if !sig.quick_check() {
+            return Err(keyring_error(FORGED));
+        }
+        let keys = self.matching_keys(sig.info(), scope, &mut CertificateVerifier(token));
+        let (candidates, invalid) = usable(keys);
+        let (allowed, denied): (Vec<_>, Vec<_>) =
+            candidates.into_iter().partition(|key| key.allowed);
+        let mut error = NO_KEY;
+        for key in &allowed {
+            if try_key(&key.key, sig, &mut error, token)? {
+                return Ok(*key);
+            }
+        }
+        if allowed.is_empty() && !denied.is_empty() {
+            let mut layers: Vec<&str> = denied.iter().map(|key| key.layer).collect();
+            layers.dedup();
+            return Err(PolicyViolation::error(format!(
+                "signing key is only trusted by {} {}, which may not validate {}",
+                if layers.len() == 1 { "layer" } else { "layers" },
+                layers.join(", "),
+                scope
+            )));
+        }
+        if allowed.is_empty() {
+            return Err(no_key_error(sig.info(), invalid));
+        }
+        Err(keyring_error(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openpgp_parser::key::PublicKey;
+    use openpgp_parser::AllowWeakHashes;
+    use std::convert::TryInto;
+
+    static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+
+    fn fake_fingerprint(key: &PublicKey<'_>) -> Vec<u8> {
+        let mut fingerprint = vec![0; 20];
+        fingerprint[15..19].copy_from_slice(&key.creation_time.to_be_bytes());
+        fingerprint[19] = key.algorithm.into();
+        fingerprint
+    }
+
+    /// A v4 EdDSA signature with key ID `key_id`, made after the test key
+    /// was created
+    fn signature_info(key_id: [u8; 8]) -> SigInfo {
+        let mut body = vec![4, 0, 22, 8, 0, 16, 5, 2, 0x70, 0, 0, 0, 9, 16];
+        body.extend_from_slice(&key_id);
+        body.extend_from_slice(&[0, 0, 0xAB, 0xCD, 0, 1, 1, 0, 1, 1]);
+        let packet = [&[0x88, body.len() as u8][..], &body].concat();
+        openpgp_parser::signature::parse(&packet, 0, AllowWeakHashes::No).unwrap()
+    }
+
+    fn keyring(data: &[u8], first_byte: u8) -> Keyring<'_> {
+        let mut keyring = Keyring::new();
+        keyring
+            .add_with(
+                data,
+                &mut |key| {
+                    let mut fingerprint = fake_fingerprint(key);
+                    fingerprint[0] = first_byte;
+                    fingerprint
+                },
+                &mut |certificate| vec![true; certificate.subkeys().len()],
+            )
+            .unwrap();
+        keyring
+    }
+
+    #[test]
+    fn layers_keyrings() {
+        let token = rpm_crypto::init();
+        let mut stack = KeyringStack::new();
+        stack
+            .push(KeyringLayer::new("system", keyring(CERT, 0)))
+            .push(KeyringLayer::new("org", keyring(CERT, 1)).only_for(&["org-updates"]))
+            // Shadowed by the system layer
+            .push(KeyringLayer::new("repo", keyring(CERT, 0)));
+        let names: Vec<_> = stack.layers().iter().map(|l| l.name()).collect();
+        assert_eq!(names, ["system", "org", "repo"]);
+        assert!(stack.layers()[1].allows("org-updates"));
+        assert!(!stack.layers()[1].allows("fedora"));
+
+        let primary = stack.layers()[0].keyring().certificates().next().unwrap();
+        let mut fingerprint = fake_fingerprint(primary.primary());
+        let key_id = fingerprint[12..].try_into().unwrap();
+        let candidates = stack.candidates(&signature_info(key_id), "fedora", token);
+        let found: Vec<_> = candidates
+            .iter()
+            .map(|k| (k.layer, k.key.fingerprint[0], k.allowed))
+            .collect();
+        assert_eq!(found, [("system", 0, true), ("org", 1, false)]);
+        let candidates = stack.candidates(&signature_info(key_id), "org-updates", token);
+        assert!(candidates.iter().all(|k| k.allowed));
+
+        assert_eq!(stack.owner(&fingerprint).unwrap().layer, "system");
+        fingerprint[0] = 1;
+        assert_eq!(stack.owner(&fingerprint).unwrap().layer, "org");
+        fingerprint[0] = 2;
+        assert!(stack.owner(&fingerprint).is_none());
+    }
+
+    /// A hard revocation (key compromised) of the primary key, with no
+    /// actual signature
+    fn revocation_packet() -> Vec<u8> {
+        let hashed = [5, 2, 0, 0, 0, 1, 3, 29, 2, b'x'];
+        let mut body = vec![4, 0x20, 22, 8, 0, hashed.len() as u8];
+        body.extend_from_slice(&hashed);
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        [&[0x88, body.len() as u8][..], &body].concat()
+    }
+
+    #[test]
+    fn revoked_keys_shadow_lower_layers() {
+        let (primary, rest) = CERT.split_at(53);
+        let revoked = [primary, &revocation_packet(), rest].concat();
+        let mut stack = KeyringStack::new();
+        stack
+            .push(KeyringLayer::new("system", keyring(&revoked, 0)))
+            // Re-adds the key the system layer revoked
+            .push(KeyringLayer::new("repo", keyring(CERT, 0)));
+        let certificate = stack.layers()[1].keyring().certificates().next().unwrap();
+        let fingerprint = fake_fingerprint(certificate.primary());
+        assert!(stack.layers()[0].keyring().is_revoked(&fingerprint));
+        assert!(stack.layers()[1]
+            .keyring()
+            .by_fingerprint(&fingerprint)
+            .is_some());
+        let key_id = fingerprint[12..].try_into().unwrap();
+        assert!(stack
+            .candidates(&signature_info(key_id), "fedora", rpm_crypto::init())
+            .is_empty());
+        assert!(stack.owner(&fingerprint).is_none());
+    }
+}
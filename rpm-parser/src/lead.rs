@@ -38,6 +38,10 @@ impl RPMLead {
         }
     }
 
+    pub fn version(&self) -> (u8, u8) {
+        (self.major, self.minor)
+    }
+
     pub fn ty(&self) -> u16 {
         self.ty.to_be()
     }
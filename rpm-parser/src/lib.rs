@@ -40,17 +40,67 @@ macro_rules! fail_if {
     }
 }
 
+mod archive;
+mod bootstrap;
+mod bundle;
+mod config;
+mod deadline;
+mod domain;
 mod ffi;
+mod files;
 mod header;
+mod keyring;
+mod layers;
 mod lead;
+mod offsets;
 mod package;
+mod platform;
+mod policy;
+pub mod prelude;
+mod sanitize;
+mod selftest;
+mod sources;
 mod tagdata;
+mod template;
+mod transaction;
+mod triage;
 mod verify;
-pub use ffi::TagType;
+pub use archive::{
+    detect_archive, for_each_package, ArchiveContents, ArchiveFormat, PackageCallback,
+};
+pub use bootstrap::{fetch_pinned, fingerprint, import_pinned, CertificateVerifier};
+pub use bootstrap::{FEDORA_KEYS_URL, RHEL_KEY_URL};
+pub use bundle::{verify_bundle, BundleEntry, BundleReader, BundleWriter, BUNDLE_MAGIC};
+pub use config::{verify_config, SignedConfig};
+pub use deadline::{Deadline, DeadlineExceeded};
+pub use domain::{verify_detached_in, Domain, Verified};
+pub use ffi::{tag_by_name, TagType};
+pub use files::{check_symlinks, file_list, SymlinkFinding, SymlinkPolicy};
 pub use header::Header as RPMHeader;
-pub use header::{load_immutable, load_signature, parse_header_magic, RPM_HDRMAGIC};
+pub use header::{inspect_header, HeaderFinding};
+pub use header::{load_header_file, load_immutable, load_signature, load_signature_with_options};
+pub use header::{parse_header_events, TagValue};
+pub use header::{parse_header_magic, RPM_HDRMAGIC};
+pub use header::{repair_header, repair_package, PackageRepairs, Repair};
 pub use header::{ImmutableHeader as MainHeader, SignatureHeader};
+pub use keyring::{KeyRef, Keyring};
+pub use layers::{KeyringLayer, KeyringStack, LayerKey};
 pub use lead::{read_lead, RPMLead};
+pub use offsets::{offset_map, EntryLocation, OffsetMap};
+pub use openpgp_parser::ErrorCategory;
 pub use package::RPMPackage;
+pub use platform::{check_platform, PlatformFinding, PlatformPolicy};
+pub use policy::{Decision, Policy, PolicyViolation, VerdictFacts};
+pub use sanitize::{
+    escape_html, escape_terminal, is_unsafe_char, sanitize, sanitize_text, StringMode,
+};
+pub use selftest::selftest;
+pub use sources::{extract_entry, source_entries, source_entry, SourceEntry, SourceKind};
 pub use tagdata::TagData;
-pub use verify::{verify_package, VerifyResult};
+pub use template::{check_scriptlets, check_template_name, verify_template, TemplatePolicy};
+pub use template::{SCRIPTLET_TAGS, TEMPLATE_NAME_PREFIX};
+pub use transaction::{Rejection, TransactionDecision, TransactionMode, TransactionVerdict};
+pub use triage::{evidence_bundle, triage, SignatureSummary, Triage};
+pub use verify::{error_category, failure_kind, verify_cleartext, verify_detached};
+pub use verify::{verify_package, VerifyCallback, VerifyResult};
+pub use verify::{DigestRecord, DigestRegion, FailureKind, KeyMatch, VerificationFailure};
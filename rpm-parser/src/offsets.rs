@@ -0,0 +1,131 @@
+//! File offsets of header entries
+//!
+//! Tools that patch or diff packages at the byte level need to know where
+//! each header entry lives in the file.  [`offset_map`] parses the lead and
+//! both headers and reports the absolute offset of every entry, so such tools
+//! do not need to reimplement the header format.  Only the structure of the
+//! headers is checked: nothing is verified, and the offsets are only as
+//! trustworthy as the package.
+
+use crate::header::load_header;
+use crate::{read_lead, TagData};
+use std::io::{Read, Result};
+use std::ops::Range;
+
+/// The location of one header entry in a package file
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EntryLocation {
+    /// The tag
+    pub tag: u32,
+    /// The file offset of the 16-byte index entry
+    pub index_offset: u64,
+    /// The file offset and length of the entry’s data.  For the region
+    /// entry, this is the region trailer.
+    pub data: Range<u64>,
+}
+
+/// The locations of the parts of a package file
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OffsetMap {
+    /// The signature header, excluding the padding after it
+    pub signature_header: Range<u64>,
+    /// The entries of the signature header, in index order, starting with
+    /// the region entry
+    pub signature_entries: Vec<EntryLocation>,
+    /// The main header
+    pub main_header: Range<u64>,
+    /// The entries of the main header, in index order, starting with the
+    /// region entry
+    pub main_entries: Vec<EntryLocation>,
+    /// The offset of the payload, which extends to the end of the file
+    pub payload_offset: u64,
+}
+
+/// Loads the header starting at file offset `start` from `r`, returning the
+/// locations of its entries and the offset of its end
+fn locate_entries(
+    r: &mut dyn Read,
+    start: u64,
+    region_tag: u32,
+) -> Result<(Vec<EntryLocation>, u64)> {
+    let mut entries = vec![];
+    let mut cb = |_, entry: &TagData, body: &[u8]| {
+        entries.push((entry.tag(), u64::from(entry.offset()), body.len() as u64));
+        Ok(())
+    };
+    let header = load_header(r, region_tag, &mut cb)?;
+    let index_start = start + 16;
+    let data_start = index_start + 16 * header.index.len() as u64;
+    let data_len = header.data.len() as u64;
+    let region = EntryLocation {
+        tag: region_tag,
+        index_offset: index_start,
+        data: data_start + data_len - 16..data_start + data_len,
+    };
+    let locations = std::iter::once(region)
+        .chain(
+            entries
+                .into_iter()
+                .zip(1..)
+                .map(|((tag, offset, len), i): (_, u64)| EntryLocation {
+                    tag,
+                    index_offset: index_start + 16 * i,
+                    data: data_start + offset..data_start + offset + len,
+                }),
+        )
+        .collect();
+    Ok((locations, data_start + data_len))
+}
+
+/// Map the headers of the package read from `r`.  The payload is not read.
+pub fn offset_map(r: &mut dyn Read) -> Result<OffsetMap> {
+    read_lead(r)?;
+    let signature_start = 96;
+    let (signature_entries, signature_end) = locate_entries(r, signature_start, 62)?;
+    // The signature header is padded to a multiple of 8 bytes
+    let padding = (8 - (signature_end & 7)) & 7;
+    let mut zeros = [0; 8];
+    r.read_exact(&mut zeros[..padding as usize])?;
+    fail_if!(zeros != [0; 8], "signature header padding is not zeroed");
+    let main_start = signature_end + padding;
+    let (main_entries, main_end) = locate_entries(r, main_start, 63)?;
+    Ok(OffsetMap {
+        signature_header: signature_start..signature_end,
+        signature_entries,
+        main_header: main_start..main_end,
+        main_entries,
+        payload_offset: main_end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn maps_offsets() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let map = offset_map(&mut &s[..]).unwrap();
+        assert_eq!(map.signature_header.start, 96);
+        assert_eq!(map.main_header.start, 4504);
+        let range = |r: &Range<u64>| &s[r.start as usize..r.end as usize];
+        let name = map.main_entries.iter().find(|e| e.tag == 1000).unwrap();
+        assert_eq!(range(&name.data), b"lua\0");
+        assert_eq!(
+            range(&(name.index_offset..name.index_offset + 4)),
+            1000u32.to_be_bytes()
+        );
+        for (entries, region_tag) in [(&map.signature_entries, 62), (&map.main_entries, 63)] {
+            assert_eq!(entries[0].tag, region_tag);
+            // The region trailer repeats the region tag
+            assert_eq!(range(&entries[0].data)[..4], region_tag.to_be_bytes());
+            for pair in entries.windows(2) {
+                assert_eq!(pair[1].index_offset, pair[0].index_offset + 16);
+            }
+        }
+        let mut r = &s[4504..];
+        load_header(&mut r, 63, &mut |_, _, _| Ok(())).unwrap();
+        assert_eq!(map.payload_offset, (s.len() - r.len()) as u64);
+    }
+}
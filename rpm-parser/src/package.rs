@@ -5,7 +5,7 @@
 
 use crate::{
     header::{ImmutableHeader, SignatureHeader},
-    load_immutable, load_signature, read_lead, RPMLead,
+    load_immutable, load_signature, read_lead, PlatformPolicy, RPMLead,
 };
 use openpgp_parser::AllowWeakHashes;
 use rpm_crypto::InitToken;
@@ -18,22 +18,30 @@ pub struct RPMPackage {
     pub immutable: ImmutableHeader,
 }
 
-include!("tables.rs");
-
 impl RPMPackage {
-    /// Load a package from `r`
+    /// Load a package from `r`.  The numbers in the lead must be the ones RPM
+    /// uses for the architecture and OS in the header.
     pub fn read(
         r: &mut dyn Read,
         allow_weak_hashes: AllowWeakHashes,
         token: InitToken,
+    ) -> Result<Self> {
+        Self::read_with_platform(r, allow_weak_hashes, &PlatformPolicy::default(), token)
+    }
+
+    /// As [`RPMPackage::read`], but the numbers in the lead may also be those
+    /// `platform` gives for names RPM does not know, such as a distribution’s
+    /// `x86_64_v2`.
+    pub fn read_with_platform(
+        r: &mut dyn Read,
+        allow_weak_hashes: AllowWeakHashes,
+        platform: &PlatformPolicy,
+        token: InitToken,
     ) -> Result<Self> {
         let lead = read_lead(r)?;
         let signature = load_signature(r, allow_weak_hashes, token)?;
         let immutable = load_immutable(r, token)?;
-        let (osnum, archnum) = (
-            os_to_osnum(immutable.os.as_bytes()).unwrap_or(255),
-            arch_to_archnum(immutable.arch.as_bytes()).unwrap_or(255),
-        );
+        let (archnum, osnum) = platform.lead_numbers(&immutable.arch, &immutable.os);
         if lead.osnum() != osnum {
             bad_data!(
                 "Wrong OS number in lead (expected {}, found {})",
@@ -76,6 +84,12 @@ impl RPMPackage {
             immutable,
         })
     }
+
+    /// Quickly classify a package from `r` without verifying it.  See
+    /// [`crate::triage()`].
+    pub fn triage(r: &mut dyn Read) -> Result<crate::Triage> {
+        crate::triage(r)
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +111,7 @@ mod tests {
             header_payload_weak_digest: _,
             header_sha1_hash: _,
             header_sha256_hash: _,
+            unknown_tags: _,
         } = signature;
         assert!(header_signature.is_some());
         assert!(header_payload_signature.is_some());
@@ -104,4 +119,81 @@ mod tests {
         ctx.update(s);
         assert_eq!(ctx.finalize(true), digest);
     }
+
+    /// Builds a tiny package with `defect` and checks its digests.  Returns
+    /// the signature header and whether the header and payload digests
+    /// match.
+    fn check_tiny_package(
+        defect: Option<rpm_writer::testing::Defect>,
+    ) -> Result<(SignatureHeader, bool, bool)> {
+        let token = rpm_crypto::init();
+        let mut package = rpm_writer::testing::TestPackage::new("tiny");
+        package.defect = defect;
+        let package = package.build(token)?;
+        let mut s = &package[..];
+        read_lead(&mut s)?;
+        let mut signature = load_signature(&mut s, AllowWeakHashes::No, token)?;
+        let main_header = s;
+        let immutable = load_immutable(&mut s, token)?;
+        let main_header = &main_header[..main_header.len() - s.len()];
+        let (mut ctx, digest) = signature.header_sha256_hash.take().unwrap();
+        ctx.update(main_header);
+        let header_ok = ctx.finalize(true) == digest;
+        let (mut ctx, digest) = immutable.payload_digest()?;
+        ctx.update(s);
+        Ok((signature, header_ok, ctx.finalize(true) == digest))
+    }
+
+    #[test]
+    fn reads_tiny_packages() {
+        use rpm_writer::testing::{Defect, TestPackage};
+        let token = rpm_crypto::init();
+        let package = TestPackage::new("tiny").build(token).unwrap();
+        RPMPackage::read(&mut &package[..], AllowWeakHashes::No, token).unwrap();
+        let (signature, header_ok, payload_ok) = check_tiny_package(None).unwrap();
+        assert!(signature.header_signature.is_some());
+        assert!(header_ok && payload_ok);
+        let (_, header_ok, payload_ok) = check_tiny_package(Some(Defect::BadHeaderDigest)).unwrap();
+        assert!(!header_ok && payload_ok);
+        let (_, header_ok, payload_ok) =
+            check_tiny_package(Some(Defect::BadPayloadDigest)).unwrap();
+        assert!(header_ok && !payload_ok);
+        let (signature, _, _) = check_tiny_package(Some(Defect::StrippedSignature)).unwrap();
+        assert!(signature.header_signature.is_none());
+        let e = check_tiny_package(Some(Defect::OversizedTag))
+            .err()
+            .unwrap();
+        assert!(e.to_string().starts_with("BAD: tag size"), "{}", e);
+    }
+
+    #[test]
+    fn reads_distro_arches() {
+        let token = rpm_crypto::init();
+        let mut package = rpm_writer::testing::TestPackage::new("v2");
+        package.arch = "x86_64_v2".to_owned();
+        package.archnum = Some(1);
+        let package = package.build(token).unwrap();
+        let e = RPMPackage::read(&mut &package[..], AllowWeakHashes::No, token)
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Wrong arch number in lead (expected 255, found 1)"
+        );
+        let rhel = PlatformPolicy::distro("rhel").unwrap();
+        let read = |platform| {
+            RPMPackage::read_with_platform(&mut &package[..], AllowWeakHashes::No, platform, token)
+        };
+        let package = read(&rhel).unwrap();
+        assert_eq!(package.immutable.arch, "x86_64_v2");
+        let findings = crate::check_platform(
+            &package.lead,
+            &package.immutable.arch,
+            &package.immutable.os,
+            &rhel,
+        );
+        assert_eq!(findings.unwrap(), []);
+        let openeuler = PlatformPolicy::distro("openeuler").unwrap();
+        assert!(read(&openeuler).is_err());
+    }
 }
@@ -0,0 +1,187 @@
+//! Validation of the architecture and operating system of a package
+//!
+//! The architecture and OS appear twice: as names in the main header, and as
+//! numbers in the lead.
+//! [`RPMPackage::read_with_platform`](crate::RPMPackage::read_with_platform)
+//! requires the two to agree, using RPM’s table plus any distribution-specific
+//! names from a [`PlatformPolicy`].  [`check_platform`] goes further and
+//! reports names that are in neither.
+
+use crate::{PolicyViolation, RPMLead};
+use std::io::Result;
+
+include!("tables.rs");
+
+/// The number used in the lead for names that RPM does not know
+const UNKNOWN: u16 = 255;
+
+/// Which architectures and operating systems to accept
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlatformPolicy {
+    /// Architectures not in RPM’s table, with their lead numbers
+    pub extra_arches: Vec<(String, u16)>,
+    /// Operating systems not in RPM’s table, with their lead numbers
+    pub extra_oses: Vec<(String, u16)>,
+    /// Treat every finding as an error?
+    pub strict: bool,
+}
+
+impl PlatformPolicy {
+    /// The names accepted by [`PlatformPolicy::distro`]
+    pub const DISTROS: &'static [&'static str] = &["fedora", "rhel", "openeuler"];
+
+    /// Looks up the extensions used by a distribution.  Returns [`None`] if
+    /// the distribution is not known.
+    ///
+    /// - `fedora`: everything Fedora builds is in RPM’s table.
+    /// - `rhel`: adds the `x86_64_v2` microarchitecture level, used by
+    ///   rebuilds of RHEL 10 for older hardware.
+    /// - `openeuler`: adds `loongarch64`.
+    pub fn distro(name: &str) -> Option<Self> {
+        let extra_arches: &[(&str, u16)] = match name {
+            "fedora" => &[],
+            "rhel" => &[("x86_64_v2", 1)],
+            "openeuler" => &[("loongarch64", 23)],
+            _ => return None,
+        };
+        Some(Self {
+            extra_arches: extra_arches
+                .iter()
+                .map(|&(arch, num)| (arch.to_owned(), num))
+                .collect(),
+            ..Self::default()
+        })
+    }
+
+    fn archnum(&self, arch: &str) -> Option<u16> {
+        arch_to_archnum(arch.as_bytes()).or_else(|| lookup(&self.extra_arches, arch))
+    }
+
+    fn osnum(&self, os: &str) -> Option<u16> {
+        os_to_osnum(os.as_bytes()).or_else(|| lookup(&self.extra_oses, os))
+    }
+
+    /// The lead numbers for `arch` and `os`, as `(archnum, osnum)`
+    pub(crate) fn lead_numbers(&self, arch: &str, os: &str) -> (u16, u16) {
+        (
+            self.archnum(arch).unwrap_or(UNKNOWN),
+            self.osnum(os).unwrap_or(UNKNOWN),
+        )
+    }
+}
+
+fn lookup(table: &[(String, u16)], name: &str) -> Option<u16> {
+    table
+        .iter()
+        .find(|(i, _)| i.eq_ignore_ascii_case(name))
+        .map(|&(_, num)| num)
+}
+
+/// A problem found by [`check_platform`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlatformFinding {
+    /// The architecture in the header is not known
+    UnknownArch(String),
+    /// The operating system in the header is not known
+    UnknownOs(String),
+    /// The architecture number in the lead does not match the header
+    LeadArchMismatch {
+        /// The number expected from the header
+        expected: u16,
+        /// The number in the lead
+        found: u16,
+    },
+    /// The OS number in the lead does not match the header
+    LeadOsMismatch {
+        /// The number expected from the header
+        expected: u16,
+        /// The number in the lead
+        found: u16,
+    },
+}
+
+/// Check the `arch` and `os` of the main header, and the numbers in `lead`,
+/// against `policy`.  Unknown names and mismatched numbers are returned as
+/// findings, unless `policy.strict` is set, in which case the first one is
+/// an error.
+pub fn check_platform(
+    lead: &RPMLead,
+    arch: &str,
+    os: &str,
+    policy: &PlatformPolicy,
+) -> Result<Vec<PlatformFinding>> {
+    let mut findings = vec![];
+    let archnum = policy.archnum(arch).unwrap_or_else(|| {
+        findings.push(PlatformFinding::UnknownArch(arch.to_owned()));
+        UNKNOWN
+    });
+    let osnum = policy.osnum(os).unwrap_or_else(|| {
+        findings.push(PlatformFinding::UnknownOs(os.to_owned()));
+        UNKNOWN
+    });
+    if lead.archnum() != archnum {
+        findings.push(PlatformFinding::LeadArchMismatch {
+            expected: archnum,
+            found: lead.archnum(),
+        })
+    }
+    if lead.osnum() != osnum {
+        findings.push(PlatformFinding::LeadOsMismatch {
+            expected: osnum,
+            found: lead.osnum(),
+        })
+    }
+    match findings.first() {
+        Some(finding) if policy.strict => Err(PolicyViolation::error(format!(
+            "Bad platform: {:?}",
+            finding
+        ))),
+        _ => Ok(findings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn checks_platforms() {
+        let lead = RPMLead::new(false, 1, 1, b"foo-1-1");
+        let policy = PlatformPolicy::default();
+        assert_eq!(
+            check_platform(&lead, "x86_64", "Linux", &policy).unwrap(),
+            []
+        );
+        assert_eq!(
+            check_platform(&lead, "x86_64_v2", "linux", &policy).unwrap(),
+            [
+                PlatformFinding::UnknownArch("x86_64_v2".to_owned()),
+                PlatformFinding::LeadArchMismatch {
+                    expected: 255,
+                    found: 1
+                },
+            ]
+        );
+        let rhel = PlatformPolicy::distro("rhel").unwrap();
+        assert_eq!(
+            check_platform(&lead, "x86_64_v2", "linux", &rhel).unwrap(),
+            []
+        );
+        assert_eq!(
+            check_platform(&lead, "aarch64", "linux", &rhel).unwrap(),
+            [PlatformFinding::LeadArchMismatch {
+                expected: 19,
+                found: 1
+            }]
+        );
+        let strict = PlatformPolicy {
+            strict: true,
+            ..rhel
+        };
+        assert!(check_platform(&lead, "x86_64", "plan9", &strict).is_err());
+        for &i in PlatformPolicy::DISTROS {
+            assert!(PlatformPolicy::distro(i).is_some(), "missing distro {}", i)
+        }
+    }
+}
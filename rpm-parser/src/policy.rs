@@ -0,0 +1,419 @@
+//! Named verification policies
+//!
+//! The verification functions take their policy as individual flags.  This
+//! module bundles those flags into a [`Policy`] and provides presets for the
+//! ecosystems rpm-oxide is commonly used with, so that callers do not need to
+//! know which flags a given distribution needs.
+
+use crate::{KeyMatch, VerifyResult};
+use openpgp_parser::{signature, AllowWeakHashes, HashAlgorithm, PublicKeyAlgorithm};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+
+/// A policy violation: the package is well-formed, but the caller’s policy
+/// rejects it.  This is wrapped in the [`std::io::Error`] returned by policy
+/// checks; use [`crate::error_category`] to detect it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyViolation {
+    message: Cow<'static, str>,
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+impl PolicyViolation {
+    fn new<T: Into<Cow<'static, str>>>(message: T) -> Self {
+        let message = message.into();
+        Self { message }
+    }
+
+    pub(crate) fn error<T: Into<Cow<'static, str>>>(message: T) -> Error {
+        Error::new(ErrorKind::InvalidData, Self::new(message))
+    }
+}
+
+/// The name of the OpenPGP hash algorithm `id`, or its number if unknown
+fn hash_name(id: u8) -> String {
+    match HashAlgorithm::try_from(id) {
+        Ok(alg) => alg.to_string(),
+        Err(_) => id.to_string(),
+    }
+}
+
+/// The facts about a verified package that a [`Policy`] depends on.  They
+/// are small and involve no cryptography, so they can be cached and later
+/// passed to [`Policy::evaluate`] to see how a different policy would have
+/// classified the package.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct VerdictFacts {
+    /// How the header signature identified the signing key
+    pub header_sig_key_match: KeyMatch,
+    /// The OpenPGP hash algorithms of the header signature and, if present,
+    /// the header+payload signature, in that order
+    pub signature_hashes: Vec<u8>,
+    /// The OpenPGP hash algorithm of the payload digest, or [`None`] for old
+    /// packages without one
+    pub payload_digest_algorithm: Option<u8>,
+    /// The OpenPGP hash algorithms of the main header digests in the
+    /// signature header
+    pub header_digest_algorithms: Vec<u8>,
+    /// The size in bits of the RSA or DSA key that made the header
+    /// signature, if known (see [`VerifyResult::header_signer_bits`])
+    pub header_signer_bits: Option<u32>,
+    /// The public-key algorithm of the header signature, if known
+    pub header_signer_algorithm: Option<PublicKeyAlgorithm>,
+    /// Signature header tags that were not understood
+    pub unknown_signature_tags: Vec<u32>,
+}
+
+impl VerdictFacts {
+    /// Creates facts from cached values.  There are no header digests, the
+    /// size and algorithm of the signing key are unknown, and there are no
+    /// unknown signature header tags; set the fields to change that.
+    pub fn new(
+        header_sig_key_match: KeyMatch,
+        signature_hashes: Vec<u8>,
+        payload_digest_algorithm: Option<u8>,
+    ) -> Self {
+        Self {
+            header_sig_key_match,
+            signature_hashes,
+            payload_digest_algorithm,
+            header_digest_algorithms: vec![],
+            header_signer_bits: None,
+            header_signer_algorithm: None,
+            unknown_signature_tags: vec![],
+        }
+    }
+
+    /// Extracts the facts from the result of [`crate::verify_package`]
+    pub fn from_result(result: &VerifyResult) -> Result<Self> {
+        let signatures = std::iter::once(&result.header_sig).chain(&result.header_payload_sig);
+        let infos = signatures
+            .map(|sig| {
+                signature::parse(sig, 0, AllowWeakHashes::Yes).map_err(crate::verify::openpgp_error)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let signature_hashes = infos.iter().map(|info| info.hash_alg.into()).collect();
+        Ok(Self {
+            header_digest_algorithms: result.header_digest_algorithms.clone(),
+            header_signer_bits: result.header_signer_bits,
+            header_signer_algorithm: Some(infos[0].pkey_alg),
+            unknown_signature_tags: result.unknown_signature_tags.clone(),
+            ..Self::new(
+                result.header_sig_key_match,
+                signature_hashes,
+                result.main_header.payload_digest_algorithm(),
+            )
+        })
+    }
+}
+
+/// The outcome of [`Policy::evaluate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Decision {
+    /// The policy accepts the package
+    Accept,
+    /// The policy rejects the package, for the given reason
+    Reject(PolicyViolation),
+}
+
+/// A verification policy
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Policy {
+    /// Allow signatures using SHA-1 or SHA-224?
+    pub allow_weak_hashes: AllowWeakHashes,
+    /// Allow packages without payload digests?
+    pub allow_old_pkgs: bool,
+    /// Reject header signatures whose key was found only by key ID?  RPM
+    /// always finds keys that way, so this needs [`crate::Keyring::verify_package`].
+    pub require_fingerprint: bool,
+    /// If set, the OpenPGP hash algorithm that every signature, header
+    /// digest, and the payload digest must use.  Packages using any other
+    /// algorithm, even a strong one, are rejected before their payload is
+    /// read.
+    pub required_hash: Option<HashAlgorithm>,
+    /// The smallest RSA or DSA key, in bits, that may make the header
+    /// signature.  Elliptic-curve keys are always allowed.  RPM does not say
+    /// which key it used, so this needs [`crate::Keyring::verify_package`];
+    /// unless this is zero, other keys of unknown size are rejected.
+    pub min_key_bits: u32,
+    /// Allow signature header tags that this library does not understand?
+    /// They are not covered by any signature, but newer versions of RPM add
+    /// tags that older parsers do not know.
+    pub allow_unknown_tags: bool,
+}
+
+impl Default for Policy {
+    /// The strictest policy.  This is the same as the `qubes-dom0` preset.
+    fn default() -> Self {
+        Self {
+            allow_weak_hashes: AllowWeakHashes::No,
+            allow_old_pkgs: false,
+            require_fingerprint: true,
+            required_hash: None,
+            min_key_bits: 3072,
+            allow_unknown_tags: false,
+        }
+    }
+}
+
+impl Policy {
+    /// The names accepted by [`Policy::preset`]
+    pub const PRESETS: &'static [&'static str] =
+        &["fedora", "epel", "qubes-dom0", "legacy-el7", "sha512-only"];
+
+    /// Looks up a preset by name.  Returns [`None`] if there is no such preset.
+    ///
+    /// - `fedora`: strong hashes and payload digests are required, but
+    ///   signatures by older versions of RPM carry only a key ID.  Keys must
+    ///   be at least 2048 bits, and signature header tags added by newer
+    ///   versions of RPM are allowed.
+    /// - `epel`: the same as `fedora`.  EPEL is built and signed by Fedora’s
+    ///   infrastructure, so nothing distinguishes its packages; the name
+    ///   exists so that configurations can say what they verify.
+    /// - `qubes-dom0`: as `fedora`, but key IDs alone are not trusted, keys
+    ///   must be at least 3072 bits, and unknown tags are rejected.
+    /// - `legacy-el7`: RHEL 7 and derivatives.  RPM 4.11 does not write
+    ///   payload digests, and some packages are signed with SHA-1.  Keys
+    ///   and tags are checked as for `fedora`.
+    /// - `sha512-only`: as `qubes-dom0`, but signatures and all digests must
+    ///   use SHA-512.  Packages built with RPM’s default SHA-256 payload
+    ///   digests are rejected, and so are packages whose signature header
+    ///   has the SHA-256 or SHA-1 header digest that RPM adds by default.
+    pub fn preset(name: &str) -> Option<Self> {
+        let strict = Self::default();
+        Some(match name {
+            "fedora" | "epel" => Self {
+                require_fingerprint: false,
+                min_key_bits: 2048,
+                allow_unknown_tags: true,
+                ..strict
+            },
+            "qubes-dom0" => strict,
+            "legacy-el7" => Self {
+                allow_weak_hashes: AllowWeakHashes::Yes,
+                allow_old_pkgs: true,
+                require_fingerprint: false,
+                required_hash: None,
+                min_key_bits: 2048,
+                allow_unknown_tags: true,
+            },
+            "sha512-only" => Self {
+                required_hash: Some(HashAlgorithm::Sha512),
+                ..strict
+            },
+            _ => return None,
+        })
+    }
+
+    /// Checks the parts of the policy that [`crate::verify_package`] does not
+    /// enforce itself.  Call this from the callback passed to
+    /// [`crate::verify_package`].
+    pub fn check(&self, result: &VerifyResult) -> Result<()> {
+        match self.evaluate(&VerdictFacts::from_result(result)?) {
+            Decision::Accept => Ok(()),
+            Decision::Reject(violation) => Err(Error::new(ErrorKind::InvalidData, violation)),
+        }
+    }
+
+    /// Decides whether this policy accepts a package with the given facts.
+    /// This covers the whole policy, including the parts enforced by
+    /// [`crate::verify_package`], but performs no cryptography: the facts
+    /// are assumed to come from a package whose signatures were valid.
+    pub fn evaluate(&self, facts: &VerdictFacts) -> Decision {
+        let reject = |message: String| Decision::Reject(PolicyViolation::new(message));
+        for &hash_alg in &facts.signature_hashes {
+            let allowed = HashAlgorithm::try_from(hash_alg)
+                .and_then(|alg| alg.check(self.allow_weak_hashes))
+                .is_ok();
+            if !allowed {
+                return reject(format!(
+                    "Signature uses hash algorithm {}, which policy does not allow",
+                    hash_name(hash_alg)
+                ));
+            }
+        }
+        if facts.payload_digest_algorithm.is_none() && !self.allow_old_pkgs {
+            return reject("Package has no payload digest".to_owned());
+        }
+        if self.require_fingerprint && facts.header_sig_key_match != KeyMatch::Fingerprint {
+            return reject("Header signature key was found only by key ID".to_owned());
+        }
+        let elliptic_curve = facts
+            .header_signer_algorithm
+            .is_some_and(PublicKeyAlgorithm::is_elliptic_curve);
+        match facts.header_signer_bits {
+            Some(bits) if bits < self.min_key_bits => {
+                return reject(format!(
+                    "Header signature key has {} bits, but policy requires at least {}",
+                    bits, self.min_key_bits
+                ))
+            }
+            None if self.min_key_bits > 0 && !elliptic_curve => return reject(format!(
+                "Header signature key has an unknown size, but policy requires at least {} bits",
+                self.min_key_bits
+            )),
+            _ => {}
+        }
+        if !self.allow_unknown_tags {
+            if let Some(tag) = facts.unknown_signature_tags.first() {
+                return reject(format!("Signature header has unknown tag {}", tag));
+            }
+        }
+        if let Some(required) = self.required_hash {
+            let is_required = |id: u8| HashAlgorithm::try_from(id).ok() == Some(required);
+            for &hash_alg in &facts.signature_hashes {
+                if !is_required(hash_alg) {
+                    return reject(format!(
+                        "Signature uses hash algorithm {}, but policy requires {}",
+                        hash_name(hash_alg),
+                        required
+                    ));
+                }
+            }
+            for &hash_alg in &facts.header_digest_algorithms {
+                if !is_required(hash_alg) {
+                    return reject(format!(
+                        "Header digest uses hash algorithm {}, but policy requires {}",
+                        hash_name(hash_alg),
+                        required
+                    ));
+                }
+            }
+            match facts.payload_digest_algorithm {
+                Some(alg) if is_required(alg) => {}
+                alg => {
+                    return reject(format!(
+                        "Payload digest uses hash algorithm {:?}, but policy requires {}",
+                        alg.map(hash_name),
+                        required
+                    ))
+                }
+            }
+        }
+        Decision::Accept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn presets() {
+        for &i in Policy::PRESETS {
+            assert!(Policy::preset(i).is_some(), "missing preset {}", i)
+        }
+        assert_eq!(Policy::preset("qubes-dom0"), Some(Policy::default()));
+        assert_eq!(Policy::preset("Fedora"), None);
+        let el7 = Policy::preset("legacy-el7").unwrap();
+        assert!(el7.allow_old_pkgs);
+        assert!(!Policy::preset("epel").unwrap().allow_old_pkgs);
+        let sha512 = Policy::preset("sha512-only").unwrap();
+        assert_eq!(sha512.required_hash, Some(HashAlgorithm::Sha512));
+        assert!(sha512.require_fingerprint);
+        assert_eq!(Policy::preset("epel"), Policy::preset("fedora"));
+        assert!(el7.allow_unknown_tags && !sha512.allow_unknown_tags);
+        assert_eq!((el7.min_key_bits, sha512.min_key_bits), (2048, 3072));
+    }
+
+    /// Facts about a package signed by a 4096-bit RSA key
+    fn facts(
+        key_match: KeyMatch,
+        signature_hashes: Vec<u8>,
+        payload_digest_algorithm: Option<u8>,
+    ) -> VerdictFacts {
+        let mut facts = VerdictFacts::new(key_match, signature_hashes, payload_digest_algorithm);
+        facts.header_signer_bits = Some(4096);
+        facts.header_signer_algorithm = Some(PublicKeyAlgorithm::Rsa);
+        facts
+    }
+
+    #[test]
+    fn evaluates_cached_facts() {
+        let fedora = Policy::preset("fedora").unwrap();
+        let strict = Policy::default();
+        let el7 = Policy::preset("legacy-el7").unwrap();
+        let sha512 = Policy::preset("sha512-only").unwrap();
+        let modern = facts(KeyMatch::Fingerprint, vec![8], Some(8));
+        for policy in &[fedora, strict, el7] {
+            assert_eq!(policy.evaluate(&modern), Decision::Accept);
+        }
+        let reason = |decision| match decision {
+            Decision::Reject(violation) => violation.to_string(),
+            Decision::Accept => panic!("accepted"),
+        };
+        assert_eq!(
+            reason(sha512.evaluate(&modern)),
+            "Signature uses hash algorithm SHA256, but policy requires SHA512"
+        );
+        let key_id_only = facts(KeyMatch::KeyIdOnly, vec![8, 8], Some(8));
+        assert_eq!(fedora.evaluate(&key_id_only), Decision::Accept);
+        assert_eq!(
+            reason(strict.evaluate(&key_id_only)),
+            "Header signature key was found only by key ID"
+        );
+        let old = facts(KeyMatch::KeyIdOnly, vec![2], None);
+        assert_eq!(el7.evaluate(&old), Decision::Accept);
+        assert_eq!(
+            reason(fedora.evaluate(&old)),
+            "Signature uses hash algorithm SHA1, which policy does not allow"
+        );
+        let old = facts(KeyMatch::KeyIdOnly, vec![8], None);
+        assert_eq!(
+            reason(fedora.evaluate(&old)),
+            "Package has no payload digest"
+        );
+        let mut sha512_facts = facts(KeyMatch::Fingerprint, vec![10], Some(10));
+        assert_eq!(sha512.evaluate(&sha512_facts), Decision::Accept);
+        sha512_facts.header_digest_algorithms = vec![8];
+        assert_eq!(
+            reason(sha512.evaluate(&sha512_facts)),
+            "Header digest uses hash algorithm SHA256, but policy requires SHA512"
+        );
+        assert_eq!(strict.evaluate(&sha512_facts), Decision::Accept);
+        let mut weak_key = modern.clone();
+        weak_key.header_signer_bits = Some(2048);
+        assert_eq!(fedora.evaluate(&weak_key), Decision::Accept);
+        assert_eq!(
+            reason(strict.evaluate(&weak_key)),
+            "Header signature key has 2048 bits, but policy requires at least 3072"
+        );
+        // The size of an RSA key is unknown when RPM verified the package
+        let mut unknown_size = modern.clone();
+        unknown_size.header_signer_bits = None;
+        assert_eq!(
+            reason(fedora.evaluate(&unknown_size)),
+            "Header signature key has an unknown size, but policy requires at least 2048 bits"
+        );
+        unknown_size.header_signer_algorithm = None;
+        assert!(matches!(
+            strict.evaluate(&unknown_size),
+            Decision::Reject(_)
+        ));
+        let any_size = Policy {
+            min_key_bits: 0,
+            ..fedora
+        };
+        assert_eq!(any_size.evaluate(&unknown_size), Decision::Accept);
+        // Elliptic-curve keys have no size to check
+        unknown_size.header_signer_algorithm = Some(PublicKeyAlgorithm::EdDsaLegacy);
+        assert_eq!(strict.evaluate(&unknown_size), Decision::Accept);
+        let mut unknown_tag = modern;
+        unknown_tag.unknown_signature_tags = vec![9999];
+        assert_eq!(fedora.evaluate(&unknown_tag), Decision::Accept);
+        assert_eq!(
+            reason(strict.evaluate(&unknown_tag)),
+            "Signature header has unknown tag 9999"
+        );
+    }
+}
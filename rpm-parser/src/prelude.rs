@@ -0,0 +1,19 @@
+//! The stable, high-level API
+//!
+//! Most callers only need to read, verify, and apply a policy to packages.
+//! `use rpm_parser::prelude::*;` imports everything needed for that, under
+//! stable names.  Items are only removed from this module, or changed
+//! incompatibly, in a new major version.  The rest of the crate exposes
+//! lower-level details (header layout, lead numbers, tag tables) that may
+//! change as RPM itself changes.
+//!
+//! Public enums and structs with public fields that may grow are
+//! `#[non_exhaustive]`, so adding variants or fields is not a breaking change.
+
+pub use crate::RPMPackage as Package;
+pub use crate::VerifyResult as Verdict;
+pub use crate::{error_category, failure_kind, ErrorCategory, FailureKind, KeyMatch};
+pub use crate::{verify_detached, verify_package, Keyring, Policy, PolicyViolation};
+pub use openpgp_parser::AllowWeakHashes;
+pub use rpm_crypto::transaction::RpmKeyring;
+pub use rpm_crypto::InitToken;
@@ -0,0 +1,138 @@
+//! Sanitization of untrusted strings from package headers
+//!
+//! Strings returned by the header parser are always valid UTF-8 and never
+//! contain NUL, but they can contain anything else, including terminal escape
+//! sequences and bidirectional overrides.  The functions here guarantee that
+//! neither is present, and render untrusted strings for display.
+
+pub use openpgp_parser::is_unsafe_char;
+use std::borrow::Cow;
+use std::io::Result;
+
+/// How to handle unsafe characters in untrusted strings
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StringMode {
+    /// Reject strings with unsafe characters
+    Strict,
+    /// Replace unsafe characters with U+FFFD REPLACEMENT CHARACTER
+    Lossy,
+}
+
+/// Sanitize `untrusted` according to `mode`.  Fails only in
+/// [`StringMode::Strict`] mode.
+pub fn sanitize(untrusted: &str, mode: StringMode) -> Result<Cow<'_, str>> {
+    sanitize_with(untrusted, mode, is_unsafe_char)
+}
+
+/// As [`sanitize`], but line feeds and tabs are allowed, for multi-line text
+/// such as package descriptions
+pub fn sanitize_text(untrusted: &str, mode: StringMode) -> Result<Cow<'_, str>> {
+    sanitize_with(untrusted, mode, |c| {
+        is_unsafe_char(c) && !matches!(c, '\n' | '\t')
+    })
+}
+
+fn sanitize_with(
+    untrusted: &str,
+    mode: StringMode,
+    is_unsafe: fn(char) -> bool,
+) -> Result<Cow<'_, str>> {
+    if !untrusted.chars().any(is_unsafe) {
+        return Ok(Cow::Borrowed(untrusted));
+    }
+    match mode {
+        StringMode::Strict => bad_data!("string contains control or bidirectional characters"),
+        StringMode::Lossy => Ok(Cow::Owned(
+            untrusted
+                .chars()
+                .map(|c| if is_unsafe(c) { '\u{FFFD}' } else { c })
+                .collect(),
+        )),
+    }
+}
+
+/// Render `untrusted` for display on a terminal.  Unsafe characters are
+/// replaced with Rust-style escapes (such as `\u{1b}`), so nothing is lost.
+pub fn escape_terminal(untrusted: &str) -> Cow<'_, str> {
+    if !untrusted.chars().any(is_unsafe_char) {
+        return Cow::Borrowed(untrusted);
+    }
+    let mut out = String::with_capacity(untrusted.len() + 8);
+    for c in untrusted.chars() {
+        if is_unsafe_char(c) {
+            out.extend(c.escape_default())
+        } else {
+            out.push(c)
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Render `untrusted` for inclusion in HTML text or a quoted attribute value.
+/// Unsafe characters are replaced with U+FFFD REPLACEMENT CHARACTER, since
+/// character references to them would still take effect.
+pub fn escape_html(untrusted: &str) -> Cow<'_, str> {
+    if !untrusted
+        .chars()
+        .any(|c| is_unsafe_char(c) || matches!(c, '&' | '<' | '>' | '"' | '\''))
+    {
+        return Cow::Borrowed(untrusted);
+    }
+    let mut out = String::with_capacity(untrusted.len() + 16);
+    for c in untrusted.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c if is_unsafe_char(c) => out.push('\u{FFFD}'),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn sanitizes_strings() {
+        assert!(matches!(
+            sanitize("lua-5.4", StringMode::Strict).unwrap(),
+            Cow::Borrowed("lua-5.4")
+        ));
+        assert!(sanitize("a\x1b[31mb", StringMode::Strict).is_err());
+        assert!(sanitize("a\u{202E}b", StringMode::Strict).is_err());
+        assert!(sanitize("a\u{9b}b", StringMode::Strict).is_err());
+        assert_eq!(
+            sanitize("a\x1b[31mb\n", StringMode::Lossy).unwrap(),
+            "a\u{FFFD}[31mb\u{FFFD}"
+        );
+        assert!(sanitize("a\nb", StringMode::Strict).is_err());
+        assert_eq!(
+            sanitize_text("a\n\tb", StringMode::Strict).unwrap(),
+            "a\n\tb"
+        );
+        assert!(sanitize_text("a\rb", StringMode::Strict).is_err());
+        assert_eq!(
+            sanitize_text("a\x1b\nb", StringMode::Lossy).unwrap(),
+            "a\u{FFFD}\nb"
+        );
+    }
+
+    #[test]
+    fn escapes_for_display() {
+        assert!(matches!(escape_terminal("lua"), Cow::Borrowed("lua")));
+        assert_eq!(
+            escape_terminal("a\x1b[2Jb\u{202E}"),
+            "a\\u{1b}[2Jb\\u{202e}"
+        );
+        assert!(matches!(escape_html("lua"), Cow::Borrowed("lua")));
+        assert_eq!(
+            escape_html("<a href='x'>&\"\x07</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&quot;\u{FFFD}&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Known-answer self-tests
+//!
+//! Some regulated environments require a verifier to check its cryptography
+//! before use.  [`selftest`] runs the digests and signature verification
+//! used by this crate, all provided by RPM, against embedded known-answer
+//! vectors.  A signature that must be rejected is included too, so that a
+//! backend that accepts everything does not pass.
+
+use crate::verify_detached;
+use openpgp_parser::{cert, AllowWeakHashes, HashAlgorithm};
+use rpm_crypto::{transaction::RpmKeyring, DigestCtx, InitToken};
+use std::io::{Error, Result};
+
+/// Digests of `abc`, from FIPS 180 and RFC 1321
+const DIGESTS: &[(HashAlgorithm, &str)] = &[
+    (HashAlgorithm::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+    (
+        HashAlgorithm::Sha1,
+        "a9993e364706816aba3e25717850c26c9cd0d89d",
+    ),
+    (
+        HashAlgorithm::Sha224,
+        "23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7",
+    ),
+    (
+        HashAlgorithm::Sha256,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+    ),
+    (
+        HashAlgorithm::Sha384,
+        "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded163\
+         1a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7",
+    ),
+    (
+        HashAlgorithm::Sha512,
+        "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+         2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+    ),
+];
+
+/// A certificate with an ECDSA P-256 signing subkey, and a SHA-256 signature
+/// by that subkey over [`SIGNED`]
+static CERT: &[u8] = include_bytes!("../../test-key.pgp");
+static SIGNATURE: &[u8] = include_bytes!("../../ecdsa.asc");
+const SIGNED: &[u8] = b"hello\n";
+const FINGERPRINT: &str = "E60F4C724B87E01AC383FADC930F2682774CECF4";
+/// A byte in the middle of the first MPI of [`SIGNATURE`]
+const FORGED_BYTE: usize = 60;
+
+fn failed(test: &str, why: &str) -> Error {
+    Error::other(format!("self-test {} failed: {}", test, why))
+}
+
+/// Runs every known-answer test.  Returns the names of the tests, all of
+/// which passed, or fails with the first test that did not.
+pub fn selftest(token: InitToken) -> Result<Vec<String>> {
+    let mut passed = vec![];
+    for &(alg, expected) in DIGESTS {
+        let name = format!("digest-{}", alg);
+        let mut ctx = DigestCtx::init(alg.into(), AllowWeakHashes::Yes, token)
+            .map_err(|_| failed(&name, "not supported by RPM"))?;
+        ctx.update(b"abc");
+        let hex = ctx.finalize(true);
+        if hex.split_last() != Some((&0, expected.as_bytes())) {
+            return Err(failed(&name, "wrong digest"));
+        }
+        passed.push(name)
+    }
+
+    let name = "fingerprint-v4";
+    let certificate =
+        cert::parse(CERT).map_err(|e| failed(name, &format!("bad certificate: {:?}", e)))?;
+    let fingerprint = crate::fingerprint(certificate.primary(), token);
+    let hex: String = fingerprint.iter().map(|b| format!("{:02X}", b)).collect();
+    if hex != FINGERPRINT {
+        return Err(failed(name, "wrong fingerprint"));
+    }
+    passed.push(name.to_owned());
+
+    let name = "signature-ecdsa-p256";
+    let keyring = RpmKeyring::new(token);
+    keyring
+        .add_key(CERT)
+        .map_err(|e| failed(name, &e.to_string()))?;
+    let verify =
+        |sig: &[u8]| verify_detached(&mut &SIGNED[..], sig, &keyring, AllowWeakHashes::No, token);
+    verify(SIGNATURE).map_err(|e| failed(name, &e.to_string()))?;
+    passed.push(name.to_owned());
+    // Corrupt the first MPI, leaving the hash prefix intact, so that only the
+    // public-key operation can catch it
+    let name = "signature-ecdsa-p256-forged";
+    let mut forged = SIGNATURE.to_vec();
+    forged[FORGED_BYTE] ^= 1;
+    if verify(&forged).is_ok() {
+        return Err(failed(name, "forged signature accepted"));
+    }
+    passed.push(name.to_owned());
+    Ok(passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_selftest() {
+        let passed = selftest(rpm_crypto::init()).unwrap();
+        assert_eq!(passed.len(), DIGESTS.len() + 3);
+        assert_eq!(passed[0], "digest-MD5");
+    }
+}
@@ -0,0 +1,324 @@
+//! Sources and patches of source packages
+//!
+//! A source package lists its sources and patches in the `SOURCE` and
+//! `PATCH` tags as the spec file wrote them, which is often a URL.  The
+//! payload holds each one under its file name alone.  [`source_entries`]
+//! maps `Source0`, `Patch1`, and so on to their entries in the file list, and
+//! [`extract_entry`] reads one entry out of the payload, which the caller
+//! must already have decompressed.
+
+use crate::{file_list, RPMHeader, TagValue};
+use std::io::{copy, sink, Read, Result};
+
+const RPMTAG_SOURCE: u32 = 1018;
+const RPMTAG_PATCH: u32 = 1019;
+const RPMTAG_NOSOURCE: u32 = 1051;
+const RPMTAG_NOPATCH: u32 = 1052;
+const RPMTAG_SOURCENUM: u32 = 1080;
+const RPMTAG_PATCHNUM: u32 = 1081;
+
+/// Whether a [`SourceEntry`] is a source or a patch
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SourceKind {
+    /// A `SourceN` line
+    Source,
+    /// A `PatchN` line
+    Patch,
+}
+
+impl SourceKind {
+    fn name(self) -> &'static str {
+        match self {
+            SourceKind::Source => "Source",
+            SourceKind::Patch => "Patch",
+        }
+    }
+}
+
+/// A source or patch of a source package
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SourceEntry {
+    /// Source or patch
+    pub kind: SourceKind,
+    /// The number, as in `Source0`
+    pub number: u32,
+    /// The location given in the spec file, such as a URL
+    pub location: String,
+    /// The path of the entry in the payload, or [`None`] if the package
+    /// does not include it (`NoSource` or `NoPatch`)
+    pub path: Option<String>,
+}
+
+impl SourceEntry {
+    /// The name of the entry, such as `Source0` or `Patch1`
+    pub fn name(&self) -> String {
+        format!("{}{}", self.kind.name(), self.number)
+    }
+}
+
+/// The last component of `path`, so that `https://example.com/foo.tar.gz`
+/// and `foo.tar.gz` both give `foo.tar.gz`
+fn file_name(path: &str) -> &str {
+    path.rsplit('/')
+        .next()
+        .expect("rsplit yields at least one item")
+}
+
+fn int32_array(header: &RPMHeader, tag: u32) -> Option<Vec<u32>> {
+    match header.get(tag) {
+        None => Some(vec![]),
+        Some(TagValue::Int32(numbers)) => Some(numbers),
+        Some(_) => None,
+    }
+}
+
+/// List the sources and patches of `header`, with the path of each in the
+/// payload.  Returns [`None`] if the tags are inconsistent, or if the file
+/// list cannot be reconstructed (see [`file_list`]).  Packages with neither
+/// sources nor patches, including binary packages, return an empty list.
+pub fn source_entries(header: &RPMHeader) -> Option<Vec<SourceEntry>> {
+    let files = file_list(header)?;
+    let mut entries = vec![];
+    for &(kind, tag, num_tag, missing_tag) in &[
+        (
+            SourceKind::Source,
+            RPMTAG_SOURCE,
+            RPMTAG_SOURCENUM,
+            RPMTAG_NOSOURCE,
+        ),
+        (
+            SourceKind::Patch,
+            RPMTAG_PATCH,
+            RPMTAG_PATCHNUM,
+            RPMTAG_NOPATCH,
+        ),
+    ] {
+        let locations = match header.get(tag) {
+            None => vec![],
+            Some(TagValue::StringArray(locations)) => locations,
+            Some(_) => return None,
+        };
+        let numbers = int32_array(header, num_tag)?;
+        let missing = int32_array(header, missing_tag)?;
+        // Old packages have no numbers; their entries are numbered in order
+        if !numbers.is_empty() && numbers.len() != locations.len() {
+            return None;
+        }
+        for (i, location) in locations.into_iter().enumerate() {
+            let number = numbers.get(i).copied().unwrap_or(i as u32);
+            let path = if missing.contains(&number) {
+                None
+            } else {
+                let (path, _, _) = files
+                    .iter()
+                    .find(|(path, _, _)| file_name(path) == file_name(location))?;
+                Some(path.clone())
+            };
+            entries.push(SourceEntry {
+                kind,
+                number,
+                location: location.to_owned(),
+                path,
+            })
+        }
+    }
+    Some(entries)
+}
+
+/// Find the source or patch called `name`, such as `Source0` or `patch1`
+/// (case does not matter).  `Source` and `Patch` alone mean `Source0` and
+/// `Patch0`, as in spec files.  Returns [`None`] if there is no such entry or
+/// [`source_entries`] fails.
+pub fn source_entry(header: &RPMHeader, name: &str) -> Option<SourceEntry> {
+    let name = name.to_ascii_lowercase();
+    let (kind, number) = match (name.strip_prefix("source"), name.strip_prefix("patch")) {
+        (Some(number), _) => (SourceKind::Source, number),
+        (_, Some(number)) => (SourceKind::Patch, number),
+        _ => return None,
+    };
+    let number = match number {
+        "" => 0,
+        n if n.bytes().all(|c| c.is_ascii_digit()) => n.parse().ok()?,
+        _ => return None,
+    };
+    source_entries(header)?
+        .into_iter()
+        .find(|entry| entry.kind == kind && entry.number == number)
+}
+
+/// The magic number of a `newc` cpio header
+const CPIO_MAGIC: &[u8; 6] = b"070701";
+const CPIO_TRAILER: &[u8] = b"TRAILER!!!";
+
+fn hex_field(field: &[u8]) -> Result<u32> {
+    match std::str::from_utf8(field)
+        .ok()
+        .and_then(|field| u32::from_str_radix(field, 16).ok())
+    {
+        Some(value) => Ok(value),
+        None => bad_data!("bad cpio header field {:?}", field),
+    }
+}
+
+fn skip(r: &mut dyn Read, len: u64) -> Result<()> {
+    fail_if!(
+        copy(&mut r.take(len), &mut sink())? != len,
+        "truncated cpio archive"
+    );
+    Ok(())
+}
+
+/// The padding after `len` bytes, to a multiple of 4
+fn padding(len: u64) -> u64 {
+    len.wrapping_neg() & 3
+}
+
+/// Read the contents of the entry at `path` from `payload`, a decompressed
+/// `newc` cpio archive.  A leading `./` or `/` in `path` or in the archive
+/// is ignored.  Returns [`None`] if the archive has no such entry.  The
+/// entry is trusted only if the payload digest has been verified.
+pub fn extract_entry(payload: &mut dyn Read, path: &str) -> Result<Option<Vec<u8>>> {
+    fn strip(path: &[u8]) -> &[u8] {
+        let path = path.strip_prefix(b"./").unwrap_or(path);
+        path.strip_prefix(b"/").unwrap_or(path)
+    }
+    let wanted = strip(path.as_bytes());
+    loop {
+        let mut header = [0; 110];
+        payload.read_exact(&mut header)?;
+        fail_if!(&header[..6] != CPIO_MAGIC, "not a newc cpio archive");
+        let field = |i: usize| hex_field(&header[6 + 8 * i..14 + 8 * i]);
+        let (file_size, name_size) = (u64::from(field(6)?), field(11)?);
+        fail_if!(name_size == 0 || name_size > 4096, "bad cpio name size");
+        let mut name = vec![0; name_size as usize];
+        payload.read_exact(&mut name)?;
+        fail_if!(name.pop() != Some(0), "cpio name not NUL-terminated");
+        skip(payload, padding(110 + u64::from(name_size)))?;
+        if name == CPIO_TRAILER {
+            return Ok(None);
+        }
+        if strip(&name) == wanted {
+            let mut contents = vec![];
+            payload.take(file_size).read_to_end(&mut contents)?;
+            fail_if!(contents.len() as u64 != file_size, "truncated cpio archive");
+            return Ok(Some(contents));
+        }
+        skip(payload, file_size + padding(file_size))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use rpm_writer::{HeaderBuilder, HeaderEntry, HeaderKind};
+    use std::ffi::{CStr, CString};
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    fn cpio_entry(name: &str, contents: &[u8]) -> Vec<u8> {
+        let name_size = name.len() + 1;
+        let mut out = format!("070701{:08X}{:08X}", 1, 0o100644).into_bytes();
+        for _ in 0..4 {
+            out.extend_from_slice(b"00000000")
+        }
+        out.extend(format!("{:08X}", contents.len()).into_bytes());
+        for _ in 0..4 {
+            out.extend_from_slice(b"00000000")
+        }
+        out.extend(format!("{:08X}{:08X}", name_size, 0).into_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.resize(out.len() + padding(110 + name_size as u64) as usize, 0);
+        out.extend_from_slice(contents);
+        out.resize(out.len() + padding(contents.len() as u64) as usize, 0);
+        out
+    }
+
+    #[test]
+    fn maps_sources_to_files() {
+        let sources = [c("https://example.com/foo-1.0.tar.gz"), c("foo.conf")];
+        let sources: Vec<&CStr> = sources.iter().map(|i| &**i).collect();
+        let patches = [c("fix-build.patch")];
+        let patches: Vec<&CStr> = patches.iter().map(|i| &**i).collect();
+        let basenames = [c("foo-1.0.tar.gz"), c("fix-build.patch"), c("foo.spec")];
+        let basenames: Vec<&CStr> = basenames.iter().map(|i| &**i).collect();
+        let (dirnames, linktos) = ([c("")], [c(""), c(""), c("")]);
+        let dirnames: Vec<&CStr> = dirnames.iter().map(|i| &**i).collect();
+        let linktos: Vec<&CStr> = linktos.iter().map(|i| &**i).collect();
+        let mut builder = HeaderBuilder::new(HeaderKind::Main);
+        builder.push(RPMTAG_SOURCE, HeaderEntry::StringArray(&sources));
+        builder.push(RPMTAG_PATCH, HeaderEntry::StringArray(&patches));
+        builder.push(1030, HeaderEntry::U16(&[0o100644; 3]));
+        builder.push(1036, HeaderEntry::StringArray(&linktos));
+        builder.push(RPMTAG_NOSOURCE, HeaderEntry::U32(&[1]));
+        builder.push(RPMTAG_SOURCENUM, HeaderEntry::U32(&[0, 1]));
+        builder.push(RPMTAG_PATCHNUM, HeaderEntry::U32(&[3]));
+        builder.push(1116, HeaderEntry::U32(&[0; 3]));
+        builder.push(1117, HeaderEntry::StringArray(&basenames));
+        builder.push(1118, HeaderEntry::StringArray(&dirnames));
+        let mut bytes = vec![];
+        builder.emit(&mut bytes).unwrap();
+        let header =
+            crate::header::load_header(&mut &bytes[..], 63, &mut |_, _, _| Ok(())).unwrap();
+        let entries = source_entries(&header).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].name(), "Source0");
+        assert_eq!(entries[0].path.as_deref(), Some("foo-1.0.tar.gz"));
+        assert_eq!(entries[1].path, None);
+        let patch = source_entry(&header, "patch3").unwrap();
+        assert_eq!(patch.path.as_deref(), Some("fix-build.patch"));
+        assert_eq!(source_entry(&header, "Source"), Some(entries[0].clone()));
+        assert_eq!(source_entry(&header, "Patch0"), None);
+        assert_eq!(source_entry(&header, "Source+1"), None);
+    }
+
+    #[test]
+    fn extracts_cpio_entries() {
+        let mut archive = cpio_entry("foo.spec", b"Name: foo\n");
+        archive.extend(cpio_entry("./foo-1.0.tar.gz", b"tarball"));
+        archive.extend(cpio_entry("TRAILER!!!", b""));
+        let extract = |path| extract_entry(&mut &archive[..], path);
+        assert_eq!(extract("foo-1.0.tar.gz").unwrap().unwrap(), b"tarball");
+        assert_eq!(extract("foo.spec").unwrap().unwrap(), b"Name: foo\n");
+        assert_eq!(extract("missing").unwrap(), None);
+        let e = extract_entry(&mut &archive[..archive.len() - 120], "missing").unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+        archive[5] = b'7';
+        let e = extract_entry(&mut &archive[..], "foo.spec").unwrap_err();
+        assert_eq!(e.to_string(), "not a newc cpio archive");
+    }
+
+    proptest! {
+        #[test]
+        fn cpio_roundtrip(
+            // Every padding length, for both names and contents
+            files in vec(
+                (
+                    prop::sample::select(&["", "./", "/"][..]),
+                    "[a-z]{0,19}",
+                    vec(any::<u8>(), 0..600),
+                ),
+                0..8,
+            ),
+        ) {
+            let mut archive = vec![];
+            let mut entries = vec![];
+            for (i, (prefix, suffix, contents)) in files.into_iter().enumerate() {
+                let name = format!("{}{}-{}", prefix, i, suffix);
+                archive.extend(cpio_entry(&name, &contents));
+                entries.push((format!("{}-{}", i, suffix), contents))
+            }
+            archive.extend(cpio_entry("TRAILER!!!", b""));
+            for (name, contents) in &entries {
+                let extracted = extract_entry(&mut &archive[..], name).unwrap();
+                prop_assert_eq!(extracted.as_ref(), Some(contents), "entry {}", name);
+            }
+            prop_assert_eq!(extract_entry(&mut &archive[..], "missing").unwrap(), None);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Checks specific to Qubes template packages
+//!
+//! Qubes template RPMs are never installed with RPM; `qvm-template` extracts
+//! them instead.  They are therefore held to a stricter standard than other
+//! packages: they must be signed by a dedicated key, have a template name, and
+//! carry no scriptlets beyond an explicitly allowed set.
+
+use crate::{load_signature, read_lead, Keyring, PolicyViolation, RPMHeader, VerifyResult};
+use openpgp_parser::AllowWeakHashes;
+use rpm_crypto::InitToken;
+use std::io::{Read, Result};
+
+/// The prefix of all Qubes template package names
+pub const TEMPLATE_NAME_PREFIX: &str = "qubes-template-";
+
+/// Scriptlet tags, the tags naming their interpreters, and their names.  A
+/// scriptlet can consist of only an interpreter (`%post -p /sbin/ldconfig`),
+/// so both tags must be checked.
+pub const SCRIPTLET_TAGS: &[(u32, u32, &str)] = &[
+    (1023, 1085, "%pre"),
+    (1024, 1086, "%post"),
+    (1025, 1087, "%preun"),
+    (1026, 1088, "%postun"),
+    (1065, 1092, "%trigger"),
+    (1079, 1091, "%verifyscript"),
+    (1151, 1153, "%pretrans"),
+    (1152, 1154, "%posttrans"),
+    (5066, 5067, "%filetrigger"),
+    (5076, 5077, "%transfiletrigger"),
+    (5104, 5106, "%preuntrans"),
+    (5105, 5107, "%postuntrans"),
+];
+
+/// Policy for Qubes template packages
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct TemplatePolicy {
+    /// Fingerprint of the dedicated template-signing key
+    pub signing_key: [u8; 20],
+    /// Scriptlets (identified by the first tag in [`SCRIPTLET_TAGS`]) that
+    /// may be present
+    pub allowed_scriptlets: Vec<u32>,
+}
+
+impl TemplatePolicy {
+    /// Creates a policy requiring packages to be signed by the key with
+    /// fingerprint `signing_key`.  No scriptlets are allowed.
+    pub fn new(signing_key: [u8; 20]) -> Self {
+        Self {
+            signing_key,
+            allowed_scriptlets: vec![],
+        }
+    }
+}
+
+/// Check that `name` is a valid template name: [`TEMPLATE_NAME_PREFIX`]
+/// followed by at least one of `[A-Za-z0-9._+-]`.
+pub fn check_template_name(name: &str) -> Result<()> {
+    let suffix = match name.strip_prefix(TEMPLATE_NAME_PREFIX) {
+        Some(suffix) if !suffix.is_empty() => suffix,
+        _ => return Err(PolicyViolation::error("not a template package name")),
+    };
+    for i in suffix.bytes() {
+        match i {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'_' | b'+' | b'-' => {}
+            _ => return Err(PolicyViolation::error("invalid character in template name")),
+        }
+    }
+    Ok(())
+}
+
+/// Check that the main header `header` has no scriptlets other than those
+/// allowed by `policy`.
+pub fn check_scriptlets(header: &RPMHeader, policy: &TemplatePolicy) -> Result<()> {
+    for &(tag, prog_tag, name) in SCRIPTLET_TAGS {
+        if policy.allowed_scriptlets.contains(&tag) {
+            continue;
+        }
+        if header.get(tag).is_some() || header.get(prog_tag).is_some() {
+            return Err(PolicyViolation::error(format!(
+                "template package has a forbidden {} scriptlet",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Verify a Qubes template package read from `src`.  In addition to the
+/// checks done by [`Keyring::verify_package`], this checks that the package:
+///
+/// - is signed by the key in `policy`: the key in `keyring` that verifies
+///   the header signature must have that fingerprint.  What the signature
+///   says about its issuer is not trusted;
+/// - has a valid template name (see [`check_template_name`]);
+/// - is `noarch`;
+/// - has no scriptlets except those allowed by `policy`.
+///
+/// Everything except the payload digest is checked before the payload is
+/// read.  Weak hashes and packages without payload digests are never allowed.
+pub fn verify_template(
+    src: &mut dyn Read,
+    policy: &TemplatePolicy,
+    keyring: &Keyring<'_>,
+    token: InitToken,
+) -> Result<VerifyResult> {
+    read_lead(src)?;
+    let mut sig_header = load_signature(src, AllowWeakHashes::No, token)?;
+    let mut cb = |result: &VerifyResult, _: Option<&mut dyn std::io::Write>| -> Result<()> {
+        if result.header_signer.as_deref() != Some(&policy.signing_key[..]) {
+            return Err(PolicyViolation::error(
+                "template not signed by the template signing key",
+            ));
+        }
+        let main_header = &result.main_header;
+        check_template_name(&main_header.name)?;
+        if main_header.arch != "noarch" {
+            return Err(PolicyViolation::error("template package is not noarch"));
+        }
+        check_scriptlets(&main_header.header, policy)
+    };
+    keyring.verify_package(
+        src,
+        &mut sig_header,
+        false,
+        false,
+        token,
+        Some(&mut cb),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error_category, failure_kind, ErrorCategory};
+    use rpm_writer::testing::{placeholder_signature, TestPackage};
+    #[test]
+    fn checks_template_names() {
+        check_template_name("qubes-template-fedora-34").unwrap();
+        check_template_name("qubes-template-debian-11-minimal").unwrap();
+        check_template_name("qubes-template-").unwrap_err();
+        check_template_name("fedora-34").unwrap_err();
+        check_template_name("qubes-template-fedora/../34").unwrap_err();
+        check_template_name("qubes-template-fedora\x1b").unwrap_err();
+    }
+
+    static TEMPLATE_KEY: &[u8] = include_bytes!("../../template-key.pgp");
+    /// A signature by `TEMPLATE_KEY` over the main header of `template()`
+    static TEMPLATE_SIG: &[u8] = include_bytes!("../../template-header.sig");
+    const TEMPLATE_FINGERPRINT: [u8; 20] = [
+        0xDF, 0x64, 0x10, 0xC4, 0x45, 0x1B, 0x40, 0x12, 0x36, 0x8A, 0x74, 0x54, 0x90, 0xE2, 0x32,
+        0x94, 0xD0, 0x1F, 0xB6, 0x49,
+    ];
+
+    /// A template package with a `%post` scriptlet
+    fn template() -> TestPackage {
+        let mut package = TestPackage::new("qubes-template-test");
+        package.scriptlets.push((1024, "echo hi".to_owned()));
+        package.signature = TEMPLATE_SIG.to_vec();
+        package
+    }
+
+    #[test]
+    fn verifies_templates() {
+        let token = rpm_crypto::init();
+        let package = template().build(token).unwrap();
+        let mut keyring = Keyring::new();
+        keyring.add(TEMPLATE_KEY, token).unwrap();
+        let verify =
+            |policy: &TemplatePolicy| verify_template(&mut &package[..], policy, &keyring, token);
+        let mut policy = TemplatePolicy::new(TEMPLATE_FINGERPRINT);
+        let e = verify(&policy).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "template package has a forbidden %post scriptlet"
+        );
+        assert_eq!(error_category(&e), Some(ErrorCategory::Policy));
+        policy.allowed_scriptlets.push(1024);
+        let result = verify(&policy).unwrap();
+        assert_eq!(
+            result.header_signer.as_deref(),
+            Some(&TEMPLATE_FINGERPRINT[..])
+        );
+        // The signature names the template key as its issuer, but another
+        // key is required
+        let mut other = TEMPLATE_FINGERPRINT;
+        other[0] ^= 1;
+        policy.signing_key = other;
+        let e = verify(&policy).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "template not signed by the template signing key"
+        );
+        // Signatures that do not verify are rejected before the policy is
+        // checked
+        let mut unsigned = template();
+        unsigned.signature = placeholder_signature();
+        let unsigned = unsigned.build(token).unwrap();
+        policy.signing_key = TEMPLATE_FINGERPRINT;
+        let e = verify_template(&mut &unsigned[..], &policy, &keyring, token)
+            .err()
+            .unwrap();
+        assert!(failure_kind(&e).is_some());
+    }
+
+    #[test]
+    fn checks_scriptlets() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let header =
+            crate::header::load_header(&mut &s[4504..], 63, &mut |_, _, _| Ok(())).unwrap();
+        let mut policy = TemplatePolicy::new([0; 20]);
+        check_scriptlets(&header, &policy).unwrap();
+        policy.allowed_scriptlets.push(1024);
+        check_scriptlets(&header, &policy).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Transaction-level decisions
+//!
+//! Package managers commit a transaction as a whole, so a verdict on each
+//! package is not enough: someone has to decide what happens to the
+//! transaction when some of its packages fail.  A [`TransactionVerdict`]
+//! collects the verdicts on the packages of a transaction, judges each
+//! against a [`Policy`], and produces a [`TransactionDecision`].
+
+use crate::{Decision, Policy, VerdictFacts, VerifyResult};
+use std::io::Result;
+
+/// How a transaction with some rejected packages is handled
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TransactionMode {
+    /// Reject the whole transaction if any package is rejected.  This is how
+    /// RPM and DNF behave.
+    Atomic,
+    /// Install the packages that were accepted, and reject the rest
+    Partial,
+}
+
+/// A package rejected from a transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Rejection {
+    /// The name given to [`TransactionVerdict::add`]
+    pub package: String,
+    /// Why the package was rejected.  This comes from the package, so it must
+    /// be escaped before being displayed.
+    pub reason: String,
+}
+
+/// The outcome of [`TransactionVerdict::decide`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransactionDecision {
+    /// Every package was accepted
+    InstallAll,
+    /// The transaction must not be committed.  Every rejected package is
+    /// listed, in the order it was added.
+    RejectAll(Vec<Rejection>),
+    /// Only some packages may be installed.  Only returned in
+    /// [`TransactionMode::Partial`].
+    Partial {
+        /// The packages to install, in the order they were added
+        install: Vec<String>,
+        /// The packages rejected, in the order they were added
+        rejected: Vec<Rejection>,
+    },
+}
+
+/// Collects the verdicts on the packages of a transaction
+#[derive(Clone, Debug)]
+pub struct TransactionVerdict {
+    policy: Policy,
+    mode: TransactionMode,
+    accepted: Vec<String>,
+    rejected: Vec<Rejection>,
+}
+
+impl TransactionVerdict {
+    /// Starts an empty transaction.  Each package is judged against `policy`.
+    pub fn new(policy: Policy, mode: TransactionMode) -> Self {
+        Self {
+            policy,
+            mode,
+            accepted: vec![],
+            rejected: vec![],
+        }
+    }
+
+    /// Adds the package `package`, given the result of verifying it, such as
+    /// the one passed to the callback of [`crate::verify_bundle`].  A package
+    /// that failed verification is rejected with the error as the reason.
+    pub fn add(&mut self, package: &str, verdict: &Result<VerifyResult>) {
+        let facts = verdict
+            .as_ref()
+            .map_err(|e| e.to_string())
+            .and_then(|result| VerdictFacts::from_result(result).map_err(|e| e.to_string()));
+        match facts {
+            Ok(facts) => self.add_facts(package, &facts),
+            Err(reason) => self.reject(package, reason),
+        }
+    }
+
+    /// Adds the package `package`, which verified with the cached `facts`
+    pub fn add_facts(&mut self, package: &str, facts: &VerdictFacts) {
+        match self.policy.evaluate(facts) {
+            Decision::Accept => self.accepted.push(package.to_owned()),
+            Decision::Reject(violation) => self.reject(package, violation.to_string()),
+        }
+    }
+
+    fn reject(&mut self, package: &str, reason: String) {
+        self.rejected.push(Rejection {
+            package: package.to_owned(),
+            reason,
+        })
+    }
+
+    /// Decides what to do with the transaction.  An empty transaction is
+    /// installed, as there is nothing wrong with it.
+    pub fn decide(&self) -> TransactionDecision {
+        if self.rejected.is_empty() {
+            TransactionDecision::InstallAll
+        } else if self.mode == TransactionMode::Atomic || self.accepted.is_empty() {
+            TransactionDecision::RejectAll(self.rejected.clone())
+        } else {
+            TransactionDecision::Partial {
+                install: self.accepted.clone(),
+                rejected: self.rejected.clone(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyMatch;
+    use openpgp_parser::PublicKeyAlgorithm;
+    use std::io::{Error, ErrorKind};
+
+    fn rejection(package: &str, reason: &str) -> Rejection {
+        Rejection {
+            package: package.to_owned(),
+            reason: reason.to_owned(),
+        }
+    }
+
+    #[test]
+    fn aggregates_verdicts() {
+        let mut good = VerdictFacts::new(KeyMatch::Fingerprint, vec![8], Some(8));
+        good.header_signer_algorithm = Some(PublicKeyAlgorithm::Ed25519);
+        let mut old = good.clone();
+        old.payload_digest_algorithm = None;
+        let mut atomic = TransactionVerdict::new(Policy::default(), TransactionMode::Atomic);
+        assert_eq!(atomic.decide(), TransactionDecision::InstallAll);
+        atomic.add_facts("good", &good);
+        assert_eq!(atomic.decide(), TransactionDecision::InstallAll);
+        let mut partial = atomic.clone();
+        partial.mode = TransactionMode::Partial;
+        for verdict in &mut [&mut atomic, &mut partial] {
+            verdict.add_facts("old", &old);
+            verdict.add(
+                "forged",
+                &Err(Error::new(ErrorKind::InvalidData, "bad digest")),
+            );
+        }
+        let rejected = vec![
+            rejection("old", "Package has no payload digest"),
+            rejection("forged", "bad digest"),
+        ];
+        assert_eq!(
+            atomic.decide(),
+            TransactionDecision::RejectAll(rejected.clone())
+        );
+        assert_eq!(
+            partial.decide(),
+            TransactionDecision::Partial {
+                install: vec!["good".to_owned()],
+                rejected: rejected.clone(),
+            }
+        );
+        let mut none_good = TransactionVerdict::new(Policy::default(), TransactionMode::Partial);
+        none_good.add_facts("old", &old);
+        assert_eq!(
+            none_good.decide(),
+            TransactionDecision::RejectAll(rejected[..1].to_vec())
+        );
+    }
+}
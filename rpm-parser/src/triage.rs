@@ -0,0 +1,400 @@
+//! Quick classification of untrusted packages
+//!
+//! Triage only parses the lead and the two headers.  No cryptographic
+//! operations are performed and the payload is never read, so large corpora of
+//! untrusted packages can be sorted before the (much more expensive)
+//! verification step.  Nothing reported here is trustworthy.
+
+use crate::ffi::TagType;
+use crate::header::load_header;
+use crate::{read_lead, TagData};
+use openpgp_parser::{signature, AllowWeakHashes};
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Result};
+
+/// A summary of an OpenPGP signature found in the signature header
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SignatureSummary {
+    /// The signature header tag containing the signature
+    pub tag: u32,
+    /// Hash algorithm.  Signatures using an unknown hash algorithm cannot be
+    /// parsed, so they are reported in [`Triage::problems`] instead.
+    pub hash_alg: openpgp_parser::HashAlgorithm,
+    /// Public-key algorithm
+    pub pkey_alg: openpgp_parser::PublicKeyAlgorithm,
+    /// Key ID
+    pub key_id: [u8; 8],
+    /// Fingerprint, if any
+    pub fingerprint: Option<signature::Fingerprint>,
+}
+
+/// The result of triaging a package
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Triage {
+    /// Major and minor format version from the lead
+    pub format_version: Option<(u8, u8)>,
+    /// Is this a source package, according to the lead?
+    pub source: Option<bool>,
+    /// The payload compressor, if any
+    pub compression: Option<String>,
+    /// Signatures present in the signature header
+    pub signatures: Vec<SignatureSummary>,
+    /// Size of the signature header, including trailing padding
+    pub signature_header_size: Option<u64>,
+    /// Size of the main header
+    pub main_header_size: Option<u64>,
+    /// Size of the main header and payload, as claimed by the signature header
+    pub claimed_size: Option<u64>,
+    /// Size of the uncompressed payload, as claimed by the signature header
+    pub claimed_payload_size: Option<u64>,
+    /// Size of the zeroed space reserved in the signature header for
+    /// signatures added later
+    pub reserved_space: Option<u64>,
+    /// Obvious structural problems.  A package with problems will not pass
+    /// verification.
+    pub problems: Vec<String>,
+}
+
+const RPMSIGTAG_DSAHEADER: u32 = 256 + 11;
+const RPMSIGTAG_RSAHEADER: u32 = 256 + 12;
+const RPMSIGTAG_LONGSIZE: u32 = 256 + 14;
+const RPMSIGTAG_LONGARCHIVESIZE: u32 = 256 + 15;
+const RPMSIGTAG_SIZE: u32 = 1000;
+const RPMSIGTAG_PGP: u32 = 1002;
+const RPMSIGTAG_GPG: u32 = 1005;
+const RPMSIGTAG_PAYLOADSIZE: u32 = 1007;
+const RPMSIGTAG_RESERVEDSPACE: u32 = 1008;
+const RPMTAG_PAYLOADCOMPRESSOR: u32 = 1125;
+
+fn int_value(ty: TagType, body: &[u8]) -> Option<u64> {
+    match ty {
+        TagType::Int32 => Some(u32::from_be_bytes(body.try_into().ok()?).into()),
+        TagType::Int64 => Some(u64::from_be_bytes(body.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// The 32-bit and 64-bit size tags should not both be present, but if they are,
+/// they must agree.  A tag that is not an integer is a problem, and does not
+/// replace a size already found.
+fn set_size(size: &mut Option<u64>, value: Option<u64>, what: &str, problems: &mut Vec<String>) {
+    match (*size, value) {
+        (Some(old), Some(new)) if old != new => {
+            problems.push(format!("conflicting {} sizes {} and {}", what, old, new))
+        }
+        (_, Some(new)) => *size = Some(new),
+        (_, None) => problems.push(format!("bad {} size tag", what)),
+    }
+}
+
+fn header_size(header: &crate::RPMHeader) -> u64 {
+    (16 + 16 * header.index.len() + header.data.len()) as u64
+}
+
+impl Triage {
+    /// Compare the sizes recorded by triage with `file_len`, the length of
+    /// the whole package file, adding any mismatch to [`Self::problems`].
+    /// This catches truncated packages and packages with trailing data before
+    /// any cryptographic operation is attempted.
+    pub fn check_file_size(&mut self, file_len: u64) {
+        let (signature_header_size, claimed_size) =
+            match (self.signature_header_size, self.claimed_size) {
+                (Some(s), Some(c)) => (s, c),
+                (_, None) => {
+                    return self
+                        .problems
+                        .push("signature header has no size".to_owned())
+                }
+                (None, _) => return,
+            };
+        // The claimed size is untrusted, and may be as large as u64::MAX
+        let expected = 96u64
+            .checked_add(signature_header_size)
+            .and_then(|size| size.checked_add(claimed_size));
+        match expected {
+            None => self.problems.push("claimed size overflows".to_owned()),
+            Some(expected) if expected > file_len => self.problems.push(format!(
+                "package truncated: {} bytes missing",
+                expected - file_len
+            )),
+            Some(expected) if expected < file_len => self.problems.push(format!(
+                "{} bytes of data after the payload",
+                file_len - expected
+            )),
+            Some(_) => {}
+        }
+        if let Some(main_header_size) = self.main_header_size {
+            if main_header_size > claimed_size {
+                self.problems
+                    .push("main header larger than the claimed size".to_owned())
+            }
+        }
+    }
+}
+
+/// Triage a package.  Structural problems are reported in
+/// [`Triage::problems`]; only I/O errors other than premature EOF are returned
+/// as [`Err`].
+pub fn triage(r: &mut dyn Read) -> Result<Triage> {
+    let mut triage = Triage::default();
+    match triage_inner(r, &mut triage) {
+        Ok(()) => Ok(triage),
+        Err(e) if e.kind() == ErrorKind::InvalidData || e.kind() == ErrorKind::UnexpectedEof => {
+            triage.problems.push(e.to_string());
+            Ok(triage)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// A reader that keeps a copy of everything read through it
+struct Recorder<'a> {
+    inner: &'a mut dyn Read,
+    data: Vec<u8>,
+}
+
+impl Read for Recorder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.data.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Extract the smallest prefix of the package in `r` that is needed to
+/// reproduce a verification failure, for attaching to bug reports: the lead
+/// and both headers, but not the payload.  If the package is malformed, the
+/// prefix stops just after the offending data, so it fails in the same way.
+/// Failures of the payload digest cannot be reproduced without the payload,
+/// but the returned headers contain the expected digest.
+pub fn evidence_bundle(r: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut recorder = Recorder {
+        inner: r,
+        data: vec![],
+    };
+    match triage_inner(&mut recorder, &mut Triage::default()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::InvalidData || e.kind() == ErrorKind::UnexpectedEof => {}
+        Err(e) => return Err(e),
+    }
+    Ok(recorder.data)
+}
+
+fn triage_inner(r: &mut dyn Read, triage: &mut Triage) -> Result<()> {
+    let lead = read_lead(r)?;
+    triage.format_version = Some(lead.version());
+    triage.source = Some(lead.ty() == 1);
+    let mut signatures = vec![];
+    let mut problems = vec![];
+    let (mut claimed_size, mut claimed_payload_size, mut reserved_space) = (None, None, None);
+    let mut cb = |ty: TagType, tag_data: &TagData, body: &[u8]| -> Result<()> {
+        let tag = tag_data.tag();
+        match tag {
+            RPMSIGTAG_LONGSIZE | RPMSIGTAG_SIZE => set_size(
+                &mut claimed_size,
+                int_value(ty, body),
+                "header+payload",
+                &mut problems,
+            ),
+            RPMSIGTAG_LONGARCHIVESIZE | RPMSIGTAG_PAYLOADSIZE => set_size(
+                &mut claimed_payload_size,
+                int_value(ty, body),
+                "payload",
+                &mut problems,
+            ),
+            RPMSIGTAG_RESERVEDSPACE => {
+                if ty != TagType::Bin || body.iter().any(|&i| i != 0) {
+                    problems.push("reserved space is not zeroed binary data".to_owned())
+                }
+                reserved_space = Some(body.len() as u64)
+            }
+            RPMSIGTAG_DSAHEADER | RPMSIGTAG_RSAHEADER | RPMSIGTAG_PGP | RPMSIGTAG_GPG
+                if ty == TagType::Bin =>
+            {
+                // Accept everything the parser understands; policy is not
+                // triage’s job.
+                match signature::parse(body, 0, AllowWeakHashes::Yes) {
+                    Ok(sig) => signatures.push(SignatureSummary {
+                        tag,
+                        hash_alg: sig.hash_alg,
+                        pkey_alg: sig.pkey_alg,
+                        key_id: sig.key_id,
+                        fingerprint: sig.fingerprint,
+                    }),
+                    Err(e) => {
+                        problems.push(format!("bad OpenPGP signature in tag {}: {:?}", tag, e))
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    };
+    let header = load_header(r, 62, &mut cb);
+    triage.signatures = signatures;
+    triage.problems = problems;
+    triage.claimed_size = claimed_size;
+    triage.claimed_payload_size = claimed_payload_size;
+    triage.reserved_space = reserved_space;
+    let header = header?;
+    let remainder = header.data.len() & 7;
+    let padding = if remainder == 0 { 0 } else { 8 - remainder };
+    let mut s = [0u8; 7];
+    r.read_exact(&mut s[..padding])?;
+    if s[..padding].iter().any(|&i| i != 0) {
+        triage
+            .problems
+            .push("nonzero padding after signature header".to_owned())
+    }
+    triage.signature_header_size = Some(header_size(&header) + padding as u64);
+    let mut compression = None;
+    let mut cb = |ty: TagType, tag_data: &TagData, body: &[u8]| -> Result<()> {
+        if tag_data.tag() == RPMTAG_PAYLOADCOMPRESSOR && ty == TagType::String {
+            compression = Some(String::from_utf8_lossy(&body[..body.len() - 1]).into_owned())
+        }
+        Ok(())
+    };
+    let header = load_header(r, 63, &mut cb);
+    triage.compression = compression;
+    triage.main_header_size = Some(header_size(&header?));
+    if triage.signatures.is_empty() {
+        triage.problems.push("package is not signed".to_owned())
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn triages_lua_rpm() {
+        let mut s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let len = s.len() as u64;
+        let triage = triage(&mut s).unwrap();
+        assert!(triage.problems.is_empty(), "{:?}", triage.problems);
+        assert_eq!(triage.format_version, Some((3, 0)));
+        assert_eq!(triage.source, Some(false));
+        assert_eq!(triage.compression.as_deref(), Some("zstd"));
+        assert_eq!(triage.signatures.len(), 2);
+        for signature in &triage.signatures {
+            assert_eq!(signature.hash_alg, openpgp_parser::HashAlgorithm::Sha256);
+            assert_eq!(signature.pkey_alg, openpgp_parser::PublicKeyAlgorithm::Rsa);
+        }
+        assert_eq!(
+            96 + triage.signature_header_size.unwrap() + triage.claimed_size.unwrap(),
+            len
+        );
+        assert!(triage.reserved_space.is_some());
+        let mut t = triage.clone();
+        t.check_file_size(len);
+        assert!(t.problems.is_empty(), "{:?}", t.problems);
+        let mut t = triage.clone();
+        t.check_file_size(len - 1);
+        assert_eq!(t.problems, ["package truncated: 1 bytes missing"]);
+        let mut t = triage;
+        t.check_file_size(len + 2);
+        assert_eq!(t.problems, ["2 bytes of data after the payload"]);
+    }
+
+    #[test]
+    fn reports_truncation() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let triage = triage(&mut &s[..200]).unwrap();
+        assert_eq!(triage.format_version, Some((3, 0)));
+        assert_eq!(triage.problems.len(), 1);
+    }
+
+    #[test]
+    fn extracts_evidence() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let bundle = evidence_bundle(&mut &s[..]).unwrap();
+        let triage = triage(&mut &bundle[..]).unwrap();
+        assert!(triage.problems.is_empty(), "{:?}", triage.problems);
+        assert_eq!(
+            bundle.len() as u64,
+            96 + triage.signature_header_size.unwrap() + triage.main_header_size.unwrap()
+        );
+        assert_eq!(bundle[..], s[..bundle.len()]);
+        // Corrupt the main header index
+        let mut corrupt = s.to_vec();
+        corrupt[4504 + 16] ^= 0xFF;
+        let bundle = evidence_bundle(&mut &corrupt[..]).unwrap();
+        assert!(bundle.len() < s.len());
+        assert_eq!(triage_problems(&bundle), triage_problems(&corrupt));
+    }
+
+    #[test]
+    fn checks_sizes() {
+        let mut problems = vec![];
+        let mut size = None;
+        set_size(&mut size, Some(5), "payload", &mut problems);
+        set_size(&mut size, Some(5), "payload", &mut problems);
+        assert_eq!((size, &problems[..]), (Some(5), &[][..]));
+        set_size(&mut size, None, "payload", &mut problems);
+        assert_eq!(size, Some(5));
+        set_size(&mut size, Some(6), "payload", &mut problems);
+        assert_eq!(size, Some(5));
+        assert_eq!(
+            problems,
+            ["bad payload size tag", "conflicting payload sizes 5 and 6"]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_hash_algorithms() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let count = u32::from_be_bytes(s[104..108].try_into().unwrap()) as usize;
+        // The hash algorithm of the RSA header signature, the first entry
+        let offset = 112 + 16 * count + 7;
+        assert_eq!(s[offset], 8);
+        let mut corrupt = s.to_vec();
+        corrupt[offset] = 100;
+        let triage = triage(&mut &corrupt[..]).unwrap();
+        assert_eq!(
+            triage.problems,
+            ["bad OpenPGP signature in tag 268: UnsupportedHashAlgorithm(100)"]
+        );
+        assert_eq!(triage.signatures.len(), 1);
+        assert_eq!(triage.signatures[0].tag, RPMSIGTAG_PGP);
+    }
+
+    #[test]
+    fn reports_size_overflow() {
+        let mut problems = vec![];
+        let mut claimed_size = None;
+        let longsize = int_value(TagType::Int64, &u64::MAX.to_be_bytes());
+        set_size(&mut claimed_size, longsize, "header+payload", &mut problems);
+        assert!(problems.is_empty());
+        let mut triage = Triage {
+            signature_header_size: Some(4248),
+            claimed_size,
+            ..Triage::default()
+        };
+        triage.check_file_size(1 << 20);
+        assert_eq!(triage.problems, ["claimed size overflows"]);
+    }
+
+    #[test]
+    fn checks_reserved_space() {
+        let s: &[u8] = include_bytes!("../../lua-5.4.2-1.fc33.x86_64.rpm");
+        let count = u32::from_be_bytes(s[104..108].try_into().unwrap()) as usize;
+        let data = 112 + 16 * count;
+        let offset = s[112..data]
+            .chunks(16)
+            .find(|entry| entry[..4] == RPMSIGTAG_RESERVEDSPACE.to_be_bytes())
+            .map(|entry| u32::from_be_bytes(entry[8..12].try_into().unwrap()) as usize)
+            .unwrap();
+        let mut corrupt = s.to_vec();
+        corrupt[data + offset + 1] = 1;
+        assert_eq!(
+            triage_problems(&corrupt),
+            ["reserved space is not zeroed binary data"]
+        );
+    }
+
+    fn triage_problems(s: &[u8]) -> Vec<String> {
+        triage(&mut &s[..]).unwrap().problems
+    }
+}
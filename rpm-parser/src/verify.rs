@@ -1,13 +1,67 @@
 //! RPM package verification
 
-use crate::SignatureHeader;
+use crate::{PolicyViolation, SignatureHeader};
+use openpgp_parser::signature::{ParseOptions, SignatureKind};
+use openpgp_parser::ErrorCategory;
 use rpm_crypto::{transaction::RpmKeyring, DigestCtx, InitToken};
+use std::borrow::Cow;
 use std::convert::TryInto;
 use std::io::{copy, Error, ErrorKind, Read, Result, Write};
 
 mod validator;
 
+/// How the key that verified a signature was found
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KeyMatch {
+    /// The key was looked up by the full fingerprint in the signature.
+    Fingerprint,
+    /// The key was looked up by its 64-bit key ID.  Collisions between key IDs
+    /// are cheap to generate, so policies may wish to reject this.  RPM always
+    /// looks keys up this way, even if the signature has a fingerprint.
+    KeyIdOnly,
+}
+
+/// The data covered by a digest
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DigestRegion {
+    /// The main header
+    Header,
+    /// The main header and the (compressed) payload
+    HeaderAndPayload,
+    /// The (compressed) payload
+    Payload,
+}
+
+/// A digest computed during verification
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct DigestRecord {
+    /// The OpenPGP hash algorithm
+    pub algorithm: u8,
+    /// The data that was hashed
+    pub region: DigestRegion,
+    /// The computed digest, as lowercase hex.  This is what was *computed*,
+    /// which is not necessarily what the package claims.
+    pub digest: String,
+}
+
+impl DigestRecord {
+    /// `ascii_digest` is the NUL-terminated output of
+    /// [`DigestCtx::finalize`] with `ascii` set.
+    fn new(algorithm: u8, region: DigestRegion, ascii_digest: &[u8]) -> Self {
+        let digest = ascii_digest.strip_suffix(b"\0").unwrap_or(ascii_digest);
+        Self {
+            algorithm,
+            region,
+            digest: String::from_utf8_lossy(digest).into_owned(),
+        }
+    }
+}
+
 /// Package verification result
+#[non_exhaustive]
 pub struct VerifyResult {
     /// The package main header
     pub main_header: crate::MainHeader,
@@ -18,6 +72,17 @@ pub struct VerifyResult {
     pub header_payload_sig: Option<Vec<u8>>,
     /// The header signature.  This library requires header signatures.
     pub header_sig: Vec<u8>,
+    /// How the key that verified the header signature was found
+    pub header_sig_key_match: KeyMatch,
+    /// The fingerprint of the key that verified the header signature.  Only
+    /// known when the package was verified with
+    /// [`Keyring::verify_package`](crate::Keyring::verify_package), as RPM
+    /// does not say which key it used.
+    pub header_signer: Option<Vec<u8>>,
+    /// The size in bits of the RSA modulus or DSA prime of the key that
+    /// verified the header signature.  [`None`] for elliptic-curve keys, and
+    /// whenever [`VerifyResult::header_signer`] is.
+    pub header_signer_bits: Option<u32>,
     /// The bytes of the main header
     pub main_header_bytes: Vec<u8>,
     /// The SHA256 hash of the main header, hex-encoded with a trailing NUL
@@ -25,13 +90,237 @@ pub struct VerifyResult {
     /// The MD5 header+payload digest (yuck!).  Will only be [`Some`] for old
     /// packages with no payload digests.
     pub header_payload_weak_digest: Option<Vec<u8>>,
+    /// The OpenPGP hash algorithms of the main header digests in the
+    /// signature header, all of which matched.  [`VerifyResult::digests`]
+    /// has a SHA-256 digest of the main header even if this does not.
+    pub header_digest_algorithms: Vec<u8>,
+    /// Every digest computed during verification, in the order they were
+    /// computed.  When passed to the callback of [`verify_package`], this only
+    /// includes digests of the header, as the payload has not been read yet.
+    pub digests: Vec<DigestRecord>,
+    /// Signature header tags that were not understood (see
+    /// [`SignatureHeader::unknown_tags`]).  They are not covered by any
+    /// signature.
+    pub unknown_signature_tags: Vec<u32>,
+    /// What the package was verified as.  Always
+    /// [`RPM_PACKAGE`](crate::Domain::RPM_PACKAGE).
+    pub domain: crate::Domain,
+}
+
+/// The callback of [`verify_package`], called once the main header has been
+/// verified and before the payload is read
+pub type VerifyCallback<'a> = dyn FnMut(&VerifyResult, Option<&mut dyn Write>) -> Result<()> + 'a;
+
+/// Why verification failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FailureKind {
+    /// The package could not be verified: no key is available, the key is not
+    /// trusted, or an algorithm is not supported.  The package may be fine.
+    Unverifiable,
+    /// Cryptographic verification failed.  The package has been tampered with.
+    Forged,
+}
+
+/// A verification failure.  This is wrapped in the [`std::io::Error`]
+/// returned by the functions in this module; use [`failure_kind`] to retrieve
+/// its kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerificationFailure {
+    kind: FailureKind,
+    category: ErrorCategory,
+    message: Cow<'static, str>,
+}
+
+impl std::fmt::Display for VerificationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for VerificationFailure {}
+
+impl VerificationFailure {
+    pub(crate) fn error<T: Into<Cow<'static, str>>>(kind: FailureKind, message: T) -> Error {
+        let message = message.into();
+        let category = ErrorCategory::Crypto;
+        Error::new(
+            ErrorKind::InvalidData,
+            Self {
+                kind,
+                category,
+                message,
+            },
+        )
+    }
+}
+
+/// Converts an error from parsing an OpenPGP signature.  Insecure algorithms
+/// make the package unverifiable (see [`FailureKind`]), even though they are a
+/// matter of policy.
+pub(crate) fn openpgp_error(e: openpgp_parser::Error) -> Error {
+    match e.category() {
+        ErrorCategory::Malformed => Error::new(
+            ErrorKind::InvalidData,
+            format!("bad OpenPGP signature: {:?}", e),
+        ),
+        ErrorCategory::Policy if !matches!(e, openpgp_parser::Error::InsecureAlgorithm(_)) => {
+            PolicyViolation::error(format!("OpenPGP signature rejected: {:?}", e))
+        }
+        category => Error::new(
+            ErrorKind::InvalidData,
+            VerificationFailure {
+                kind: FailureKind::Unverifiable,
+                category,
+                message: format!("unsupported OpenPGP signature: {:?}", e).into(),
+            },
+        ),
+    }
+}
+
+/// As [`openpgp_error`], but for an error from reading a signature packet,
+/// which carries the [`openpgp_parser::Error`] as its payload if parsing failed
+pub(crate) fn openpgp_io_error(e: Error) -> Error {
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<openpgp_parser::Error>())
+    {
+        Some(&inner) => openpgp_error(inner),
+        None => e,
+    }
+}
+
+/// Retrieves the kind of verification failure from an error returned by this
+/// module.  Returns [`None`] for errors that are not verification failures,
+/// such as I/O errors and malformed packages.
+pub fn failure_kind(e: &Error) -> Option<FailureKind> {
+    e.get_ref()?
+        .downcast_ref::<VerificationFailure>()
+        .map(|f| f.kind)
+}
+
+/// Retrieves the category of an error returned by this crate: malformed
+/// input, a policy violation, or a cryptographic failure.  Returns [`None`]
+/// for I/O errors.
+pub fn error_category(e: &Error) -> Option<ErrorCategory> {
+    if let Some(inner) = e.get_ref() {
+        if let Some(f) = inner.downcast_ref::<VerificationFailure>() {
+            return Some(f.category);
+        } else if inner.is::<PolicyViolation>() {
+            return Some(ErrorCategory::Policy);
+        } else if let Some(e) = inner.downcast_ref::<openpgp_parser::Error>() {
+            return Some(e.category());
+        }
+    }
+    match e.kind() {
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Some(ErrorCategory::Malformed),
+        _ => None,
+    }
+}
+
+fn keyring_failure_kind(e: std::os::raw::c_int) -> FailureKind {
+    match e {
+        2 => FailureKind::Forged,
+        1 | 3 | 4 => FailureKind::Unverifiable,
+        _ => panic!("bad RPM retval"),
+    }
+}
+
+pub(crate) fn keyring_error(e: std::os::raw::c_int) -> Error {
+    VerificationFailure::error(
+        keyring_failure_kind(e),
+        match e {
+            1 => "Signature not supported!",
+            2 => "Signature forged!",
+            3 => "Key not trusted!",
+            4 => "No key available!",
+            _ => unreachable!("checked by keyring_failure_kind"),
+        },
+    )
+}
+
+/// Verify a detached OpenPGP signature over the data read from `src`.  This is
+/// a minimal replacement for `gpgv`.
+///
+/// # Parameters
+///
+/// - `src`: The signed data.  Wrap it in a [`crate::Deadline`] to bound the
+///   time taken.
+/// - `untrusted_sig`: The binary (not armored) signature
+/// - `keyring`: The RPM keyring for verification
+/// - `allow_weak_hashes`: Allow signatures using SHA-1 or SHA-224?
+/// - `token`: Token to prove that RPM has been initialized
+pub fn verify_detached(
+    src: &mut dyn Read,
+    untrusted_sig: &[u8],
+    keyring: &RpmKeyring,
+    allow_weak_hashes: openpgp_parser::AllowWeakHashes,
+    token: InitToken,
+) -> Result<()> {
+    let mut sig = rpm_crypto::Signature::parse(untrusted_sig, 0, allow_weak_hashes, token)
+        .map_err(openpgp_error)?;
+    copy(src, &mut sig)?;
+    if !sig.quick_check() {
+        return Err(keyring_error(2));
+    }
+    keyring.validate_sig(sig).map_err(keyring_error)
+}
+
+/// Parses the cleartext-signed `document` and its signature, which must be a
+/// text signature using one of the hash algorithms in the `Hash` headers, if
+/// there are any.  Returns the signed text and the signature, which has
+/// already hashed it.
+pub(crate) fn parse_cleartext(
+    document: &[u8],
+    allow_weak_hashes: openpgp_parser::AllowWeakHashes,
+    token: InitToken,
+) -> Result<(Vec<u8>, rpm_crypto::Signature)> {
+    let document = match openpgp_parser::cleartext::parse(document) {
+        Ok(document) => document,
+        Err(e) => bad_data!("bad cleartext signed document: {:?}", e),
+    };
+    let mut options = ParseOptions::new(allow_weak_hashes);
+    options.kind = Some(SignatureKind::Text);
+    let mut sig =
+        rpm_crypto::Signature::parse_with_options(&document.signature, 0, &options, token)
+            .map_err(openpgp_error)?;
+    let hash_alg = sig.info().hash_alg.into();
+    fail_if!(
+        !document.hash_algorithms.is_empty() && !document.hash_algorithms.contains(&hash_alg),
+        "signature uses hash algorithm {}, which the Hash headers do not list",
+        hash_alg
+    );
+    sig.update(&document.text);
+    Ok((document.text, sig))
+}
+
+/// Verify a cleartext-signed document, such as a signed configuration file.
+/// The signature must be a text signature and, if the document has `Hash`
+/// headers, must use one of the hash algorithms they name.  Returns the
+/// signed text in canonical form: dash-escaping and trailing whitespace
+/// removed, and lines ending with CR LF.
+///
+/// Parameters are as for [`verify_detached`].
+pub fn verify_cleartext(
+    document: &[u8],
+    keyring: &RpmKeyring,
+    allow_weak_hashes: openpgp_parser::AllowWeakHashes,
+    token: InitToken,
+) -> Result<Vec<u8>> {
+    let (text, sig) = parse_cleartext(document, allow_weak_hashes, token)?;
+    if !sig.quick_check() {
+        return Err(keyring_error(2));
+    }
+    keyring.validate_sig(sig).map_err(keyring_error)?;
+    Ok(text)
 }
 
 /// Verify a package
 ///
 /// # Parameters
 ///
-/// - `src`: The source of the package
+/// - `src`: The source of the package.  Wrap it in a [`crate::Deadline`] to
+///   bound the time taken.
 /// - `sig_header`: The signature header
 /// - `keyring`: The RPM keyring for verification
 /// - `allow_old_pkgs`: Allow packages without payload digests?
@@ -46,7 +335,60 @@ pub fn verify_package(
     allow_old_pkgs: bool,
     preserve_old_sig: bool,
     token: InitToken,
-    mut cb: Option<&mut dyn FnMut(&VerifyResult, Option<&mut dyn Write>) -> Result<()>>,
+    cb: Option<&mut dyn FnMut(&VerifyResult, Option<&mut dyn Write>) -> Result<()>>,
+    output: Option<&mut dyn Write>,
+) -> std::io::Result<VerifyResult> {
+    verify_package_with(
+        src,
+        sig_header,
+        &mut |signature| {
+            keyring.validate_sig(signature).map_err(keyring_error)?;
+            // RPM finds the key by key ID, whatever the signature says
+            Ok((KeyMatch::KeyIdOnly, None, None))
+        },
+        &mut rpm_verifier(keyring),
+        allow_old_pkgs,
+        preserve_old_sig,
+        token,
+        cb,
+        output,
+    )
+}
+
+/// Verifies a header signature that has already hashed the main header.
+/// Returns how the key that made the signature was found, and its
+/// fingerprint and size in bits if known.
+pub(crate) type HeaderVerifier<'a> =
+    dyn FnMut(rpm_crypto::Signature) -> Result<(KeyMatch, Option<Vec<u8>>, Option<u32>)> + 'a;
+
+/// Verifies a signature that has already hashed the signed data and passed
+/// [`rpm_crypto::Signature::quick_check`]
+pub(crate) type SignatureVerifier<'a> =
+    dyn FnMut(rpm_crypto::Signature) -> std::result::Result<(), FailureKind> + 'a;
+
+/// Verifies signatures with `keyring`
+fn rpm_verifier(
+    keyring: &RpmKeyring,
+) -> impl FnMut(rpm_crypto::Signature) -> std::result::Result<(), FailureKind> + '_ {
+    move |signature| {
+        keyring
+            .validate_sig(signature)
+            .map_err(keyring_failure_kind)
+    }
+}
+
+/// As [`verify_package`], but the header signature is verified by
+/// `verify_header` and the header+payload signature by `verify_payload`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn verify_package_with(
+    src: &mut dyn Read,
+    sig_header: &mut SignatureHeader,
+    verify_header: &mut HeaderVerifier<'_>,
+    verify_payload: &mut SignatureVerifier<'_>,
+    allow_old_pkgs: bool,
+    preserve_old_sig: bool,
+    token: InitToken,
+    mut cb: Option<&mut VerifyCallback<'_>>,
     output: Option<&mut dyn Write>,
 ) -> std::io::Result<VerifyResult> {
     use validator::Validator;
@@ -67,7 +409,12 @@ pub fn verify_package(
         header_payload_weak_digest = Some(weak_digest.1.clone());
         let digest_value = u128::from_be_bytes(weak_digest.1.try_into().expect("length checked earlier"));
         let _digest_value = format!("{:032x}\0", digest_value);
-        validator.add_untrusted_digest(weak_digest.0, _digest_value.as_bytes().to_vec());
+        validator.add_untrusted_digest(
+            weak_digest.0,
+            _digest_value.as_bytes().to_vec(),
+            1,
+            DigestRegion::HeaderAndPayload,
+        );
     }
 
     let mut prelude = [0u8; 16];
@@ -92,6 +439,11 @@ pub fn verify_package(
         main_header_hash.update(&main_header_bytes);
         main_header_hash.finalize(true)
     };
+    let mut digests = vec![DigestRecord::new(
+        8,
+        DigestRegion::Header,
+        &main_header_hash,
+    )];
     assert_eq!(
         validator.write(&main_header_bytes).unwrap(),
         main_header_bytes.len()
@@ -101,24 +453,27 @@ pub fn verify_package(
         .take()
         .ok_or_else(|| Error::new(ErrorKind::InvalidData, "header not signed"))?;
     signature.update(&main_header_bytes);
-    keyring.validate_sig(signature).map_err(|e| {
-        Error::new(
-            ErrorKind::InvalidData,
-            match e {
-                2 => "Signature forged!",
-                3 => "Key not trusted!",
-                4 => "No key available!",
-                _ => panic!("bad RPM retval"),
-            },
-        )
-    })?;
+    if !signature.quick_check() {
+        return Err(keyring_error(2));
+    }
+    let (header_sig_key_match, header_signer, header_signer_bits) = verify_header(signature)?;
+    let mut header_digest_algorithms = vec![];
     let s: Option<(DigestCtx, Vec<u8>)> = sig_header.header_sha1_hash.take();
-    for i in vec![s, sig_header.header_sha256_hash.take()].into_iter() {
+    for (i, alg) in vec![(s, 2), (sig_header.header_sha256_hash.take(), 8)].into_iter() {
         let i: Option<(DigestCtx, Vec<u8>)> = i;
         if let Some((mut ctx, value)) = i {
             ctx.update(&main_header_bytes);
-            if ctx.finalize(true) != value {
-                return Err(Error::new(ErrorKind::InvalidData, "bad digest"));
+            let computed = ctx.finalize(true);
+            header_digest_algorithms.push(alg);
+            // The SHA-256 digest is already in the transcript
+            if alg != 8 {
+                digests.push(DigestRecord::new(alg, DigestRegion::Header, &computed))
+            }
+            if computed != value {
+                return Err(VerificationFailure::error(
+                    FailureKind::Forged,
+                    "bad digest",
+                ));
             }
         }
     }
@@ -128,11 +483,14 @@ pub fn verify_package(
     match main_header.payload_digest() {
         Ok(s) => {
             header_payload_weak_digest = None;
+            let alg = main_header
+                .payload_digest_algorithm()
+                .expect("payload_digest() succeeded");
             if preserve_old_sig {
-                validator.add_untrusted_digest(s.0, s.1);
+                validator.add_untrusted_digest(s.0, s.1, alg, DigestRegion::Payload);
             } else {
                 header_payload_sig = None;
-                validator.add_trusted_digest(s.0, s.1);
+                validator.add_trusted_digest(s.0, s.1, alg, DigestRegion::Payload);
             }
         }
         Err(_) if allow_old_pkgs => {
@@ -150,13 +508,20 @@ pub fn verify_package(
         }
         Err(e) => return Err(e),
     };
-    let vfy_result = VerifyResult {
+    let mut vfy_result = VerifyResult {
         main_header,
         header_payload_sig,
         header_sig,
+        header_sig_key_match,
+        header_signer,
+        header_signer_bits,
         main_header_bytes,
         main_header_hash,
         header_payload_weak_digest,
+        header_digest_algorithms,
+        digests,
+        domain: crate::Domain::RPM_PACKAGE,
+        unknown_signature_tags: std::mem::take(&mut sig_header.unknown_tags),
     };
     if let Some(ref mut cb) = cb {
         let mut output = validator.set_output(None);
@@ -168,9 +533,16 @@ pub fn verify_package(
     }
     drop(cb);
     copy(src, &mut validator)?;
-    validator
-        .validate(&keyring)
-        .map_err(|()| Error::new(ErrorKind::InvalidData, "Payload forged!"))?;
+    let transcript = validator.validate(verify_payload).map_err(|kind| {
+        VerificationFailure::error(
+            kind,
+            match kind {
+                FailureKind::Forged => "Payload forged!",
+                FailureKind::Unverifiable => "Payload could not be verified!",
+            },
+        )
+    })?;
+    vfy_result.digests.extend(transcript);
     Ok(vfy_result)
 }
 
@@ -205,41 +577,126 @@ mod tests {
     #[test]
     fn empty_validator_bad() {
         let (keyring, _, _) = globals();
-        assert!(Validator::new(None).validate(&keyring).is_err());
+        assert_eq!(
+            Validator::new(None).validate(&mut rpm_verifier(&keyring)),
+            Err(FailureKind::Unverifiable)
+        );
+    }
+
+    #[test]
+    fn failure_kinds() {
+        let e = keyring_error(4);
+        assert_eq!(failure_kind(&e), Some(FailureKind::Unverifiable));
+        assert_eq!(e.to_string(), "No key available!");
+        assert_eq!(failure_kind(&keyring_error(2)), Some(FailureKind::Forged));
+        assert_eq!(
+            failure_kind(&Error::new(ErrorKind::InvalidData, "bad")),
+            None
+        );
+        assert_eq!(error_category(&e), Some(ErrorCategory::Crypto));
+        assert_eq!(
+            error_category(&Error::new(ErrorKind::InvalidData, "bad")),
+            Some(ErrorCategory::Malformed)
+        );
+        assert_eq!(error_category(&Error::from(ErrorKind::NotFound)), None);
+        let e = openpgp_error(openpgp_parser::Error::SignatureExpired);
+        assert_eq!(error_category(&e), Some(ErrorCategory::Policy));
+        assert_eq!(failure_kind(&e), None);
+        let e = openpgp_error(openpgp_parser::Error::InsecureAlgorithm(2));
+        assert_eq!(error_category(&e), Some(ErrorCategory::Policy));
+        assert_eq!(failure_kind(&e), Some(FailureKind::Unverifiable));
+        let e = Error::from(openpgp_parser::Error::UnknownCurve);
+        assert_eq!(error_category(&e), Some(ErrorCategory::Crypto));
+        // What rpmcanon gets if a signature cannot be re-serialized
+        let e = Error::from(openpgp_parser::Error::IllFormedSignature);
+        assert_eq!(error_category(&e), Some(ErrorCategory::Malformed));
+        assert_eq!(failure_kind(&e), None);
+        let e = openpgp_io_error(Error::from(openpgp_parser::Error::SignatureExpired));
+        assert!(e.get_ref().unwrap().is::<PolicyViolation>());
+    }
+
+    #[test]
+    fn records_ascii_digests() {
+        let record = DigestRecord::new(8, DigestRegion::Payload, EMPTY_SHA256);
+        assert_eq!(record.digest.len(), 64);
+        assert_eq!(record.digest.as_bytes(), &EMPTY_SHA256[..64]);
+    }
+
+    #[test]
+    fn transcript_includes_digests() {
+        let (keyring, _, sha256) = globals();
+        let mut v = Validator::new(None);
+        v.add_trusted_digest(
+            sha256.clone(),
+            A_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.write(b"a").unwrap();
+        let transcript = v.validate(&mut rpm_verifier(&keyring)).unwrap();
+        assert_eq!(transcript.len(), 1);
+        assert_eq!(transcript[0].region, DigestRegion::Payload);
+        assert_eq!(transcript[0].digest.as_bytes(), &A_SHA256[..64]);
     }
 
     #[test]
     fn untrusted_digest_not_sufficient() {
         let (keyring, _, sha256) = globals();
         let mut v = Validator::new(None);
-        v.add_untrusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
-        v.validate(&keyring).unwrap_err();
+        v.add_untrusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        assert_eq!(
+            v.validate(&mut rpm_verifier(&keyring)),
+            Err(FailureKind::Unverifiable)
+        );
     }
 
     #[test]
     fn trusted_digest_sufficient() {
         let (keyring, _, sha256) = globals();
         let mut v = Validator::new(None);
-        v.add_trusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
-        v.validate(&keyring).unwrap();
+        v.add_trusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.validate(&mut rpm_verifier(&keyring)).unwrap();
     }
 
     #[test]
     fn bad_trusted_digest() {
         let (keyring, _, sha256) = globals();
         let mut v = Validator::new(None);
-        v.add_trusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
+        v.add_trusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
         assert!(matches!(v.write(b"a"), Ok(1)));
-        v.validate(&keyring).unwrap_err();
+        assert_eq!(
+            v.validate(&mut rpm_verifier(&keyring)),
+            Err(FailureKind::Forged)
+        );
     }
 
     #[test]
     fn bad_untrusted_digest() {
         let (keyring, _, sha256) = globals();
         let mut v = Validator::new(None);
-        v.add_trusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
-        v.add_untrusted_digest(sha256.clone(), vec![]);
-        v.validate(&keyring).unwrap_err();
+        v.add_trusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.add_untrusted_digest(sha256.clone(), vec![], 8, DigestRegion::Payload);
+        v.validate(&mut rpm_verifier(&keyring)).unwrap_err();
     }
 
     #[test]
@@ -248,9 +705,19 @@ mod tests {
 
         // Mixture of digests
         let mut v = Validator::new(None);
-        v.add_untrusted_digest(sha256.clone(), A_SHA256.to_owned());
-        v.add_trusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
-        v.validate(&keyring).unwrap_err();
+        v.add_untrusted_digest(
+            sha256.clone(),
+            A_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.add_trusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.validate(&mut rpm_verifier(&keyring)).unwrap_err();
     }
 
     #[test]
@@ -258,10 +725,20 @@ mod tests {
         let (keyring, _, sha256) = globals();
         // Mixture of digests
         let mut v = Validator::new(None);
-        v.add_untrusted_digest(sha256.clone(), A_SHA256.to_owned());
+        v.add_untrusted_digest(
+            sha256.clone(),
+            A_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
         v.write(b"a").unwrap();
-        v.add_trusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
-        v.validate(&keyring).unwrap();
+        v.add_trusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.validate(&mut rpm_verifier(&keyring)).unwrap();
     }
 
     #[test]
@@ -271,9 +748,49 @@ mod tests {
         let mut buf = [1];
         let mut cursor = std::io::Cursor::new(&mut buf[..]);
         let mut v = Validator::new(Some(&mut cursor));
-        v.add_untrusted_digest(sha256.clone(), A_SHA256.to_owned());
+        v.add_untrusted_digest(
+            sha256.clone(),
+            A_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
         assert_eq!(v.write(b"ab").unwrap(), 1);
-        v.add_trusted_digest(sha256.clone(), EMPTY_SHA256.to_owned());
-        v.validate(&keyring).unwrap();
+        v.add_trusted_digest(
+            sha256.clone(),
+            EMPTY_SHA256.to_owned(),
+            8,
+            DigestRegion::Payload,
+        );
+        v.validate(&mut rpm_verifier(&keyring)).unwrap();
+    }
+
+    #[test]
+    fn rejects_tiny_packages() {
+        use rpm_writer::testing::{Defect, TestPackage};
+        let (keyring, token, _) = globals();
+        let verify = |package: TestPackage| {
+            let package = package.build(token).unwrap();
+            let mut s = &package[..];
+            crate::read_lead(&mut s).unwrap();
+            let mut sig_header =
+                crate::load_signature(&mut s, openpgp_parser::AllowWeakHashes::No, token)
+                    .unwrap();
+            verify_package(
+                &mut s,
+                &mut sig_header,
+                &keyring,
+                false,
+                false,
+                token,
+                None,
+                None,
+            )
+        };
+        // The placeholder signature fails the hash prefix check
+        let e = verify(TestPackage::new("tiny")).err().unwrap();
+        assert_eq!(failure_kind(&e), Some(FailureKind::Forged));
+        let stripped = TestPackage::new("tiny").with_defect(Defect::StrippedSignature);
+        let e = verify(stripped).err().unwrap();
+        assert_eq!(e.to_string(), "header not signed");
     }
 }
@@ -1,28 +1,29 @@
-use rpm_crypto::{transaction::RpmKeyring, DigestCtx, Signature};
+use super::{DigestRecord, DigestRegion, FailureKind, SignatureVerifier};
+use rpm_crypto::{DigestCtx, Signature};
 use std::io::{Result, Write};
 
 /// Something that can be cryptographically verified
 enum Verifyable {
     /// An untrusted digest
-    UntrustedDigest(DigestCtx, Vec<u8>),
+    UntrustedDigest(DigestCtx, Vec<u8>, u8, DigestRegion),
     /// A signature
     Signature(Signature),
     /// A trusted digest
-    TrustedDigest(DigestCtx, Vec<u8>),
+    TrustedDigest(DigestCtx, Vec<u8>, u8, DigestRegion),
 }
 
 impl Verifyable {
     fn update(&mut self, data: &[u8]) {
         match self {
-            Self::UntrustedDigest(dgst, _) | Self::TrustedDigest(dgst, _) => dgst.update(data),
+            Self::UntrustedDigest(dgst, ..) | Self::TrustedDigest(dgst, ..) => dgst.update(data),
             Self::Signature(sig) => sig.update(data),
         }
     }
 
     fn trusted(&self) -> bool {
         match self {
-            Self::Signature(_) | Self::TrustedDigest(_, _) => true,
-            Self::UntrustedDigest(_, _) => false,
+            Self::Signature(_) | Self::TrustedDigest(..) => true,
+            Self::UntrustedDigest(..) => false,
         }
     }
 }
@@ -73,9 +74,17 @@ impl<'a> Validator<'a> {
     }
 
     /// Add an untrusted digest.  An incorrect untrusted digest will result in verification
-    /// failure, but a correct untrusted digest is not sufficient.
-    pub(super) fn add_untrusted_digest(&mut self, dgst: DigestCtx, data: Vec<u8>) -> &mut Self {
-        self.objects.push(Verifyable::UntrustedDigest(dgst, data));
+    /// failure, but a correct untrusted digest is not sufficient.  `alg` and `region` are only
+    /// used for the transcript returned by [`Self::validate`].
+    pub(super) fn add_untrusted_digest(
+        &mut self,
+        dgst: DigestCtx,
+        data: Vec<u8>,
+        alg: u8,
+        region: DigestRegion,
+    ) -> &mut Self {
+        self.objects
+            .push(Verifyable::UntrustedDigest(dgst, data, alg, region));
         self
     }
 
@@ -90,8 +99,15 @@ impl<'a> Validator<'a> {
     ///
     /// For example, it is safe to use a digest that comes from a header signed with a trusted
     /// signature.
-    pub(super) fn add_trusted_digest(&mut self, dgst: DigestCtx, data: Vec<u8>) -> &mut Self {
-        self.objects.push(Verifyable::TrustedDigest(dgst, data));
+    pub(super) fn add_trusted_digest(
+        &mut self,
+        dgst: DigestCtx,
+        data: Vec<u8>,
+        alg: u8,
+        region: DigestRegion,
+    ) -> &mut Self {
+        self.objects
+            .push(Verifyable::TrustedDigest(dgst, data, alg, region));
         self
     }
 
@@ -101,21 +117,46 @@ impl<'a> Validator<'a> {
     ///
     /// - All digests and signatures must validate correctly.
     /// - [`self`] must contain either a signature or a trusted digest.
-    pub(super) fn validate(self, keyring: &RpmKeyring) -> std::result::Result<(), ()> {
+    ///
+    /// A digest mismatch or bad signature is reported as [`FailureKind::Forged`], even if other
+    /// objects could not be verified.  Otherwise, a signature made by an unavailable or untrusted
+    /// key, or the lack of any trusted object, is reported as [`FailureKind::Unverifiable`].
+    ///
+    /// Signatures are checked by `verify_sig`.  On success, returns the digests that were
+    /// computed.
+    pub(super) fn validate(
+        self,
+        verify_sig: &mut SignatureVerifier<'_>,
+    ) -> std::result::Result<Vec<DigestRecord>, FailureKind> {
         let mut trusted = false;
-        let mut no_bad_found = true;
+        let mut result = Ok(());
+        let mut transcript = vec![];
         for i in self.objects.into_iter() {
             trusted |= i.trusted();
-            no_bad_found &= match i {
-                Verifyable::TrustedDigest(ctx, digest)
-                | Verifyable::UntrustedDigest(ctx, digest) => ctx.finalize(true) == digest,
-                Verifyable::Signature(sig) => keyring.validate_sig(sig).is_ok(),
+            let r = match i {
+                Verifyable::TrustedDigest(ctx, digest, alg, region)
+                | Verifyable::UntrustedDigest(ctx, digest, alg, region) => {
+                    let computed = ctx.finalize(true);
+                    let r = if computed == digest {
+                        Ok(())
+                    } else {
+                        Err(FailureKind::Forged)
+                    };
+                    transcript.push(DigestRecord::new(alg, region, &computed));
+                    r
+                }
+                // A wrong hash prefix means the signature cannot be valid
+                Verifyable::Signature(sig) if !sig.quick_check() => Err(FailureKind::Forged),
+                Verifyable::Signature(sig) => verify_sig(sig),
             };
+            match (r, result) {
+                (Err(e), Ok(())) | (Err(e @ FailureKind::Forged), _) => result = Err(e),
+                _ => {}
+            }
         }
-        if trusted && no_bad_found {
-            Ok(())
-        } else {
-            Err(())
+        match result {
+            Ok(()) if !trusted => Err(FailureKind::Unverifiable),
+            result => result.map(|()| transcript),
         }
     }
 }
@@ -0,0 +1,399 @@
+//! Generates synthetic test vectors for downstream test suites
+//!
+//! Everything is derived deterministically from a seed.  The outputs are
+//! structurally valid, but no private key is involved: signatures contain
+//! random numbers in place of real signature values, and keys contain random
+//! moduli.  Digests (header and payload digests, key fingerprints) are real,
+//! and package payloads are uncompressed cpio archives of the files in the
+//! header.
+//!
+//! Each vector is parsed again after it is written, and generation fails
+//! unless the parser gives the result listed in [`EXPECTED`], which is also
+//! written to the output directory for downstream test suites.
+
+use openpgp_parser::signature::{self, Fingerprint, KeyIdSource, ParseOptions};
+use openpgp_parser::subpacket::{serialize_area, Subpacket};
+use openpgp_parser::{AllowWeakHashes, Error as PgpError, PublicKeyAlgorithm};
+use rpm_crypto::{DigestCtx, InitToken};
+use rpm_parser::RPMPackage;
+use rpm_writer::{HeaderBuilder, HeaderEntry, HeaderKind};
+use std::ffi::{CStr, CString};
+use std::fs::{read, File};
+use std::io::{Error, Read, Result, Write};
+use std::path::Path;
+
+const USAGE: &str = "Usage: rpm-testvectors SEED DIRECTORY\n\n\
+                     Writes synthetic OpenPGP keys, signatures, and RPM packages to DIRECTORY,\n\
+                     which must exist.  The same SEED always produces the same output.";
+
+/// What the parser does with each vector, written to `EXPECTED`
+const EXPECTED: &str = "\
+key-rsa.pgp: accepted; v4 RSA, 2048 bits
+sig-v4.pgp: accepted; issuer fingerprint of key-rsa.pgp in the hashed area
+sig-v3.pgp: accepted; key ID of key-rsa.pgp in the v3 header
+sig-v4-key-id-only.pgp: accepted; key ID of key-rsa.pgp in the unhashed area only
+sig-v4-huge-subpackets.pgp: rejected with UnsupportedCriticalSubpacket, unless the notation huge@testvectors is allowed
+minimal.rpm: accepted; no files, header signed with sig-v4.pgp
+many-files.rpm: accepted; every fourth file is a symlink, header signed with sig-v4.pgp
+";
+
+/// The directory holding the files of generated packages
+const FILE_DIR: &str = "/usr/share/testvectors/";
+const HUGE_NOTATION: &str = "huge@testvectors";
+
+/// xorshift64*; not cryptographically secure, which is fine for test data
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift has a fixed point at zero
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    /// A random MPI of exactly `bits` bits, including its length prefix
+    fn mpi(&mut self, bits: u16) -> Vec<u8> {
+        let mut out = bits.to_be_bytes().to_vec();
+        let mut body = self.bytes((bits as usize).div_ceil(8));
+        let top_bits = bits % 8;
+        body[0] = match top_bits {
+            0 => body[0] | 0x80,
+            _ => (body[0] & ((1 << top_bits) - 1)) | (1 << (top_bits - 1)),
+        };
+        out.extend(body);
+        out
+    }
+}
+
+/// Wraps `body` in an old-format packet with the given tag
+fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    match body.len() {
+        0..=0xFF => out.extend_from_slice(&[0x80 | tag << 2, body.len() as u8]),
+        0x100..=0xFFFF => {
+            out.push(0x81 | tag << 2);
+            out.extend_from_slice(&(body.len() as u16).to_be_bytes())
+        }
+        _ => {
+            out.push(0x82 | tag << 2);
+            out.extend_from_slice(&(body.len() as u32).to_be_bytes())
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+fn hex_digest(alg: u8, data: &[u8], token: InitToken) -> CString {
+    let mut ctx = DigestCtx::init(alg, AllowWeakHashes::Yes, token).expect("hash supported");
+    ctx.update(data);
+    CString::from_vec_with_nul(ctx.finalize(true)).expect("RPM NUL-terminates its hex data")
+}
+
+/// A v4 RSA public key packet and its fingerprint
+fn rsa_key(rng: &mut Rng, creation_time: u32, token: InitToken) -> (Vec<u8>, [u8; 20]) {
+    let mut body = vec![4];
+    body.extend_from_slice(&creation_time.to_be_bytes());
+    body.push(1);
+    body.extend(rng.mpi(2048));
+    body.extend_from_slice(&[0, 17, 1, 0, 1]);
+    let mut ctx = DigestCtx::init(2, AllowWeakHashes::Yes, token).expect("SHA-1 supported");
+    ctx.update(&[0x99]);
+    ctx.update(&(body.len() as u16).to_be_bytes());
+    ctx.update(&body);
+    let mut fingerprint = [0; 20];
+    fingerprint.copy_from_slice(&ctx.finalize(false));
+    (packet(6, &body), fingerprint)
+}
+
+/// A v4 RSA signature packet over nothing in particular
+fn v4_signature(rng: &mut Rng, hashed: &[Subpacket], unhashed: &[Subpacket]) -> Vec<u8> {
+    let mut body = vec![4, 0, 1, 8];
+    body.extend(serialize_area(hashed).expect("hashed area fits"));
+    body.extend(serialize_area(unhashed).expect("unhashed area fits"));
+    body.extend(rng.bytes(2));
+    body.extend(rng.mpi(2048));
+    packet(2, &body)
+}
+
+/// A v3 RSA signature packet
+fn v3_signature(rng: &mut Rng, creation_time: u32, key_id: [u8; 8]) -> Vec<u8> {
+    let mut body = vec![3, 5, 0];
+    body.extend_from_slice(&creation_time.to_be_bytes());
+    body.extend_from_slice(&key_id);
+    body.extend_from_slice(&[1, 8]);
+    body.extend(rng.bytes(2));
+    body.extend(rng.mpi(2048));
+    packet(2, &body)
+}
+
+fn standard_subpackets(creation_time: u32, fingerprint: [u8; 20]) -> Vec<Subpacket> {
+    vec![
+        Subpacket::creation_time(creation_time, false),
+        Subpacket::issuer_fingerprint(Fingerprint::V4(fingerprint), false),
+    ]
+}
+
+fn key_id(fingerprint: [u8; 20]) -> [u8; 8] {
+    let mut key_id = [0; 8];
+    key_id.copy_from_slice(&fingerprint[12..]);
+    key_id
+}
+
+/// Appends a `newc` cpio entry to `archive`
+fn cpio_entry(archive: &mut Vec<u8>, ino: u32, mode: u16, path: &str, contents: &[u8]) {
+    let name_size = path.len() as u32 + 1;
+    // Owner, mtime, and device numbers are all zero, and newc has no checksum
+    let fields = [
+        ino,
+        mode.into(),
+        0,
+        0,
+        1,
+        0,
+        contents.len() as u32,
+        0,
+        0,
+        0,
+        0,
+        name_size,
+        0,
+    ];
+    archive.extend_from_slice(b"070701");
+    for field in &fields {
+        archive.extend_from_slice(format!("{:08X}", field).as_bytes())
+    }
+    archive.extend_from_slice(path.as_bytes());
+    archive.push(0);
+    archive.resize((archive.len() + 3) & !3, 0);
+    archive.extend_from_slice(contents);
+    archive.resize((archive.len() + 3) & !3, 0);
+}
+
+/// A minimal binary RPM with `files` regular files and symlinks
+fn package(
+    rng: &mut Rng,
+    name: &str,
+    files: usize,
+    signature: &[u8],
+    token: InitToken,
+) -> Result<Vec<u8>> {
+    let c = |s: &str| CString::new(s).expect("no NUL in generated strings");
+    let source_rpm = c(&format!("{}-1.0-1.src.rpm", name));
+    let (name, version, release) = (c(name), c("1.0"), c("1"));
+    let (os, arch, payload_format) = (c("linux"), c("noarch"), c("cpio"));
+    let dirnames = [c(FILE_DIR)];
+    let basenames: Vec<CString> = (0..files).map(|i| c(&format!("f{}", i))).collect();
+    let basenames: Vec<&CStr> = basenames.iter().map(|i| &**i).collect();
+    // Every fourth file is a symlink to the previous file
+    let modes: Vec<u16> = (0..files)
+        .map(|i| if i % 4 == 3 { 0o120777 } else { 0o100644 })
+        .collect();
+    let linktos: Vec<CString> = (0..files)
+        .map(|i| match i % 4 {
+            3 => c(&format!("f{}", i - 1)),
+            _ => c(""),
+        })
+        .collect();
+    let linktos: Vec<&CStr> = linktos.iter().map(|i| &**i).collect();
+    let contents: Vec<Vec<u8>> = (0..files)
+        .map(|i| match i % 4 {
+            3 => linktos[i].to_bytes().to_vec(),
+            _ => {
+                let len = rng.next() as usize % 64;
+                rng.bytes(len)
+            }
+        })
+        .collect();
+    let sizes: Vec<u32> = contents.iter().map(|i| i.len() as u32).collect();
+    let mut payload = vec![];
+    for (i, contents) in contents.iter().enumerate() {
+        let path = format!(".{}{}", FILE_DIR, basenames[i].to_str().expect("ASCII"));
+        cpio_entry(&mut payload, i as u32 + 1, modes[i], &path, contents)
+    }
+    cpio_entry(&mut payload, 0, 0, "TRAILER!!!", b"");
+    let payload_digest = [hex_digest(8, &payload, token)];
+    let dirindexes = vec![0u32; files];
+    let dirnames: Vec<&CStr> = dirnames.iter().map(|i| &**i).collect();
+    let payload_digest: Vec<&CStr> = payload_digest.iter().map(|i| &**i).collect();
+    let mut main = HeaderBuilder::new(HeaderKind::Main);
+    main.push(1000, HeaderEntry::String(&name));
+    main.push(1001, HeaderEntry::String(&version));
+    main.push(1002, HeaderEntry::String(&release));
+    main.push(1021, HeaderEntry::String(&os));
+    main.push(1022, HeaderEntry::String(&arch));
+    main.push(1044, HeaderEntry::String(&source_rpm));
+    if files > 0 {
+        main.push(1028, HeaderEntry::U32(&sizes));
+        main.push(1030, HeaderEntry::U16(&modes));
+        main.push(1036, HeaderEntry::StringArray(&linktos));
+        main.push(1116, HeaderEntry::U32(&dirindexes));
+        main.push(1117, HeaderEntry::StringArray(&basenames));
+        main.push(1118, HeaderEntry::StringArray(&dirnames));
+    }
+    main.push(1124, HeaderEntry::String(&payload_format));
+    main.push(5092, HeaderEntry::StringArray(&payload_digest));
+    main.push(5093, HeaderEntry::U32(&[8]));
+    let mut main_bytes = vec![];
+    main.emit(&mut main_bytes)?;
+    let lead = rpm_parser::load_immutable(&mut &main_bytes[..], token)?.lead();
+    let header_digest = hex_digest(8, &main_bytes, token);
+    let size = [(main_bytes.len() + payload.len()) as u32];
+    let mut sig = HeaderBuilder::new(HeaderKind::Signature);
+    sig.push(256 + 12, HeaderEntry::Bin(signature));
+    sig.push(256 + 17, HeaderEntry::String(&header_digest));
+    sig.push(1000, HeaderEntry::U32(&size));
+    let mut out = lead.to_vec();
+    sig.emit(&mut out)?;
+    out.resize((out.len() + 7) & !7, 0);
+    out.extend(main_bytes);
+    out.extend(payload);
+    Ok(out)
+}
+
+fn generate(seed: u64, dir: &Path, token: InitToken) -> Result<()> {
+    let mut rng = Rng::new(seed);
+    let creation_time = 1_600_000_000 + (rng.next() % 100_000_000) as u32;
+    let write = |name: &str, data: &[u8]| File::create(dir.join(name))?.write_all(data);
+    let (key, fingerprint) = rsa_key(&mut rng, creation_time, token);
+    write("key-rsa.pgp", &key)?;
+    let standard = standard_subpackets(creation_time, fingerprint);
+    let issuer = [Subpacket::issuer_key_id(key_id(fingerprint), false)];
+    let sig = v4_signature(&mut rng, &standard, &issuer);
+    write("sig-v4.pgp", &sig)?;
+    write(
+        "sig-v3.pgp",
+        &v3_signature(&mut rng, creation_time, key_id(fingerprint)),
+    )?;
+    write(
+        "sig-v4-key-id-only.pgp",
+        &v4_signature(
+            &mut rng,
+            &[Subpacket::creation_time(creation_time, false)],
+            &issuer,
+        ),
+    )?;
+    // Fill the hashed area as far as it will go
+    let mut huge = standard.clone();
+    let used = serialize_area(&huge).expect("fits").len() - 2;
+    // 5 length bytes, the tag, flags, and the two lengths
+    let value_len = 0xFFFF - used - (5 + 1 + 8 + HUGE_NOTATION.len());
+    let value = vec![b'x'; value_len];
+    huge.push(Subpacket::notation(HUGE_NOTATION.as_bytes(), &value, true, false).expect("fits"));
+    write(
+        "sig-v4-huge-subpackets.pgp",
+        &v4_signature(&mut rng, &huge, &issuer),
+    )?;
+    write(
+        "minimal.rpm",
+        &package(&mut rng, "testvectors-minimal", 0, &sig, token)?,
+    )?;
+    let files = 1000 + (rng.next() % 1000) as usize;
+    write(
+        "many-files.rpm",
+        &package(&mut rng, "testvectors-many-files", files, &sig, token)?,
+    )?;
+    check(dir, fingerprint, files, token)?;
+    write("EXPECTED", EXPECTED.as_bytes())
+}
+
+fn expect(ok: bool, what: &str) -> Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::other(format!(
+            "generated {} is not as expected",
+            what
+        )))
+    }
+}
+
+/// Parses the vectors in `dir` again and checks the results against
+/// [`EXPECTED`]
+fn check(dir: &Path, fingerprint: [u8; 20], files: usize, token: InitToken) -> Result<()> {
+    let read = |name: &str| read(dir.join(name));
+    let key = read("key-rsa.pgp")?;
+    let key = openpgp_parser::key::parse(&key)?;
+    expect(
+        (key.version, key.algorithm, key.material.bits())
+            == (4, PublicKeyAlgorithm::Rsa, Some(2048)),
+        "key-rsa.pgp",
+    )?;
+    let parse = |name: &str, options: &ParseOptions<'_>| -> Result<_> {
+        Ok(signature::parse_with_options(&read(name)?, 0, options))
+    };
+    let options = ParseOptions::new(AllowWeakHashes::No);
+    let sig = parse("sig-v4.pgp", &options)?;
+    expect(
+        matches!(sig, Ok(ref info) if info.key_id_source == KeyIdSource::Hashed
+            && info.fingerprint.as_ref().map(|i| i.as_bytes()) == Some(&fingerprint[..])),
+        "sig-v4.pgp",
+    )?;
+    let sig = parse("sig-v3.pgp", &options)?;
+    expect(
+        matches!(sig, Ok(ref info) if info.key_id_source == KeyIdSource::V3Header
+            && info.key_id == key_id(fingerprint)),
+        "sig-v3.pgp",
+    )?;
+    let sig = parse("sig-v4-key-id-only.pgp", &options)?;
+    expect(
+        matches!(sig, Ok(ref info) if info.key_id_source == KeyIdSource::Unhashed
+            && info.fingerprint.is_none()),
+        "sig-v4-key-id-only.pgp",
+    )?;
+    let sig = parse("sig-v4-huge-subpackets.pgp", &options)?;
+    expect(
+        matches!(sig, Err(PgpError::UnsupportedCriticalSubpacket)),
+        "sig-v4-huge-subpackets.pgp",
+    )?;
+    let mut options = options;
+    options.subpackets.allowed_notations = &[HUGE_NOTATION];
+    let sig = parse("sig-v4-huge-subpackets.pgp", &options)?;
+    expect(sig.is_ok(), "sig-v4-huge-subpackets.pgp")?;
+    for &(name, files) in &[("minimal.rpm", 0), ("many-files.rpm", files)] {
+        let data = read(name)?;
+        let mut r = &data[..];
+        let package = RPMPackage::read(&mut r, AllowWeakHashes::No, token)?;
+        let list = rpm_parser::file_list(&package.immutable.header).unwrap_or_default();
+        expect(list.len() == files, name)?;
+        // Every file is in the payload, symlinks with their targets
+        let mut payload = vec![];
+        r.read_to_end(&mut payload)?;
+        for (path, _, link_to) in list.iter().step_by(97).chain(list.last()) {
+            let contents = rpm_parser::extract_entry(&mut &payload[..], path)?;
+            expect(
+                contents.is_some_and(|i| link_to.is_empty() || i == link_to.as_bytes()),
+                name,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let seed = match args.first().and_then(|s| s.to_str()?.parse().ok()) {
+        Some(seed) if args.len() == 2 => seed,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1)
+        }
+    };
+    let token = rpm_crypto::init();
+    if let Err(e) = generate(seed, Path::new(&args[1]), token) {
+        eprintln!(
+            "Error generating test vectors: {}",
+            rpm_parser::escape_terminal(&e.to_string())
+        );
+        std::process::exit(1)
+    }
+}
@@ -1,12 +1,13 @@
 #![feature(rustc_private)] // hack hack
 extern crate libc;
 
-use openpgp_parser::AllowWeakHashes;
+use openpgp_parser::{signature::ParseOptions, AllowWeakHashes};
 use rpm_crypto::transaction::RpmTransactionSet;
+use rpm_parser::{Keyring, Policy};
 use rpm_writer::{HeaderBuilder, HeaderEntry};
 use std::ffi::{CStr, CString, OsStr};
 use std::fs::{File, OpenOptions};
-use std::io::{Result, Write};
+use std::io::{Error, ErrorKind, Result, Seek, SeekFrom, Write};
 use std::os::unix::{
     ffi::OsStrExt,
     fs::OpenOptionsExt,
@@ -30,12 +31,23 @@ const RPMSIGTAG_MD5: u32 = 1004;
 
 fn usage(success: bool) -> i32 {
     const USAGE: &'static str = "Usage: rpmcanon [OPTIONS] -- SOURCE DESTINATION\n\n\
+                                 If DESTINATION is -, the package is written to standard output once it has been verified.\n\n\
                                  Options:\n\n\
                                  --help print this message\n\
                                  --preserve-old-signature Preserve and require the RPMv3 (header+payload) signature\n\
                                  --allow-weak-hashes allow packages signed with SHA-1 or SHA-224\n\
                                  --allow-old-pkgs allow packages that don’t have a payload digest in the main header\n\
-                                 --directory copy packages in SOURCE to DESTINATION; both directories must exist";
+                                 --keyring=FILE take keys from FILE (binary or ASCII-armored certificates) instead of the RPM database\n\
+                                 --require-fingerprint reject header signatures that identify their key only by key ID; needs --keyring\n\
+                                 --allow-v6 allow RFC 9580 signatures (v6, Ed25519, Ed448); RPM must be able to verify them\n\
+                                 --upgrade-digests add missing SHA-256 digests; a package without a payload digest is written unsigned and must be re-signed\n\
+                                 --profile=NAME start from a named policy (fedora, epel, qubes-dom0, legacy-el7, sha512-only); later options override it.  Profiles check key sizes, so they all need --keyring; with neither option, the fedora policy is used without that check\n\
+                                 --directory copy packages in SOURCE to DESTINATION; both directories must exist\n\n\
+                                 Exit status:\n\n\
+                                 0 success\n\
+                                 1 the package is malformed, or another error occurred\n\
+                                 2 the package could not be verified (missing key or unsupported algorithm)\n\
+                                 3 the package failed cryptographic verification";
     if success {
         println!("{}", USAGE);
         0
@@ -53,12 +65,20 @@ fn emit_header(
         ref main_header_bytes,
         ref main_header_hash,
         ref header_payload_weak_digest,
+        ref unknown_signature_tags,
+        ..
     }: &rpm_parser::VerifyResult,
     mut dest: Option<&mut dyn std::io::Write>,
-    _allow_weak_hashes: AllowWeakHashes,
-    _token: rpm_crypto::InitToken,
+    options: &ParseOptions,
+    reserved_len: Option<usize>,
+    token: rpm_crypto::InitToken,
 ) -> std::io::Result<()> {
     let dest = dest.as_mut().expect("we always pass a stream; qed");
+    // The signature header is regenerated from scratch, so unknown tags are
+    // dropped.  Say so rather than doing it silently.
+    for tag in unknown_signature_tags {
+        eprintln!("Dropping unknown signature header tag {}", tag)
+    }
     let magic_offset = 96;
     let mut hdr = HeaderBuilder::new(rpm_writer::HeaderKind::Signature);
     hdr.push(
@@ -67,42 +87,179 @@ fn emit_header(
             CStr::from_bytes_with_nul(&main_header_hash).expect("RPM NUL-terminates its hex data"),
         ),
     );
-    hdr.push(RPMSIGTAG_RSAHEADER, HeaderEntry::Bin(&*header_sig));
+    // Re-emit the signatures in canonical form, so that the output does not
+    // depend on how the input encoded them.  A signature that cannot be
+    // re-serialized is reported as malformed.
+    let canonical = |sig: &[u8]| -> Result<Vec<u8>> {
+        let sig = rpm_crypto::Signature::parse_with_options(sig, 0, options, token)?;
+        Ok(sig.serialize()?)
+    };
+    let header_sig = canonical(header_sig)?;
+    hdr.push(RPMSIGTAG_RSAHEADER, HeaderEntry::Bin(&header_sig));
+    let header_payload_sig = match header_payload_sig {
+        Some(sig) => Some(canonical(sig)?),
+        None => None,
+    };
     if let Some(ref sig) = header_payload_sig {
         hdr.push(RPMSIGTAG_PGP, HeaderEntry::Bin(sig));
     }
     if let Some(ref weak_digest) = header_payload_weak_digest {
         hdr.push(RPMSIGTAG_MD5, HeaderEntry::Bin(weak_digest));
     }
+    if let Some(reserved_len) = reserved_len {
+        if !hdr.reserve_space(reserved_len) {
+            eprintln!("Signature header does not fit in its reserved space; package size will change")
+        }
+    }
     let mut out_data = vec![0; magic_offset];
     out_data[..magic_offset].copy_from_slice(&main_header.lead());
     hdr.emit(&mut out_data).expect("writes to a vec never fail");
     let fixup = (out_data.len() + 7 & !7) - out_data.len();
     out_data.extend_from_slice(&[0u8; 7][..fixup]);
     #[cfg(debug_assertions)]
-    rpm_parser::load_signature(&mut &out_data[magic_offset..], _allow_weak_hashes, _token).unwrap();
+    rpm_parser::load_signature_with_options(&mut &out_data[magic_offset..], options, token)
+        .unwrap();
     dest.write_all(&out_data)?;
     dest.write_all(&main_header_bytes)
 }
 
+fn report_upgrades(upgrades: rpm_writer::DigestUpgrades) {
+    if upgrades.header_digest {
+        eprintln!("Added a SHA-256 header digest")
+    }
+    if upgrades.payload_digest {
+        eprintln!("Added a SHA-256 payload digest; the package must be re-signed")
+    }
+}
+
+/// An unnamed temporary file, which disappears when closed
+#[cfg(target_os = "linux")]
+fn anonymous_file() -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .mode(0o600)
+        .custom_flags(libc::O_TMPFILE | libc::O_CLOEXEC)
+        .open(std::env::temp_dir())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn anonymous_file() -> Result<File> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "writing to standard output requires O_TMPFILE",
+    ))
+}
+
+fn read_keyring(path: &OsStr) -> Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    match data.first() {
+        Some(i) if i & 0x80 != 0 => Ok(data),
+        _ => match openpgp_parser::armor::decode(&data) {
+            Ok((_, data)) => Ok(data),
+            Err(e) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("bad ASCII armor in keyring: {:?}", e),
+            )),
+        },
+    }
+}
+
+fn parse_keyring(data: &[u8], token: rpm_crypto::InitToken) -> Result<Keyring<'_>> {
+    let mut keyring = Keyring::new();
+    if keyring.add(data, token)? == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "keyring has no keys"));
+    }
+    Ok(keyring)
+}
+
+/// Where to find the keys that packages must be signed with
+enum Keys<'a> {
+    /// The RPM database.  RPM finds keys by key ID and does not say which
+    /// one it used.
+    Database(RpmTransactionSet),
+    /// A keyring file, which says exactly which key made the header signature
+    File(Keyring<'a>),
+}
+
+fn keyring_error(e: Error) -> i32 {
+    eprintln!(
+        "Error loading keyring: {}",
+        rpm_parser::escape_terminal(&e.to_string())
+    );
+    1
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_file(
-    tx: &RpmTransactionSet,
+    keys: &Keys<'_>,
     src: &std::ffi::OsStr,
     dst: &std::ffi::OsStr,
-    allow_weak_hashes: AllowWeakHashes,
-    allow_old_pkgs: bool,
+    policy: Policy,
     preserve_old_signature: bool,
+    upgrade_digests: bool,
+    allow_v6: bool,
     token: rpm_crypto::InitToken,
 ) -> Result<()> {
-    let emit_header: &mut dyn FnMut(
-        &rpm_parser::VerifyResult,
-        Option<&mut dyn std::io::Write>,
-    ) -> std::io::Result<()> = &mut |x, y| emit_header(x, y, allow_weak_hashes, token);
+    let mut options = ParseOptions::new(policy.allow_weak_hashes);
+    options.allow_v6 = allow_v6;
+    options.allow_native_eddsa = allow_v6;
     let mut s = File::open(src)?;
     // Ignore the lead
     let _ = rpm_parser::read_lead(&mut s)?;
     // Read the signature header
-    let mut sig_header = rpm_parser::load_signature(&mut s, allow_weak_hashes, token)?;
+    let mut sig_header = rpm_parser::load_signature_with_options(&mut s, &options, token)?;
+    // If the package reserved space for signatures, keep its size the same
+    let reserved_len = match sig_header.header.get(rpm_writer::RPMSIGTAG_RESERVEDSPACE) {
+        Some(_) => {
+            let header = &sig_header.header;
+            Some(16 * (header.index.len() + 1) + header.data.len())
+        }
+        None => None,
+    };
+    let emit_header: &mut dyn FnMut(
+        &rpm_parser::VerifyResult,
+        Option<&mut dyn std::io::Write>,
+    ) -> std::io::Result<()> = &mut |x, y| {
+        policy.check(x)?;
+        emit_header(x, y, &options, reserved_len, token)
+    };
+    let mut verify = |output: &mut dyn Write| match keys {
+        Keys::File(keyring) => keyring.verify_package(
+            &mut s,
+            &mut sig_header,
+            policy.allow_old_pkgs,
+            preserve_old_signature,
+            token,
+            Some(&mut *emit_header),
+            Some(output),
+        ),
+        Keys::Database(tx) => rpm_parser::verify_package(
+            &mut s,
+            &mut sig_header,
+            &tx.keyring(),
+            policy.allow_old_pkgs,
+            preserve_old_signature,
+            token,
+            Some(&mut *emit_header),
+            Some(output),
+        ),
+    };
+    if dst.as_bytes() == b"-" {
+        // Standard output cannot be unlinked on failure, so spool the package
+        // and only write it out once it has been verified
+        let mut spool = anonymous_file()?;
+        verify(&mut spool)?;
+        spool.seek(SeekFrom::Start(0))?;
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        if upgrade_digests {
+            report_upgrades(rpm_writer::upgrade_digests(&mut spool, &mut stdout, token)?)
+        } else {
+            std::io::copy(&mut spool, &mut stdout)?;
+        }
+        return stdout.flush();
+    }
     let mut do_rename = true;
     let (parent_dir, mut dest, fname, tmp_path) = {
         let mut options = OpenOptions::new();
@@ -182,16 +339,25 @@ fn process_file(
             (parent, File::from_raw_fd(res), c_fname, tmp_path)
         }
     };
-    let rpm_parser::VerifyResult { .. } = rpm_parser::verify_package(
-        &mut s,
-        &mut sig_header,
-        &tx.keyring(),
-        allow_old_pkgs,
-        preserve_old_signature,
-        token,
-        Some(emit_header),
-        Some(&mut dest),
-    )
+    // Digests are added to the verified package, which must be spooled
+    // because the payload digest is needed before the main header is written
+    let mut spool = if upgrade_digests {
+        Some(anonymous_file()?)
+    } else {
+        None
+    };
+    verify(match spool {
+        Some(ref mut spool) => spool,
+        None => &mut dest,
+    })
+    .and_then(|_| match spool {
+        Some(ref mut spool) => {
+            spool.seek(SeekFrom::Start(0))?;
+            report_upgrades(rpm_writer::upgrade_digests(spool, &mut dest, token)?);
+            Ok(())
+        }
+        None => Ok(()),
+    })
     .map_err(|e| {
         if cfg!(not(target_os = "linux")) {
             unsafe {
@@ -247,22 +413,40 @@ fn process_file(
 fn inner_main() -> i32 {
     let token = rpm_crypto::init();
     let mut args = std::env::args_os().into_iter();
-    let mut allow_weak_hashes = AllowWeakHashes::No;
-    let mut allow_old_pkgs = false;
+    let mut policy = Policy::preset("fedora").expect("fedora preset exists");
     let mut directory = false;
     let mut preserve_old_signature = false;
+    let mut upgrade_digests = false;
+    let mut allow_v6 = false;
+    let mut keyring_path = None;
+    let mut profile = false;
     let _ = match args.next() {
         Some(s) => s,
         None => return usage(false),
     };
     for i in &mut args {
         match i.as_bytes() {
-            b"--allow-weak-hashes" => allow_weak_hashes = AllowWeakHashes::Yes,
+            b"--allow-weak-hashes" => policy.allow_weak_hashes = AllowWeakHashes::Yes,
             b"--help" => return usage(true),
             b"--directory" => directory = true,
-            b"--allow-old-pkgs" => allow_old_pkgs = true,
+            b"--allow-old-pkgs" => policy.allow_old_pkgs = true,
             b"--preserve-old-signature" => preserve_old_signature = true,
+            b"--require-fingerprint" => policy.require_fingerprint = true,
+            b"--upgrade-digests" => upgrade_digests = true,
+            b"--allow-v6" => allow_v6 = true,
             b"--" => break,
+            s if s.starts_with(b"--keyring=") => {
+                keyring_path = Some(OsStr::from_bytes(&s[10..]).to_owned())
+            }
+            s if s.starts_with(b"--profile=") => {
+                match std::str::from_utf8(&s[10..]).ok().and_then(Policy::preset) {
+                    Some(preset) => {
+                        policy = preset;
+                        profile = true
+                    }
+                    None => return usage(false),
+                }
+            }
             _ => return usage(false),
         }
     }
@@ -271,23 +455,56 @@ fn inner_main() -> i32 {
         return usage(false);
     }
     let (src, dst) = (args[0].clone(), args[1].clone());
-    let tx = RpmTransactionSet::new(token);
+    // RPM finds keys by key ID and does not say which one it used
+    if keyring_path.is_none() && policy.require_fingerprint {
+        eprintln!("Policy requires fingerprints, which needs --keyring");
+        return 1;
+    }
+    // Nor does it say how large the key is
+    if keyring_path.is_none() && policy.min_key_bits > 0 {
+        if profile {
+            eprintln!("Policy requires a minimum key size, which needs --keyring");
+            return 1;
+        }
+        policy.min_key_bits = 0
+    }
+    let keyring_data = match keyring_path.as_deref().map(read_keyring).transpose() {
+        Ok(data) => data,
+        Err(e) => return keyring_error(e),
+    };
+    let keys = match keyring_data.as_deref() {
+        Some(data) => match parse_keyring(data, token) {
+            Ok(keyring) => Keys::File(keyring),
+            Err(e) => return keyring_error(e),
+        },
+        None => Keys::Database(RpmTransactionSet::new(token)),
+    };
     if directory {
         todo!()
     }
     match process_file(
-        &tx,
+        &keys,
         &src,
         &dst,
-        allow_weak_hashes,
-        allow_old_pkgs,
+        policy,
         preserve_old_signature,
+        upgrade_digests,
+        allow_v6,
         token,
     ) {
         Ok(()) => 0,
+        // Whoever was reading the output went away; there is nobody to tell
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => 1,
         Err(e) => {
-            eprintln!("Error canonicalizing file: {}", e);
-            1
+            eprintln!(
+                "Error canonicalizing file: {}",
+                rpm_parser::escape_terminal(&e.to_string())
+            );
+            match rpm_parser::failure_kind(&e) {
+                Some(rpm_parser::FailureKind::Unverifiable) => 2,
+                Some(rpm_parser::FailureKind::Forged) => 3,
+                _ => 1,
+            }
         }
     }
 }
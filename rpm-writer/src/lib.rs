@@ -9,6 +9,13 @@ use std::convert::TryInto;
 use std::ffi::CStr;
 use std::io::Write;
 
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "rpm-crypto")]
+mod upgrade;
+#[cfg(feature = "rpm-crypto")]
+pub use upgrade::{upgrade_digests, DigestUpgrades};
+
 /// What kind of header is this?
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum HeaderKind {
@@ -91,7 +98,9 @@ impl<'a> HeaderEntry<'a> {
                 }
                 Ok(())
             }
-            _ => unimplemented!(),
+            Self::U16(e) => e.iter().try_for_each(|i| w.write_all(&i.to_be_bytes())),
+            Self::U32(e) => e.iter().try_for_each(|i| w.write_all(&i.to_be_bytes())),
+            Self::U64(e) => e.iter().try_for_each(|i| w.write_all(&i.to_be_bytes())),
         }
     }
     pub fn count(&self) -> usize {
@@ -109,6 +118,13 @@ impl<'a> HeaderEntry<'a> {
     }
 }
 
+/// Signature header tag holding zeroed space reserved for later signatures
+pub const RPMSIGTAG_RESERVEDSPACE: u32 = 1008;
+
+/// Zeroes for [`HeaderBuilder::reserve_space`].  rpmsign reserves 4 KiB, so
+/// this is plenty.
+static ZEROES: [u8; 0x10000] = [0; 0x10000];
+
 /// A header builder
 pub struct HeaderBuilder<'a> {
     kind: HeaderKind,
@@ -131,6 +147,40 @@ impl<'a> HeaderBuilder<'a> {
             .iter()
             .fold(16, |len, entry| entry.1.advance_length(len) + entry.1.len())
     }
+    /// The number of bytes that [`Self::emit`] will write
+    pub fn emitted_len(&self) -> usize {
+        16 * (self.data.len() + 2) + self.len()
+    }
+    /// Adds a zeroed [`RPMSIGTAG_RESERVEDSPACE`] entry so that [`Self::emit`]
+    /// writes exactly `target_len` bytes, as rpmsign does.  This allows a
+    /// package to be re-signed in place without changing its size.  Any
+    /// existing reserved space is replaced.
+    ///
+    /// Returns `false`, without changing `self`, if this is not possible.
+    pub fn reserve_space(&mut self, target_len: usize) -> bool {
+        let old = self.data.remove(&RPMSIGTAG_RESERVEDSPACE);
+        // Adding the entry costs an index entry and at least one byte of data
+        let space = match target_len.checked_sub(self.emitted_len() + 16) {
+            Some(space) if space > 0 && space <= ZEROES.len() => space,
+            _ => {
+                if let Some(old) = old {
+                    self.data.insert(RPMSIGTAG_RESERVEDSPACE, old);
+                }
+                return false;
+            }
+        };
+        self.data
+            .insert(RPMSIGTAG_RESERVEDSPACE, HeaderEntry::Bin(&ZEROES[..space]));
+        // Entries after the reserved space may need different padding
+        if self.emitted_len() != target_len {
+            self.data.remove(&RPMSIGTAG_RESERVEDSPACE);
+            if let Some(old) = old {
+                self.data.insert(RPMSIGTAG_RESERVEDSPACE, old);
+            }
+            return false;
+        }
+        true
+    }
     pub fn emit(&self, t: &mut dyn Write) -> std::io::Result<()> {
         let (dl, il) = (self.len(), self.data.len() + 1);
         let res = [
@@ -183,6 +233,9 @@ impl<'a> HeaderBuilder<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
     #[test]
     fn it_works() {
         let mut builder = HeaderBuilder::new(HeaderKind::Signature);
@@ -194,4 +247,125 @@ mod tests {
         assert!(builder.push(1005, HeaderEntry::Bin(&b"abc"[..])).is_none());
         builder.emit(&mut vec![]).unwrap()
     }
+
+    #[test]
+    fn reserves_space() {
+        let mut builder = HeaderBuilder::new(HeaderKind::Signature);
+        builder.push(1000, HeaderEntry::U32(&[5]));
+        let len = builder.emitted_len();
+        assert!(!builder.reserve_space(len + 16));
+        assert_eq!(builder.emitted_len(), len);
+        assert!(builder.reserve_space(len + 4096));
+        assert_eq!(builder.emitted_len(), len + 4096);
+        assert!(builder.reserve_space(len + 17));
+        let mut out = vec![];
+        builder.emit(&mut out).unwrap();
+        assert_eq!(out.len(), len + 17);
+    }
+
+    #[test]
+    fn emits_integers() {
+        let mut builder = HeaderBuilder::new(HeaderKind::Main);
+        builder.push(1000, HeaderEntry::U8(&[1]));
+        builder.push(1001, HeaderEntry::U16(&[0x1234]));
+        builder.push(1002, HeaderEntry::U64(&[u64::MAX - 1]));
+        builder.push(1003, HeaderEntry::U32(&[1, 2, 3]));
+        let mut out = vec![];
+        builder.emit(&mut out).unwrap();
+        let mut values = vec![];
+        rpm_parser::parse_header_events(&mut &out[..], 63, &mut |tag, _, value| {
+            values.push(format!("{} {:?}", tag, value));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            values,
+            [
+                "1000 Int8([1])",
+                "1001 Int16([4660])",
+                "1002 Int64([18446744073709551614])",
+                "1003 Int32([1, 2, 3])",
+            ]
+        );
+    }
+
+    /// A header entry: the gap to the previous tag, the number of values,
+    /// which string to use, and the type
+    fn entry() -> impl Strategy<Value = (u32, usize, usize, u8)> {
+        (1..=50u32, 1..=20usize, 0..20usize, 0..7u8)
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip(
+            bytes in vec(any::<u8>(), 20),
+            u16s in vec(any::<u16>(), 20),
+            u32s in vec(any::<u32>(), 20),
+            u64s in vec(any::<u64>(), 20),
+            strings in vec("[ -~]{1,20}", 20),
+            entries in vec(entry(), 1..=30),
+        ) {
+            use rpm_parser::TagValue;
+            use std::ffi::CString;
+            let strings: Vec<CString> = strings
+                .into_iter()
+                .map(|s| CString::new(s).unwrap())
+                .collect();
+            let string_refs: Vec<&CStr> = strings.iter().map(|i| &**i).collect();
+            let mut builder = HeaderBuilder::new(HeaderKind::Main);
+            let mut expected = vec![];
+            let mut tag = 1000;
+            for (gap, len, string, kind) in entries {
+                tag += gap;
+                let s = string_refs[string];
+                let (entry, value) = match kind {
+                    0 => (
+                        HeaderEntry::U8(&bytes[..len]),
+                        TagValue::Int8(&bytes[..len]),
+                    ),
+                    1 => (
+                        HeaderEntry::U16(&u16s[..len]),
+                        TagValue::Int16(u16s[..len].to_vec()),
+                    ),
+                    2 => (
+                        HeaderEntry::U32(&u32s[..len]),
+                        TagValue::Int32(u32s[..len].to_vec()),
+                    ),
+                    3 => (
+                        HeaderEntry::U64(&u64s[..len]),
+                        TagValue::Int64(u64s[..len].to_vec()),
+                    ),
+                    4 => (
+                        HeaderEntry::String(s),
+                        TagValue::String(s.to_str().unwrap()),
+                    ),
+                    5 => (
+                        HeaderEntry::Bin(&bytes[..len]),
+                        TagValue::Bin(&bytes[..len]),
+                    ),
+                    _ => (
+                        HeaderEntry::StringArray(&string_refs[..len]),
+                        TagValue::StringArray(
+                            string_refs[..len]
+                                .iter()
+                                .map(|s| s.to_str().unwrap())
+                                .collect(),
+                        ),
+                    ),
+                };
+                prop_assert!(builder.push(tag, entry).is_none());
+                expected.push(format!("{} {:?}", tag, value));
+            }
+            let mut out = vec![];
+            builder.emit(&mut out).unwrap();
+            prop_assert_eq!(out.len(), builder.emitted_len());
+            let mut values = vec![];
+            rpm_parser::parse_header_events(&mut &out[..], 63, &mut |tag, _, value| {
+                values.push(format!("{} {:?}", tag, value));
+                Ok(())
+            })
+            .unwrap();
+            prop_assert_eq!(values, expected);
+        }
+    }
 }
@@ -0,0 +1,181 @@
+//! Tiny packages for tests
+//!
+//! [`TestPackage`] builds a minimal binary RPM on the fly, optionally with
+//! one deliberate [`Defect`], so that tests of the verifier need not check
+//! large packages into git.  The digests in the package are real, but its
+//! header signature is a placeholder made without any private key: it
+//! parses, but never verifies.
+
+use crate::{HeaderBuilder, HeaderEntry, HeaderKind};
+use openpgp_parser::subpacket::{serialize_area, Subpacket};
+use openpgp_parser::AllowWeakHashes;
+use rpm_crypto::{DigestCtx, InitToken};
+use std::ffi::{CStr, CString};
+use std::io::{Error, ErrorKind, Result};
+
+/// A deliberate defect in a [`TestPackage`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Defect {
+    /// The SHA-256 header digest in the signature header is wrong
+    BadHeaderDigest,
+    /// The payload digest in the main header is wrong
+    BadPayloadDigest,
+    /// The signature header has no header signature
+    StrippedSignature,
+    /// The SHA-256 header digest is longer than that tag allows
+    OversizedTag,
+    /// The signature header has no SHA-256 header digest
+    NoHeaderDigest,
+    /// The main header has no payload digest, as in packages built by RPM
+    /// before 4.14
+    NoPayloadDigest,
+}
+
+/// A minimal binary package to build
+#[derive(Clone, Debug)]
+pub struct TestPackage {
+    /// The package name
+    pub name: String,
+    /// The architecture.  Defaults to `noarch`.
+    pub arch: String,
+    /// The architecture number in the lead.  Defaults to the one RPM uses
+    /// for `arch`.
+    pub archnum: Option<u16>,
+    /// The payload, which is not a real archive
+    pub payload: Vec<u8>,
+    /// The header signature.  Defaults to [`placeholder_signature`].
+    pub signature: Vec<u8>,
+    /// Scriptlets, as pairs of the scriptlet tag (such as 1024 for `%post`)
+    /// and the script.  Defaults to none.
+    pub scriptlets: Vec<(u32, String)>,
+    /// The defect to introduce, if any
+    pub defect: Option<Defect>,
+}
+
+const RPMSIGTAG_RSA: u32 = 256 + 12;
+const RPMSIGTAG_SHA256: u32 = 256 + 17;
+const RPMSIGTAG_SIZE: u32 = 1000;
+const SHA256: u8 = 8;
+
+/// Flips the last hex digit of `digest`, which must be a hex digest
+fn corrupt(digest: &mut [u8]) {
+    let last = digest.last_mut().expect("digests are not empty");
+    *last = if *last == b'0' { b'1' } else { b'0' }
+}
+
+fn hex_digest(data: &[u8], token: InitToken) -> Vec<u8> {
+    let mut ctx = DigestCtx::init(SHA256, AllowWeakHashes::No, token).expect("SHA-256 supported");
+    ctx.update(data);
+    let mut digest = ctx.finalize(true);
+    assert_eq!(digest.pop(), Some(0), "RPM NUL-terminates its hex data");
+    digest
+}
+
+fn c_string(s: Vec<u8>) -> Result<CString> {
+    CString::new(s).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// A v4 RSA signature that parses, but is not made by any key.  Its issuer
+/// is the key ID `01 02 03 04 05 06 07 08`.
+pub fn placeholder_signature() -> Vec<u8> {
+    let hashed = [Subpacket::creation_time(1_600_000_000, false)];
+    let unhashed = [Subpacket::issuer_key_id([1, 2, 3, 4, 5, 6, 7, 8], false)];
+    let mut body = vec![4, 0, 1, SHA256];
+    body.extend(serialize_area(&hashed).expect("hashed area fits"));
+    body.extend(serialize_area(&unhashed).expect("unhashed area fits"));
+    // The hash prefix, then a 2048-bit MPI
+    body.extend_from_slice(&[0xAB, 0xCD, 8, 0]);
+    body.push(0x80);
+    body.resize(body.len() + 255, 0x5A);
+    let mut packet = vec![0x89];
+    packet.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    packet.extend(body);
+    packet
+}
+
+impl TestPackage {
+    /// A package named `name` with a short payload and no defect
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            arch: "noarch".to_owned(),
+            archnum: None,
+            payload: b"not really an archive".to_vec(),
+            signature: placeholder_signature(),
+            scriptlets: vec![],
+            defect: None,
+        }
+    }
+
+    /// Sets the defect to introduce
+    pub fn with_defect(mut self, defect: Defect) -> Self {
+        self.defect = Some(defect);
+        self
+    }
+
+    /// Builds the package.  This needs RPM to compute the digests.
+    pub fn build(&self, token: InitToken) -> Result<Vec<u8>> {
+        let mut payload_digest = hex_digest(&self.payload, token);
+        if self.defect == Some(Defect::BadPayloadDigest) {
+            corrupt(&mut payload_digest)
+        }
+        let payload_digest = [c_string(payload_digest)?];
+        let payload_digest: Vec<&CStr> = payload_digest.iter().map(|i| &**i).collect();
+        let source_rpm = c_string(format!("{}-1.0-1.src.rpm", self.name).into_bytes())?;
+        let name = c_string(self.name.clone().into_bytes())?;
+        let (version, release) = (c_string(b"1.0".to_vec())?, c_string(b"1".to_vec())?);
+        let (os, arch) = (
+            c_string(b"linux".to_vec())?,
+            c_string(self.arch.clone().into_bytes())?,
+        );
+        let scriptlets = self
+            .scriptlets
+            .iter()
+            .map(|(tag, script)| Ok((*tag, c_string(script.clone().into_bytes())?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut main = HeaderBuilder::new(HeaderKind::Main);
+        main.push(1000, HeaderEntry::String(&name));
+        main.push(1001, HeaderEntry::String(&version));
+        main.push(1002, HeaderEntry::String(&release));
+        main.push(1021, HeaderEntry::String(&os));
+        main.push(1022, HeaderEntry::String(&arch));
+        main.push(1044, HeaderEntry::String(&source_rpm));
+        for (tag, script) in &scriptlets {
+            main.push(*tag, HeaderEntry::String(script));
+        }
+        if self.defect != Some(Defect::NoPayloadDigest) {
+            main.push(5092, HeaderEntry::StringArray(&payload_digest));
+            main.push(5093, HeaderEntry::U32(&[SHA256 as u32]));
+        }
+        let mut main_bytes = vec![];
+        main.emit(&mut main_bytes)?;
+        let mut lead = rpm_parser::load_immutable(&mut &main_bytes[..], token)?.lead();
+        if let Some(archnum) = self.archnum {
+            lead[8..10].copy_from_slice(&archnum.to_be_bytes())
+        }
+
+        let mut header_digest = hex_digest(&main_bytes, token);
+        match self.defect {
+            Some(Defect::BadHeaderDigest) => corrupt(&mut header_digest),
+            Some(Defect::OversizedTag) => header_digest.extend_from_slice(b"00"),
+            _ => {}
+        }
+        let header_digest = c_string(header_digest)?;
+        let size = [(main_bytes.len() + self.payload.len()) as u32];
+        let mut sig = HeaderBuilder::new(HeaderKind::Signature);
+        if self.defect != Some(Defect::StrippedSignature) {
+            sig.push(RPMSIGTAG_RSA, HeaderEntry::Bin(&self.signature));
+        }
+        if self.defect != Some(Defect::NoHeaderDigest) {
+            sig.push(RPMSIGTAG_SHA256, HeaderEntry::String(&header_digest));
+        }
+        sig.push(RPMSIGTAG_SIZE, HeaderEntry::U32(&size));
+        let mut out = lead.to_vec();
+        sig.emit(&mut out)?;
+        out.resize((out.len() + 7) & !7, 0);
+        out.extend(main_bytes);
+        out.extend_from_slice(&self.payload);
+        Ok(out)
+    }
+}
@@ -0,0 +1,282 @@
+//! Digest algorithm upgrades
+//!
+//! Stricter consumers reject packages whose only header digest is SHA-1, or
+//! whose main header has no payload digest.  [`upgrade_digests`] adds the
+//! missing digests, using SHA-256, without touching the payload.
+//!
+//! A SHA-256 header digest lives in the signature header, so adding one leaves
+//! every signature intact.  A payload digest lives in the main header, so
+//! adding one invalidates every signature in the package.  Such packages are
+//! written without signatures and must be re-signed.
+
+use crate::{HeaderBuilder, HeaderEntry, HeaderKind, RPMSIGTAG_RESERVEDSPACE};
+use openpgp_parser::AllowWeakHashes;
+use rpm_crypto::{DigestCtx, InitToken};
+use rpm_parser::{RPMHeader, TagData, TagValue};
+use std::convert::TryInto;
+use std::ffi::CStr;
+use std::io::{copy, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+const RPMSIGTAG_LONGSIZE: u32 = 256 + 14;
+const RPMSIGTAG_LONGARCHIVESIZE: u32 = 256 + 15;
+const RPMSIGTAG_SHA256HEADER: u32 = 256 + 17;
+const RPMSIGTAG_SIZE: u32 = 1000;
+const RPMSIGTAG_PAYLOADSIZE: u32 = 1007;
+const RPMTAG_PAYLOADDIGEST: u32 = 5092;
+const RPMTAG_PAYLOADDIGESTALGO: u32 = 5093;
+const SHA256: u8 = 8;
+
+/// The digests added by [`upgrade_digests`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DigestUpgrades {
+    /// A SHA-256 digest of the main header was added to the signature header
+    pub header_digest: bool,
+    /// A SHA-256 payload digest was added to the main header.  The package
+    /// has no signatures and must be re-signed.
+    pub payload_digest: bool,
+}
+
+/// The value of a header entry, converted so that it can be passed to
+/// [`HeaderBuilder::push`]
+enum Value<'a> {
+    Borrowed(HeaderEntry<'a>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+    Strings(Vec<&'a CStr>, bool),
+}
+
+fn bad_entry(tag: u32) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("cannot copy header entry {}", tag),
+    )
+}
+
+/// The string `s`, from a [`TagValue`] parsed from `data`, as a C string.
+/// Such strings borrow from `data`, where they are followed by their NUL
+/// terminator.
+fn c_str<'a>(data: &'a [u8], s: &str) -> &'a CStr {
+    let start = s.as_ptr() as usize - data.as_ptr() as usize;
+    CStr::from_bytes_with_nul(&data[start..=start + s.len()]).expect("one NUL, at the end")
+}
+
+/// Converts the entries in `index` other than the first, which is the region
+fn values<'a>(index: &[TagData], data: &'a [u8]) -> Result<Vec<(u32, Value<'a>)>> {
+    let mut values = vec![];
+    for entry in index.get(1..).unwrap_or(&[]) {
+        let tag = entry.tag();
+        let data = data
+            .get(entry.offset() as usize..)
+            .ok_or_else(|| bad_entry(tag))?;
+        let strings = |s: Vec<&str>| s.into_iter().map(|s| c_str(data, s)).collect();
+        values.push((
+            tag,
+            match TagValue::parse(entry, data).ok_or_else(|| bad_entry(tag))? {
+                TagValue::Char(e) => Value::Borrowed(HeaderEntry::Char(e)),
+                TagValue::Int8(e) => Value::Borrowed(HeaderEntry::U8(e)),
+                TagValue::Int16(e) => Value::U16(e),
+                TagValue::Int32(e) => Value::U32(e),
+                TagValue::Int64(e) => Value::U64(e),
+                TagValue::String(s) => Value::Borrowed(HeaderEntry::String(c_str(data, s))),
+                TagValue::Bin(e) => Value::Borrowed(HeaderEntry::Bin(e)),
+                TagValue::StringArray(s) => Value::Strings(strings(s), false),
+                TagValue::I18NString(s) => Value::Strings(strings(s), true),
+            },
+        ))
+    }
+    Ok(values)
+}
+
+impl<'a> Value<'a> {
+    fn entry(&'a self) -> HeaderEntry<'a> {
+        match self {
+            Value::Borrowed(entry) => *entry,
+            Value::U16(e) => HeaderEntry::U16(e),
+            Value::U32(e) => HeaderEntry::U32(e),
+            Value::U64(e) => HeaderEntry::U64(e),
+            Value::Strings(e, false) => HeaderEntry::StringArray(e),
+            Value::Strings(e, true) => HeaderEntry::I18NTable(e),
+        }
+    }
+}
+
+fn header_values(header: &RPMHeader) -> Result<Vec<(u32, Value<'_>)>> {
+    values(&header.index, &header.data)
+}
+
+fn hex_digest(r: &mut dyn Read, token: InitToken) -> Result<(Vec<u8>, u64)> {
+    let mut ctx = DigestCtx::init(SHA256, AllowWeakHashes::No, token).expect("SHA-256 supported");
+    let len = copy(r, &mut ctx)?;
+    let mut digest = ctx.finalize(true);
+    assert_eq!(digest.pop(), Some(0), "RPM NUL-terminates its hex data");
+    Ok((digest, len))
+}
+
+fn read_raw_header(src: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut magic = [0; 16];
+    src.read_exact(&mut magic)?;
+    let (index_length, data_length) = rpm_parser::parse_header_magic(&magic)?;
+    let mut header = vec![0; 16 * (index_length as usize + 1) + data_length as usize];
+    header[..16].copy_from_slice(&magic);
+    src.read_exact(&mut header[16..])?;
+    Ok(header)
+}
+
+/// Add missing SHA-256 digests to the package read from `src`, writing the
+/// result to `dst`.  The lead and payload are copied unchanged, and so is
+/// the main header unless it lacks a payload digest.  If nothing is missing,
+/// the package is copied unchanged.  `src` is read twice if a payload digest
+/// is added.  Nothing is verified.
+pub fn upgrade_digests<R: Read + Seek>(
+    src: &mut R,
+    dst: &mut dyn Write,
+    token: InitToken,
+) -> Result<DigestUpgrades> {
+    let lead = rpm_parser::read_lead(src)?;
+    let sig_header = rpm_parser::load_signature(src, AllowWeakHashes::Yes, token)?;
+    let old_sig_len = 16 * (sig_header.header.index.len() + 1) + sig_header.header.data.len();
+    let mut main_bytes = read_raw_header(src)?;
+    let main_header = rpm_parser::load_immutable(&mut &main_bytes[..], token)?;
+    let mut upgrades = DigestUpgrades::default();
+    let sig_values = header_values(&sig_header.header)?;
+    let mut sig = HeaderBuilder::new(HeaderKind::Signature);
+    let payload_digest;
+    let payload_digest_entry: [&CStr; 1];
+    let mut size = None;
+    if main_header.payload_digest_algorithm().is_some() {
+        for (tag, value) in &sig_values {
+            sig.push(*tag, value.entry());
+        }
+    } else {
+        upgrades.payload_digest = true;
+        let payload_start = src.stream_position()?;
+        let (digest, len) = hex_digest(src, token)?;
+        src.seek(SeekFrom::Start(payload_start))?;
+        payload_digest = std::ffi::CString::new(digest).expect("hex digits are not NUL");
+        payload_digest_entry = [&payload_digest];
+        let main_values = header_values(&main_header.header)?;
+        let mut main = HeaderBuilder::new(HeaderKind::Main);
+        for (tag, value) in &main_values {
+            main.push(*tag, value.entry());
+        }
+        main.push(
+            RPMTAG_PAYLOADDIGEST,
+            HeaderEntry::StringArray(&payload_digest_entry),
+        );
+        main.push(RPMTAG_PAYLOADDIGESTALGO, HeaderEntry::U32(&[SHA256 as u32]));
+        let mut new_main = vec![];
+        main.emit(&mut new_main)?;
+        main_bytes = new_main;
+        // Only the sizes of the uncompressed payload are still valid
+        for (tag, value) in &sig_values {
+            if let RPMSIGTAG_PAYLOADSIZE | RPMSIGTAG_LONGARCHIVESIZE = *tag {
+                sig.push(*tag, value.entry());
+            }
+        }
+        size = Some(main_bytes.len() as u64 + len);
+    }
+    let header_digest;
+    if sig_header.header_sha256_hash.is_none() || upgrades.payload_digest {
+        upgrades.header_digest = sig_header.header_sha256_hash.is_none();
+        let (digest, _) = hex_digest(&mut &main_bytes[..], token)?;
+        header_digest = std::ffi::CString::new(digest).expect("hex digits are not NUL");
+        sig.push(RPMSIGTAG_SHA256HEADER, HeaderEntry::String(&header_digest));
+    }
+    let size32: [u32; 1];
+    let size64: [u64; 1];
+    if let Some(size) = size {
+        match size.try_into() {
+            Ok(size) => {
+                size32 = [size];
+                sig.push(RPMSIGTAG_SIZE, HeaderEntry::U32(&size32));
+            }
+            Err(_) => {
+                size64 = [size];
+                sig.push(RPMSIGTAG_LONGSIZE, HeaderEntry::U64(&size64));
+            }
+        }
+    }
+    // Keep the size of the signature header, so that the package can still
+    // be signed in place
+    if sig_header.header.get(RPMSIGTAG_RESERVEDSPACE).is_some() {
+        sig.reserve_space(old_sig_len);
+    }
+    let mut out = lead.as_slice().to_vec();
+    sig.emit(&mut out)?;
+    out.resize((out.len() + 7) & !7, 0);
+    dst.write_all(&out)?;
+    dst.write_all(&main_bytes)?;
+    copy(src, dst)?;
+    Ok(upgrades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_entries() {
+        let strings = [
+            CStr::from_bytes_with_nul(b"a\0").unwrap(),
+            CStr::from_bytes_with_nul(b"\xc3\xa9\0").unwrap(),
+        ];
+        let mut builder = HeaderBuilder::new(HeaderKind::Main);
+        builder.push(1000, HeaderEntry::String(strings[0]));
+        builder.push(1001, HeaderEntry::U16(&[1, 2]));
+        builder.push(1002, HeaderEntry::U64(&[u64::MAX]));
+        builder.push(1003, HeaderEntry::StringArray(&strings));
+        builder.push(1004, HeaderEntry::I18NTable(&strings[..1]));
+        builder.push(1005, HeaderEntry::Bin(b"\0\x01"));
+        let mut original = vec![];
+        builder.emit(&mut original).unwrap();
+        let index_len = u32::from_be_bytes(original[8..12].try_into().unwrap()) as usize;
+        let mut index = vec![TagData::default(); index_len];
+        TagData::as_bytes_mut(&mut index).copy_from_slice(&original[16..16 * (index_len + 1)]);
+        let values = values(&index, &original[16 * (index_len + 1)..]).unwrap();
+        let mut copy = HeaderBuilder::new(HeaderKind::Main);
+        for (tag, value) in &values {
+            copy.push(*tag, value.entry());
+        }
+        let mut copied = vec![];
+        copy.emit(&mut copied).unwrap();
+        assert_eq!(copied, original);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn upgrades_test_packages() {
+        use crate::testing::{Defect, TestPackage};
+        use std::io::Cursor;
+        let token = rpm_crypto::init();
+        let upgrade = |defect| {
+            let package = TestPackage::new("old").with_defect(defect);
+            let package = package.build(token).unwrap();
+            let mut out = vec![];
+            let upgrades = upgrade_digests(&mut Cursor::new(&package), &mut out, token).unwrap();
+            let mut s = &out[..];
+            rpm_parser::read_lead(&mut s).unwrap();
+            let mut sig = rpm_parser::load_signature(&mut s, AllowWeakHashes::No, token).unwrap();
+            let main_header = s;
+            let immutable = rpm_parser::load_immutable(&mut s, token).unwrap();
+            let main_header = &main_header[..main_header.len() - s.len()];
+            let (mut ctx, digest) = sig.header_sha256_hash.take().unwrap();
+            ctx.update(main_header);
+            assert_eq!(ctx.finalize(true), digest);
+            let (mut ctx, digest) = immutable.payload_digest().unwrap();
+            ctx.update(s);
+            assert_eq!(ctx.finalize(true), digest);
+            (upgrades, sig)
+        };
+        // The header digest is added, and the signature is kept
+        let (upgrades, sig) = upgrade(Defect::NoHeaderDigest);
+        assert!(upgrades.header_digest && !upgrades.payload_digest);
+        assert!(sig.header_signature.is_some());
+        // Adding a payload digest changes the main header, so the package
+        // comes out unsigned
+        let (upgrades, sig) = upgrade(Defect::NoPayloadDigest);
+        assert!(!upgrades.header_digest && upgrades.payload_digest);
+        assert!(sig.header_signature.is_none());
+    }
+}